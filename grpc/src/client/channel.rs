@@ -22,36 +22,42 @@
  *
  */
 
-use core::panic;
 use std::{
     any::Any,
     error::Error,
+    future::{self, Future},
     mem,
+    pin::Pin,
     str::FromStr,
     sync::{Arc, Mutex},
+    task::{Context, Poll},
     time::{Duration, Instant},
     vec,
 };
 
-use tokio::sync::{mpsc, watch, Notify};
+use bytes::Bytes;
+use tokio::sync::{mpsc, oneshot, watch, Notify};
+use tokio_stream::Stream;
+use tonic::{Code, Response as TonicResponse, Status};
 
 use serde_json::json;
 use url::Url; // NOTE: http::Uri requires non-empty authority portion of URI
 
 use crate::attributes::Attributes;
-use crate::rt;
-use crate::service::{Request, Response, Service};
+use crate::rt::{self, Sleep};
+use crate::service::{Message, Request, Response, Service};
 use crate::{client::ConnectivityState, rt::Runtime};
 use crate::{credentials::Credentials, rt::default_runtime};
 
 use super::name_resolution::{self, global_registry, Address, ResolverUpdate};
-use super::service_config::ServiceConfig;
+use super::retry::{self, RetryMemory, RetryThrottler};
+use super::service_config::{MethodConfig, RetryThrottlingPolicy, ServiceConfig};
 use super::transport::{TransportRegistry, GLOBAL_TRANSPORT_REGISTRY};
 use super::{
     load_balancing::{
-        self, pick_first, ExternalSubchannel, LbPolicy, LbPolicyBuilder, LbPolicyOptions, LbState,
-        ParsedJsonLbConfig, PickResult, Picker, Subchannel, SubchannelState, WorkScheduler,
-        GLOBAL_LB_REGISTRY,
+        self, graceful_switch::GracefulSwitchPolicy, pick_first, round_robin, ExternalSubchannel,
+        LbPolicy, LbState, ParsedJsonLbConfig, PickResult, Picker, Subchannel, SubchannelState,
+        WorkScheduler,
     },
     subchannel::{
         InternalSubchannel, InternalSubchannelPool, NopBackoff, SubchannelKey,
@@ -123,6 +129,43 @@ impl ChannelOptions {
     // etc
 }
 
+/// Options of an RPC, set by inserting them into the extensions of its
+/// request.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct CallOptions {
+    /// The time by which the RPC must complete.  The RPC fails with
+    /// DEADLINE_EXCEEDED if the deadline expires first, and the earliest of
+    /// this deadline and the timeout set by the service config applies.
+    pub deadline: Option<Instant>,
+    /// Whether the RPC waits for the channel to be able to serve it instead of
+    /// failing when connections fail.  Overrides the service config.
+    pub wait_for_ready: Option<bool>,
+}
+
+impl CallOptions {
+    /// Sets the time by which the RPC must complete.
+    pub fn deadline(self, deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    /// Sets the deadline of the RPC to `timeout` from now.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.deadline(Instant::now() + timeout)
+    }
+
+    /// Sets whether the RPC waits for the channel to be able to serve it.
+    pub fn wait_for_ready(self, wait_for_ready: bool) -> Self {
+        Self {
+            wait_for_ready: Some(wait_for_ready),
+            ..self
+        }
+    }
+}
+
 // All of Channel needs to be thread-safe.  Arc<inner>?  Or give out
 // Arc<Channel> from constructor?
 #[derive(Clone)]
@@ -142,6 +185,7 @@ impl Channel {
         options: ChannelOptions,
     ) -> Self {
         pick_first::reg();
+        round_robin::reg();
         Self {
             inner: Arc::new(PersistentChannel::new(
                 target,
@@ -247,6 +291,9 @@ struct ActiveChannel {
     abort_handle: Box<dyn rt::TaskHandle>,
     picker: Arc<Watcher<Arc<dyn Picker>>>,
     connectivity_state: Arc<Watcher<ConnectivityState>>,
+    service_config: Arc<Watcher<Arc<ServiceConfig>>>,
+    retry_memory: Arc<RetryMemory>,
+    retry_throttler: Mutex<Option<Arc<RetryThrottler>>>,
     runtime: Arc<dyn Runtime>,
 }
 
@@ -258,12 +305,31 @@ impl ActiveChannel {
         let resolve_now = Arc::new(Notify::new());
         let connectivity_state = Arc::new(Watcher::new());
         let picker = Arc::new(Watcher::new());
+        let service_config = Arc::new(Watcher::new());
+        let default_service_config = options
+            .default_service_config
+            .as_deref()
+            .map(|config| {
+                ServiceConfig::parse(config).map(|mut config| {
+                    if options.disable_health_checks {
+                        config.health_check_config = None;
+                    }
+                    Arc::new(config)
+                })
+            })
+            .transpose();
         let mut channel_controller = InternalChannelController::new(
             transport_registry,
             resolve_now.clone(),
             tx.clone(),
             picker.clone(),
             connectivity_state.clone(),
+            ServiceConfigSelector {
+                default_service_config,
+                disable_service_config_lookup: options.disable_service_config_lookup,
+                disable_health_checks: options.disable_health_checks,
+                service_config: service_config.clone(),
+            },
             runtime.clone(),
         );
 
@@ -301,36 +367,196 @@ impl ActiveChannel {
             abort_handle: jh,
             picker: picker.clone(),
             connectivity_state: connectivity_state.clone(),
+            service_config,
+            retry_memory: Arc::new(RetryMemory::new(options.max_retry_memory as usize)),
+            retry_throttler: Mutex::default(),
             runtime,
         })
     }
 
+    /// Returns the method config of a method from the current service config.
+    fn method_config(&self, method: &str) -> Option<Arc<MethodConfig>> {
+        self.service_config.cur()?.method_config(method)
+    }
+
     async fn call(&self, method: String, request: Request) -> Response {
-        // TODO: pre-pick tasks (e.g. deadlines, interceptors, retry)
-        let mut i = self.picker.iter();
+        // TODO: pre-pick tasks (e.g. interceptors)
+        let service_config = self.service_config.cur().unwrap_or_default();
+        let method_config = service_config.method_config(&method);
+        // The deadline of the RPC is the earliest of the deadline of the call
+        // and the timeout of the method.
+        let call_options = request
+            .extensions()
+            .get::<CallOptions>()
+            .cloned()
+            .unwrap_or_default();
+        let wait_for_ready = call_options
+            .wait_for_ready
+            .or_else(|| method_config.as_ref()?.wait_for_ready)
+            .unwrap_or(false);
+        let method_deadline = method_config
+            .as_ref()
+            .and_then(|config| config.timeout)
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+        let deadline = call_options
+            .deadline
+            .into_iter()
+            .chain(method_deadline)
+            .min();
+
+        let max_request_bytes = method_config
+            .as_ref()
+            .and_then(|config| config.max_request_message_bytes);
+        let max_response_bytes = method_config
+            .as_ref()
+            .and_then(|config| config.max_response_message_bytes);
+        let (request, request_too_large) = match max_request_bytes {
+            Some(max_bytes) => {
+                let (tx, rx) = oneshot::channel();
+                let (metadata, extensions, stream) = request.into_parts();
+                let stream = RequestSizeLimit {
+                    inner: stream,
+                    max_bytes: max_bytes as usize,
+                    exceeded: Some(tx),
+                };
+                let request = Request::from_parts(metadata, extensions, Box::pin(stream));
+                (request, Some(rx))
+            }
+            None => (request, None),
+        };
+        let expired = deadline.map(|deadline| {
+            self.runtime
+                .sleep(deadline.saturating_duration_since(Instant::now()))
+        });
+
+        let rpc = self.call_with_policies(
+            &method,
+            request,
+            &service_config,
+            method_config,
+            deadline,
+            wait_for_ready,
+        );
+        if expired.is_none() && request_too_large.is_none() && max_response_bytes.is_none() {
+            return rpc.await;
+        }
+        let mut failed: BoxFuture<Status> = Box::pin(failure(expired, request_too_large));
+        let response = tokio::select! {
+            response = rpc => response,
+            status = &mut failed => return retry::trailers_only_response(status),
+        };
+        let (metadata, stream, extensions) = response.into_parts();
+        let stream = LimitedStream {
+            inner: stream,
+            failed: Some(failed),
+            max_message_bytes: max_response_bytes.map(|max_bytes| max_bytes as usize),
+        };
+        TonicResponse::from_parts(metadata, Box::pin(stream), extensions)
+    }
+
+    /// Performs an RPC according to the retry or hedging policy of its
+    /// method.
+    async fn call_with_policies(
+        &self,
+        method: &str,
+        request: Request,
+        service_config: &ServiceConfig,
+        method_config: Option<Arc<MethodConfig>>,
+        deadline: Option<Instant>,
+        wait_for_ready: bool,
+    ) -> Response {
+        let throttler = self.retry_throttler(service_config.retry_throttling.as_ref());
+        let attempt = |request| self.attempt(method, request, deadline, wait_for_ready);
+        if let Some(policy) = method_config
+            .as_ref()
+            .and_then(|config| config.hedging_policy.clone())
+        {
+            return retry::call_with_hedging(
+                request,
+                policy,
+                throttler,
+                self.retry_memory.clone(),
+                self.runtime.clone(),
+                attempt,
+            )
+            .await;
+        }
+        retry::call_with_retries(
+            request,
+            method_config.and_then(|config| config.retry_policy.clone()),
+            throttler,
+            self.retry_memory.clone(),
+            self.runtime.clone(),
+            attempt,
+        )
+        .await
+    }
+
+    /// Returns the retry throttler of the channel, which is replaced when the
+    /// retry throttling policy changes.
+    fn retry_throttler(
+        &self,
+        policy: Option<&RetryThrottlingPolicy>,
+    ) -> Option<Arc<RetryThrottler>> {
+        let mut throttler = self.retry_throttler.lock().unwrap();
+        match policy {
+            None => *throttler = None,
+            Some(policy) if throttler.as_ref().is_some_and(|t| t.policy() == policy) => {}
+            Some(policy) => *throttler = Some(Arc::new(RetryThrottler::new(policy.clone()))),
+        }
+        throttler.clone()
+    }
+
+    /// Performs an attempt of an RPC on the subchannel chosen by the picker.
+    ///
+    /// The time left before the deadline is sent to the server as the
+    /// grpc-timeout of the attempt.
+    ///
+    /// The RPC waits for a new picker while the picker queues it, or fails it
+    /// and the RPC is wait-for-ready.
+    async fn attempt(
+        &self,
+        method: &str,
+        mut request: Request,
+        deadline: Option<Instant>,
+        wait_for_ready: bool,
+    ) -> Response {
+        let mut pickers = self.picker.iter();
         loop {
-            if let Some(p) = i.next().await {
-                let result = &p.pick(&request);
-                // TODO: handle picker errors (queue or fail RPC)
-                match result {
-                    PickResult::Pick(pr) => {
-                        if let Some(sc) = (pr.subchannel.as_ref() as &dyn Any)
-                            .downcast_ref::<ExternalSubchannel>()
-                        {
-                            return sc.isc.as_ref().unwrap().call(method, request).await;
-                        } else {
-                            panic!("picked subchannel is not an implementation provided by the channel");
+            let Some(picker) = pickers.next().await else {
+                return retry::trailers_only_response(Status::unavailable("channel is shut down"));
+            };
+            match picker.pick(&request) {
+                PickResult::Pick(pr) => {
+                    if let Some(sc) =
+                        (pr.subchannel.as_ref() as &dyn Any).downcast_ref::<ExternalSubchannel>()
+                    {
+                        if let Some(deadline) = deadline {
+                            request.set_timeout(deadline.saturating_duration_since(Instant::now()));
                         }
+                        return sc
+                            .isc
+                            .as_ref()
+                            .unwrap()
+                            .call(method.to_string(), request)
+                            .await;
                     }
-                    PickResult::Queue => {
-                        // Continue and retry the RPC with the next picker.
-                    }
-                    PickResult::Fail(status) => {
-                        panic!("failed pick: {}", status);
-                    }
-                    PickResult::Drop(status) => {
-                        panic!("dropped pick: {}", status);
-                    }
+                    // LB policies must pick subchannels created by the channel.
+                    return retry::trailers_only_response(Status::internal(
+                        "picked subchannel is not an implementation provided by the channel",
+                    ));
+                }
+                PickResult::Queue => {
+                    // Continue and retry the RPC with the next picker.
+                }
+                PickResult::Fail(_) if wait_for_ready => {
+                    // Wait-for-ready RPCs are retried with the next picker.
+                }
+                PickResult::Fail(status) => {
+                    return retry::trailers_only_response(Status::unavailable(status.message()));
+                }
+                PickResult::Drop(status) => {
+                    return retry::dropped_response(restrict_control_plane_code(status));
                 }
             }
         }
@@ -363,6 +589,7 @@ pub(crate) struct InternalChannelController {
     wqtx: WorkQueueTx,
     picker: Arc<Watcher<Arc<dyn Picker>>>,
     connectivity_state: Arc<Watcher<ConnectivityState>>,
+    service_config: ServiceConfigSelector,
     runtime: Arc<dyn Runtime>,
 }
 
@@ -373,6 +600,7 @@ impl InternalChannelController {
         wqtx: WorkQueueTx,
         picker: Arc<Watcher<Arc<dyn Picker>>>,
         connectivity_state: Arc<Watcher<ConnectivityState>>,
+        service_config: ServiceConfigSelector,
         runtime: Arc<dyn Runtime>,
     ) -> Self {
         let lb = Arc::new(GracefulSwitchBalancer::new(wqtx.clone(), runtime.clone()));
@@ -385,6 +613,7 @@ impl InternalChannelController {
             wqtx,
             picker,
             connectivity_state,
+            service_config,
             runtime,
         }
     }
@@ -400,13 +629,74 @@ impl InternalChannelController {
 
 impl name_resolution::ChannelController for InternalChannelController {
     fn update(&mut self, update: ResolverUpdate) -> Result<(), String> {
+        let service_config = self.service_config.select(&update.service_config)?;
+        self.service_config
+            .service_config
+            .update(service_config.clone());
+        // The LB policy receives the service config chosen by the channel,
+        // with the health check config of its subchannels.
+        let update = ResolverUpdate {
+            service_config: Ok(Some(ServiceConfig::clone(&service_config))),
+            ..update
+        };
         let lb = self.lb.clone();
-        lb.handle_resolver_update(update, self)
+        lb.handle_resolver_update(update, &service_config, self)
             .map_err(|err| err.to_string())
     }
 
     fn parse_service_config(&self, config: &str) -> Result<ServiceConfig, String> {
-        Err("service configs not supported".to_string())
+        ServiceConfig::parse(config)
+    }
+}
+
+/// Chooses the service config used by the channel, following [gRFC A21].
+///
+/// [gRFC A21]:
+///     https://github.com/grpc/proposal/blob/master/A21-service-config-error-handling.md
+struct ServiceConfigSelector {
+    /// The parsed ChannelOptions::default_service_config.
+    default_service_config: Result<Option<Arc<ServiceConfig>>, String>,
+    disable_service_config_lookup: bool,
+    /// Whether client-side health checking is disabled, in which case the
+    /// health check config of resolved service configs is ignored.
+    disable_health_checks: bool,
+    /// The service config in use, shared with the ActiveChannel for RPCs.
+    service_config: Arc<Watcher<Arc<ServiceConfig>>>,
+}
+
+impl ServiceConfigSelector {
+    /// Returns the service config to use given the service config of a
+    /// resolver update, or an error if the channel has no valid config.
+    fn select(
+        &self,
+        resolved: &Result<Option<ServiceConfig>, String>,
+    ) -> Result<Arc<ServiceConfig>, String> {
+        let default = || match &self.default_service_config {
+            Ok(config) => Ok(config.clone().unwrap_or_default()),
+            Err(err) => Err(format!("invalid default service config: {err}")),
+        };
+        if self.disable_service_config_lookup {
+            return default();
+        }
+        match resolved {
+            Ok(Some(config)) => {
+                let mut config = config.clone();
+                if self.disable_health_checks {
+                    config.health_check_config = None;
+                }
+                Ok(Arc::new(config))
+            }
+            Ok(None) => default(),
+            // Keep using the previous config if there is one, otherwise fall
+            // back to the default config.
+            Err(err) => match (
+                self.service_config.cur().as_ref(),
+                &self.default_service_config,
+            ) {
+                (Some(config), _) | (None, Ok(Some(config))) => Ok(config.clone()),
+                _ => Err(format!("invalid service config: {err}")),
+            },
+        }
     }
 }
 
@@ -459,7 +749,6 @@ impl load_balancing::ChannelController for InternalChannelController {
 #[derive(Debug)]
 pub(super) struct GracefulSwitchBalancer {
     pub(super) policy: Mutex<Option<Box<dyn LbPolicy>>>,
-    policy_builder: Mutex<Option<Arc<dyn LbPolicyBuilder>>>,
     work_scheduler: WorkQueueTx,
     pending: Mutex<bool>,
     runtime: Arc<dyn Runtime>,
@@ -489,7 +778,6 @@ impl WorkScheduler for GracefulSwitchBalancer {
 impl GracefulSwitchBalancer {
     fn new(work_scheduler: WorkQueueTx, runtime: Arc<dyn Runtime>) -> Self {
         Self {
-            policy_builder: Mutex::default(),
             policy: Mutex::default(), // new(None::<Box<dyn LbPolicy>>),
             work_scheduler,
             pending: Mutex::default(),
@@ -500,42 +788,30 @@ impl GracefulSwitchBalancer {
     fn handle_resolver_update(
         self: &Arc<Self>,
         update: ResolverUpdate,
+        service_config: &ServiceConfig,
         controller: &mut InternalChannelController,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        if update.service_config.as_ref().is_ok_and(|sc| sc.is_some()) {
-            return Err("can't do service configs yet".into());
-        }
-        let policy_name = pick_first::POLICY_NAME;
         let mut p = self.policy.lock().unwrap();
         if p.is_none() {
-            let builder = GLOBAL_LB_REGISTRY.get_policy(policy_name).unwrap();
-            let newpol = builder.build(LbPolicyOptions {
-                work_scheduler: self.clone(),
-                runtime: self.runtime.clone(),
-            });
-            *self.policy_builder.lock().unwrap() = Some(builder);
-            *p = Some(newpol);
+            // The graceful switch policy switches between the LB policies
+            // chosen by the service config.
+            *p = Some(Box::new(GracefulSwitchPolicy::new(
+                self.runtime.clone(),
+                self.clone(),
+            )));
         }
 
-        // TODO: config should come from ServiceConfig.
-        let builder = self.policy_builder.lock().unwrap();
-        let config = match builder
-            .as_ref()
-            .unwrap()
-            .parse_config(&ParsedJsonLbConfig::from_value(
-                json!({"shuffleAddressList": true, "unknown_field": false}),
-            )) {
-            Ok(cfg) => cfg,
-            Err(e) => {
-                return Err(e);
-            }
+        // pick_first is used when the service config has no LB policy.
+        let config = match &service_config.load_balancing_config {
+            Some(config) => config.clone(),
+            None => GracefulSwitchPolicy::parse_config(&ParsedJsonLbConfig::from_value(
+                json!([{ (pick_first::POLICY_NAME): {} }]),
+            ))?,
         };
 
         p.as_mut()
             .unwrap()
-            .resolver_update(update, config.as_ref(), controller)
-
-        // TODO: close old LB policy gracefully vs. drop?
+            .resolver_update(update, Some(&config), controller)
     }
     pub(super) fn subchannel_update(
         &self,
@@ -560,6 +836,161 @@ pub(super) enum WorkQueueItem {
 
 pub struct TODO;
 
+/// Converts the status of an RPC dropped by the LB policy to INTERNAL if its
+/// code is not one the gRPC library may produce, per [gRFC A54].
+///
+/// [gRFC A54]:
+///     https://github.com/grpc/proposal/blob/master/A54-restrict-control-plane-status-codes.md
+fn restrict_control_plane_code(status: Status) -> Status {
+    match status.code() {
+        Code::Ok
+        | Code::InvalidArgument
+        | Code::NotFound
+        | Code::AlreadyExists
+        | Code::FailedPrecondition
+        | Code::Aborted
+        | Code::OutOfRange
+        | Code::DataLoss => Status::internal(format!(
+            "LB policy returned an illegal status code {:?}: {}",
+            status.code(),
+            status.message()
+        )),
+        _ => status,
+    }
+}
+
+fn deadline_exceeded() -> Status {
+    Status::deadline_exceeded("deadline exceeded")
+}
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// The stream of the messages sent by an RPC.
+type RequestStream = Pin<Box<dyn Stream<Item = Box<dyn Message>> + Send + Sync>>;
+
+/// The stream of the messages received by an RPC.
+type ResponseStream = Pin<Box<dyn Stream<Item = Result<Box<dyn Message>, Status>> + Send>>;
+
+/// Returns the size of a message, if it is serialized.
+fn message_size(message: &dyn Message) -> Option<usize> {
+    (message as &dyn Any)
+        .downcast_ref::<Bytes>()
+        .map(Bytes::len)
+}
+
+/// Completes with the status of an RPC that fails before its response stream
+/// ends: once its deadline expires, or once it sends a message larger than
+/// allowed.
+async fn failure(
+    expired: Option<Pin<Box<dyn Sleep>>>,
+    request_too_large: Option<oneshot::Receiver<Status>>,
+) -> Status {
+    let expired = async {
+        match expired {
+            Some(expired) => expired.await,
+            None => future::pending().await,
+        }
+    };
+    let request_too_large = async {
+        match request_too_large {
+            Some(rx) => match rx.await {
+                Ok(status) => status,
+                // The request stream ended without exceeding the limit.
+                Err(_) => future::pending().await,
+            },
+            None => future::pending().await,
+        }
+    };
+    tokio::select! {
+        () = expired => deadline_exceeded(),
+        status = request_too_large => status,
+    }
+}
+
+/// The request stream of an RPC with a maximum message size.  The stream
+/// stops, without ending, at the first message larger than the maximum, and
+/// the RPC then fails with RESOURCE_EXHAUSTED.
+struct RequestSizeLimit {
+    inner: RequestStream,
+    max_bytes: usize,
+    /// Receives the status of the RPC when a message is too large.  None once
+    /// a message was too large.
+    exceeded: Option<oneshot::Sender<Status>>,
+}
+
+impl Stream for RequestSizeLimit {
+    type Item = Box<dyn Message>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.exceeded.is_none() {
+            return Poll::Pending;
+        }
+        match self.inner.as_mut().poll_next(cx) {
+            Poll::Ready(Some(message)) => match message_size(message.as_ref()) {
+                Some(size) if size > self.max_bytes => {
+                    let status = Status::resource_exhausted(format!(
+                        "trying to send message larger than max ({size} vs. {})",
+                        self.max_bytes
+                    ));
+                    let _ = self.exceeded.take().unwrap().send(status);
+                    Poll::Pending
+                }
+                _ => Poll::Ready(Some(message)),
+            },
+            poll => poll,
+        }
+    }
+}
+
+/// The response stream of an RPC with a deadline or maximum message sizes.
+/// It fails with DEADLINE_EXCEEDED if the deadline expires before the stream
+/// ends, and with RESOURCE_EXHAUSTED if a message sent or received is too
+/// large.
+struct LimitedStream {
+    inner: ResponseStream,
+    /// Completes if the RPC fails before the response stream ends.  None once
+    /// the RPC has failed.
+    failed: Option<BoxFuture<Status>>,
+    max_message_bytes: Option<usize>,
+}
+
+impl LimitedStream {
+    fn fail(&mut self, status: Status) -> Poll<Option<Result<Box<dyn Message>, Status>>> {
+        // Dropping the response stream cancels the RPC.
+        self.inner = Box::pin(tokio_stream::empty());
+        self.failed = None;
+        Poll::Ready(Some(Err(status)))
+    }
+}
+
+impl Stream for LimitedStream {
+    type Item = Result<Box<dyn Message>, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.failed.is_none() {
+            return Poll::Ready(None);
+        }
+        if let Poll::Ready(item) = self.inner.as_mut().poll_next(cx) {
+            let size = match &item {
+                Some(Ok(message)) => message_size(message.as_ref()),
+                _ => None,
+            };
+            return match (size, self.max_message_bytes) {
+                (Some(size), Some(max_bytes)) if size > max_bytes => {
+                    self.fail(Status::resource_exhausted(format!(
+                        "received message larger than max ({size} vs. {max_bytes})"
+                    )))
+                }
+                _ => Poll::Ready(item),
+            };
+        }
+        match self.failed.as_mut().map(|failed| failed.as_mut().poll(cx)) {
+            Some(Poll::Ready(status)) => self.fail(status),
+            _ => Poll::Pending,
+        }
+    }
+}
+
 // Enables multiple receivers to view data output from a single producer.
 // Producer calls update.  Consumers call iter() and call next() until they find
 // a good value or encounter None.
@@ -611,3 +1042,111 @@ impl<T: Clone> WatcherIter<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rt::default_runtime;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn deadline_stream_fails_when_deadline_expires() {
+        let message: Box<dyn Message> = Box::new(bytes::Bytes::from_static(b"message"));
+        let inner = tokio_stream::once(Ok(message)).chain(tokio_stream::pending());
+        let expired = default_runtime().sleep(Duration::from_millis(10));
+        let mut stream = LimitedStream {
+            inner: Box::pin(inner),
+            failed: Some(Box::pin(failure(Some(expired), None))),
+            max_message_bytes: None,
+        };
+        assert!(stream.next().await.unwrap().is_ok());
+        let status = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::DeadlineExceeded);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn limited_stream_fails_when_message_too_large() {
+        let small: Box<dyn Message> = Box::new(Bytes::from_static(b"abcd"));
+        let large: Box<dyn Message> = Box::new(Bytes::from_static(b"abcde"));
+        let inner = tokio_stream::iter([Ok(small), Ok(large)]).chain(tokio_stream::pending());
+        let mut stream = LimitedStream {
+            inner: Box::pin(inner),
+            failed: Some(Box::pin(failure(None, None))),
+            max_message_bytes: Some(4),
+        };
+        assert!(stream.next().await.unwrap().is_ok());
+        let status = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn request_size_limit_fails_rpc_when_message_too_large() {
+        let small: Box<dyn Message> = Box::new(Bytes::from_static(b"abcd"));
+        let large: Box<dyn Message> = Box::new(Bytes::from_static(b"abcde"));
+        let (tx, rx) = oneshot::channel();
+        let mut request = RequestSizeLimit {
+            inner: Box::pin(tokio_stream::iter([small, large])),
+            max_bytes: 4,
+            exceeded: Some(tx),
+        };
+        let mut response = LimitedStream {
+            inner: Box::pin(tokio_stream::pending()),
+            failed: Some(Box::pin(failure(None, Some(rx)))),
+            max_message_bytes: None,
+        };
+        assert!(request.next().await.is_some());
+        // The request stream stops at the large message without ending.
+        tokio::select! {
+            biased;
+            _ = request.next() => unreachable!("request stream did not stop"),
+            status = response.next() => {
+                assert_eq!(status.unwrap().unwrap_err().code(), Code::ResourceExhausted);
+            }
+        }
+        assert!(response.next().await.is_none());
+    }
+
+    #[test]
+    fn health_check_config_ignored_when_health_checks_disabled() {
+        let config = ServiceConfig::parse(r#"{"healthCheckConfig": {"serviceName": "svc"}}"#);
+        let mut selector = ServiceConfigSelector {
+            default_service_config: Ok(None),
+            disable_service_config_lookup: false,
+            disable_health_checks: false,
+            service_config: Arc::new(Watcher::new()),
+        };
+        let selected = selector.select(&config.clone().map(Some)).unwrap();
+        assert!(selected.health_check_config.is_some());
+
+        selector.disable_health_checks = true;
+        let selected = selector.select(&config.map(Some)).unwrap();
+        assert!(selected.health_check_config.is_none());
+    }
+
+    #[test]
+    fn call_options_deadline() {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        assert_eq!(CallOptions::default().deadline, None);
+        assert_eq!(
+            CallOptions::default().deadline(deadline).deadline,
+            Some(deadline)
+        );
+        assert!(CallOptions::default()
+            .timeout(Duration::from_secs(10))
+            .deadline
+            .is_some_and(|d| d > deadline));
+    }
+
+    #[test]
+    fn restricts_control_plane_codes() {
+        let status = restrict_control_plane_code(Status::not_found("no route"));
+        assert_eq!(status.code(), Code::Internal);
+        assert!(status.message().contains("no route"));
+
+        let status = restrict_control_plane_code(Status::unavailable("overloaded"));
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(status.message(), "overloaded");
+    }
+}
@@ -291,14 +291,20 @@ impl PartialEq for PickResult {
                 _ => false,
             },
             PickResult::Queue => matches!(other, PickResult::Queue),
-            PickResult::Fail(status) => {
-                // TODO: implement me.
-                false
-            }
-            PickResult::Drop(status) => {
-                // TODO: implement me.
-                false
-            }
+            PickResult::Fail(status) => match other {
+                PickResult::Fail(other_status) => {
+                    status.code() == other_status.code()
+                        && status.message() == other_status.message()
+                }
+                _ => false,
+            },
+            PickResult::Drop(status) => match other {
+                PickResult::Drop(other_status) => {
+                    status.code() == other_status.code()
+                        && status.message() == other_status.message()
+                }
+                _ => false,
+            },
         }
     }
 }
@@ -25,8 +25,10 @@
 use std::fmt::Display;
 
 pub mod channel;
+mod retry;
 pub mod service_config;
 mod subchannel;
+pub use channel::CallOptions;
 pub use channel::Channel;
 pub use channel::ChannelOptions;
 
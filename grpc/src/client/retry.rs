@@ -0,0 +1,937 @@
+/*
+ *
+ * Copyright 2025 gRPC authors.
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to
+ * deal in the Software without restriction, including without limitation the
+ * rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+ * sell copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS
+ * IN THE SOFTWARE.
+ *
+ */
+
+//! Retries and hedging of RPCs, as described in [gRFC A6].
+//!
+//! The messages sent by an RPC are buffered so that they can be replayed on
+//! each attempt, until the RPC is committed to an attempt: when the attempt
+//! receives response headers, when no more attempts can be made, or when the
+//! buffer would exceed the channel's retry memory.  RPCs without a retry or
+//! hedging policy are committed from the start and buffer nothing.
+//!
+//! [gRFC A6]: https://github.com/grpc/proposal/blob/master/A6-client-retries.md
+
+use std::{
+    any::Any,
+    future::{poll_fn, Future},
+    mem,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use http::Extensions;
+use tokio_stream::Stream;
+use tonic::{
+    metadata::{MetadataMap, MetadataValue},
+    Request as TonicRequest, Response as TonicResponse, Status,
+};
+
+use crate::rt::{Runtime, Sleep};
+use crate::service::{Message, Request, Response};
+
+use super::service_config::{HedgingPolicy, RetryPolicy, RetryThrottlingPolicy};
+
+/// The metadata key telling servers how many attempts of an RPC preceded the
+/// current one.
+pub(crate) const PREVIOUS_RPC_ATTEMPTS: &str = "grpc-previous-rpc-attempts";
+
+/// The trailer with which servers tell clients how long to wait before
+/// retrying, in milliseconds, or not to retry if its value is not a
+/// non-negative integer.
+const RETRY_PUSHBACK_MS: &str = "grpc-retry-pushback-ms";
+
+/// Inserted into the extensions of a response made of a status only, without
+/// response headers: a Trailers-Only response from the server, or an error of
+/// the channel.  The response stream only yields the status.
+///
+/// This lets retries see how an attempt failed without reading its response
+/// stream, which belongs to the caller once the RPC is committed.
+#[derive(Clone, Debug)]
+pub(crate) struct TrailersOnly(pub(crate) Status);
+
+/// Returns a response failing an RPC attempt with `status`.
+pub(crate) fn trailers_only_response(status: Status) -> Response {
+    let mut response: Response =
+        TonicResponse::new(Box::pin(tokio_stream::once(Err(status.clone()))));
+    response.extensions_mut().insert(TrailersOnly(status));
+    response
+}
+
+/// Returns the status of a response made of a status only, or None if the
+/// response has headers.
+pub(crate) fn response_status(response: &Response) -> Option<Status> {
+    response
+        .extensions()
+        .get::<TrailersOnly>()
+        .map(|TrailersOnly(status)| status.clone())
+}
+
+/// Inserted into the extensions of the response of an RPC attempt that failed
+/// before being sent to the server, so that it can be retried transparently.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AttemptNotSent;
+
+/// Returns a response failing an RPC attempt that was never sent to the
+/// server.
+pub(crate) fn not_sent_response(status: Status) -> Response {
+    let mut response = trailers_only_response(status);
+    response.extensions_mut().insert(AttemptNotSent);
+    response
+}
+
+/// Inserted into the extensions of the response of an RPC attempt dropped by
+/// the LB policy, which must not be retried.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AttemptDropped;
+
+/// Returns a response failing an RPC attempt dropped by the LB policy.
+pub(crate) fn dropped_response(status: Status) -> Response {
+    let mut response = trailers_only_response(status);
+    response.extensions_mut().insert(AttemptDropped);
+    response
+}
+
+fn is_dropped(response: &Response) -> bool {
+    response.extensions().get::<AttemptDropped>().is_some()
+}
+
+/// Performs an RPC, calling `attempt` for each of its attempts.
+///
+/// Attempts that fail before being sent to the server are retried once
+/// transparently, without counting against the retry policy, as long as the
+/// messages of the RPC can be replayed.  Attempts dropped by the LB policy are
+/// not retried.  Other failed attempts are retried according to `policy`,
+/// unless `throttler` throttles retries.
+///
+/// An attempt fails when its response is made of a status only, and is
+/// committed when it receives response headers.  The response stream is left
+/// to the caller.
+pub(crate) async fn call_with_retries<F, Fut>(
+    request: Request,
+    policy: Option<RetryPolicy>,
+    throttler: Option<Arc<RetryThrottler>>,
+    memory: Arc<RetryMemory>,
+    runtime: Arc<dyn Runtime>,
+    mut attempt: F,
+) -> Response
+where
+    F: FnMut(Request) -> Fut,
+    Fut: Future<Output = Response>,
+{
+    let buffer = RetryBuffer::new(request, memory);
+    if policy.is_none() {
+        // Without retries, only unsent attempts are retried, which do not
+        // need any buffered message.
+        buffer.commit();
+    }
+    let mut attempts = 0;
+    let mut transparent_retried = false;
+    let mut backoff = policy
+        .as_ref()
+        .map_or(Duration::ZERO, |policy| policy.initial_backoff);
+    loop {
+        let response = attempt(buffer.attempt(attempts)).await;
+        let Some(status) = response_status(&response) else {
+            buffer.commit();
+            if let Some(throttler) = &throttler {
+                throttler.record_success();
+            }
+            return response;
+        };
+        if is_dropped(&response) {
+            return response;
+        }
+        let not_sent = response.extensions().get::<AttemptNotSent>().is_some();
+        if not_sent && !transparent_retried && buffer.is_replayable() {
+            transparent_retried = true;
+            continue;
+        }
+        if buffer.is_committed() {
+            return response;
+        }
+        attempts += 1;
+
+        let Some(policy) = &policy else {
+            return response;
+        };
+        if !policy.retryable_status_codes.contains(&status.code()) {
+            return response;
+        }
+        let pushback = retry_pushback(&status);
+        if let Some(throttler) = &throttler {
+            throttler.record_failure();
+        }
+        let throttled = throttler
+            .as_ref()
+            .is_some_and(|throttler| !throttler.allow_retry());
+        if attempts >= policy.max_attempts as usize || throttled || pushback == Some(None) {
+            return response;
+        }
+
+        let delay = match pushback {
+            // A pushback from the server resets the backoff.
+            Some(Some(delay)) => {
+                backoff = policy.initial_backoff;
+                delay
+            }
+            _ => {
+                let delay = backoff.mul_f64(rand::random_range(0.0..1.0));
+                backoff = backoff
+                    .mul_f64(policy.backoff_multiplier)
+                    .min(policy.max_backoff);
+                delay
+            }
+        };
+        runtime.sleep(delay).await;
+    }
+}
+
+/// Performs an RPC according to a hedging policy, calling `attempt` for each
+/// of its attempts.
+///
+/// A new attempt is started every hedging delay, or after the delay requested
+/// by a server pushback, until the RPC is committed to an attempt that
+/// succeeds or fails with a fatal status code.  The other attempts are then
+/// cancelled.  No attempt is started while `throttler` throttles retries.
+pub(crate) async fn call_with_hedging<F, Fut>(
+    request: Request,
+    policy: HedgingPolicy,
+    throttler: Option<Arc<RetryThrottler>>,
+    memory: Arc<RetryMemory>,
+    runtime: Arc<dyn Runtime>,
+    mut attempt: F,
+) -> Response
+where
+    F: FnMut(Request) -> Fut,
+    Fut: Future<Output = Response>,
+{
+    enum Event<T> {
+        AttemptDone(usize, T),
+        Hedge,
+    }
+
+    let buffer = RetryBuffer::new(request, memory);
+    let mut in_flight = vec![Box::pin(attempt_status(attempt(buffer.attempt(0))))];
+    let mut started = 1;
+    let mut hedge: Option<Pin<Box<dyn Sleep>>> =
+        (policy.max_attempts > 1).then(|| runtime.sleep(policy.hedging_delay));
+    let mut last_failure = None;
+    loop {
+        let event = poll_fn(|cx| {
+            for (i, attempt) in in_flight.iter_mut().enumerate() {
+                if let Poll::Ready(result) = attempt.as_mut().poll(cx) {
+                    return Poll::Ready(Event::AttemptDone(i, result));
+                }
+            }
+            match hedge.as_mut().map(|hedge| hedge.as_mut().poll(cx)) {
+                Some(Poll::Ready(())) => Poll::Ready(Event::Hedge),
+                _ => Poll::Pending,
+            }
+        })
+        .await;
+
+        match event {
+            Event::Hedge => {
+                hedge = None;
+                let throttled = throttler
+                    .as_ref()
+                    .is_some_and(|throttler| !throttler.allow_retry());
+                if buffer.is_committed() || throttled {
+                    match last_failure {
+                        Some(response) if in_flight.is_empty() => return response,
+                        _ => continue,
+                    }
+                }
+                in_flight.push(Box::pin(attempt_status(attempt(buffer.attempt(started)))));
+                started += 1;
+                if started < policy.max_attempts as usize {
+                    hedge = Some(runtime.sleep(policy.hedging_delay));
+                }
+            }
+            Event::AttemptDone(i, (response, status)) => {
+                drop(in_flight.remove(i));
+                let Some(status) = status else {
+                    buffer.commit();
+                    if let Some(throttler) = &throttler {
+                        throttler.record_success();
+                    }
+                    return response;
+                };
+                if buffer.is_committed()
+                    || is_dropped(&response)
+                    || !policy.non_fatal_status_codes.contains(&status.code())
+                {
+                    return response;
+                }
+                if let Some(throttler) = &throttler {
+                    throttler.record_failure();
+                }
+                match retry_pushback(&status) {
+                    // The server asked not to start more attempts.
+                    Some(None) => hedge = None,
+                    Some(Some(delay)) if hedge.is_some() => {
+                        hedge = Some(runtime.sleep(delay));
+                    }
+                    _ => {}
+                }
+                if in_flight.is_empty() && hedge.is_none() {
+                    return response;
+                }
+                last_failure = Some(response);
+            }
+        }
+    }
+}
+
+/// Waits for the response of an attempt, returning it with its status if it
+/// failed, see response_status.
+async fn attempt_status<Fut>(attempt: Fut) -> (Response, Option<Status>)
+where
+    Fut: Future<Output = Response>,
+{
+    let response = attempt.await;
+    let status = response_status(&response);
+    (response, status)
+}
+
+/// Returns the delay before retrying requested by the server with a status,
+/// `Some(None)` if the server asked not to retry, or None if the status has no
+/// pushback.
+pub(crate) fn retry_pushback(status: &Status) -> Option<Option<Duration>> {
+    let value = status.metadata().get(RETRY_PUSHBACK_MS)?;
+    Some(
+        value
+            .to_str()
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .map(Duration::from_millis),
+    )
+}
+
+/// The memory used by the retry buffers of the RPCs of a channel, limited by
+/// ChannelOptions::max_retry_memory.
+#[derive(Debug)]
+pub(crate) struct RetryMemory {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl RetryMemory {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Reserves `size` bytes, returning false if the limit would be exceeded.
+    fn reserve(&self, size: usize) -> bool {
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(size).filter(|used| *used <= self.limit)
+            })
+            .is_ok()
+    }
+
+    fn release(&self, size: usize) {
+        self.used.fetch_sub(size, Ordering::AcqRel);
+    }
+}
+
+/// The retry token bucket of a channel.  Tokens are counted in thousandths, as
+/// the token ratio has a precision of three decimal places.
+#[derive(Debug)]
+pub(crate) struct RetryThrottler {
+    policy: RetryThrottlingPolicy,
+    max_tokens: u32,
+    token_ratio: u32,
+    tokens: Mutex<u32>,
+}
+
+impl RetryThrottler {
+    pub(crate) fn new(policy: RetryThrottlingPolicy) -> Self {
+        let max_tokens = policy.max_tokens * 1000;
+        Self {
+            max_tokens,
+            token_ratio: (policy.token_ratio * 1000.0).round() as u32,
+            tokens: Mutex::new(max_tokens),
+            policy,
+        }
+    }
+
+    /// Returns the policy the throttler was created with.
+    pub(crate) fn policy(&self) -> &RetryThrottlingPolicy {
+        &self.policy
+    }
+
+    /// Records an attempt failing with a retryable status code.
+    pub(crate) fn record_failure(&self) {
+        let mut tokens = self.tokens.lock().unwrap();
+        *tokens = tokens.saturating_sub(1000);
+    }
+
+    /// Records a successful attempt.
+    pub(crate) fn record_success(&self) {
+        let mut tokens = self.tokens.lock().unwrap();
+        *tokens = (*tokens + self.token_ratio).min(self.max_tokens);
+    }
+
+    /// Returns whether failed attempts may be retried, which is the case while
+    /// more than half of the tokens are left.
+    pub(crate) fn allow_retry(&self) -> bool {
+        *self.tokens.lock().unwrap() > self.max_tokens / 2
+    }
+}
+
+/// The request of an RPC, from which the requests of its attempts are
+/// created.
+pub(crate) struct RetryBuffer {
+    metadata: MetadataMap,
+    extensions: Extensions,
+    messages: Arc<Mutex<BufferedMessages>>,
+}
+
+impl RetryBuffer {
+    pub(crate) fn new(request: Request, memory: Arc<RetryMemory>) -> Self {
+        let (metadata, extensions, source) = request.into_parts();
+        Self {
+            metadata,
+            extensions,
+            messages: Arc::new(Mutex::new(BufferedMessages {
+                source: Some(source),
+                messages: Vec::new(),
+                pulled: 0,
+                size: 0,
+                committed: false,
+                memory,
+                wakers: Arc::default(),
+            })),
+        }
+    }
+
+    /// Returns the request of an attempt, which replays the messages of the
+    /// RPC.
+    pub(crate) fn attempt(&self, previous_attempts: usize) -> Request {
+        let mut metadata = self.metadata.clone();
+        if previous_attempts > 0 {
+            metadata.insert(
+                PREVIOUS_RPC_ATTEMPTS,
+                MetadataValue::from(previous_attempts),
+            );
+        }
+        let messages = AttemptMessages {
+            messages: self.messages.clone(),
+            next: 0,
+        };
+        TonicRequest::from_parts(metadata, self.extensions.clone(), Box::pin(messages))
+    }
+
+    /// Commits the RPC to the current attempt: messages are no longer
+    /// buffered.
+    pub(crate) fn commit(&self) {
+        self.messages.lock().unwrap().committed = true;
+    }
+
+    /// Returns whether the RPC was committed, either by commit or because a
+    /// message could not be buffered.
+    pub(crate) fn is_committed(&self) -> bool {
+        self.messages.lock().unwrap().committed
+    }
+
+    /// Returns whether a new attempt can send all the messages of the RPC,
+    /// which is the case until a message is received after the commit.
+    pub(crate) fn is_replayable(&self) -> bool {
+        let messages = self.messages.lock().unwrap();
+        messages.pulled == messages.messages.len()
+    }
+}
+
+/// The stream of the messages sent by an RPC.
+type MessageStream = Pin<Box<dyn Stream<Item = Box<dyn Message>> + Send + Sync>>;
+
+/// The messages of an RPC, shared by its attempts.
+struct BufferedMessages {
+    /// The messages of the RPC, or None once they have all been received.
+    source: Option<MessageStream>,
+    /// The messages received from the source before the RPC was committed.
+    messages: Vec<Bytes>,
+    /// The number of messages received from the source.
+    pulled: usize,
+    /// The size of `messages`, reserved from `memory`.
+    size: usize,
+    committed: bool,
+    memory: Arc<RetryMemory>,
+    /// The attempts waiting for the next message of the source.
+    wakers: Arc<Wakers>,
+}
+
+impl BufferedMessages {
+    /// Buffers a message received from the source, committing the RPC if it
+    /// cannot be buffered.
+    fn buffer(&mut self, message: &dyn Message) {
+        self.pulled += 1;
+        if self.committed {
+            return;
+        }
+        // Only serialized messages can be replayed.
+        match (message as &dyn Any).downcast_ref::<Bytes>() {
+            Some(bytes) if self.memory.reserve(bytes.len()) => {
+                self.size += bytes.len();
+                self.messages.push(bytes.clone());
+            }
+            _ => self.committed = true,
+        }
+    }
+}
+
+impl Drop for BufferedMessages {
+    fn drop(&mut self) {
+        self.memory.release(self.size);
+    }
+}
+
+/// The messages sent by an attempt.
+struct AttemptMessages {
+    messages: Arc<Mutex<BufferedMessages>>,
+    /// The index of the next message to send.
+    next: usize,
+}
+
+impl Stream for AttemptMessages {
+    type Item = Box<dyn Message>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let messages = self.messages.clone();
+        let mut messages = messages.lock().unwrap();
+        if let Some(message) = messages.messages.get(self.next) {
+            self.next += 1;
+            return Poll::Ready(Some(Box::new(message.clone())));
+        }
+        if self.next < messages.pulled {
+            // The RPC was committed to another attempt, which received
+            // messages that were not buffered.  This attempt is about to be
+            // cancelled, and must not half-close its stream.
+            return Poll::Pending;
+        }
+        let wakers = messages.wakers.clone();
+        let Some(source) = messages.source.as_mut() else {
+            return Poll::Ready(None);
+        };
+        // All the attempts waiting for the next message are woken when it is
+        // received, whichever attempt polled the source last.
+        wakers.register(cx.waker());
+        let waker = Waker::from(wakers.clone());
+        let result = source.as_mut().poll_next(&mut Context::from_waker(&waker));
+        match result {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(None) => {
+                messages.source = None;
+                wakers.wake_by_ref();
+                Poll::Ready(None)
+            }
+            Poll::Ready(Some(message)) => {
+                messages.buffer(message.as_ref());
+                self.next += 1;
+                wakers.wake_by_ref();
+                Poll::Ready(Some(message))
+            }
+        }
+    }
+}
+
+/// A waker waking all the registered wakers.
+#[derive(Default)]
+struct Wakers(Mutex<Vec<Waker>>);
+
+impl Wakers {
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.0.lock().unwrap();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+}
+
+impl Wake for Wakers {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        for waker in mem::take(&mut *self.0.lock().unwrap()) {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rt::default_runtime;
+    use tokio_stream::StreamExt;
+    use tonic::Code;
+
+    fn request(messages: &[&'static str]) -> Request {
+        let messages: Vec<Box<dyn Message>> = messages
+            .iter()
+            .map(|m| Box::new(Bytes::from_static(m.as_bytes())) as Box<dyn Message>)
+            .collect();
+        TonicRequest::new(Box::pin(tokio_stream::iter(messages)))
+    }
+
+    async fn messages(request: Request) -> Vec<Bytes> {
+        request
+            .into_inner()
+            .map(|m| *(m as Box<dyn Any>).downcast::<Bytes>().unwrap())
+            .collect()
+            .await
+    }
+
+    fn ok_response() -> Response {
+        let message: Box<dyn Message> = Box::new(Bytes::from_static(b"response"));
+        TonicResponse::new(Box::pin(tokio_stream::once(Ok(message))))
+    }
+
+    fn error_response(status: Status) -> Response {
+        trailers_only_response(status)
+    }
+
+    fn retry_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+            backoff_multiplier: 2.0,
+            retryable_status_codes: vec![Code::Unavailable],
+        }
+    }
+
+    fn hedging_policy(max_attempts: u32, hedging_delay: Duration) -> HedgingPolicy {
+        HedgingPolicy {
+            max_attempts,
+            hedging_delay,
+            non_fatal_status_codes: vec![Code::Unavailable],
+        }
+    }
+
+    /// The previous attempts sent by an attempt, and its messages.
+    type SentAttempts = Arc<Mutex<Vec<(Option<String>, Vec<Bytes>)>>>;
+
+    /// Sends the request of an attempt, which then gets `response`, or never
+    /// completes for None.  Attempts failing before being sent send nothing.
+    async fn send(sent: SentAttempts, request: Request, response: Option<Response>) -> Response {
+        let previous_attempts = request
+            .metadata()
+            .get(PREVIOUS_RPC_ATTEMPTS)
+            .map(|value| value.to_str().unwrap().to_string());
+        let not_sent = response
+            .as_ref()
+            .is_some_and(|response| response.extensions().get::<AttemptNotSent>().is_some());
+        let messages = if not_sent {
+            Vec::new()
+        } else {
+            messages(request).await
+        };
+        sent.lock().unwrap().push((previous_attempts, messages));
+        match response {
+            Some(response) => response,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Performs a hedged RPC whose attempts get the given responses, returning
+    /// the code of the RPC and what its attempts sent.
+    async fn hedged_call(
+        policy: HedgingPolicy,
+        mut responses: Vec<Option<Response>>,
+    ) -> (Option<Code>, Vec<(Option<String>, Vec<Bytes>)>) {
+        responses.reverse();
+        let sent = SentAttempts::default();
+        let response = call_with_hedging(
+            request(&["a"]),
+            policy,
+            None,
+            Arc::new(RetryMemory::new(1024)),
+            default_runtime(),
+            |request| {
+                let response = responses.pop().expect("unexpected attempt");
+                send(sent.clone(), request, response)
+            },
+        )
+        .await;
+        let status = response_status(&response);
+        let sent = sent.lock().unwrap().clone();
+        (status.map(|status| status.code()), sent)
+    }
+
+    /// Performs an RPC whose attempts get the given responses, returning the
+    /// code of the RPC and what its attempts sent.
+    async fn call(
+        policy: Option<RetryPolicy>,
+        throttler: Option<Arc<RetryThrottler>>,
+        mut responses: Vec<Response>,
+    ) -> (Option<Code>, Vec<(Option<String>, Vec<Bytes>)>) {
+        responses.reverse();
+        let sent = SentAttempts::default();
+        let response = call_with_retries(
+            request(&["a", "b"]),
+            policy,
+            throttler,
+            Arc::new(RetryMemory::new(1024)),
+            default_runtime(),
+            |request| {
+                let response = responses.pop().expect("unexpected attempt");
+                send(sent.clone(), request, Some(response))
+            },
+        )
+        .await;
+        let status = response_status(&response);
+        let sent = sent.lock().unwrap().clone();
+        (status.map(|status| status.code()), sent)
+    }
+
+    #[tokio::test]
+    async fn retry_buffer_replays_messages() {
+        let buffer = RetryBuffer::new(request(&["a", "b"]), Arc::new(RetryMemory::new(1024)));
+        let first = buffer.attempt(0);
+        assert!(first.metadata().get(PREVIOUS_RPC_ATTEMPTS).is_none());
+        assert_eq!(messages(first).await, ["a", "b"]);
+
+        let second = buffer.attempt(1);
+        assert_eq!(second.metadata().get(PREVIOUS_RPC_ATTEMPTS).unwrap(), "1");
+        assert_eq!(messages(second).await, ["a", "b"]);
+        assert!(!buffer.is_committed());
+    }
+
+    #[tokio::test]
+    async fn retry_buffer_commits_when_out_of_memory() {
+        let memory = Arc::new(RetryMemory::new(4));
+        let buffer = RetryBuffer::new(request(&["abc", "def"]), memory.clone());
+        assert_eq!(messages(buffer.attempt(0)).await, ["abc", "def"]);
+        assert!(buffer.is_committed());
+        drop(buffer);
+        // The memory of the buffered message is released with the buffer.
+        assert!(memory.reserve(4));
+    }
+
+    #[tokio::test]
+    async fn retries_retryable_codes() {
+        let (code, attempts) = call(
+            Some(retry_policy(3)),
+            None,
+            vec![
+                error_response(Status::unavailable("1")),
+                error_response(Status::unavailable("2")),
+                ok_response(),
+            ],
+        )
+        .await;
+        assert_eq!(code, None);
+        assert_eq!(attempts.len(), 3);
+        for (i, (previous_attempts, messages)) in attempts.into_iter().enumerate() {
+            assert_eq!(previous_attempts, (i > 0).then(|| i.to_string()));
+            assert_eq!(messages, ["a", "b"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn stops_retrying() {
+        // Attempts are exhausted.
+        let (code, attempts) = call(
+            Some(retry_policy(2)),
+            None,
+            vec![
+                error_response(Status::unavailable("1")),
+                error_response(Status::unavailable("2")),
+            ],
+        )
+        .await;
+        assert_eq!((code, attempts.len()), (Some(Code::Unavailable), 2));
+
+        // The code is not retryable.
+        let (code, attempts) = call(
+            Some(retry_policy(2)),
+            None,
+            vec![error_response(Status::internal("1"))],
+        )
+        .await;
+        assert_eq!((code, attempts.len()), (Some(Code::Internal), 1));
+
+        // The server asks not to retry.
+        let mut status = Status::unavailable("1");
+        status
+            .metadata_mut()
+            .insert(RETRY_PUSHBACK_MS, MetadataValue::from_static("-1"));
+        let (code, attempts) =
+            call(Some(retry_policy(2)), None, vec![error_response(status)]).await;
+        assert_eq!((code, attempts.len()), (Some(Code::Unavailable), 1));
+
+        // There is no retry policy.
+        let (code, attempts) =
+            call(None, None, vec![error_response(Status::unavailable("1"))]).await;
+        assert_eq!((code, attempts.len()), (Some(Code::Unavailable), 1));
+    }
+
+    #[tokio::test]
+    async fn retries_transparently() {
+        let (code, attempts) = call(
+            None,
+            None,
+            vec![
+                not_sent_response(Status::unavailable("not sent")),
+                ok_response(),
+            ],
+        )
+        .await;
+        assert_eq!((code, attempts.len()), (None, 2));
+        assert_eq!(attempts[1].1, ["a", "b"]);
+
+        // Only one transparent retry is performed.
+        let (code, attempts) = call(
+            None,
+            None,
+            vec![
+                not_sent_response(Status::unavailable("not sent")),
+                not_sent_response(Status::unavailable("not sent")),
+            ],
+        )
+        .await;
+        assert_eq!((code, attempts.len()), (Some(Code::Unavailable), 2));
+    }
+
+    #[tokio::test]
+    async fn throttles_retries() {
+        let throttler = Arc::new(RetryThrottler::new(RetryThrottlingPolicy {
+            max_tokens: 3,
+            token_ratio: 1.0,
+        }));
+        // The first failure leaves 2 tokens, which still allows a retry, but
+        // the second leaves 1.
+        let (code, attempts) = call(
+            Some(retry_policy(5)),
+            Some(throttler.clone()),
+            vec![
+                error_response(Status::unavailable("1")),
+                error_response(Status::unavailable("2")),
+            ],
+        )
+        .await;
+        assert_eq!((code, attempts.len()), (Some(Code::Unavailable), 2));
+        assert!(!throttler.allow_retry());
+
+        throttler.record_success();
+        assert!(throttler.allow_retry());
+    }
+
+    #[tokio::test]
+    async fn hedges_until_success() {
+        let (code, attempts) = hedged_call(
+            hedging_policy(3, Duration::from_millis(1)),
+            vec![None, Some(ok_response())],
+        )
+        .await;
+        assert_eq!((code, attempts.len()), (None, 2));
+        for (i, (previous_attempts, messages)) in attempts.into_iter().enumerate() {
+            assert_eq!(previous_attempts, (i > 0).then(|| i.to_string()));
+            assert_eq!(messages, ["a"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn hedging_commits_to_fatal_failures() {
+        let (code, attempts) = hedged_call(
+            hedging_policy(3, Duration::from_secs(60)),
+            vec![Some(error_response(Status::internal("fatal")))],
+        )
+        .await;
+        assert_eq!((code, attempts.len()), (Some(Code::Internal), 1));
+    }
+
+    #[tokio::test]
+    async fn hedging_fails_after_max_attempts() {
+        let (code, attempts) = hedged_call(
+            hedging_policy(3, Duration::ZERO),
+            vec![
+                Some(error_response(Status::unavailable("1"))),
+                Some(error_response(Status::unavailable("2"))),
+                Some(error_response(Status::unavailable("3"))),
+            ],
+        )
+        .await;
+        assert_eq!((code, attempts.len()), (Some(Code::Unavailable), 3));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_dropped_attempts() {
+        let (code, attempts) = call(
+            Some(retry_policy(3)),
+            None,
+            vec![dropped_response(Status::unavailable("dropped"))],
+        )
+        .await;
+        assert_eq!((code, attempts.len()), (Some(Code::Unavailable), 1));
+    }
+
+    #[tokio::test]
+    async fn commits_on_response_headers() {
+        for policy in [None, Some(retry_policy(3))] {
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<&'static str>();
+            let messages = tokio_stream::wrappers::UnboundedReceiverStream::new(rx)
+                .map(|m| Box::new(Bytes::from_static(m.as_bytes())) as Box<dyn Message>);
+            let rpc = call_with_retries(
+                TonicRequest::new(Box::pin(messages)),
+                policy,
+                None,
+                Arc::new(RetryMemory::new(1024)),
+                default_runtime(),
+                // The server sends headers right away, and only replies once
+                // it gets a message.
+                |request: Request| async move {
+                    let replies = request.into_inner().map(Ok);
+                    let response: Response = TonicResponse::new(Box::pin(replies));
+                    response
+                },
+            );
+            let response = tokio::time::timeout(Duration::from_secs(5), rpc)
+                .await
+                .expect("the call waits for a response message");
+
+            // The client only sends a message once the call returned.
+            tx.send("a").unwrap();
+            let mut replies = response.into_inner();
+            let reply = tokio::time::timeout(Duration::from_secs(5), replies.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            assert_eq!(*(reply as Box<dyn Any>).downcast::<Bytes>().unwrap(), "a");
+        }
+    }
+}
@@ -21,12 +21,388 @@
  * IN THE SOFTWARE.
  *
  */
-use std::{any::Any, sync::Arc};
+
+use std::{any::Any, collections::HashMap, sync::Arc, time::Duration};
+
+use serde::Deserialize;
+use tonic::Code;
+
+use super::load_balancing::{graceful_switch::GracefulSwitchPolicy, ParsedJsonLbConfig};
+
+/// The maximum number of attempts of a retried or hedged RPC.  Larger values
+/// in the service config are treated as this value, per [gRFC A6].
+///
+/// [gRFC A6]: https://github.com/grpc/proposal/blob/master/A6-client-retries.md
+pub(crate) const MAX_ATTEMPTS_LIMIT: u32 = 5;
 
 /// An in-memory representation of a service config, usually provided to gRPC as
 /// a JSON object.
 #[derive(Debug, Default, Clone)]
-pub(crate) struct ServiceConfig;
+pub(crate) struct ServiceConfig {
+    /// The LB policy configuration, in the form accepted by the graceful switch
+    /// policy, or None if the channel should use its default LB policy.
+    pub load_balancing_config: Option<LbConfig>,
+    /// Throttles retries and hedged attempts when too many RPCs fail.
+    pub retry_throttling: Option<RetryThrottlingPolicy>,
+    /// Enables client-side health checking of the backends.
+    pub health_check_config: Option<HealthCheckConfig>,
+    /// Method configs keyed by (service, method) name.  An empty method
+    /// matches all the methods of the service, and an empty service matches
+    /// all the methods of the channel.
+    method_configs: HashMap<(String, String), Arc<MethodConfig>>,
+}
+
+/// The configuration of the RPCs of a set of methods.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct MethodConfig {
+    /// Whether RPCs wait for the channel to be ready instead of failing when
+    /// connections fail, unless overridden by the RPC.
+    pub wait_for_ready: Option<bool>,
+    /// The default deadline of RPCs, relative to their start.
+    pub timeout: Option<Duration>,
+    /// The maximum size of the messages sent by the client.
+    pub max_request_message_bytes: Option<u32>,
+    /// The maximum size of the messages received by the client.
+    pub max_response_message_bytes: Option<u32>,
+    /// How failed RPCs are retried.  Exclusive with `hedging_policy`.
+    pub retry_policy: Option<RetryPolicy>,
+    /// How RPCs are hedged.  Exclusive with `retry_policy`.
+    pub hedging_policy: Option<HedgingPolicy>,
+}
+
+/// The retry policy of a method config.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RetryPolicy {
+    /// The maximum number of attempts, including the original attempt.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub backoff_multiplier: f64,
+    /// The status codes with which failed attempts may be retried.
+    pub retryable_status_codes: Vec<Code>,
+}
+
+/// The hedging policy of a method config.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HedgingPolicy {
+    /// The maximum number of attempts, including the original attempt.
+    pub max_attempts: u32,
+    /// The delay between the start of two attempts.
+    pub hedging_delay: Duration,
+    /// The status codes with which a failed attempt does not fail the RPC.
+    pub non_fatal_status_codes: Vec<Code>,
+}
+
+/// The retry throttling policy of a channel.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RetryThrottlingPolicy {
+    /// The number of tokens of the channel's token bucket, in (0, 1000].
+    pub max_tokens: u32,
+    /// The tokens added to the bucket for each successful RPC, with a
+    /// precision of three decimal places.
+    pub token_ratio: f64,
+}
+
+/// The client-side health checking configuration of a channel.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HealthCheckConfig {
+    /// The service name sent in health check requests.
+    pub service_name: String,
+}
+
+impl ServiceConfig {
+    /// Parses and validates a JSON service config, as defined in
+    /// [service_config.proto].
+    ///
+    /// [service_config.proto]:
+    ///     https://github.com/grpc/grpc-proto/blob/master/grpc/service_config/service_config.proto
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let config: JsonServiceConfig = serde_json::from_str(json)
+            .map_err(|e| format!("failed to parse service config JSON: {e}"))?;
+
+        // The deprecated loadBalancingPolicy field only applies when
+        // loadBalancingConfig is not set.
+        let lb_config = match (config.load_balancing_config, config.load_balancing_policy) {
+            (Some(lb_config), _) => Some(lb_config),
+            (None, Some(policy)) => Some(serde_json::json!([{ policy.to_lowercase(): {} }])),
+            (None, None) => None,
+        };
+        let load_balancing_config = lb_config
+            .map(|lb_config| {
+                GracefulSwitchPolicy::parse_config(&ParsedJsonLbConfig::from_value(lb_config))
+                    .map_err(|e| format!("invalid loadBalancingConfig: {e}"))
+            })
+            .transpose()?;
+
+        let mut method_configs = HashMap::new();
+        for json_method_config in config.method_config {
+            let method_config = Arc::new(MethodConfig::from_json(&json_method_config)?);
+            for name in json_method_config.name {
+                let service = name.service.unwrap_or_default();
+                let method = name.method.unwrap_or_default();
+                if service.is_empty() && !method.is_empty() {
+                    return Err(format!("method name {method:?} has no service"));
+                }
+                if method_configs
+                    .insert((service.clone(), method.clone()), method_config.clone())
+                    .is_some()
+                {
+                    return Err(format!(
+                        "duplicate method config name: service {service:?}, method {method:?}"
+                    ));
+                }
+            }
+        }
+
+        let retry_throttling = config
+            .retry_throttling
+            .map(|throttling| {
+                let max_tokens = throttling
+                    .max_tokens
+                    .filter(|tokens| (1..=1000).contains(tokens))
+                    .ok_or("retryThrottling.maxTokens must be in (0, 1000]")?;
+                let token_ratio = throttling
+                    .token_ratio
+                    .filter(|ratio| *ratio > 0.0)
+                    .ok_or("retryThrottling.tokenRatio must be positive")?;
+                Ok::<_, String>(RetryThrottlingPolicy {
+                    max_tokens,
+                    token_ratio: (token_ratio * 1000.0).round() / 1000.0,
+                })
+            })
+            .transpose()?;
+
+        Ok(ServiceConfig {
+            load_balancing_config,
+            retry_throttling,
+            health_check_config: config
+                .health_check_config
+                .map(|health_check| HealthCheckConfig {
+                    service_name: health_check.service_name.unwrap_or_default(),
+                }),
+            method_configs,
+        })
+    }
+
+    /// Returns the method config of a method, given as "/service/method".
+    ///
+    /// The config naming the method takes precedence over the config naming
+    /// its service, which takes precedence over the default config.
+    pub fn method_config(&self, method: &str) -> Option<Arc<MethodConfig>> {
+        let (service, method) = method
+            .strip_prefix('/')
+            .and_then(|path| path.split_once('/'))
+            .unwrap_or_default();
+        [(service, method), (service, ""), ("", "")]
+            .into_iter()
+            .find_map(|(service, method)| {
+                self.method_configs
+                    .get(&(service.to_string(), method.to_string()))
+                    .cloned()
+            })
+    }
+}
+
+impl MethodConfig {
+    fn from_json(config: &JsonMethodConfig) -> Result<Self, String> {
+        if config.retry_policy.is_some() && config.hedging_policy.is_some() {
+            return Err("method config has both a retryPolicy and a hedgingPolicy".to_string());
+        }
+        Ok(MethodConfig {
+            wait_for_ready: config.wait_for_ready,
+            timeout: config
+                .timeout
+                .as_deref()
+                .map(|timeout| parse_duration("timeout", timeout))
+                .transpose()?,
+            max_request_message_bytes: config.max_request_message_bytes,
+            max_response_message_bytes: config.max_response_message_bytes,
+            retry_policy: config
+                .retry_policy
+                .as_ref()
+                .map(RetryPolicy::from_json)
+                .transpose()?,
+            hedging_policy: config
+                .hedging_policy
+                .as_ref()
+                .map(HedgingPolicy::from_json)
+                .transpose()?,
+        })
+    }
+}
+
+impl RetryPolicy {
+    fn from_json(policy: &JsonRetryPolicy) -> Result<Self, String> {
+        let positive_duration = |field: &str, value: &Option<String>| -> Result<Duration, String> {
+            let value = value
+                .as_deref()
+                .ok_or_else(|| format!("retryPolicy.{field} is required"))?;
+            let duration = parse_duration(field, value)?;
+            if duration.is_zero() {
+                return Err(format!("retryPolicy.{field} must be positive"));
+            }
+            Ok(duration)
+        };
+        let backoff_multiplier = policy
+            .backoff_multiplier
+            .filter(|multiplier| *multiplier > 0.0)
+            .ok_or("retryPolicy.backoffMultiplier must be positive")?;
+        let retryable_status_codes = parse_status_codes(&policy.retryable_status_codes)?;
+        if retryable_status_codes.is_empty() {
+            return Err("retryPolicy.retryableStatusCodes must not be empty".to_string());
+        }
+        Ok(RetryPolicy {
+            max_attempts: parse_max_attempts("retryPolicy", policy.max_attempts)?,
+            initial_backoff: positive_duration("initialBackoff", &policy.initial_backoff)?,
+            max_backoff: positive_duration("maxBackoff", &policy.max_backoff)?,
+            backoff_multiplier,
+            retryable_status_codes,
+        })
+    }
+}
+
+impl HedgingPolicy {
+    fn from_json(policy: &JsonHedgingPolicy) -> Result<Self, String> {
+        Ok(HedgingPolicy {
+            max_attempts: parse_max_attempts("hedgingPolicy", policy.max_attempts)?,
+            hedging_delay: policy
+                .hedging_delay
+                .as_deref()
+                .map(|delay| parse_duration("hedgingDelay", delay))
+                .transpose()?
+                .unwrap_or_default(),
+            non_fatal_status_codes: parse_status_codes(&policy.non_fatal_status_codes)?,
+        })
+    }
+}
+
+/// Validates the maxAttempts field of a retry or hedging policy, capping it to
+/// MAX_ATTEMPTS_LIMIT.
+fn parse_max_attempts(policy: &str, max_attempts: Option<u32>) -> Result<u32, String> {
+    match max_attempts {
+        Some(max_attempts) if max_attempts >= 2 => Ok(max_attempts.min(MAX_ATTEMPTS_LIMIT)),
+        _ => Err(format!("{policy}.maxAttempts must be at least 2")),
+    }
+}
+
+/// Parses a duration in the JSON format of google.protobuf.Duration, e.g.
+/// "1.5s".  Negative durations are not supported.
+fn parse_duration(field: &str, value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration for {field}: {value:?}");
+    let seconds = value.strip_suffix('s').ok_or_else(invalid)?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if seconds.is_empty() || !is_digits(seconds) || !is_digits(fraction) || fraction.len() > 9 {
+        return Err(invalid());
+    }
+    let seconds = seconds.parse().map_err(|_| invalid())?;
+    let nanos = format!("{fraction:0<9}").parse().map_err(|_| invalid())?;
+    Ok(Duration::new(seconds, nanos))
+}
+
+/// The status code names used in service configs, indexed by code.
+const STATUS_CODE_NAMES: [&str; 17] = [
+    "OK",
+    "CANCELLED",
+    "UNKNOWN",
+    "INVALID_ARGUMENT",
+    "DEADLINE_EXCEEDED",
+    "NOT_FOUND",
+    "ALREADY_EXISTS",
+    "PERMISSION_DENIED",
+    "RESOURCE_EXHAUSTED",
+    "FAILED_PRECONDITION",
+    "ABORTED",
+    "OUT_OF_RANGE",
+    "UNIMPLEMENTED",
+    "INTERNAL",
+    "UNAVAILABLE",
+    "DATA_LOSS",
+    "UNAUTHENTICATED",
+];
+
+/// Parses a list of status codes, given either by name or by number.
+fn parse_status_codes(codes: &[serde_json::Value]) -> Result<Vec<Code>, String> {
+    codes
+        .iter()
+        .map(|code| {
+            let index = match code {
+                serde_json::Value::String(name) => STATUS_CODE_NAMES.iter().position(|n| n == name),
+                serde_json::Value::Number(number) => number
+                    .as_u64()
+                    .and_then(|n| usize::try_from(n).ok())
+                    .filter(|n| *n < STATUS_CODE_NAMES.len()),
+                _ => None,
+            };
+            index
+                .map(|index| Code::from_i32(index as i32))
+                .ok_or_else(|| format!("invalid status code: {code}"))
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonServiceConfig {
+    load_balancing_policy: Option<String>,
+    load_balancing_config: Option<serde_json::Value>,
+    #[serde(default)]
+    method_config: Vec<JsonMethodConfig>,
+    retry_throttling: Option<JsonRetryThrottling>,
+    health_check_config: Option<JsonHealthCheckConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonMethodConfig {
+    #[serde(default)]
+    name: Vec<JsonMethodName>,
+    wait_for_ready: Option<bool>,
+    timeout: Option<String>,
+    max_request_message_bytes: Option<u32>,
+    max_response_message_bytes: Option<u32>,
+    retry_policy: Option<JsonRetryPolicy>,
+    hedging_policy: Option<JsonHedgingPolicy>,
+}
+
+#[derive(Deserialize)]
+struct JsonMethodName {
+    service: Option<String>,
+    method: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonRetryPolicy {
+    max_attempts: Option<u32>,
+    initial_backoff: Option<String>,
+    max_backoff: Option<String>,
+    backoff_multiplier: Option<f64>,
+    #[serde(default)]
+    retryable_status_codes: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonHedgingPolicy {
+    max_attempts: Option<u32>,
+    hedging_delay: Option<String>,
+    #[serde(default)]
+    non_fatal_status_codes: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonRetryThrottling {
+    max_tokens: Option<u32>,
+    token_ratio: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonHealthCheckConfig {
+    service_name: Option<String>,
+}
 
 /// A convenience wrapper for an LB policy's configuration object.
 #[derive(Debug, Clone)]
@@ -47,3 +423,148 @@ impl LbConfig {
         self.config.clone().downcast::<T>().ok()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::load_balancing::pick_first;
+
+    #[test]
+    fn parse_full_config() {
+        pick_first::reg();
+        let config = ServiceConfig::parse(
+            r#"{
+                "loadBalancingConfig": [{"unknown_policy": {}}, {"pick_first": {}}],
+                "methodConfig": [
+                    {
+                        "name": [{"service": "pkg.Service", "method": "Get"}],
+                        "waitForReady": true,
+                        "timeout": "1.5s",
+                        "maxRequestMessageBytes": 1024,
+                        "maxResponseMessageBytes": 2048,
+                        "retryPolicy": {
+                            "maxAttempts": 10,
+                            "initialBackoff": "0.1s",
+                            "maxBackoff": "1s",
+                            "backoffMultiplier": 2,
+                            "retryableStatusCodes": ["UNAVAILABLE", 4]
+                        }
+                    },
+                    {
+                        "name": [{"service": "pkg.Service"}],
+                        "hedgingPolicy": {
+                            "maxAttempts": 3,
+                            "hedgingDelay": "0.05s",
+                            "nonFatalStatusCodes": ["ABORTED"]
+                        }
+                    },
+                    {"name": [{}], "timeout": "30s"}
+                ],
+                "retryThrottling": {"maxTokens": 10, "tokenRatio": 0.12345},
+                "healthCheckConfig": {"serviceName": "health"}
+            }"#,
+        )
+        .unwrap();
+
+        assert!(config.load_balancing_config.is_some());
+        assert_eq!(
+            config.retry_throttling,
+            Some(RetryThrottlingPolicy {
+                max_tokens: 10,
+                token_ratio: 0.123,
+            })
+        );
+        assert_eq!(
+            config.health_check_config,
+            Some(HealthCheckConfig {
+                service_name: "health".to_string(),
+            })
+        );
+
+        let get = config.method_config("/pkg.Service/Get").unwrap();
+        assert_eq!(
+            *get,
+            MethodConfig {
+                wait_for_ready: Some(true),
+                timeout: Some(Duration::from_millis(1500)),
+                max_request_message_bytes: Some(1024),
+                max_response_message_bytes: Some(2048),
+                retry_policy: Some(RetryPolicy {
+                    max_attempts: MAX_ATTEMPTS_LIMIT,
+                    initial_backoff: Duration::from_millis(100),
+                    max_backoff: Duration::from_secs(1),
+                    backoff_multiplier: 2.0,
+                    retryable_status_codes: vec![Code::Unavailable, Code::DeadlineExceeded],
+                }),
+                hedging_policy: None,
+            }
+        );
+
+        let list = config.method_config("/pkg.Service/List").unwrap();
+        assert_eq!(
+            list.hedging_policy,
+            Some(HedgingPolicy {
+                max_attempts: 3,
+                hedging_delay: Duration::from_millis(50),
+                non_fatal_status_codes: vec![Code::Aborted],
+            })
+        );
+
+        let other = config.method_config("/other.Service/Get").unwrap();
+        assert_eq!(other.timeout, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn parse_empty_config() {
+        let config = ServiceConfig::parse("{}").unwrap();
+        assert!(config.load_balancing_config.is_none());
+        assert!(config.retry_throttling.is_none());
+        assert!(config.health_check_config.is_none());
+        assert!(config.method_config("/pkg.Service/Get").is_none());
+    }
+
+    #[test]
+    fn parse_invalid_configs() {
+        pick_first::reg();
+        let invalid = [
+            "not json",
+            r#"{"loadBalancingConfig": [{"unknown_policy": {}}]}"#,
+            r#"{"loadBalancingPolicy": "UNKNOWN_POLICY"}"#,
+            r#"{"methodConfig": [{"name": [{"method": "Get"}]}]}"#,
+            r#"{"methodConfig": [{"name": [{"service": "s"}]}, {"name": [{"service": "s"}]}]}"#,
+            r#"{"methodConfig": [{"name": [{}], "timeout": "1"}]}"#,
+            r#"{"methodConfig": [{"name": [{}], "timeout": "-1s"}]}"#,
+            r#"{"methodConfig": [{"name": [{}], "timeout": "1.0000000001s"}]}"#,
+            r#"{"methodConfig": [{"name": [{}], "retryPolicy": {
+                "maxAttempts": 1, "initialBackoff": "1s", "maxBackoff": "1s",
+                "backoffMultiplier": 1, "retryableStatusCodes": ["UNAVAILABLE"]}}]}"#,
+            r#"{"methodConfig": [{"name": [{}], "retryPolicy": {
+                "maxAttempts": 2, "initialBackoff": "0s", "maxBackoff": "1s",
+                "backoffMultiplier": 1, "retryableStatusCodes": ["UNAVAILABLE"]}}]}"#,
+            r#"{"methodConfig": [{"name": [{}], "retryPolicy": {
+                "maxAttempts": 2, "initialBackoff": "1s", "maxBackoff": "1s",
+                "backoffMultiplier": 1, "retryableStatusCodes": []}}]}"#,
+            r#"{"methodConfig": [{"name": [{}], "retryPolicy": {
+                "maxAttempts": 2, "initialBackoff": "1s", "maxBackoff": "1s",
+                "backoffMultiplier": 1, "retryableStatusCodes": ["NOT_A_CODE"]}}]}"#,
+            r#"{"methodConfig": [{"name": [{}],
+                "retryPolicy": {
+                    "maxAttempts": 2, "initialBackoff": "1s", "maxBackoff": "1s",
+                    "backoffMultiplier": 1, "retryableStatusCodes": ["UNAVAILABLE"]},
+                "hedgingPolicy": {"maxAttempts": 2}}]}"#,
+            r#"{"retryThrottling": {"maxTokens": 0, "tokenRatio": 1}}"#,
+            r#"{"retryThrottling": {"maxTokens": 1001, "tokenRatio": 1}}"#,
+            r#"{"retryThrottling": {"maxTokens": 10, "tokenRatio": 0}}"#,
+        ];
+        for json in invalid {
+            assert!(ServiceConfig::parse(json).is_err(), "parsed {json}");
+        }
+    }
+
+    #[test]
+    fn parse_deprecated_load_balancing_policy() {
+        pick_first::reg();
+        let config = ServiceConfig::parse(r#"{"loadBalancingPolicy": "PICK_FIRST"}"#).unwrap();
+        assert!(config.load_balancing_config.is_some());
+    }
+}
@@ -2,6 +2,7 @@ use super::{
     channel::{InternalChannelController, WorkQueueTx},
     load_balancing::{ExternalSubchannel, SubchannelState},
     name_resolution::Address,
+    retry::not_sent_response,
     transport::Transport,
     ConnectivityState,
 };
@@ -19,7 +20,7 @@ use std::{
     sync::{Arc, Mutex, RwLock, Weak},
 };
 use tokio::sync::{mpsc, oneshot};
-use tonic::async_trait;
+use tonic::{async_trait, Status};
 
 type SharedService = Arc<dyn Service>;
 
@@ -189,13 +190,13 @@ struct InnerSubchannel {
 impl Service for InternalSubchannel {
     async fn call(&self, method: String, request: Request) -> Response {
         let svc = self.inner.lock().unwrap().state.connected_transport();
-        if svc.is_none() {
-            // TODO(easwars): Change the signature of this method to return a
-            // Result<Response, Error>
-            panic!("todo: handle !ready");
-        }
+        let Some(svc) = svc else {
+            // The subchannel disconnected after being picked, so the RPC can be
+            // retried transparently.
+            return not_sent_response(Status::unavailable("subchannel is not connected"));
+        };
 
-        let svc = svc.unwrap().clone();
+        let svc = svc.clone();
         return svc.call(method, request).await;
     }
 }
@@ -1,3 +1,4 @@
+use crate::client::retry::{not_sent_response, trailers_only_response};
 use crate::client::transport::registry::GLOBAL_TRANSPORT_REGISTRY;
 use crate::client::transport::ConnectedTransport;
 use crate::client::transport::Transport;
@@ -22,7 +23,7 @@ use std::any::Any;
 use std::task::{Context, Poll};
 use std::time::Instant;
 use std::{error::Error, future::Future, net::SocketAddr, pin::Pin, str::FromStr, sync::Arc};
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, watch};
 use tokio_stream::Stream;
 use tokio_stream::StreamExt;
 use tonic::client::GrpcService;
@@ -66,7 +67,7 @@ impl Service for TonicTransport {
     async fn call(&self, method: String, request: GrpcRequest) -> GrpcResponse {
         let Ok(path) = PathAndQuery::from_maybe_shared(method) else {
             let err = Status::internal("Failed to parse path");
-            return create_error_response(err);
+            return trailers_only_response(err);
         };
         let mut grpc = self.grpc.clone();
         if let Err(e) = grpc.ready().await {
@@ -74,20 +75,18 @@ impl Service for TonicTransport {
             // may return an error and re-evaluate the status code returned
             // below.
             let err = Status::unknown(format!("Service was not ready: {e}"));
-            return create_error_response(err);
+            return not_sent_response(err);
         };
-        let request = convert_request(request);
+        // Dropping the response stream cancels the RPC: the request body then
+        // fails, so that hyper resets the HTTP/2 stream with RST_STREAM.
+        let (response_dropped, cancelled) = watch::channel(());
+        let mut request = convert_request(request);
+        request.extensions_mut().insert(Cancellation(cancelled));
         let response = grpc.streaming(request, path, BytesCodec {}).await;
-        convert_response(response)
+        convert_response(response, response_dropped)
     }
 }
 
-/// Helper function to create an error response stream.
-fn create_error_response(status: Status) -> GrpcResponse {
-    let stream = tokio_stream::once(Err(status));
-    TonicResponse::new(Box::pin(stream))
-}
-
 fn convert_request(req: GrpcRequest) -> TonicRequest<Pin<Box<dyn Stream<Item = Bytes> + Send>>> {
     let (metadata, extensions, stream) = req.into_parts();
 
@@ -104,16 +103,20 @@ fn convert_request(req: GrpcRequest) -> TonicRequest<Pin<Box<dyn Stream<Item = B
     TonicRequest::from_parts(metadata, extensions, bytes_stream as _)
 }
 
-fn convert_response(res: Result<TonicResponse<Streaming<Bytes>>, Status>) -> GrpcResponse {
+fn convert_response(
+    res: Result<TonicResponse<Streaming<Bytes>>, Status>,
+    response_dropped: watch::Sender<()>,
+) -> GrpcResponse {
     let response = match res {
         Ok(s) => s,
-        Err(e) => {
-            let stream = tokio_stream::once(Err(e));
-            return TonicResponse::new(Box::pin(stream));
-        }
+        // The server sent a Trailers-Only response, or the RPC failed
+        // before receiving headers.
+        Err(e) => return trailers_only_response(e),
     };
     let (metadata, stream, extensions) = response.into_parts();
-    let message_stream: BoxStream<Box<dyn Message>> = Box::pin(stream.map(|msg| {
+    let message_stream: BoxStream<Box<dyn Message>> = Box::pin(stream.map(move |msg| {
+        // Dropping the stream drops the sender, cancelling the request.
+        let _ = &response_dropped;
         msg.map(|b| {
             let msg: Box<dyn Message> = Box::new(b);
             msg
@@ -122,6 +125,62 @@ fn convert_response(res: Result<TonicResponse<Streaming<Bytes>>, Status>) -> Grp
     TonicResponse::from_parts(metadata, message_stream, extensions)
 }
 
+/// Inserted into the extensions of the request of an RPC.  The RPC is
+/// cancelled when the sender of the channel is dropped.
+#[derive(Clone)]
+struct Cancellation(watch::Receiver<()>);
+
+/// The body of the HTTP request of an RPC, failing once the RPC is cancelled so
+/// that hyper resets the HTTP/2 stream.
+struct CancellableBody {
+    inner: Body,
+    /// Completes when the RPC is cancelled.  None once it has completed.
+    cancelled: Option<BoxFuture<'static, ()>>,
+}
+
+impl CancellableBody {
+    fn new(inner: Body, Cancellation(mut cancelled): Cancellation) -> Self {
+        Self {
+            inner,
+            cancelled: Some(Box::pin(async move {
+                // Nothing is ever sent: this only completes when the sender is
+                // dropped.
+                let _ = cancelled.changed().await;
+            })),
+        }
+    }
+}
+
+impl http_body::Body for CancellableBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        let Some(cancelled) = self.cancelled.as_mut() else {
+            return Poll::Ready(None);
+        };
+        if cancelled.as_mut().poll(cx).is_ready() {
+            self.cancelled = None;
+            // hyper resets the stream with the reason of an h2 error found in
+            // the source of the body error.
+            let cancel = h2::Error::from(h2::Reason::CANCEL);
+            return Poll::Ready(Some(Err(Status::from_error(Box::new(cancel)))));
+        }
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.cancelled.is_some() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
 #[async_trait]
 impl Transport for TransportBuilder {
     async fn connect(
@@ -254,7 +313,10 @@ impl GrpcService<Body> for TonicService {
         tower::Service::poll_ready(&mut self.inner, cx)
     }
 
-    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+    fn call(&mut self, mut request: http::Request<Body>) -> Self::Future {
+        if let Some(cancellation) = request.extensions_mut().remove::<Cancellation>() {
+            request = request.map(|body| Body::new(CancellableBody::new(body, cancellation)));
+        }
         ResponseFuture {
             inner: tower::Service::call(&mut self.inner, request),
         }
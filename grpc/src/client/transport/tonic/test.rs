@@ -163,3 +163,120 @@ impl Echo for EchoService {
         ))
     }
 }
+
+// Tests that dropping the response stream of an RPC resets its HTTP/2 stream,
+// which cancels the RPC on the server.
+#[tokio::test]
+pub(crate) async fn tonic_transport_cancels_dropped_rpcs() {
+    super::reg();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let cancelled = Arc::new(Notify::new());
+    let server_cancelled = cancelled.clone();
+    tokio::spawn(async move {
+        let svc = EchoServer::new(CancellationObserver {
+            cancelled: server_cancelled,
+        });
+        let _ = Server::builder()
+            .add_service(svc)
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener))
+            .await;
+    });
+
+    let builder = GLOBAL_TRANSPORT_REGISTRY
+        .get_transport(TCP_IP_NETWORK_TYPE)
+        .unwrap();
+    let config = Arc::new(TransportOptions::default());
+    let connected_transport = builder
+        .connect(addr.to_string(), Arc::new(TokioRuntime {}), &config)
+        .await
+        .unwrap();
+    let conn = connected_transport.service;
+
+    let (tx, rx) = mpsc::channel::<Box<dyn Message>>(1);
+    let outbound: GrpcRequest = Request::new(Box::pin(ReceiverStream::new(rx)));
+    let mut inbound = conn
+        .call(
+            "/grpc.examples.echo.Echo/BidirectionalStreamingEcho".to_string(),
+            outbound,
+        )
+        .await
+        .into_inner();
+    let request = EchoRequest {
+        message: "hello".to_string(),
+    };
+    tx.send(Box::new(Bytes::from(request.encode_to_vec())))
+        .await
+        .unwrap();
+    timeout(DEFAULT_TEST_DURATION, inbound.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    // The request stream is still open, so only a reset ends the RPC.
+    drop(inbound);
+    timeout(DEFAULT_TEST_DURATION, cancelled.notified())
+        .await
+        .expect("the RPC was not cancelled on the server");
+    drop(tx);
+}
+
+/// An echo service notifying when a bidirectional RPC is cancelled by the
+/// client, which drops the response stream of the RPC on the server.
+#[derive(Debug)]
+struct CancellationObserver {
+    cancelled: Arc<Notify>,
+}
+
+#[async_trait]
+impl Echo for CancellationObserver {
+    async fn unary_echo(
+        &self,
+        _: tonic::Request<EchoRequest>,
+    ) -> std::result::Result<tonic::Response<EchoResponse>, tonic::Status> {
+        unimplemented!()
+    }
+
+    type ServerStreamingEchoStream = ReceiverStream<Result<EchoResponse, Status>>;
+
+    async fn server_streaming_echo(
+        &self,
+        _: tonic::Request<EchoRequest>,
+    ) -> std::result::Result<tonic::Response<Self::ServerStreamingEchoStream>, tonic::Status> {
+        unimplemented!()
+    }
+
+    async fn client_streaming_echo(
+        &self,
+        _: tonic::Request<tonic::Streaming<EchoRequest>>,
+    ) -> std::result::Result<tonic::Response<EchoResponse>, tonic::Status> {
+        unimplemented!()
+    }
+
+    type BidirectionalStreamingEchoStream = ReceiverStream<Result<EchoResponse, Status>>;
+
+    async fn bidirectional_streaming_echo(
+        &self,
+        request: tonic::Request<tonic::Streaming<EchoRequest>>,
+    ) -> std::result::Result<tonic::Response<Self::BidirectionalStreamingEchoStream>, tonic::Status>
+    {
+        let mut inbound = request.into_inner();
+        let (tx, rx) = mpsc::channel(1);
+        let replies = tx.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(req)) = inbound.next().await {
+                let reply = EchoResponse {
+                    message: req.message,
+                };
+                let _ = replies.send(Ok(reply)).await;
+            }
+        });
+        let cancelled = self.cancelled.clone();
+        tokio::spawn(async move {
+            tx.closed().await;
+            cancelled.notify_one();
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
@@ -1,27 +1,31 @@
+use crate::client::cluster::ClusterClientRegistryGrpc;
 use crate::client::endpoint::{EndpointAddress, EndpointChannel};
+use crate::client::fault::{FaultInjectionLayer, FaultInjectionService};
 use crate::client::lb::XdsLbService;
-use crate::client::route::XdsRoutingService;
+use crate::client::retry::{RetryLayer, RetryService, DEFAULT_RETRY_BUFFER_SIZE};
+use crate::client::route::{XdsRoutingLayer, XdsRoutingService};
 use crate::common::async_util::BoxFuture;
+use crate::xds::client_manager::XdsClientManager;
+use crate::xds::xds_manager::XdsManager;
 use crate::XdsUri;
 use http::Request;
 use std::fmt::Debug;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use tonic::{body::Body as TonicBody, client::GrpcService, transport::channel::Channel};
-use tower::{load::Load, util::BoxCloneService, BoxError, Service};
-
-#[cfg(test)]
-use {
-    crate::client::cluster::ClusterClientRegistryGrpc, crate::client::route::XdsRoutingLayer,
-    crate::xds::xds_manager::XdsManager, tower::ServiceBuilder,
-};
+use tower::{load::Load, util::BoxCloneService, BoxError, Service, ServiceBuilder};
+use xds_client::{ClientConfig, XdsClient};
 
 /// Configuration for building [`XdsChannel`] / [`XdsChannelGrpc`].
-/// Currently, only support specifying the xDS URI for the target service.
-/// In the future, more configurations such as xDS management server address will be added.
+///
+/// Specifies the xDS URI of the target service and, optionally, the configuration of the
+/// xDS client used to reach the xDS management server.
 #[derive(Clone, Debug, Default)]
 pub struct XdsChannelConfig {
     target_uri: Option<XdsUri>,
+    xds_client_config: Option<ClientConfig>,
+    xds_client: Option<XdsClient>,
+    retry_buffer_size: Option<usize>,
 }
 
 impl XdsChannelConfig {
@@ -31,6 +35,50 @@ impl XdsChannelConfig {
         self.target_uri = Some(target_uri);
         self
     }
+
+    /// Sets the configuration of the xDS client.
+    ///
+    /// If not set, the configuration is read from the bootstrap file named by the
+    /// `GRPC_XDS_BOOTSTRAP` environment variable, see [`ClientConfig::from_env`].
+    #[must_use]
+    pub fn with_xds_client_config(mut self, config: ClientConfig) -> Self {
+        self.xds_client_config = Some(config);
+        self
+    }
+
+    /// Sets the xDS client used to reach the xDS management server, so that it can be
+    /// shared with other channels or with a [`CsdsService`](xds_client::CsdsService)
+    /// reporting its resources.
+    ///
+    /// The configuration of the client takes precedence over the one set by
+    /// [`with_xds_client_config`](Self::with_xds_client_config).
+    #[must_use]
+    pub fn with_xds_client(mut self, client: XdsClient) -> Self {
+        self.xds_client = Some(client);
+        self
+    }
+
+    /// Sets the maximum number of bytes of a request body buffered so that the request can
+    /// be retried, according to the retry policy of its route. Requests with larger bodies
+    /// are not retried once the buffer is full.
+    ///
+    /// Defaults to 1 MiB.
+    #[must_use]
+    pub fn with_retry_buffer_size(mut self, bytes: usize) -> Self {
+        self.retry_buffer_size = Some(bytes);
+        self
+    }
+}
+
+/// Errors that can occur when building an [`XdsChannel`] / [`XdsChannelGrpc`].
+#[derive(Debug, thiserror::Error)]
+pub enum XdsChannelError {
+    /// No target URI was configured.
+    #[error("no xDS target URI configured")]
+    MissingTargetUri,
+    /// The xDS client could not be configured for the target.
+    #[error("invalid xDS client configuration: {0}")]
+    XdsClient(#[from] xds_client::Error),
 }
 
 /// `XdsChannel` is an xDS-capable [`tower::Service`] implementation.
@@ -50,9 +98,10 @@ where
     S::Response: Send + 'static,
 {
     config: Arc<XdsChannelConfig>,
-    // Currently the routing decision is directly executed by the XdsLbService.
-    // In the future, we will add more layers in between for retries, request mirroring, etc.
-    inner: XdsRoutingService<XdsLbService<Req, Endpoint, S>>,
+    // The routing decision, with the cluster picked for the request, is executed by the
+    // XdsLbService, after the faults it decides are injected and for each attempt of the retry
+    // policy. In the future, we will add more layers in between for request mirroring, etc.
+    inner: XdsRoutingService<FaultInjectionService<RetryService<XdsLbService<Req, Endpoint, S>>>>,
 }
 
 #[allow(clippy::missing_fields_in_debug)]
@@ -74,7 +123,7 @@ where
     Req: Send + 'static,
     S: Service<Req>,
     S::Response: Send + 'static,
-    XdsRoutingService<XdsLbService<Req, Endpoint, S>>: Clone,
+    XdsRoutingService<FaultInjectionService<RetryService<XdsLbService<Req, Endpoint, S>>>>: Clone,
 {
     fn clone(&self) -> Self {
         Self {
@@ -84,14 +133,15 @@ where
     }
 }
 
-impl<B, Endpoint, S> Service<http::Request<B>> for XdsChannel<Request<B>, Endpoint, S>
+impl<Endpoint, S> Service<http::Request<TonicBody>> for XdsChannel<Request<TonicBody>, Endpoint, S>
 where
-    B: Send + 'static,
-    Request<B>: Send + 'static,
-    Endpoint: std::hash::Hash + Eq + Clone + Send + 'static,
-    S: Service<Request<B>> + Load + Send + 'static,
-    S::Response: Send + 'static,
-    S::Error: Into<BoxError>,
+    Endpoint: std::hash::Hash + Eq + Clone + std::fmt::Display + Send + 'static,
+    S: Service<Request<TonicBody>, Response = http::Response<TonicBody>>
+        + Load
+        + Clone
+        + Send
+        + 'static,
+    S::Error: Into<BoxError> + Send,
     S::Future: Send,
     <S as tower::load::Load>::Metric: std::fmt::Debug,
 {
@@ -100,11 +150,11 @@ where
     type Future = BoxFuture<Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.inner.poll_ready(cx)
+        Service::poll_ready(&mut self.inner, cx)
     }
 
-    fn call(&mut self, request: Request<B>) -> Self::Future {
-        self.inner.call(request)
+    fn call(&mut self, request: Request<TonicBody>) -> Self::Future {
+        Service::call(&mut self.inner, request)
     }
 }
 
@@ -127,7 +177,6 @@ const _: fn() = || {
 /// Builder for creating an [`XdsChannel`] or [`XdsChannelGrpc`].
 #[derive(Clone, Debug)]
 pub struct XdsChannelBuilder {
-    #[allow(dead_code)]
     config: Arc<XdsChannelConfig>,
 }
 
@@ -152,35 +201,66 @@ impl XdsChannelBuilder {
         todo!("Implement XdsChannel building logic");
     }
 
-    pub(crate) fn build_tonic_grpc_channel(&self) -> XdsChannelTonicGrpc {
-        todo!("Implement XdsChannel building logic");
+    pub(crate) fn build_tonic_grpc_channel(&self) -> Result<XdsChannelTonicGrpc, XdsChannelError> {
+        let target_uri = self
+            .config
+            .target_uri
+            .as_ref()
+            .ok_or(XdsChannelError::MissingTargetUri)?;
+        let xds_manager = match (&self.config.xds_client, &self.config.xds_client_config) {
+            (Some(client), _) => XdsClientManager::start_with_client(client.clone(), target_uri)?,
+            (None, Some(config)) => XdsClientManager::start(config.clone(), target_uri)?,
+            (None, None) => XdsClientManager::start(ClientConfig::from_env()?, target_uri)?,
+        };
+        Ok(self.build_tonic_grpc_channel_from_xds_manager(Arc::new(xds_manager)))
     }
 
     /// Builds an `XdsChannelGrpc`, which is a type-erased gRPC channel.
-    #[must_use]
-    pub fn build_grpc_channel(&self) -> XdsChannelGrpc {
-        BoxCloneService::new(self.build_tonic_grpc_channel())
+    ///
+    /// The channel fetches the configuration of the target from the xDS management server
+    /// in the background. Requests wait until the routes of the target have been received.
+    ///
+    /// This must be called from within a Tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no target URI is configured, or if the xDS client configuration
+    /// cannot be loaded or does not support the target.
+    pub fn build_grpc_channel(&self) -> Result<XdsChannelGrpc, XdsChannelError> {
+        Ok(BoxCloneService::new(self.build_tonic_grpc_channel()?))
     }
 
-    /// Builds an `XdsChannelGrpc` from the given xDS manager dependencies.
-    /// This is primarily intended for testing purposes for now.
-    /// [`XdsChannelBuilder::build_grpc_channel`] should build [`XdsManager`](crate::xds::xds_manager::XdsManager)
-    /// as part of constructing `XdsChannelGrpc`.
-    #[cfg(test)]
-    pub(crate) fn build_grpc_channel_from_xds_manager(
+    /// Builds an `XdsChannelTonicGrpc` that routes and load-balances with the given xDS manager.
+    pub(crate) fn build_tonic_grpc_channel_from_xds_manager(
         &self,
         xds_manager: Arc<dyn XdsManager<EndpointAddress, EndpointChannel<Channel>>>,
-    ) -> XdsChannelGrpc {
+    ) -> XdsChannelTonicGrpc {
         let routing_layer = XdsRoutingLayer::new(xds_manager.clone());
         let cluster_registry = Arc::new(ClusterClientRegistryGrpc::new());
-        let lb_service = XdsLbService::new(cluster_registry, xds_manager.clone());
+        let lb_service = XdsLbService::new(cluster_registry, xds_manager);
         let service = ServiceBuilder::new()
             .layer(routing_layer)
+            .layer(FaultInjectionLayer::new())
+            .layer(RetryLayer::new(
+                self.config
+                    .retry_buffer_size
+                    .unwrap_or(DEFAULT_RETRY_BUFFER_SIZE),
+            ))
             .service(lb_service);
-        BoxCloneService::new(XdsChannelTonicGrpc {
+        XdsChannelTonicGrpc {
             config: self.config.clone(),
             inner: service,
-        })
+        }
+    }
+
+    /// Builds an `XdsChannelGrpc` from the given xDS manager dependencies.
+    /// This is primarily intended for testing purposes.
+    #[cfg(test)]
+    pub(crate) fn build_grpc_channel_from_xds_manager(
+        &self,
+        xds_manager: Arc<dyn XdsManager<EndpointAddress, EndpointChannel<Channel>>>,
+    ) -> XdsChannelGrpc {
+        BoxCloneService::new(self.build_tonic_grpc_channel_from_xds_manager(xds_manager))
     }
 }
 
@@ -191,14 +271,18 @@ mod tests {
     use crate::client::channel::XdsChannelGrpc;
     use crate::client::endpoint::EndpointAddress;
     use crate::client::endpoint::EndpointChannel;
+    use crate::client::endpoint::LocalizedEndpoint;
     use crate::common::async_util::BoxFuture;
     use crate::testutil::grpc::GreeterClient;
     use crate::testutil::grpc::HelloRequest;
     use crate::testutil::grpc::TestServer;
     use crate::xds::route::RouteDecision;
     use crate::xds::route::RouteInput;
+    use crate::xds::route::RoutingError;
     use crate::xds::xds_manager::BoxDiscover;
-    use crate::xds::xds_manager::{XdsClusterDiscovery, XdsRouter};
+    use crate::xds::xds_manager::{
+        ClusterConfig, ClusterDiscovery, XdsClusterDiscovery, XdsRouter,
+    };
     use std::sync::Arc;
     use tokio::sync::mpsc;
     use tonic::transport::Channel;
@@ -246,12 +330,8 @@ mod tests {
     }
 
     impl XdsRouter for MockXdsManager {
-        fn route(&self, _input: &RouteInput<'_>) -> BoxFuture<RouteDecision> {
-            Box::pin(async move {
-                RouteDecision {
-                    cluster: "test-cluster".to_string(),
-                }
-            })
+        fn route(&self, _input: &RouteInput<'_>) -> BoxFuture<Result<RouteDecision, RoutingError>> {
+            Box::pin(async move { Ok(RouteDecision::cluster("test-cluster")) })
         }
     }
 
@@ -259,19 +339,25 @@ mod tests {
         fn discover_cluster(
             &self,
             _cluster_name: &str,
-        ) -> BoxDiscover<EndpointAddress, EndpointChannel<Channel>> {
+        ) -> ClusterDiscovery<BoxDiscover<EndpointAddress, EndpointChannel<Channel>>> {
             let endpoints = self.endpoints.clone();
             let (tx, rx) = mpsc::channel(16);
 
             tokio::spawn(async move {
                 for (addr, channel) in endpoints {
-                    let endpoint_channel = EndpointChannel::new(channel);
-                    let change = Change::Insert(addr, endpoint_channel);
+                    let endpoint = LocalizedEndpoint {
+                        locality: Default::default(),
+                        service: EndpointChannel::new(channel),
+                    };
+                    let change = Change::Insert(addr, endpoint);
                     tx.send(Ok(change)).await.expect("Failed to send SD change");
                 }
             });
 
-            Box::pin(tokio_stream::wrappers::ReceiverStream::new(rx))
+            ClusterDiscovery {
+                endpoints: Box::pin(tokio_stream::wrappers::ReceiverStream::new(rx)),
+                config: tokio::sync::watch::channel(ClusterConfig::default()).1,
+            }
         }
     }
 
@@ -384,4 +470,137 @@ mod tests {
             let _ = server.handle.await;
         }
     }
+
+    #[tokio::test]
+    /// Tests that an `xds:///` target is resolved through a local xDS management server.
+    async fn test_xds_channel_grpc_with_xds_server() {
+        use crate::testutil::xds::{
+            cluster, cluster_load_assignment, listener, route_configuration, spawn_xds_server,
+        };
+        use crate::XdsUri;
+        use xds_client::{ClientConfig, ServerConfig};
+
+        let num_requests = 100;
+        let num_servers = 3;
+        let (_, servers) = setup_grpc_servers(num_servers).await;
+        let addrs: Vec<_> = servers.iter().map(|s| s.addr).collect();
+        let xds_server = spawn_xds_server(vec![
+            listener("myservice", "route-1"),
+            route_configuration("route-1", "cluster-1"),
+            cluster("cluster-1"),
+            cluster_load_assignment("cluster-1", &addrs),
+        ])
+        .await
+        .expect("Failed to spawn xDS server");
+
+        let client_config = ClientConfig::default()
+            .with_servers(vec![ServerConfig::new(xds_server.addr.to_string())]);
+        let config = XdsChannelConfig::default()
+            .with_target_uri(XdsUri::parse("xds:///myservice").unwrap())
+            .with_xds_client_config(client_config);
+        let xds_channel = XdsChannelBuilder::with_config(config)
+            .build_grpc_channel()
+            .expect("Failed to build xDS channel");
+
+        let (successful_requests, error_types, server_counts) =
+            send_grpc_requests(GreeterClient::new(xds_channel), num_requests).await;
+
+        assert_eq!(
+            successful_requests, num_requests,
+            "Expected 100% success rate. Errors: {error_types:?}",
+        );
+        assert_eq!(
+            server_counts.len(),
+            num_servers,
+            "Expected all servers to receive requests. Server counts: {server_counts:?}",
+        );
+
+        let _ = xds_server.shutdown.send(());
+        let _ = xds_server.handle.await;
+        for server in servers {
+            let _ = server.shutdown.send(());
+            let _ = server.handle.await;
+        }
+    }
+
+    #[tokio::test]
+    /// Tests that the load of a cluster with an LRS server is reported to the xDS server.
+    async fn test_xds_channel_reports_load() {
+        use crate::testutil::xds::{
+            cluster_load_assignment, cluster_with_load_reporting, listener, route_configuration,
+            spawn_xds_server,
+        };
+        use crate::XdsUri;
+        use std::time::Duration;
+        use xds_client::{ClientConfig, ServerConfig};
+
+        let num_requests = 20;
+        let (_, servers) = setup_grpc_servers(2).await;
+        let addrs: Vec<_> = servers.iter().map(|s| s.addr).collect();
+        let mut xds_server = spawn_xds_server(vec![
+            listener("myservice", "route-1"),
+            route_configuration("route-1", "cluster-1"),
+            cluster_with_load_reporting("cluster-1"),
+            cluster_load_assignment("cluster-1", &addrs),
+        ])
+        .await
+        .expect("Failed to spawn xDS server");
+
+        let client_config = ClientConfig::default()
+            .with_servers(vec![ServerConfig::new(xds_server.addr.to_string())]);
+        let config = XdsChannelConfig::default()
+            .with_target_uri(XdsUri::parse("xds:///myservice").unwrap())
+            .with_xds_client_config(client_config);
+        let xds_channel = XdsChannelBuilder::with_config(config)
+            .build_grpc_channel()
+            .expect("Failed to build xDS channel");
+
+        let (successful_requests, error_types, _) =
+            send_grpc_requests(GreeterClient::new(xds_channel.clone()), num_requests).await;
+        assert_eq!(
+            successful_requests, num_requests,
+            "Expected 100% success rate. Errors: {error_types:?}",
+        );
+
+        // The requests may be split across several reports.
+        let mut reported = 0;
+        while reported < num_requests as u64 {
+            let report =
+                tokio::time::timeout(Duration::from_secs(10), xds_server.load_reports.recv())
+                    .await
+                    .expect("timed out waiting for load report")
+                    .expect("LRS server stopped");
+            for cluster_stats in report.cluster_stats {
+                assert_eq!(cluster_stats.cluster_name, "cluster-1");
+                for locality_stats in cluster_stats.upstream_locality_stats {
+                    assert_eq!(locality_stats.total_error_requests, 0);
+                    assert_eq!(
+                        locality_stats.total_issued_requests,
+                        locality_stats.total_successful_requests
+                    );
+                    reported += locality_stats.total_successful_requests;
+                }
+            }
+        }
+        assert_eq!(reported, num_requests as u64);
+
+        // Close the xDS streams before shutting the server down.
+        drop(xds_channel);
+        let _ = xds_server.shutdown.send(());
+        let _ = xds_server.handle.await;
+        for server in servers {
+            let _ = server.shutdown.send(());
+            let _ = server.handle.await;
+        }
+    }
+
+    #[test]
+    fn test_build_grpc_channel_without_target() {
+        let result =
+            XdsChannelBuilder::with_config(XdsChannelConfig::default()).build_grpc_channel();
+        assert!(matches!(
+            result,
+            Err(super::XdsChannelError::MissingTargetUri)
+        ));
+    }
 }
@@ -0,0 +1,230 @@
+//! Circuit breaking of the requests to a cluster, following
+//! [gRFC A32](https://github.com/grpc/proposal/blob/master/A32-xds-circuit-breaking.md).
+//!
+//! The requests in flight to a cluster are counted across every channel of the process
+//! that gets the cluster from the same xDS server, and requests over the cluster's
+//! `max_requests` fail without being sent.
+
+use crate::client::endpoint::InFlightTracker;
+use crate::xds::xds_manager::ClusterConfig;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use tokio::sync::watch;
+
+/// Identifies the requests counted together: the URI of the xDS server, the cluster name
+/// and the EDS service name of the cluster.
+type CounterKey = (String, String, Option<String>);
+
+/// The counters of requests in flight, keyed by xDS server and cluster.
+type InFlightCounters = Mutex<HashMap<CounterKey, Weak<AtomicU64>>>;
+
+/// The counters shared by the circuit breakers of every channel.
+static IN_FLIGHT: OnceLock<InFlightCounters> = OnceLock::new();
+
+/// The count of the requests in flight to a cluster. Counters are equal when they are
+/// shared.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RequestsCounter(Arc<AtomicU64>);
+
+impl RequestsCounter {
+    /// Returns the counter of the requests to a cluster received from an xDS server, shared
+    /// by every channel of the process.
+    pub(crate) fn shared(server_uri: &str, cluster: &str, eds_service_name: Option<&str>) -> Self {
+        let mut counters = IN_FLIGHT
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        counters.retain(|_, in_flight| in_flight.strong_count() > 0);
+        let key = (
+            server_uri.to_string(),
+            cluster.to_string(),
+            eds_service_name.map(str::to_string),
+        );
+        let in_flight = match counters.get(&key).and_then(Weak::upgrade) {
+            Some(in_flight) => in_flight,
+            None => {
+                let in_flight = Arc::new(AtomicU64::new(0));
+                counters.insert(key, Arc::downgrade(&in_flight));
+                in_flight
+            }
+        };
+        Self(in_flight)
+    }
+}
+
+impl PartialEq for RequestsCounter {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Limits the concurrent requests to a cluster.
+#[derive(Debug, Clone)]
+pub(crate) struct CircuitBreaker {
+    config: watch::Receiver<ClusterConfig>,
+}
+
+impl CircuitBreaker {
+    /// Creates a circuit breaker for a cluster, counting its requests in flight with the
+    /// counter of its configuration.
+    pub(crate) fn new(config: watch::Receiver<ClusterConfig>) -> Self {
+        Self { config }
+    }
+
+    /// Admits a request, returning a tracker to hold until the request completes, or an
+    /// `UNAVAILABLE` status if the cluster has `max_requests` requests in flight.
+    pub(crate) fn try_acquire(&self) -> Result<InFlightTracker, tonic::Status> {
+        let config = self.config.borrow();
+        let max_requests = config.max_requests;
+        InFlightTracker::try_new(config.requests.0.clone(), u64::from(max_requests)).ok_or_else(
+            || {
+                tonic::Status::unavailable(format!(
+                    "cluster reached its limit of {max_requests} concurrent requests"
+                ))
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let requests = |server, cluster, eds_service_name| {
+            watch::channel(ClusterConfig {
+                max_requests: 2,
+                requests: RequestsCounter::shared(server, cluster, eds_service_name),
+                ..Default::default()
+            })
+        };
+        let (config_tx, config) = requests("server", "test-circuit-breaker", None);
+        let breaker = CircuitBreaker::new(config);
+        // Another channel to the same cluster shares the limit.
+        let (_other_tx, other) = requests("server", "test-circuit-breaker", None);
+        let other = CircuitBreaker::new(other);
+        // Clusters of other xDS servers or EDS service names have their own limits.
+        let unrelated = [
+            requests("server", "test-circuit-breaker-other", None),
+            requests("other-server", "test-circuit-breaker", None),
+            requests("server", "test-circuit-breaker", Some("service")),
+        ]
+        .map(|(_, config)| CircuitBreaker::new(config));
+
+        let first = breaker.try_acquire().unwrap();
+        let _second = other.try_acquire().unwrap();
+        let status = breaker.try_acquire().unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        for unrelated in &unrelated {
+            assert!(unrelated.try_acquire().is_ok());
+        }
+
+        // Completed requests make room for new ones.
+        drop(first);
+        let _third = other.try_acquire().unwrap();
+        assert!(breaker.try_acquire().is_err());
+
+        // The limit follows the cluster configuration.
+        config_tx.send_modify(|config| config.max_requests = 3);
+        assert!(breaker.try_acquire().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_counter_shared_by_cluster_clients() {
+        use crate::client::cluster::ClusterClient;
+        use crate::xds::xds_manager::ClusterDiscovery;
+        use std::convert::Infallible;
+        use tokio_stream::wrappers::UnboundedReceiverStream;
+        use tower::discover::Change;
+
+        use crate::client::endpoint::{EndpointChannel, LocalizedEndpoint};
+        use tower::util::BoxCloneService;
+
+        type TestChange = Change<
+            &'static str,
+            LocalizedEndpoint<EndpointChannel<BoxCloneService<(), &'static str, Infallible>>>,
+        >;
+
+        let client = || {
+            let (_tx, rx) =
+                tokio::sync::mpsc::unbounded_channel::<Result<TestChange, Infallible>>();
+            let config = ClusterConfig {
+                max_requests: 1,
+                requests: RequestsCounter::shared("server", "test-shared-counter", None),
+                ..Default::default()
+            };
+            ClusterClient::<(), &'static str>::new(
+                "test-shared-counter".to_string(),
+                ClusterDiscovery {
+                    endpoints: UnboundedReceiverStream::new(rx),
+                    config: watch::channel(config).1,
+                },
+            )
+        };
+        let (first, second) = (client(), client());
+
+        let in_flight = first.admit().unwrap();
+        assert!(second.admit().is_err());
+        drop(in_flight);
+        let _in_flight = second.admit().unwrap();
+        assert!(first.admit().is_err());
+    }
+
+    #[test]
+    fn test_default_max_requests() {
+        use xds_client::Resource;
+
+        // The cluster has no circuit breakers.
+        let cluster = crate::testutil::xds::cluster("test-default-max-requests");
+        let cluster = xds_client::Cluster::decode(cluster.value.into()).unwrap();
+        let config = ClusterConfig::from(&cluster);
+        assert_eq!(config.max_requests, 1024);
+
+        let breaker = CircuitBreaker::new(watch::channel(config).1);
+        let _in_flight: Vec<_> = (0..1024).map(|_| breaker.try_acquire().unwrap()).collect();
+        let status = breaker.try_acquire().unwrap_err();
+        assert!(status.message().contains("1024"), "{status:?}");
+    }
+
+    #[tokio::test]
+    async fn test_request_released_when_response_dropped() {
+        use crate::client::outlier_detection::CallOutcome;
+        use bytes::Bytes;
+        use http_body_util::BodyExt;
+        use std::convert::Infallible;
+        use tonic::body::Body as TonicBody;
+
+        let breaker = CircuitBreaker::new(
+            watch::channel(ClusterConfig {
+                max_requests: 1,
+                ..Default::default()
+            })
+            .1,
+        );
+        // Holds a request until its response ends, like the load balancing service.
+        let admitted = || {
+            let in_flight = breaker.try_acquire().unwrap();
+            let frames = tokio_stream::iter([
+                Ok::<_, Infallible>(http_body::Frame::data(Bytes::from_static(b"message"))),
+                Ok(http_body::Frame::data(Bytes::from_static(b"message"))),
+            ]);
+            http::Response::new(TonicBody::new(http_body_util::StreamBody::new(frames)))
+                .on_end(move |_| drop(in_flight))
+        };
+
+        // Dropped before its body is read.
+        let response = admitted();
+        assert!(breaker.try_acquire().is_err());
+        drop(response);
+
+        // Cancelled in the middle of its body.
+        let mut body = admitted().into_body();
+        body.frame().await.unwrap().unwrap();
+        assert!(breaker.try_acquire().is_err());
+        drop(body);
+
+        assert!(breaker.try_acquire().is_ok());
+    }
+}
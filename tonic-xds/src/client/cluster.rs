@@ -1,43 +1,152 @@
+use crate::client::circuit_breaker::CircuitBreaker;
+use crate::client::consistent_hash::HashTable;
+use crate::client::endpoint::{InFlightTracker, LocalizedEndpoint};
+use crate::client::outlier_detection::{CallOutcome, OutlierDetection};
 use crate::common::async_util::BoxFuture;
+use crate::xds::route::RouteDecision;
+use crate::xds::xds_manager::{ClusterConfig, ClusterDiscovery};
 use dashmap::DashMap;
 use http::{Request, Response};
-use std::fmt::Debug;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::Infallible;
+use std::fmt::{Debug, Display};
 use std::future::Future;
 use std::hash::Hash;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::time::Sleep;
+use tokio_stream::wrappers::UnboundedReceiverStream;
 use tonic::body::Body as TonicBody;
 use tower::{
-    balance::p2c::Balance, buffer::Buffer, discover::Discover, load::Load, BoxError, Service,
+    balance::p2c::Balance,
+    buffer::Buffer,
+    discover::{Change, Discover},
+    load::Load,
+    BoxError, Service,
 };
+use xds_client::resource::prost::cluster::LbPolicy;
+use xds_client::Locality;
 
 type RespFut<Resp> = BoxFuture<Result<Resp, BoxError>>;
 
 const DEFAULT_BUFFER_CAPACITY: usize = 1024;
 
+/// The time a priority has to become ready before the next priority is used, as for the
+/// failover timer of the priority LB policy of
+/// [gRFC A56](https://github.com/grpc/proposal/blob/master/A56-priority-lb-policy.md).
+const PRIORITY_FAILOVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+type LocalityDiscover<K, S> = UnboundedReceiverStream<Result<Change<K, S>, Infallible>>;
+
+/// The key of a locality of a cluster: its priority and locality.
+type LocalityKey = (u32, Locality);
+
+type Localities<K, S, Req> = HashMap<Locality, LocalityBalancer<K, S, Req>>;
+
+/// A request that may carry a hash for consistent hashing load balancing.
+pub(crate) trait RequestHash {
+    /// Returns the hash of the request, if it has one.
+    fn request_hash(&self) -> Option<u64>;
+}
+
+impl<B> RequestHash for Request<B> {
+    fn request_hash(&self) -> Option<u64> {
+        self.extensions()
+            .get::<RouteDecision>()
+            .and_then(RouteDecision::request_hash)
+    }
+}
+
+/// Load-balances requests across the endpoints of one locality with P2C.
+struct LocalityBalancer<K, S, Req>
+where
+    K: Hash + Eq,
+{
+    /// The load balancing weight of the locality.
+    weight: u32,
+    /// The number of endpoints in the locality.
+    len: usize,
+    changes: mpsc::UnboundedSender<Result<Change<K, S>, Infallible>>,
+    balancer: Balance<LocalityDiscover<K, S>, Req>,
+}
+
+impl<K, S, Req> LocalityBalancer<K, S, Req>
+where
+    K: Hash + Eq,
+    S: Service<Req>,
+    S::Error: Into<BoxError>,
+{
+    fn new() -> Self {
+        let (changes, rx) = mpsc::unbounded_channel();
+        Self {
+            weight: 1,
+            len: 0,
+            changes,
+            balancer: Balance::new(UnboundedReceiverStream::new(rx)),
+        }
+    }
+}
+
 /// `ClusterBalancer` is responsible for managing load balancing requests across multiple channels.
-/// Currently, `ClusterBalancer` leverges `tower::balance::p2c` for doing P2C load balancing. In the future, we will
-/// support more load balancing strategies as needed.
-pub(crate) struct ClusterBalancer<D, Req>
+///
+/// Endpoints are grouped by priority and locality. Requests are sent to the highest priority
+/// with ready endpoints. Endpoints ejected by outlier detection are removed from their
+/// locality, and endpoints that are not ready, such as those that cannot connect, are not
+/// used. A priority without ready endpoints has [`PRIORITY_FAILOVER_TIMEOUT`] to get one,
+/// during which requests wait, before the next priority is used. Requests go back to a
+/// higher priority as soon as it is ready. Within a priority, a ready locality is picked at
+/// random in proportion to the locality weights, and `tower::balance::p2c` load-balances
+/// across its endpoints.
+///
+/// With the `RING_HASH` and `MAGLEV` policies, the endpoint of a request is instead looked
+/// up by the [`RequestHash`] of the request in a consistent hashing table of the endpoints
+/// of the priority in use, weighted by their locality weights. The request goes to the first
+/// ready endpoint from that entry of the table onwards. All the endpoints of the priority are
+/// polled for readiness, so that idle ones start connecting.
+pub(crate) struct ClusterBalancer<D, S, Req>
 where
     D: Discover,
-    D::Key: Hash,
+    D::Key: Hash + Eq,
 {
-    balancer: Balance<D, Req>,
+    discover: D,
+    config: watch::Receiver<ClusterConfig>,
+    /// The locality and service of each endpoint.
+    endpoints: HashMap<D::Key, (LocalityKey, S)>,
+    /// The localities with endpoints, by priority.
+    priorities: BTreeMap<u32, Localities<D::Key, S, Req>>,
+    /// The locality picked for the next request, whose balancer was polled for readiness.
+    picked: Option<LocalityKey>,
+    /// The failover timers of the priorities without ready endpoints, started when they are
+    /// first needed. The next priority is used once the timer of a priority expires.
+    failover_timers: HashMap<u32, Pin<Box<Sleep>>>,
+    /// The consistent hashing table of the priority in use, built when first needed after
+    /// the endpoints, the priority or the policy change.
+    hash_table: Option<(u32, HashTable<D::Key>)>,
+    /// The endpoints of the consistent hashing table that were polled ready.
+    ready_endpoints: HashSet<D::Key>,
 }
 
-impl<D, Req> ClusterBalancer<D, Req>
+impl<D, S, Req> ClusterBalancer<D, S, Req>
 where
-    D: Discover,
-    D::Key: Hash,
-    D::Service: Service<Req>,
-    <D::Service as Service<Req>>::Error: Into<BoxError>,
+    D: Discover<Service = LocalizedEndpoint<S>>,
+    D::Key: Hash + Eq + Clone + Display,
+    S: Service<Req> + Clone,
+    S::Error: Into<BoxError>,
 {
     /// Creates a new `ClusterBalancer` with provided service discovery.
-    pub(crate) fn new(discover: D) -> Self {
+    pub(crate) fn new(discovery: ClusterDiscovery<D>) -> Self {
         Self {
-            balancer: Balance::new(discover),
+            discover: discovery.endpoints,
+            config: discovery.config,
+            endpoints: HashMap::new(),
+            priorities: BTreeMap::new(),
+            picked: None,
+            failover_timers: HashMap::new(),
+            hash_table: None,
+            ready_endpoints: HashSet::new(),
         }
     }
 
@@ -45,31 +154,241 @@ where
     /// This can be useful for monitoring and debugging purposes.
     #[allow(dead_code)]
     pub(crate) fn len(&self) -> usize {
-        self.balancer.len()
+        self.endpoints.len()
+    }
+
+    fn insert(&mut self, key: D::Key, endpoint: LocalizedEndpoint<S>) {
+        self.hash_table = None;
+        let locality = endpoint.locality;
+        let locality_key = (locality.priority, locality.locality);
+        if self
+            .endpoints
+            .get(&key)
+            .is_some_and(|(current, _)| *current != locality_key)
+        {
+            // The endpoint moved to another locality.
+            self.remove(&key);
+        }
+        let balancer = self
+            .priorities
+            .entry(locality_key.0)
+            .or_default()
+            .entry(locality_key.1.clone())
+            .or_insert_with(LocalityBalancer::new);
+        balancer.weight = locality.weight;
+        let service = (locality_key, endpoint.service.clone());
+        if self.endpoints.insert(key.clone(), service).is_none() {
+            balancer.len += 1;
+        }
+        let _ = balancer
+            .changes
+            .send(Ok(Change::Insert(key, endpoint.service)));
+    }
+
+    fn remove(&mut self, key: &D::Key) {
+        self.hash_table = None;
+        self.ready_endpoints.remove(key);
+        let Some(((priority, locality), _)) = self.endpoints.remove(key) else {
+            return;
+        };
+        let Some(localities) = self.priorities.get_mut(&priority) else {
+            return;
+        };
+        let Some(balancer) = localities.get_mut(&locality) else {
+            return;
+        };
+        balancer.len -= 1;
+        if balancer.len > 0 {
+            let _ = balancer.changes.send(Ok(Change::Remove(key.clone())));
+            return;
+        }
+        // Dropping the balancer of an empty locality drops its last endpoint.
+        localities.remove(&locality);
+        if localities.is_empty() {
+            self.priorities.remove(&priority);
+            self.failover_timers.remove(&priority);
+        }
+    }
+
+    fn picked_balancer(&mut self) -> Option<&mut LocalityBalancer<D::Key, S, Req>> {
+        let (priority, locality) = self.picked.as_ref()?;
+        self.priorities.get_mut(priority)?.get_mut(locality)
+    }
+
+    /// Builds the consistent hashing table of a priority, if the policy uses one.
+    fn build_hash_table(&self, priority: u32) -> Option<HashTable<D::Key>> {
+        let localities = self.priorities.get(&priority)?;
+        let endpoints = self
+            .endpoints
+            .iter()
+            .filter(|(_, ((p, _), _))| *p == priority)
+            .filter_map(|(key, ((_, locality), _))| {
+                Some((key.clone(), localities.get(locality)?.weight))
+            })
+            .collect();
+        match self.config.borrow().lb_policy {
+            LbPolicy::RingHash {
+                minimum_ring_size,
+                maximum_ring_size,
+            } => Some(HashTable::ring(
+                endpoints,
+                minimum_ring_size,
+                maximum_ring_size,
+            )),
+            LbPolicy::Maglev { table_size } => Some(HashTable::maglev(endpoints, table_size)),
+            LbPolicy::RoundRobin | LbPolicy::LeastRequest { .. } => None,
+        }
+    }
+
+    fn uses_hashing(&self) -> bool {
+        matches!(
+            self.config.borrow().lb_policy,
+            LbPolicy::RingHash { .. } | LbPolicy::Maglev { .. }
+        )
+    }
+}
+
+impl<D, S, Req> ClusterBalancer<D, S, Req>
+where
+    D: Discover<Service = LocalizedEndpoint<S>> + Unpin,
+    D::Key: Hash + Eq + Clone + Display,
+    D::Error: Into<BoxError>,
+    Req: RequestHash + Send + 'static,
+    S: Service<Req> + Load + Clone + Send + 'static,
+    <S as Load>::Metric: std::fmt::Debug,
+    S::Error: Into<BoxError> + 'static,
+    S::Future: Send + 'static,
+{
+    /// Returns the priority to use and its ready localities, with their weights.
+    ///
+    /// This is the highest priority with ready endpoints, skipping the priorities whose
+    /// failover timer expired. It is pending while the first priority without ready endpoints
+    /// waits for its failover timer.
+    fn poll_priority(&mut self, cx: &mut Context<'_>) -> Poll<(u32, Vec<(Locality, u32)>)> {
+        for (priority, localities) in &mut self.priorities {
+            let ready: Vec<_> = localities
+                .iter_mut()
+                .filter_map(
+                    |(locality, balancer)| match balancer.balancer.poll_ready(cx) {
+                        Poll::Ready(Ok(())) => Some((locality.clone(), balancer.weight)),
+                        _ => None,
+                    },
+                )
+                .collect();
+            if !ready.is_empty() {
+                self.failover_timers.remove(priority);
+                return Poll::Ready((*priority, ready));
+            }
+            let timer = self
+                .failover_timers
+                .entry(*priority)
+                .or_insert_with(|| Box::pin(tokio::time::sleep(PRIORITY_FAILOVER_TIMEOUT)));
+            if timer.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+        // No endpoints yet, or none are ready. The discover stream or the endpoints will wake
+        // the task when there are.
+        Poll::Pending
     }
 }
 
-impl<D, Req> Service<Req> for ClusterBalancer<D, Req>
+impl<D, S, Req> Service<Req> for ClusterBalancer<D, S, Req>
 where
-    D: Discover + Unpin,
-    D::Key: Hash + Clone,
+    D: Discover<Service = LocalizedEndpoint<S>> + Unpin,
+    D::Key: Hash + Eq + Clone + Display,
     D::Error: Into<BoxError>,
-    D::Service: Service<Req> + Load,
-    <D::Service as Load>::Metric: std::fmt::Debug,
-    <D::Service as Service<Req>>::Error: Into<BoxError> + 'static,
-    <D::Service as Service<Req>>::Future: Send + 'static,
+    Req: RequestHash + Send + 'static,
+    S: Service<Req> + Load + Clone + Send + 'static,
+    <S as Load>::Metric: std::fmt::Debug,
+    S::Error: Into<BoxError> + 'static,
+    S::Future: Send + 'static,
 {
-    type Response = <Balance<D, Req> as Service<Req>>::Response;
-    type Error = <Balance<D, Req> as Service<Req>>::Error;
+    type Response = S::Response;
+    type Error = BoxError;
     type Future = RespFut<Self::Response>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.balancer.poll_ready(cx)
+        loop {
+            match Pin::new(&mut self.discover).poll_discover(cx) {
+                Poll::Ready(Some(Ok(Change::Insert(key, endpoint)))) => self.insert(key, endpoint),
+                Poll::Ready(Some(Ok(Change::Remove(key)))) => self.remove(&key),
+                Poll::Ready(Some(Err(error))) => return Poll::Ready(Err(error.into())),
+                Poll::Ready(None) | Poll::Pending => break,
+            }
+        }
+
+        if self.config.has_changed().unwrap_or(false) {
+            self.config.borrow_and_update();
+            self.hash_table = None;
+        }
+        let Poll::Ready((priority, ready)) = self.poll_priority(cx) else {
+            return Poll::Pending;
+        };
+        if self.uses_hashing() {
+            if self.hash_table.as_ref().map(|(p, _)| *p) != Some(priority) {
+                self.hash_table = self
+                    .build_hash_table(priority)
+                    .map(|table| (priority, table));
+            }
+            self.ready_endpoints.clear();
+            for (key, ((endpoint_priority, _), service)) in &mut self.endpoints {
+                if *endpoint_priority == priority {
+                    if let Poll::Ready(Ok(())) = service.poll_ready(cx) {
+                        self.ready_endpoints.insert(key.clone());
+                    }
+                }
+            }
+            return match &self.hash_table {
+                Some((_, table)) if !table.is_empty() && !self.ready_endpoints.is_empty() => {
+                    Poll::Ready(Ok(()))
+                }
+                // No endpoints yet, or none are ready. The discover stream or the endpoints
+                // will wake the task when there are.
+                _ => Poll::Pending,
+            };
+        }
+        self.hash_table = None;
+
+        // The balancer of the picked locality was polled ready.
+        self.picked = pick_weighted(&ready).map(|locality| (priority, locality));
+        Poll::Ready(Ok(()))
     }
 
     fn call(&mut self, req: Req) -> Self::Future {
-        Box::pin(self.balancer.call(req))
+        if let Some((_, table)) = &self.hash_table {
+            let hash = req.request_hash().unwrap_or_else(rand::random);
+            let service = table
+                .walk(hash)
+                .find(|key| self.ready_endpoints.contains(*key))
+                .and_then(|key| self.endpoints.get_mut(key))
+                .map(|(_, service)| service)
+                .expect("ClusterBalancer::call called before poll_ready");
+            let future = service.call(req);
+            self.ready_endpoints.clear();
+            return Box::pin(async move { future.await.map_err(Into::into) });
+        }
+        let balancer = self
+            .picked_balancer()
+            .expect("ClusterBalancer::call called before poll_ready");
+        let future = balancer.balancer.call(req);
+        self.picked = None;
+        Box::pin(future)
+    }
+}
+
+/// Picks one of the given localities at random, in proportion to their weights.
+fn pick_weighted(localities: &[(Locality, u32)]) -> Option<Locality> {
+    let total: u64 = localities.iter().map(|(_, w)| u64::from(*w)).sum();
+    let mut pick = rand::random_range(0..total.max(1));
+    for (locality, weight) in localities {
+        let weight = u64::from(*weight);
+        if pick < weight {
+            return Some(locality.clone());
+        }
+        pick -= weight;
     }
+    localities.last().map(|(locality, _)| locality.clone())
 }
 
 /// `ClusterChannel` is similar to `tonic::transport::Channel`, but is for load-balancing across all
@@ -139,6 +458,8 @@ where
 {
     name: String,
     channel: ClusterChannel<Req, Resp>,
+    circuit_breaker: CircuitBreaker,
+    config: watch::Receiver<ClusterConfig>,
 }
 
 impl Debug for ClusterClient<(), ()> {
@@ -156,19 +477,32 @@ where
 {
     /// Creates a new `ClusterClient` with the given cluster name and service discovery implementation.
     /// Currently, `tower::discover::Discover` is used for service discovery.
-    pub(crate) fn new<D>(name: String, discover: D) -> Self
+    pub(crate) fn new<D, S>(name: String, discovery: ClusterDiscovery<D>) -> Self
     where
-        D: Discover + Unpin + Send + 'static,
-        D::Key: std::hash::Hash + Clone + Send,
+        Req: RequestHash,
+        D: Discover<Service = LocalizedEndpoint<S>> + Unpin + Send + 'static,
+        D::Key: std::hash::Hash + Eq + Clone + Display + Send,
         D::Error: Into<BoxError>,
-        D::Service: Service<Req, Response = Resp> + Load + Send + 'static,
-        <D::Service as Load>::Metric: std::fmt::Debug,
-        <D::Service as Service<Req>>::Error: Into<BoxError>,
-        <D::Service as Service<Req>>::Future: Send + 'static,
+        S: Service<Req, Response = Resp> + Load + Clone + Send + 'static,
+        <S as Load>::Metric: std::fmt::Debug,
+        S::Error: Into<BoxError> + Send,
+        S::Future: Send + 'static,
+        Resp: CallOutcome + Send,
     {
-        let balancer = ClusterBalancer::new(discover);
+        let ClusterDiscovery { endpoints, config } = discovery;
+        let circuit_breaker = CircuitBreaker::new(config.clone());
+        let endpoints = OutlierDetection::new(endpoints, config.clone());
+        let balancer = ClusterBalancer::new(ClusterDiscovery {
+            endpoints,
+            config: config.clone(),
+        });
         let channel = ClusterChannel::from_balancer(balancer, DEFAULT_BUFFER_CAPACITY);
-        Self { name, channel }
+        Self {
+            name,
+            channel,
+            circuit_breaker,
+            config,
+        }
     }
 
     /// Returns a channel that can be used to send RPCs to the cluster.
@@ -176,6 +510,33 @@ where
         self.channel.clone()
     }
 
+    /// Admits a request to the cluster, returning a tracker to hold until the request
+    /// completes.
+    ///
+    /// Requests are dropped with an `UNAVAILABLE` status by the drop overloads of the
+    /// cluster's load assignment, each dropping its fraction of the requests, and then by
+    /// the circuit breaker. Dropped requests are recorded in the load report of the cluster.
+    pub(crate) fn admit(&self) -> Result<InFlightTracker, tonic::Status> {
+        let config = self.config.borrow();
+        for drop_overload in &config.drop_overloads {
+            if rand::random_range(0..1_000_000) < drop_overload.parts_per_million {
+                if let Some(load_stats) = &config.load_stats {
+                    load_stats.record_drop(Some(&drop_overload.category));
+                }
+                return Err(tonic::Status::unavailable(format!(
+                    "request dropped by drop category {}",
+                    drop_overload.category
+                )));
+            }
+        }
+        self.circuit_breaker.try_acquire().map_err(|status| {
+            if let Some(load_stats) = &config.load_stats {
+                load_stats.record_drop(None);
+            }
+            status
+        })
+    }
+
     /// Returns the name of the cluster.
     #[allow(dead_code)]
     pub(crate) fn name(&self) -> &str {
@@ -205,28 +566,30 @@ where
         }
     }
     /// Get the client of a cluster with lazy discovery.
-    pub(crate) fn get_cluster<F, D>(
+    pub(crate) fn get_cluster<F, D, S>(
         &self,
         key: &str,
         discover_fn: F,
     ) -> Arc<ClusterClient<Req, Resp>>
     where
-        F: FnOnce() -> D,
-        D: Discover + Unpin + Send + 'static,
-        D::Key: std::hash::Hash + Clone + Send,
+        Req: RequestHash,
+        F: FnOnce() -> ClusterDiscovery<D>,
+        D: Discover<Service = LocalizedEndpoint<S>> + Unpin + Send + 'static,
+        D::Key: std::hash::Hash + Eq + Clone + Display + Send,
         D::Error: Into<BoxError>,
-        D::Service: Service<Req, Response = Resp> + Load + Send + 'static,
-        <D::Service as Load>::Metric: std::fmt::Debug,
-        <D::Service as Service<Req>>::Error: Into<BoxError>,
-        <D::Service as Service<Req>>::Future: Send + 'static,
+        S: Service<Req, Response = Resp> + Load + Clone + Send + 'static,
+        <S as Load>::Metric: std::fmt::Debug,
+        S::Error: Into<BoxError> + Send,
+        S::Future: Send + 'static,
+        Resp: CallOutcome + Send,
     {
         let client = self
             .registry
             .entry(key.to_string())
             .or_insert_with(|| {
                 let name = key.to_string();
-                let discover = discover_fn();
-                Arc::new(ClusterClient::new(name, discover))
+                let discovery = discover_fn();
+                Arc::new(ClusterClient::new(name, discovery))
             })
             .clone();
         client
@@ -245,6 +608,370 @@ where
 
 /// A type erased registry for Tonic clients.
 /// This will be used by the xDS Tower Service implementations to get the client for a specific Tonic xDS cluster.
-#[allow(dead_code)]
 pub(crate) type ClusterClientRegistryGrpc =
     ClusterClientRegistry<Request<TonicBody>, Response<TonicBody>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::endpoint::{EndpointChannel, EndpointLocality};
+    use crate::common::xxhash::xxh64;
+    use std::future::Ready;
+    use tower::ServiceExt;
+
+    /// A service that responds with its name.
+    #[derive(Clone)]
+    struct NamedService(&'static str);
+
+    impl<Req> Service<Req> for NamedService {
+        type Response = &'static str;
+        type Error = Infallible;
+        type Future = Ready<Result<&'static str, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: Req) -> Self::Future {
+            std::future::ready(Ok(self.0))
+        }
+    }
+
+    impl CallOutcome for &'static str {
+        fn is_success(&self) -> bool {
+            true
+        }
+    }
+
+    impl RequestHash for () {
+        fn request_hash(&self) -> Option<u64> {
+            None
+        }
+    }
+
+    /// A request that is its own hash.
+    impl RequestHash for u64 {
+        fn request_hash(&self) -> Option<u64> {
+            Some(*self)
+        }
+    }
+
+    type TestDiscover = UnboundedReceiverStream<Result<TestChange, Infallible>>;
+    type TestChanges = mpsc::UnboundedSender<Result<TestChange, Infallible>>;
+    type TestBalancer<Req> = ClusterBalancer<TestDiscover, EndpointChannel<NamedService>, Req>;
+
+    fn balancer<Req>(
+        lb_policy: LbPolicy,
+    ) -> (TestChanges, watch::Sender<ClusterConfig>, TestBalancer<Req>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (config_tx, config) = watch::channel(ClusterConfig {
+            lb_policy,
+            ..Default::default()
+        });
+        let balancer = ClusterBalancer::new(ClusterDiscovery {
+            endpoints: UnboundedReceiverStream::new(rx),
+            config,
+        });
+        (tx, config_tx, balancer)
+    }
+
+    type TestChange = Change<&'static str, LocalizedEndpoint<EndpointChannel<NamedService>>>;
+
+    fn insert(name: &'static str, zone: &str, priority: u32, weight: u32) -> TestChange {
+        let locality = EndpointLocality {
+            priority,
+            locality: Locality {
+                zone: zone.to_string(),
+                ..Default::default()
+            },
+            weight,
+        };
+        Change::Insert(
+            name,
+            LocalizedEndpoint {
+                locality,
+                service: EndpointChannel::new(NamedService(name)),
+            },
+        )
+    }
+
+    async fn send_requests(
+        balancer: &mut TestBalancer<()>,
+        num_requests: usize,
+    ) -> HashMap<&'static str, usize> {
+        let mut counts = HashMap::new();
+        for _ in 0..num_requests {
+            let name = balancer.ready().await.unwrap().call(()).await.unwrap();
+            *counts.entry(name).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[tokio::test]
+    async fn test_priority_failover_and_locality_weights() {
+        let (tx, _config, mut balancer) = balancer(LbPolicy::RoundRobin);
+        for change in [
+            insert("a1", "a", 0, 3),
+            insert("a2", "a", 0, 3),
+            insert("b1", "b", 0, 1),
+            insert("c1", "c", 1, 1),
+        ] {
+            tx.send(Ok(change)).unwrap();
+        }
+
+        // Only priority 0 is used, split 3:1 between its localities.
+        let num_requests = 4000;
+        let counts = send_requests(&mut balancer, num_requests).await;
+        assert_eq!(balancer.len(), 4);
+        assert!(!counts.contains_key("c1"), "{counts:?}");
+        let a = (counts["a1"] + counts["a2"]) as f64 / num_requests as f64;
+        assert!((0.7..0.8).contains(&a), "{counts:?}");
+
+        // Priority 1 is used once priority 0 has no endpoints.
+        for name in ["a1", "a2", "b1"] {
+            tx.send(Ok(Change::Remove(name))).unwrap();
+        }
+        let counts = send_requests(&mut balancer, 100).await;
+        assert_eq!(counts.get("c1"), Some(&100), "{counts:?}");
+
+        // And priority 0 is used again when it has endpoints.
+        tx.send(Ok(insert("b1", "b", 0, 1))).unwrap();
+        let counts = send_requests(&mut balancer, 100).await;
+        assert_eq!(counts.get("b1"), Some(&100), "{counts:?}");
+
+        // An endpoint can move to another locality.
+        tx.send(Ok(insert("b1", "c", 1, 1))).unwrap();
+        let counts = send_requests(&mut balancer, 100).await;
+        assert_eq!(counts.len(), 2, "{counts:?}");
+        assert_eq!(balancer.len(), 2);
+    }
+
+    /// A service that responds with its name once it is opened.
+    #[derive(Clone)]
+    struct GatedService {
+        name: &'static str,
+        /// Whether the gate is open, and the task waiting for it to open.
+        state: Arc<std::sync::Mutex<(bool, Option<std::task::Waker>)>>,
+    }
+
+    impl GatedService {
+        fn new(name: &'static str, open: bool) -> Self {
+            Self {
+                name,
+                state: Arc::new(std::sync::Mutex::new((open, None))),
+            }
+        }
+
+        fn set_open(&self, open: bool) {
+            let mut state = self.state.lock().unwrap();
+            state.0 = open;
+            if let Some(waker) = state.1.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl<Req> Service<Req> for GatedService {
+        type Response = &'static str;
+        type Error = Infallible;
+        type Future = Ready<Result<&'static str, Infallible>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            let mut state = self.state.lock().unwrap();
+            if state.0 {
+                return Poll::Ready(Ok(()));
+            }
+            state.1 = Some(cx.waker().clone());
+            Poll::Pending
+        }
+
+        fn call(&mut self, _req: Req) -> Self::Future {
+            std::future::ready(Ok(self.name))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_priority_failover_to_ready_endpoints() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut balancer: ClusterBalancer<_, EndpointChannel<GatedService>, ()> =
+            ClusterBalancer::new(ClusterDiscovery {
+                endpoints: UnboundedReceiverStream::<Result<_, Infallible>>::new(rx),
+                config: watch::channel(ClusterConfig::default()).1,
+            });
+        let gated = |name, priority, service: &GatedService| {
+            let locality = EndpointLocality {
+                priority,
+                ..Default::default()
+            };
+            let service = EndpointChannel::new(service.clone());
+            Change::Insert(name, LocalizedEndpoint { locality, service })
+        };
+        let primary = GatedService::new("a1", false);
+        let fallback = GatedService::new("b1", true);
+        tx.send(Ok(gated("a1", 0, &primary))).unwrap();
+        tx.send(Ok(gated("b1", 1, &fallback))).unwrap();
+
+        // Priority 1 is used once priority 0 failed to get ready in time.
+        let start = tokio::time::Instant::now();
+        let name = balancer.ready().await.unwrap().call(()).await.unwrap();
+        assert_eq!(name, "b1");
+        assert!(start.elapsed() >= PRIORITY_FAILOVER_TIMEOUT);
+        let start = tokio::time::Instant::now();
+        let name = balancer.ready().await.unwrap().call(()).await.unwrap();
+        assert_eq!(name, "b1");
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // Priority 0 is used again once it is ready.
+        primary.set_open(true);
+        let name = balancer.ready().await.unwrap().call(()).await.unwrap();
+        assert_eq!(name, "a1");
+
+        // Requests wait for a priority that is no longer ready, until it fails over again.
+        primary.set_open(false);
+        let start = tokio::time::Instant::now();
+        let name = balancer.ready().await.unwrap().call(()).await.unwrap();
+        assert_eq!(name, "b1");
+        assert!(start.elapsed() >= PRIORITY_FAILOVER_TIMEOUT);
+    }
+
+    async fn pick(balancer: &mut TestBalancer<u64>, request: u64) -> &'static str {
+        let hash = xxh64(&request.to_le_bytes(), 0);
+        balancer.ready().await.unwrap().call(hash).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_consistent_hashing() {
+        let ring_hash = LbPolicy::RingHash {
+            minimum_ring_size: 1024,
+            maximum_ring_size: 4096,
+        };
+        let (tx, config, mut balancer) = balancer::<u64>(ring_hash.clone());
+        for change in [
+            insert("a1", "a", 0, 1),
+            insert("a2", "a", 0, 1),
+            insert("b1", "b", 0, 1),
+            insert("c1", "c", 1, 1),
+        ] {
+            tx.send(Ok(change)).unwrap();
+        }
+
+        for policy in [ring_hash, LbPolicy::Maglev { table_size: 65_537 }] {
+            config.send_modify(|config| config.lb_policy = policy);
+
+            // Requests with the same hash go to the same endpoint of the highest priority.
+            let mut picks = HashMap::new();
+            for request in 0..100 {
+                picks.insert(request, pick(&mut balancer, request).await);
+            }
+            for request in 0..100 {
+                assert_eq!(pick(&mut balancer, request).await, picks[&request]);
+            }
+            let endpoints: HashSet<_> = picks.values().copied().collect();
+            assert_eq!(endpoints, HashSet::from(["a1", "a2", "b1"]));
+        }
+
+        // Only the requests of a removed endpoint move.
+        let mut picks = HashMap::new();
+        for request in 0..100 {
+            picks.insert(request, pick(&mut balancer, request).await);
+        }
+        tx.send(Ok(Change::Remove("a2"))).unwrap();
+        for request in 0..100 {
+            let endpoint = pick(&mut balancer, request).await;
+            assert_ne!(endpoint, "a2");
+            if picks[&request] != "a2" {
+                assert_eq!(endpoint, picks[&request]);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consistent_hashing_skips_endpoints_not_ready() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut balancer: ClusterBalancer<_, EndpointChannel<GatedService>, u64> =
+            ClusterBalancer::new(ClusterDiscovery {
+                endpoints: UnboundedReceiverStream::<Result<_, Infallible>>::new(rx),
+                config: watch::channel(ClusterConfig {
+                    lb_policy: LbPolicy::RingHash {
+                        minimum_ring_size: 1024,
+                        maximum_ring_size: 4096,
+                    },
+                    ..Default::default()
+                })
+                .1,
+            });
+        let services = [
+            GatedService::new("a1", true),
+            GatedService::new("a2", true),
+            GatedService::new("a3", true),
+        ];
+        for service in &services {
+            let endpoint = LocalizedEndpoint {
+                locality: EndpointLocality::default(),
+                service: EndpointChannel::new(service.clone()),
+            };
+            tx.send(Ok(Change::Insert(service.name, endpoint))).unwrap();
+        }
+
+        // Find a request hash picking a2 while every endpoint is ready.
+        let mut hash = 0;
+        for request in 0u64.. {
+            hash = xxh64(&request.to_le_bytes(), 0);
+            if balancer.ready().await.unwrap().call(hash).await.unwrap() == "a2" {
+                break;
+            }
+        }
+
+        // With a2 down, the request goes to the next endpoint of the ring.
+        services[1].set_open(false);
+        let next = {
+            let (_, table) = balancer.hash_table.as_ref().unwrap();
+            *table.walk(hash).find(|key| **key != "a2").unwrap()
+        };
+        for _ in 0..10 {
+            let name = balancer.ready().await.unwrap().call(hash).await.unwrap();
+            assert_eq!(name, next);
+        }
+
+        // And back to a2 once it is ready again.
+        services[1].set_open(true);
+        let name = balancer.ready().await.unwrap().call(hash).await.unwrap();
+        assert_eq!(name, "a2");
+    }
+
+    #[tokio::test]
+    async fn test_admit_drop_overloads() {
+        use xds_client::resource::prost::endpoint::DropOverload;
+
+        let (_tx, rx) = mpsc::unbounded_channel::<Result<TestChange, Infallible>>();
+        let (config_tx, config) = watch::channel(ClusterConfig::default());
+        let client: ClusterClient<(), &'static str> = ClusterClient::new(
+            "test-admit-drop-overloads".to_string(),
+            ClusterDiscovery {
+                endpoints: UnboundedReceiverStream::new(rx),
+                config,
+            },
+        );
+        assert!(client.admit().is_ok());
+
+        let drop_overload = |category: &str, parts_per_million| DropOverload {
+            category: category.to_string(),
+            parts_per_million,
+        };
+        config_tx.send_modify(|config| {
+            config.drop_overloads =
+                vec![drop_overload("never", 0), drop_overload("all", 1_000_000)];
+        });
+        let status = client.admit().unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        assert!(status.message().contains("all"), "{status:?}");
+
+        // The circuit breaker applies to the admitted requests.
+        config_tx.send_modify(|config| {
+            config.drop_overloads = vec![drop_overload("never", 0)];
+            config.max_requests = 1;
+        });
+        let _in_flight = client.admit().unwrap();
+        assert!(client.admit().is_err());
+    }
+}
@@ -0,0 +1,251 @@
+//! Consistent hashing of requests to endpoints, for the `RING_HASH` and `MAGLEV`
+//! cluster load balancing policies.
+//!
+//! Both tables are built from the endpoint addresses hashed with XXH64, following
+//! [gRFC A42](https://github.com/grpc/proposal/blob/master/A42-xds-ring-hash-lb-policy.md)
+//! and Envoy's Maglev load balancer, so that requests with the same hash go to the same
+//! endpoint and only a few hashes move when endpoints are added or removed.
+
+use crate::common::xxhash::xxh64;
+use std::fmt::Display;
+
+/// Upper bound of the ring size, whatever the cluster configures.
+const RING_SIZE_CAP: u64 = 4096;
+
+/// A lookup table from request hashes to endpoints.
+#[derive(Debug)]
+pub(crate) enum HashTable<K> {
+    /// A ring of `(hash, endpoint)` entries, sorted by hash. A request goes to the first
+    /// entry with a hash not lower than the request hash.
+    Ring(Vec<(u64, K)>),
+    /// A Maglev lookup table. A request goes to the entry at the request hash modulo the
+    /// table size.
+    Maglev(Vec<K>),
+}
+
+impl<K: Clone + Display> HashTable<K> {
+    /// Builds a ring from endpoints and their weights.
+    ///
+    /// Each endpoint gets a number of entries proportional to its weight, with at least
+    /// `minimum_ring_size` and at most `maximum_ring_size` entries in total.
+    pub(crate) fn ring(
+        endpoints: Vec<(K, u32)>,
+        minimum_ring_size: u64,
+        maximum_ring_size: u64,
+    ) -> Self {
+        let endpoints: Vec<_> = sorted(endpoints)
+            .into_iter()
+            .filter(|(_, weight)| *weight > 0)
+            .collect();
+        let total: u64 = endpoints.iter().map(|(_, w)| u64::from(*w)).sum();
+        let Some(min_weight) = endpoints.iter().map(|(_, w)| *w).min() else {
+            return Self::Ring(Vec::new());
+        };
+        let maximum_ring_size = maximum_ring_size.min(RING_SIZE_CAP);
+        let minimum_ring_size = minimum_ring_size.min(maximum_ring_size);
+
+        // Scale the ring so that the endpoint with the lowest weight gets at least one entry.
+        let min_normalized = f64::from(min_weight) / total as f64;
+        let scale = ((min_normalized * minimum_ring_size as f64).ceil() / min_normalized)
+            .min(maximum_ring_size as f64);
+
+        let mut ring = Vec::with_capacity(scale.ceil() as usize);
+        let mut current = 0.0;
+        let mut target = 0.0;
+        for (key, weight) in endpoints {
+            target += scale * f64::from(weight) / total as f64;
+            let mut index = 0;
+            while current < target {
+                ring.push((xxh64(format!("{key}_{index}").as_bytes(), 0), key.clone()));
+                index += 1;
+                current += 1.0;
+            }
+        }
+        ring.sort_by_key(|(hash, _)| *hash);
+        Self::Ring(ring)
+    }
+
+    /// Builds a Maglev table of `table_size` entries, a prime number, from endpoints and
+    /// their weights.
+    ///
+    /// Endpoints take turns to fill the first free entry of their own permutation of the
+    /// table. An endpoint with half the highest weight skips every other turn.
+    pub(crate) fn maglev(endpoints: Vec<(K, u32)>, table_size: u64) -> Self {
+        struct Entry<K> {
+            key: K,
+            weight: u64,
+            target_weight: u64,
+            offset: u64,
+            skip: u64,
+            next: u64,
+        }
+
+        let mut entries: Vec<_> = sorted(endpoints)
+            .into_iter()
+            .filter(|(_, weight)| *weight > 0)
+            .map(|(key, weight)| {
+                let address = key.to_string();
+                Entry {
+                    offset: xxh64(address.as_bytes(), 0) % table_size,
+                    skip: xxh64(address.as_bytes(), 1) % (table_size - 1) + 1,
+                    key,
+                    weight: u64::from(weight),
+                    target_weight: 0,
+                    next: 0,
+                }
+            })
+            .collect();
+        let Some(max_weight) = entries.iter().map(|entry| entry.weight).max() else {
+            return Self::Maglev(Vec::new());
+        };
+
+        let mut table: Vec<Option<K>> = vec![None; table_size as usize];
+        let mut filled = 0;
+        let mut iteration = 1;
+        while filled < table.len() {
+            for entry in &mut entries {
+                if filled == table.len() {
+                    break;
+                }
+                if iteration * entry.weight < entry.target_weight {
+                    continue;
+                }
+                entry.target_weight += max_weight;
+                let mut index;
+                loop {
+                    index = ((entry.offset + entry.skip * entry.next) % table_size) as usize;
+                    entry.next += 1;
+                    if table[index].is_none() {
+                        break;
+                    }
+                }
+                table[index] = Some(entry.key.clone());
+                filled += 1;
+            }
+            iteration += 1;
+        }
+        Self::Maglev(table.into_iter().flatten().collect())
+    }
+}
+
+impl<K> HashTable<K> {
+    /// Returns whether the table has no endpoints.
+    pub(crate) fn is_empty(&self) -> bool {
+        match self {
+            Self::Ring(ring) => ring.is_empty(),
+            Self::Maglev(table) => table.is_empty(),
+        }
+    }
+
+    /// Returns the entries of the table from the one for a request hash onwards, wrapping
+    /// around once, so that a request can go to the next endpoint when the first one is not
+    /// usable. Endpoints appear as many times as they have entries.
+    pub(crate) fn walk(&self, hash: u64) -> impl Iterator<Item = &K> {
+        let (len, start) = match self {
+            Self::Ring(ring) => (ring.len(), ring.partition_point(|(entry, _)| *entry < hash)),
+            Self::Maglev(table) => (table.len(), (hash % table.len().max(1) as u64) as usize),
+        };
+        (0..len).map(move |offset| match self {
+            Self::Ring(ring) => &ring[(start + offset) % len].1,
+            Self::Maglev(table) => &table[(start + offset) % len],
+        })
+    }
+}
+
+/// Sorts endpoints by address, so that every client builds the same table.
+fn sorted<K: Display>(endpoints: Vec<(K, u32)>) -> Vec<(K, u32)> {
+    let mut endpoints: Vec<_> = endpoints
+        .into_iter()
+        .map(|(key, weight)| (key.to_string(), key, weight))
+        .collect();
+    endpoints.sort_by(|a, b| a.0.cmp(&b.0));
+    endpoints
+        .into_iter()
+        .map(|(_, key, weight)| (key, weight))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn endpoints(count: usize) -> Vec<(String, u32)> {
+        (0..count).map(|i| (format!("10.0.0.{i}:80"), 1)).collect()
+    }
+
+    fn distribution(table: &HashTable<String>, samples: u64) -> HashMap<String, u64> {
+        let mut counts = HashMap::new();
+        for i in 0..samples {
+            let key = table.walk(xxh64(&i.to_le_bytes(), 0)).next().unwrap();
+            *counts.entry(key.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Returns the fraction of request hashes that go to another endpoint.
+    fn moved(before: &HashTable<String>, after: &HashTable<String>, samples: u64) -> f64 {
+        let moved = (0..samples)
+            .map(|i| xxh64(&i.to_le_bytes(), 0))
+            .filter(|hash| before.walk(*hash).next() != after.walk(*hash).next())
+            .count();
+        moved as f64 / samples as f64
+    }
+
+    #[test]
+    fn test_ring() {
+        let ring = HashTable::ring(endpoints(4), 1024, 4096);
+        let HashTable::Ring(entries) = &ring else {
+            unreachable!()
+        };
+        assert_eq!(entries.len(), 1024);
+        assert_eq!(ring.walk(u64::MAX).next(), ring.walk(0).next());
+
+        // Endpoints are shuffled, and one is removed.
+        let mut fewer = endpoints(4);
+        fewer.reverse();
+        fewer.pop();
+        let after = HashTable::ring(fewer, 1024, 4096);
+        // A quarter of the hashes move off the removed endpoint, and some more as the
+        // remaining endpoints get more entries to keep the ring size.
+        let moved = moved(&ring, &after, 10_000);
+        assert!((0.2..0.4).contains(&moved), "{moved}");
+
+        let mut weighted = endpoints(2);
+        weighted[0].1 = 3;
+        let counts = distribution(&HashTable::ring(weighted, 1024, 4096), 10_000);
+        let heavy = counts["10.0.0.0:80"] as f64 / 10_000.0;
+        assert!((0.7..0.8).contains(&heavy), "{counts:?}");
+
+        assert!(HashTable::<String>::ring(Vec::new(), 1024, 4096)
+            .walk(0)
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn test_maglev() {
+        let table = HashTable::maglev(endpoints(4), 65_537);
+        let counts = distribution(&table, 10_000);
+        assert_eq!(counts.len(), 4);
+        assert!(counts.values().all(|count| (2000..3000).contains(count)));
+
+        let mut fewer = endpoints(4);
+        fewer.reverse();
+        fewer.pop();
+        let after = HashTable::maglev(fewer, 65_537);
+        let moved = moved(&table, &after, 10_000);
+        assert!((0.15..0.35).contains(&moved), "{moved}");
+
+        let mut weighted = endpoints(2);
+        weighted[0].1 = 3;
+        let counts = distribution(&HashTable::maglev(weighted, 65_537), 10_000);
+        let heavy = counts["10.0.0.0:80"] as f64 / 10_000.0;
+        assert!((0.7..0.8).contains(&heavy), "{counts:?}");
+
+        assert!(HashTable::<String>::maglev(Vec::new(), 7)
+            .walk(0)
+            .next()
+            .is_none());
+    }
+}
@@ -1,15 +1,17 @@
+use crate::client::outlier_detection::CallOutcome;
 use crate::common::async_util::BoxFuture;
-use std::net::SocketAddr;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::{atomic::AtomicU64, atomic::Ordering, Arc};
 use std::task::{Context, Poll};
 use tower::{load::Load, Service};
+use xds_client::{Locality, LocalityLoadStats};
 
 /// Represents the host part of an endpoint address
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum EndpointHost {
     Ipv4(std::net::Ipv4Addr),
     Ipv6(std::net::Ipv6Addr),
-    #[allow(dead_code)]
     Hostname(String),
 }
 
@@ -22,6 +24,28 @@ pub(crate) struct EndpointAddress {
     port: u16,
 }
 
+impl EndpointAddress {
+    /// Creates an endpoint address from an IP address or hostname and a port.
+    pub(crate) fn new(host: &str, port: u16) -> Self {
+        let host = match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => EndpointHost::Ipv4(ip),
+            Ok(IpAddr::V6(ip)) => EndpointHost::Ipv6(ip),
+            Err(_) => EndpointHost::Hostname(host.to_string()),
+        };
+        Self { host, port }
+    }
+}
+
+impl fmt::Display for EndpointAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.host {
+            EndpointHost::Ipv4(ip) => write!(f, "{ip}:{}", self.port),
+            EndpointHost::Ipv6(ip) => write!(f, "[{ip}]:{}", self.port),
+            EndpointHost::Hostname(host) => write!(f, "{host}:{}", self.port),
+        }
+    }
+}
+
 impl From<SocketAddr> for EndpointAddress {
     fn from(addr: SocketAddr) -> Self {
         match addr {
@@ -37,23 +61,94 @@ impl From<SocketAddr> for EndpointAddress {
     }
 }
 
+/// The locality of an endpoint, used for priority failover and locality-weighted load balancing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EndpointLocality {
+    /// The priority of the locality. 0 is the highest priority.
+    pub priority: u32,
+    /// The locality the endpoint is in.
+    pub locality: Locality,
+    /// The load balancing weight of the locality.
+    pub weight: u32,
+}
+
+impl Default for EndpointLocality {
+    fn default() -> Self {
+        Self {
+            priority: 0,
+            locality: Locality::default(),
+            weight: 1,
+        }
+    }
+}
+
+/// A discovered endpoint service together with its locality.
+#[derive(Debug, Clone)]
+pub(crate) struct LocalizedEndpoint<S> {
+    /// The locality of the endpoint.
+    pub locality: EndpointLocality,
+    /// The service for communicating with the endpoint.
+    pub service: S,
+}
+
 /// RAII tracker for in-flight requests.
-/// This is mainly used to implement endpoint load reporting for load balancing purposes.
-#[derive(Clone, Debug, Default)]
-struct InFlightTracker {
+/// This is used to implement endpoint load reporting for load balancing purposes, circuit
+/// breaking of clusters, and the per-locality load reported to the LRS server.
+#[derive(Debug, Default)]
+pub(crate) struct InFlightTracker {
     in_flight: Arc<AtomicU64>,
+    /// The load of the endpoint's locality, if the cluster reports its load.
+    load_stats: Option<LocalityLoadStats>,
+    /// Whether the request succeeded, reported to `load_stats` when the tracker is dropped.
+    success: bool,
 }
 
 impl InFlightTracker {
     fn new(in_flight: Arc<AtomicU64>) -> Self {
         in_flight.fetch_add(1, Ordering::Relaxed);
-        Self { in_flight }
+        Self {
+            in_flight,
+            load_stats: None,
+            success: false,
+        }
+    }
+
+    /// Tracks a request unless `max` requests are already in flight.
+    pub(crate) fn try_new(in_flight: Arc<AtomicU64>, max: u64) -> Option<Self> {
+        in_flight
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                (count < max).then_some(count + 1)
+            })
+            .ok()?;
+        Some(Self {
+            in_flight,
+            load_stats: None,
+            success: false,
+        })
+    }
+
+    /// Also records the request in the load of a locality, as failed unless
+    /// [`set_success`](Self::set_success) is called.
+    fn with_load_stats(mut self, load_stats: Option<LocalityLoadStats>) -> Self {
+        if let Some(load_stats) = &load_stats {
+            load_stats.start_request();
+        }
+        self.load_stats = load_stats;
+        self
+    }
+
+    /// Sets whether the request succeeded.
+    fn set_success(&mut self, success: bool) {
+        self.success = success;
     }
 }
 
 impl Drop for InFlightTracker {
     fn drop(&mut self) {
         self.in_flight.fetch_sub(1, Ordering::Relaxed);
+        if let Some(load_stats) = &self.load_stats {
+            load_stats.end_request(self.success);
+        }
     }
 }
 
@@ -61,18 +156,26 @@ impl Drop for InFlightTracker {
 pub(crate) struct EndpointChannel<S> {
     inner: S,
     in_flight: Arc<AtomicU64>,
+    /// The load of the endpoint's locality, if the cluster reports its load.
+    load_stats: Option<LocalityLoadStats>,
 }
 
 impl<S> EndpointChannel<S> {
     /// Creates a new `EndpointChannel`.
     /// This should be used by xDS implementations to construct channels to individual endpoints.
-    #[allow(dead_code)]
     pub(crate) fn new(inner: S) -> Self {
         Self {
             inner,
             in_flight: Arc::new(AtomicU64::new(0)),
+            load_stats: None,
         }
     }
+
+    /// Records the requests sent to the endpoint in the load of its locality.
+    pub(crate) fn with_load_stats(mut self, load_stats: LocalityLoadStats) -> Self {
+        self.load_stats = Some(load_stats);
+        self
+    }
 }
 
 impl<S> Clone for EndpointChannel<S>
@@ -83,6 +186,7 @@ where
         Self {
             inner: self.inner.clone(),
             in_flight: self.in_flight.clone(),
+            load_stats: self.load_stats.clone(),
         }
     }
 }
@@ -90,6 +194,7 @@ where
 impl<S, Req> Service<Req> for EndpointChannel<S>
 where
     S: Service<Req> + Send + 'static,
+    S::Response: CallOutcome,
     S::Future: Send + 'static,
 {
     type Response = S::Response;
@@ -101,14 +206,14 @@ where
     }
 
     fn call(&mut self, req: Req) -> Self::Future {
-        let in_flight = InFlightTracker::new(self.in_flight.clone());
+        let mut in_flight =
+            InFlightTracker::new(self.in_flight.clone()).with_load_stats(self.load_stats.clone());
         let fut = self.inner.call(req);
 
-        // -1 when the inner future completes
+        // -1 when the call ends, with its response or its error.
         Box::pin(async move {
-            let _in_flight_guard = in_flight;
-            let res = fut.await;
-            res
+            let response = fut.await?;
+            Ok(response.on_end(move |success| in_flight.set_success(success)))
         })
     }
 }
@@ -119,3 +224,24 @@ impl<S> Load for EndpointChannel<S> {
         self.in_flight.load(Ordering::Relaxed)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::EndpointAddress;
+
+    #[test]
+    fn test_endpoint_address_display() {
+        let cases = [
+            ("10.0.0.1", "10.0.0.1:8080"),
+            ("::1", "[::1]:8080"),
+            ("backend.example.com", "backend.example.com:8080"),
+        ];
+        for (host, expected) in cases {
+            assert_eq!(EndpointAddress::new(host, 8080).to_string(), expected);
+        }
+        assert_eq!(
+            EndpointAddress::new("127.0.0.1", 8080),
+            EndpointAddress::from("127.0.0.1:8080".parse::<std::net::SocketAddr>().unwrap())
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use crate::client::endpoint::InFlightTracker;
+use crate::common::async_util::BoxFuture;
+use crate::xds::route::RouteDecision;
+use dashmap::DashMap;
+use http::Request;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{BoxError, Layer, Service};
+
+/// Tower service injecting the faults decided by the routing layer into requests, following
+/// [gRFC A33](https://github.com/grpc/proposal/blob/master/A33-Fault-Injection.md).
+///
+/// Requests are delayed, then aborted or forwarded to the inner service.
+#[derive(Clone)]
+pub(crate) struct FaultInjectionService<S> {
+    inner: S,
+    /// The number of requests with an active fault, per fault injection filter.
+    active_faults: Arc<DashMap<Arc<str>, Arc<AtomicU64>>>,
+}
+
+impl<S, B> Service<Request<B>> for FaultInjectionService<S>
+where
+    S: Service<Request<B>> + Clone + Send + 'static,
+    B: Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let faults = request
+            .extensions()
+            .get::<RouteDecision>()
+            .map(|decision| decision.faults().to_vec())
+            .unwrap_or_default();
+        if faults.is_empty() {
+            let future = self.inner.call(request);
+            return Box::pin(async move { future.await.map_err(Into::into) });
+        }
+
+        let active_faults = self.active_faults.clone();
+        // Call the inner service that was polled ready, leaving a clone in its place.
+        let clone = self.inner.clone();
+        let mut inner_service = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            // The faults stay active until the request completes.
+            let mut active = Vec::with_capacity(faults.len());
+            for fault in faults {
+                let counter = active_faults
+                    .entry(fault.filter.clone())
+                    .or_default()
+                    .clone();
+                let max_active_faults = fault.max_active_faults.map_or(u64::MAX, u64::from);
+                let Some(tracker) = InFlightTracker::try_new(counter, max_active_faults) else {
+                    continue;
+                };
+                if let Some(delay) = fault.delay {
+                    tokio::time::sleep(delay).await;
+                }
+                if let Some(code) = fault.abort {
+                    let status = tonic::Status::new(code, "RPC terminated due to fault injection");
+                    return Err(status.into());
+                }
+                active.push(tracker);
+            }
+            inner_service.call(request).await.map_err(Into::into)
+        })
+    }
+}
+
+/// Tower layer injecting faults into requests, after the routing layer.
+#[derive(Clone, Default)]
+pub(crate) struct FaultInjectionLayer {
+    active_faults: Arc<DashMap<Arc<str>, Arc<AtomicU64>>>,
+}
+
+impl FaultInjectionLayer {
+    /// Creates a new `FaultInjectionLayer`.
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for FaultInjectionLayer {
+    type Service = FaultInjectionService<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        FaultInjectionService {
+            inner: service,
+            active_faults: self.active_faults.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xds::route::{RouteInput, RouteTable};
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use tokio::time::Instant;
+    use tower::ServiceExt;
+    use xds_client::resource::prost::fault::{
+        FaultAbort, FaultAbortStatus, FaultDelay, FaultDelayKind, FaultInjection, FaultPercentage,
+    };
+    use xds_client::resource::prost::listener::{HttpFilter, HttpFilterConfig};
+    use xds_client::resource::prost::route::{
+        ClusterSpecifier, ForwardingAction, PathMatcher, Route, RouteAction, RouteConfiguration,
+        RouteMatch, VirtualHost,
+    };
+
+    const ALWAYS: FaultPercentage = FaultPercentage {
+        numerator: 100,
+        denominator: 100,
+    };
+
+    fn route(path: &str, filter_overrides: HashMap<String, HttpFilterConfig>) -> Route {
+        Route {
+            route_match: RouteMatch {
+                path: PathMatcher::Path(path.to_string()),
+                case_sensitive: true,
+                headers: Vec::new(),
+                fraction_per_million: None,
+            },
+            action: RouteAction::Forward(ForwardingAction {
+                cluster: ClusterSpecifier::Cluster("cluster".to_string()),
+                hash_policies: Vec::new(),
+                retry_policy: None,
+                max_stream_duration: None,
+            }),
+            filter_overrides,
+        }
+    }
+
+    /// Routes `/abort` to the fault filter of the listener, which aborts requests, and
+    /// `/delay` to an override delaying one request at a time.
+    fn route_table() -> RouteTable {
+        let abort = FaultInjection {
+            delay: None,
+            abort: Some(FaultAbort {
+                status: FaultAbortStatus::Grpc(tonic::Code::Unavailable as u32),
+                percentage: ALWAYS,
+            }),
+            headers: Vec::new(),
+            max_active_faults: None,
+        };
+        let delay = FaultInjection {
+            delay: Some(FaultDelay {
+                delay: FaultDelayKind::Fixed(Duration::from_secs(1)),
+                percentage: ALWAYS,
+            }),
+            abort: None,
+            headers: Vec::new(),
+            max_active_faults: Some(1),
+        };
+        let config = RouteConfiguration {
+            name: "route".to_string(),
+            virtual_hosts: vec![VirtualHost {
+                name: "vhost".to_string(),
+                domains: vec!["*".to_string()],
+                routes: vec![
+                    route("/abort", HashMap::new()),
+                    route(
+                        "/delay",
+                        HashMap::from([("fault".to_string(), HttpFilterConfig::Fault(delay))]),
+                    ),
+                ],
+                filter_overrides: HashMap::new(),
+            }],
+        };
+        let filters = [
+            HttpFilter {
+                name: "fault".to_string(),
+                config: HttpFilterConfig::Fault(abort),
+            },
+            HttpFilter {
+                name: "router".to_string(),
+                config: HttpFilterConfig::Router,
+            },
+        ];
+        RouteTable::new(Arc::new(config), &filters)
+    }
+
+    fn request(table: &RouteTable, path: &str) -> Request<()> {
+        let mut request = Request::builder().uri(path).body(()).unwrap();
+        let input = RouteInput {
+            authority: "svc",
+            path,
+            headers: request.headers(),
+        };
+        let decision = table.route("svc", &input, 0).unwrap();
+        request.extensions_mut().insert(decision);
+        request
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fault_injection() {
+        let table = route_table();
+        let inner = tower::service_fn(|_: Request<()>| async { Ok::<_, BoxError>("ok") });
+        let service = FaultInjectionLayer::new().layer(inner);
+
+        let error = service
+            .clone()
+            .oneshot(request(&table, "/abort"))
+            .await
+            .unwrap_err();
+        let status = error.downcast::<tonic::Status>().unwrap();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+
+        // Only one request at a time is delayed.
+        let start = Instant::now();
+        let delayed = tokio::spawn(service.clone().oneshot(request(&table, "/delay")));
+        tokio::task::yield_now().await;
+        let response = service.oneshot(request(&table, "/delay")).await.unwrap();
+        assert_eq!(response, "ok");
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert_eq!(delayed.await.unwrap().unwrap(), "ok");
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+}
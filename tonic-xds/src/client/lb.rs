@@ -1,4 +1,5 @@
 use crate::client::cluster::ClusterClientRegistry;
+use crate::client::outlier_detection::CallOutcome;
 use crate::common::async_util::BoxFuture;
 use crate::xds::route::RouteDecision;
 use crate::xds::xds_manager::XdsClusterDiscovery;
@@ -13,6 +14,8 @@ use tower::{load::Load, BoxError, Service};
 pub(crate) enum LoadBalancingError {
     #[error("No routing decision extension from the routing layer available")]
     NoRoutingDecision,
+    #[error("cluster specifier plugin {0} is not supported")]
+    UnsupportedClusterSpecifierPlugin(String),
 }
 
 /// A Tower Service that performs load balancing based on routing decisions and xDS configuration.
@@ -33,7 +36,6 @@ where
     S::Response: Send + 'static,
 {
     /// Creates a new `XdsLbService` with the given cluster client registry and xDS cluster discovery.
-    #[allow(dead_code)]
     pub(crate) fn new(
         cluster_registry: Arc<ClusterClientRegistry<Req, S::Response>>,
         cluster_discovery: Arc<dyn XdsClusterDiscovery<Endpoint, S>>,
@@ -63,10 +65,10 @@ impl<B, Endpoint, S> Service<Request<B>> for XdsLbService<Request<B>, Endpoint,
 where
     Request<B>: Send + 'static,
     S::Response: Send + 'static,
-    Endpoint: std::hash::Hash + Eq + Clone + Send + 'static,
-    S: Service<Request<B>> + Load + Send + 'static,
-    S::Response: Send + 'static,
-    S::Error: Into<BoxError>,
+    Endpoint: std::hash::Hash + Eq + Clone + std::fmt::Display + Send + 'static,
+    S: Service<Request<B>> + Load + Clone + Send + 'static,
+    S::Response: CallOutcome + Send + 'static,
+    S::Error: Into<BoxError> + Send,
     S::Future: Send,
     <S as tower::load::Load>::Metric: std::fmt::Debug,
 {
@@ -83,17 +85,29 @@ where
 
     fn call(&mut self, request: Request<B>) -> Self::Future {
         // Extract the routing decision from the request extensions.
-        let Some(routing_decision) = request.extensions().get::<RouteDecision>().cloned() else {
+        let Some(mut routing_decision) = request.extensions().get::<RouteDecision>().cloned()
+        else {
             return Box::pin(async move { Err(LoadBalancingError::NoRoutingDecision.into()) });
         };
 
-        // Get or create the cluster client for the target xDS cluster.
+        // No cluster specifier plugin, such as RLS, is implemented yet to pick the cluster.
+        if let Some(plugin) = routing_decision.cluster_specifier_plugin() {
+            let error = LoadBalancingError::UnsupportedClusterSpecifierPlugin(plugin.name.clone());
+            return Box::pin(async move { Err(error.into()) });
+        }
+
+        // Get the target xDS cluster of this request, picked by the routing layer, and get or
+        // create its cluster client.
+        let cluster = routing_decision.pick_cluster();
         let cluster_client = self
             .cluster_registry
-            .get_cluster(&routing_decision.cluster, || {
-                self.cluster_discovery
-                    .discover_cluster(&routing_decision.cluster)
-            });
+            .get_cluster(cluster, || self.cluster_discovery.discover_cluster(cluster));
+
+        // Fail fast if the request is dropped or the cluster has too many requests in flight.
+        let in_flight = match cluster_client.admit() {
+            Ok(in_flight) => in_flight,
+            Err(status) => return Box::pin(async move { Err(status.into()) }),
+        };
 
         // Get the transport channel for the target xDS cluster.
         // The actual load-balancing will be performeed by the channel.
@@ -102,7 +116,9 @@ where
         Box::pin(async move {
             // This will block until the first endpoint is available.
             channel.ready().await?;
-            channel.call(request).await
+            let response = channel.call(request).await?;
+            // The request is in flight until its response ends.
+            Ok(response.on_end(move |_| drop(in_flight)))
         })
     }
 }
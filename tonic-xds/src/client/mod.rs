@@ -1,5 +1,10 @@
 pub(crate) mod channel;
+pub(crate) mod circuit_breaker;
 pub(crate) mod cluster;
+pub(crate) mod consistent_hash;
 pub(crate) mod endpoint;
+pub(crate) mod fault;
 pub(crate) mod lb;
+pub(crate) mod outlier_detection;
+pub(crate) mod retry;
 pub(crate) mod route;
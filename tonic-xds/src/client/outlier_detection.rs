@@ -0,0 +1,772 @@
+//! Outlier detection of the endpoints of a cluster, following
+//! [gRFC A50](https://github.com/grpc/proposal/blob/master/A50-xds-outlier-detection.md).
+//!
+//! [`OutlierDetection`] wraps the endpoint discovery of a cluster. It counts the successes
+//! and failures of the calls to each endpoint, and at every interval ejects the outliers
+//! by removing them from the discovered endpoints, which takes them out of load balancing.
+//! Ejected endpoints are inserted again once their ejection time has passed.
+
+use crate::client::endpoint::LocalizedEndpoint;
+use crate::common::async_util::BoxFuture;
+use crate::xds::xds_manager::ClusterConfig;
+use bytes::Bytes;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::{Instant, Interval};
+use tokio_stream::Stream;
+use tonic::body::Body as TonicBody;
+use tower::discover::{Change, Discover};
+use tower::load::Load;
+use tower::Service;
+use xds_client::resource::prost::cluster::{
+    FailurePercentageEjection, OutlierDetection as OutlierDetectionConfig, SuccessRateEjection,
+};
+
+/// Classifies the outcome of a call for outlier detection.
+pub(crate) trait CallOutcome: Sized {
+    /// Returns whether the call succeeded, as far as the response tells before its body.
+    fn is_success(&self) -> bool;
+
+    /// Calls `on_end` with whether the call succeeded once the call ends. By default, the
+    /// call ends with its response.
+    fn on_end(self, on_end: impl FnOnce(bool) + Send + 'static) -> Self {
+        on_end(self.is_success());
+        self
+    }
+}
+
+/// A call fails with a 5xx HTTP status or a non-OK gRPC status, in the response headers or
+/// trailers. It ends with its response body, and fails if the body errors or is dropped
+/// before its end.
+impl CallOutcome for http::Response<TonicBody> {
+    fn is_success(&self) -> bool {
+        !self.status().is_server_error() && is_grpc_ok(self.headers())
+    }
+
+    fn on_end(self, on_end: impl FnOnce(bool) + Send + 'static) -> Self {
+        let success = self.is_success();
+        if http_body::Body::is_end_stream(self.body()) {
+            on_end(success);
+            return self;
+        }
+        self.map(|inner| {
+            TonicBody::new(OutcomeBody {
+                inner,
+                success,
+                on_end: Some(Box::new(on_end)),
+            })
+        })
+    }
+}
+
+/// Returns whether headers or trailers carry no gRPC status, or the OK status.
+fn is_grpc_ok(headers: &http::HeaderMap) -> bool {
+    headers
+        .get("grpc-status")
+        .map_or(true, |status| status == "0")
+}
+
+/// A response body reporting the outcome of its call when it ends.
+struct OutcomeBody {
+    inner: TonicBody,
+    /// Whether the response headers tell the call succeeded.
+    success: bool,
+    /// None once the outcome is reported.
+    on_end: Option<Box<dyn FnOnce(bool) + Send>>,
+}
+
+impl OutcomeBody {
+    fn end(&mut self, success: bool) {
+        if let Some(on_end) = self.on_end.take() {
+            on_end(success);
+        }
+    }
+}
+
+impl http_body::Body for OutcomeBody {
+    type Data = Bytes;
+    type Error = tonic::Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Bytes>, tonic::Status>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(trailers) = frame.trailers_ref() {
+                    let success = self.success && is_grpc_ok(trailers);
+                    self.end(success);
+                }
+            }
+            Some(Err(_)) => self.end(false),
+            None => {
+                let success = self.success;
+                self.end(success);
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for OutcomeBody {
+    fn drop(&mut self) {
+        // The call is cancelled.
+        self.end(false);
+    }
+}
+
+/// The number of successful and failed calls to an endpoint since the last interval.
+#[derive(Debug, Default)]
+struct CallCounters {
+    successes: AtomicU64,
+    failures: AtomicU64,
+}
+
+impl CallCounters {
+    fn record(&self, success: bool) {
+        let counter = if success {
+            &self.successes
+        } else {
+            &self.failures
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the numbers of successes and failures, resetting them.
+    fn take(&self) -> (u64, u64) {
+        (
+            self.successes.swap(0, Ordering::Relaxed),
+            self.failures.swap(0, Ordering::Relaxed),
+        )
+    }
+}
+
+/// An endpoint service whose calls are counted for outlier detection.
+pub(crate) struct OutlierEndpoint<S> {
+    inner: S,
+    counters: Arc<CallCounters>,
+}
+
+impl<S: Clone> Clone for OutlierEndpoint<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            counters: self.counters.clone(),
+        }
+    }
+}
+
+impl<S, Req> Service<Req> for OutlierEndpoint<S>
+where
+    S: Service<Req>,
+    S::Response: CallOutcome + Send + 'static,
+    S::Error: Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let future = self.inner.call(req);
+        let counters = self.counters.clone();
+        Box::pin(async move {
+            match future.await {
+                // The outcome is recorded when the call ends.
+                Ok(response) => Ok(response.on_end(move |success| counters.record(success))),
+                Err(error) => {
+                    counters.record(false);
+                    Err(error)
+                }
+            }
+        })
+    }
+}
+
+impl<S: Load> Load for OutlierEndpoint<S> {
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+/// The outlier detection state of an endpoint.
+struct EndpointState<S> {
+    endpoint: LocalizedEndpoint<OutlierEndpoint<S>>,
+    /// The calls of the last interval.
+    successes: u64,
+    failures: u64,
+    /// When the endpoint was ejected, if it is.
+    ejected_at: Option<Instant>,
+    /// Incremented when the endpoint is ejected, and decremented at each interval it is not.
+    multiplier: u32,
+}
+
+impl<S> EndpointState<S> {
+    fn calls(&self) -> u64 {
+        self.successes + self.failures
+    }
+}
+
+type EndpointChange<K, S> = Change<K, LocalizedEndpoint<OutlierEndpoint<S>>>;
+
+/// Ejects outlier endpoints from a discovered set of endpoints.
+///
+/// Outlier detection is enabled by the cluster configuration. When it is disabled, the
+/// endpoints are only wrapped to count their calls.
+pub(crate) struct OutlierDetection<D, S>
+where
+    D: Discover,
+{
+    discover: D,
+    config: watch::Receiver<ClusterConfig>,
+    /// The current outlier detection configuration, if enabled.
+    detection: Option<OutlierDetectionConfig>,
+    /// Ticks at the outlier detection interval, if enabled.
+    interval: Option<Interval>,
+    endpoints: HashMap<D::Key, EndpointState<S>>,
+    /// Changes to emit before polling `discover` again.
+    pending: VecDeque<EndpointChange<D::Key, S>>,
+}
+
+// Only `discover` is polled, and it is `Unpin`.
+impl<D: Discover + Unpin, S> Unpin for OutlierDetection<D, S> {}
+
+impl<D, S> OutlierDetection<D, S>
+where
+    D: Discover<Service = LocalizedEndpoint<S>>,
+    D::Key: Hash + Eq + Clone,
+    S: Clone,
+{
+    /// Wraps the endpoints discovered by `discover`, configured by the cluster `config`.
+    pub(crate) fn new(discover: D, mut config: watch::Receiver<ClusterConfig>) -> Self {
+        let detection = config.borrow_and_update().outlier_detection.clone();
+        let mut this = Self {
+            discover,
+            config,
+            detection: None,
+            interval: None,
+            endpoints: HashMap::new(),
+            pending: VecDeque::new(),
+        };
+        this.reconfigure(detection);
+        this
+    }
+
+    fn reconfigure(&mut self, detection: Option<OutlierDetectionConfig>) {
+        if detection == self.detection {
+            return;
+        }
+        match &detection {
+            Some(config) => {
+                let interval_changed = self
+                    .detection
+                    .as_ref()
+                    .map_or(true, |current| current.interval != config.interval);
+                if interval_changed {
+                    // A zero interval would make the timer panic.
+                    let period = config.interval.max(Duration::from_millis(1));
+                    self.interval = Some(tokio::time::interval_at(Instant::now() + period, period));
+                }
+            }
+            None => {
+                self.interval = None;
+                for (key, state) in &mut self.endpoints {
+                    state.multiplier = 0;
+                    if state.ejected_at.take().is_some() {
+                        self.pending
+                            .push_back(Change::Insert(key.clone(), state.endpoint.clone()));
+                    }
+                }
+            }
+        }
+        self.detection = detection;
+    }
+
+    fn update(&mut self, change: Change<D::Key, LocalizedEndpoint<S>>) {
+        match change {
+            Change::Insert(key, endpoint) => {
+                let state = self.endpoints.entry(key.clone());
+                let counters = match &state {
+                    Entry::Occupied(state) => state.get().endpoint.service.counters.clone(),
+                    Entry::Vacant(_) => Arc::default(),
+                };
+                let endpoint = LocalizedEndpoint {
+                    locality: endpoint.locality,
+                    service: OutlierEndpoint {
+                        inner: endpoint.service,
+                        counters,
+                    },
+                };
+                let state = state.or_insert_with(|| EndpointState {
+                    endpoint: endpoint.clone(),
+                    successes: 0,
+                    failures: 0,
+                    ejected_at: None,
+                    multiplier: 0,
+                });
+                state.endpoint = endpoint.clone();
+                // An ejected endpoint stays ejected when it is updated.
+                if state.ejected_at.is_none() {
+                    self.pending.push_back(Change::Insert(key, endpoint));
+                }
+            }
+            Change::Remove(key) => {
+                self.endpoints.remove(&key);
+                self.pending.push_back(Change::Remove(key));
+            }
+        }
+    }
+
+    /// Ejects the outliers of the last interval, and returns the endpoints whose ejection
+    /// time has passed.
+    fn sweep(&mut self) {
+        let Some(config) = self.detection.clone() else {
+            return;
+        };
+        let now = Instant::now();
+        for state in self.endpoints.values_mut() {
+            (state.successes, state.failures) = state.endpoint.service.counters.take();
+        }
+        if let Some(ejection) = &config.success_rate_ejection {
+            self.eject_by_success_rate(ejection, config.max_ejection_percent, now);
+        }
+        if let Some(ejection) = &config.failure_percentage_ejection {
+            self.eject_by_failure_percentage(ejection, config.max_ejection_percent, now);
+        }
+
+        let max_ejection_time = config.max_ejection_time.max(config.base_ejection_time);
+        for (key, state) in &mut self.endpoints {
+            match state.ejected_at {
+                Some(ejected_at) => {
+                    let ejection_time = config
+                        .base_ejection_time
+                        .saturating_mul(state.multiplier)
+                        .min(max_ejection_time);
+                    if now >= ejected_at + ejection_time {
+                        state.ejected_at = None;
+                        self.pending
+                            .push_back(Change::Insert(key.clone(), state.endpoint.clone()));
+                    }
+                }
+                None => state.multiplier = state.multiplier.saturating_sub(1),
+            }
+        }
+    }
+
+    /// Returns the endpoints with enough calls in the last interval to be considered, if
+    /// there are at least `minimum_hosts` of them.
+    fn candidates(&self, request_volume: u32, minimum_hosts: u32) -> Vec<(D::Key, u64, u64)> {
+        let candidates: Vec<_> = self
+            .endpoints
+            .iter()
+            .filter(|(_, state)| state.calls() > 0 && state.calls() >= u64::from(request_volume))
+            .map(|(key, state)| (key.clone(), state.successes, state.failures))
+            .collect();
+        if candidates.len() < minimum_hosts as usize {
+            return Vec::new();
+        }
+        candidates
+    }
+
+    fn eject_by_success_rate(
+        &mut self,
+        ejection: &SuccessRateEjection,
+        max_ejection_percent: u32,
+        now: Instant,
+    ) {
+        let candidates = self.candidates(ejection.request_volume, ejection.minimum_hosts);
+        if candidates.is_empty() {
+            return;
+        }
+        let rates: Vec<_> = candidates
+            .into_iter()
+            .map(|(key, successes, failures)| {
+                (key, successes as f64 / (successes + failures) as f64)
+            })
+            .collect();
+        let count = rates.len() as f64;
+        let mean = rates.iter().map(|(_, rate)| rate).sum::<f64>() / count;
+        let variance = rates
+            .iter()
+            .map(|(_, rate)| (rate - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        let threshold = mean - variance.sqrt() * (f64::from(ejection.stdev_factor) / 1000.0);
+        for (key, rate) in rates {
+            if rate < threshold {
+                self.eject(
+                    &key,
+                    ejection.enforcement_percentage,
+                    max_ejection_percent,
+                    now,
+                );
+            }
+        }
+    }
+
+    fn eject_by_failure_percentage(
+        &mut self,
+        ejection: &FailurePercentageEjection,
+        max_ejection_percent: u32,
+        now: Instant,
+    ) {
+        let candidates = self.candidates(ejection.request_volume, ejection.minimum_hosts);
+        for (key, successes, failures) in candidates {
+            if failures * 100 > u64::from(ejection.threshold) * (successes + failures) {
+                self.eject(
+                    &key,
+                    ejection.enforcement_percentage,
+                    max_ejection_percent,
+                    now,
+                );
+            }
+        }
+    }
+
+    /// Ejects an endpoint with a chance of `enforcement_percentage`, unless
+    /// `max_ejection_percent` of the endpoints are already ejected.
+    fn eject(
+        &mut self,
+        key: &D::Key,
+        enforcement_percentage: u32,
+        max_ejection_percent: u32,
+        now: Instant,
+    ) {
+        let ejected = self
+            .endpoints
+            .values()
+            .filter(|state| state.ejected_at.is_some())
+            .count();
+        if ejected * 100 >= max_ejection_percent as usize * self.endpoints.len()
+            || rand::random_range(0..100) >= enforcement_percentage
+        {
+            return;
+        }
+        let Some(state) = self.endpoints.get_mut(key) else {
+            return;
+        };
+        if state.ejected_at.is_none() {
+            state.ejected_at = Some(now);
+            state.multiplier += 1;
+            self.pending.push_back(Change::Remove(key.clone()));
+        }
+    }
+}
+
+impl<D, S> Stream for OutlierDetection<D, S>
+where
+    D: Discover<Service = LocalizedEndpoint<S>> + Unpin,
+    D::Key: Hash + Eq + Clone,
+    S: Clone,
+{
+    type Item = Result<EndpointChange<D::Key, S>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.config.has_changed().unwrap_or(false) {
+            let detection = this.config.borrow_and_update().outlier_detection.clone();
+            this.reconfigure(detection);
+        }
+        loop {
+            if let Some(change) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+            match Pin::new(&mut this.discover).poll_discover(cx) {
+                Poll::Ready(Some(Ok(change))) => {
+                    this.update(change);
+                    continue;
+                }
+                Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(error))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => {}
+            }
+            let ticked = this
+                .interval
+                .as_mut()
+                .is_some_and(|interval| interval.poll_tick(cx).is_ready());
+            if !ticked {
+                return Poll::Pending;
+            }
+            this.sweep();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use std::convert::Infallible;
+    use std::future::Ready;
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use tokio_stream::StreamExt;
+    use tower::ServiceExt;
+
+    /// Returns a response whose body ends with trailers carrying a gRPC status.
+    fn response_with_trailers(grpc_status: &'static str) -> http::Response<TonicBody> {
+        let mut trailers = http::HeaderMap::new();
+        trailers.insert("grpc-status", http::HeaderValue::from_static(grpc_status));
+        let frames = tokio_stream::iter([
+            Ok::<_, Infallible>(http_body::Frame::data(Bytes::from_static(b"message"))),
+            Ok(http_body::Frame::trailers(trailers)),
+        ]);
+        http::Response::new(TonicBody::new(http_body_util::StreamBody::new(frames)))
+    }
+
+    /// Returns a response like [`response_with_trailers`], and a receiver of the outcome
+    /// reported by `CallOutcome::on_end`.
+    fn observed_response(
+        grpc_status: &'static str,
+    ) -> (http::Response<TonicBody>, std::sync::mpsc::Receiver<bool>) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let response =
+            response_with_trailers(grpc_status).on_end(move |success| tx.send(success).unwrap());
+        (response, rx)
+    }
+
+    #[tokio::test]
+    async fn test_response_outcome_reported_at_end_of_body() {
+        let (response, outcome) = observed_response("0");
+        assert!(outcome.try_recv().is_err());
+        response.into_body().collect().await.unwrap();
+        assert_eq!(outcome.try_recv(), Ok(true));
+
+        // A status in the trailers fails the call.
+        let (response, outcome) = observed_response("14");
+        assert!(response.is_success());
+        response.into_body().collect().await.unwrap();
+        assert_eq!(outcome.try_recv(), Ok(false));
+
+        // A call cancelled before the end of its response fails.
+        let (response, outcome) = observed_response("0");
+        drop(response);
+        assert_eq!(outcome.try_recv(), Ok(false));
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_records_calls_when_they_end() {
+        let mut endpoint = OutlierEndpoint {
+            inner: tower::service_fn(|grpc_status: &'static str| async move {
+                Ok::<_, Infallible>(response_with_trailers(grpc_status))
+            }),
+            counters: Arc::default(),
+        };
+        let ok = endpoint.ready().await.unwrap().call("0").await.unwrap();
+        let failed = endpoint.ready().await.unwrap().call("14").await.unwrap();
+        assert_eq!(endpoint.counters.take(), (0, 0));
+
+        ok.into_body().collect().await.unwrap();
+        failed.into_body().collect().await.unwrap();
+        assert_eq!(endpoint.counters.take(), (1, 1));
+    }
+
+    /// A service that fails a given share of its calls.
+    #[derive(Clone)]
+    struct FlakyService {
+        calls: u32,
+        /// Every `failure_period`-th call fails, if set.
+        failure_period: Option<u32>,
+    }
+
+    impl CallOutcome for () {
+        fn is_success(&self) -> bool {
+            true
+        }
+    }
+
+    impl Service<()> for FlakyService {
+        type Response = ();
+        type Error = &'static str;
+        type Future = Ready<Result<(), &'static str>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: ()) -> Self::Future {
+            self.calls += 1;
+            let failed = self
+                .failure_period
+                .is_some_and(|period| self.calls % period == 0);
+            std::future::ready(if failed { Err("failed") } else { Ok(()) })
+        }
+    }
+
+    type TestChange = Change<&'static str, LocalizedEndpoint<FlakyService>>;
+    type TestDetection =
+        OutlierDetection<UnboundedReceiverStream<Result<TestChange, Infallible>>, FlakyService>;
+
+    fn config(
+        success_rate_ejection: Option<SuccessRateEjection>,
+        failure_percentage_ejection: Option<FailurePercentageEjection>,
+    ) -> ClusterConfig {
+        ClusterConfig {
+            outlier_detection: Some(OutlierDetectionConfig {
+                interval: Duration::from_secs(1),
+                base_ejection_time: Duration::from_secs(10),
+                max_ejection_time: Duration::from_secs(100),
+                max_ejection_percent: 20,
+                success_rate_ejection,
+                failure_percentage_ejection,
+            }),
+            ..Default::default()
+        }
+    }
+
+    struct Harness {
+        detection: TestDetection,
+        config: watch::Sender<ClusterConfig>,
+        services: HashMap<&'static str, OutlierEndpoint<FlakyService>>,
+        /// Keeps the discovery open.
+        _changes: mpsc::UnboundedSender<Result<TestChange, Infallible>>,
+    }
+
+    /// Starts outlier detection of endpoints failing every `failure_period`-th call.
+    async fn start(config: ClusterConfig, endpoints: &[(&'static str, Option<u32>)]) -> Harness {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (config_tx, config) = watch::channel(config);
+        let mut detection = OutlierDetection::new(UnboundedReceiverStream::new(rx), config);
+        for (name, failure_period) in endpoints {
+            let service = FlakyService {
+                calls: 0,
+                failure_period: *failure_period,
+            };
+            let endpoint = LocalizedEndpoint {
+                locality: Default::default(),
+                service,
+            };
+            tx.send(Ok(Change::Insert(*name, endpoint))).unwrap();
+        }
+        let mut services = HashMap::new();
+        for _ in endpoints {
+            match detection.next().await {
+                Some(Ok(Change::Insert(name, endpoint))) => {
+                    services.insert(name, endpoint.service);
+                }
+                _ => panic!("expected an endpoint insertion"),
+            }
+        }
+        Harness {
+            detection,
+            config: config_tx,
+            services,
+            _changes: tx,
+        }
+    }
+
+    async fn send_requests(
+        services: &mut HashMap<&'static str, OutlierEndpoint<FlakyService>>,
+        count: usize,
+    ) {
+        for service in services.values_mut() {
+            for _ in 0..count {
+                let _ = service.ready().await.unwrap().call(()).await;
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failure_percentage_ejection() {
+        let ejection = FailurePercentageEjection {
+            threshold: 50,
+            enforcement_percentage: 100,
+            minimum_hosts: 3,
+            request_volume: 10,
+        };
+        let mut harness = start(
+            config(None, Some(ejection)),
+            &[("good-1", None), ("good-2", Some(4)), ("bad", Some(1))],
+        )
+        .await;
+        send_requests(&mut harness.services, 10).await;
+
+        // The endpoint is ejected at the end of the interval, and returns after the base
+        // ejection time.
+        let start = Instant::now();
+        assert!(matches!(
+            harness.detection.next().await,
+            Some(Ok(Change::Remove("bad")))
+        ));
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert!(matches!(
+            harness.detection.next().await,
+            Some(Ok(Change::Insert("bad", _)))
+        ));
+        assert_eq!(start.elapsed(), Duration::from_secs(11));
+
+        // Once ejected again, it stays ejected for twice as long.
+        send_requests(&mut harness.services, 10).await;
+        let start = Instant::now();
+        assert!(matches!(
+            harness.detection.next().await,
+            Some(Ok(Change::Remove("bad")))
+        ));
+        assert!(matches!(
+            harness.detection.next().await,
+            Some(Ok(Change::Insert("bad", _)))
+        ));
+        assert_eq!(start.elapsed(), Duration::from_secs(21));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_success_rate_ejection() {
+        let ejection = SuccessRateEjection {
+            stdev_factor: 1000,
+            enforcement_percentage: 100,
+            minimum_hosts: 5,
+            request_volume: 10,
+        };
+        let mut harness = start(
+            config(Some(ejection), None),
+            &[
+                ("good-1", None),
+                ("good-2", None),
+                ("flaky", Some(10)),
+                ("bad-1", Some(2)),
+                ("bad-2", Some(2)),
+            ],
+        )
+        .await;
+        send_requests(&mut harness.services, 10).await;
+
+        // One of the endpoints below the mean success rate by more than one standard
+        // deviation is ejected, as at most 20% of the endpoints may be ejected.
+        let ejected = match harness.detection.next().await {
+            Some(Ok(Change::Remove(name))) => name,
+            _ => panic!("expected an endpoint ejection"),
+        };
+        assert!(ejected.starts_with("bad-"), "{ejected}");
+
+        // Disabling outlier detection returns the ejected endpoint.
+        harness
+            .config
+            .send_modify(|config| config.outlier_detection = None);
+        match harness.detection.next().await {
+            Some(Ok(Change::Insert(name, _))) => assert_eq!(name, ejected),
+            _ => panic!("expected an endpoint insertion"),
+        }
+    }
+}
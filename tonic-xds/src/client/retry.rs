@@ -0,0 +1,610 @@
+//! Retries and timeouts of xDS routes, following
+//! [gRFC A44](https://github.com/grpc/proposal/blob/master/A44-xds-retry.md) and
+//! [gRFC A31](https://github.com/grpc/proposal/blob/master/A31-xds-timeout-support-and-config-selector.md).
+//!
+//! Request bodies are buffered as they are sent, up to a limit, so that failed attempts can be
+//! replayed. Each attempt goes through load balancing again, so it may be sent to another
+//! endpoint. A request is committed, and no longer retried, once response headers are
+//! received or once its body outgrows the buffer.
+
+use crate::common::async_util::BoxFuture;
+use crate::xds::route::RouteDecision;
+use bytes::Bytes;
+use http::{HeaderMap, HeaderValue, Request};
+use http_body::{Body, Frame};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+use tonic::body::Body as TonicBody;
+use tower::{BoxError, Layer, Service};
+use xds_client::resource::prost::route::RetryPolicy;
+
+/// The default size of the buffer of a request body kept for retries.
+pub(crate) const DEFAULT_RETRY_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// The maximum number of attempts of a request, whatever the retry policy.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Header with the timeout of a gRPC request.
+const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+/// Header with which servers ask for a retry delay, in milliseconds.
+const RETRY_PUSHBACK_HEADER: &str = "grpc-retry-pushback-ms";
+
+/// The part of a request body received so far.
+struct BufferState {
+    /// The data frames received, unless the buffer overflowed.
+    data: Vec<Bytes>,
+    /// The number of bytes in `data`.
+    size: usize,
+    /// The number of data frames pulled from `rest`, which exceeds the length of `data` once
+    /// the buffer overflowed.
+    pulled: usize,
+    /// The trailers of the body, once received.
+    trailers: Option<HeaderMap>,
+    /// The body, until it ends.
+    rest: Option<TonicBody>,
+    /// Whether the body outgrew the buffer or failed, so it can no longer be replayed.
+    overflowed: bool,
+}
+
+/// A request body buffered as it is sent, so that it can be sent again.
+#[derive(Clone)]
+struct BufferedBody {
+    state: Arc<Mutex<BufferState>>,
+    limit: usize,
+}
+
+impl BufferedBody {
+    fn new(body: TonicBody, limit: usize) -> Self {
+        let state = BufferState {
+            data: Vec::new(),
+            size: 0,
+            pulled: 0,
+            trailers: None,
+            rest: Some(body),
+            overflowed: false,
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            limit,
+        }
+    }
+
+    /// Returns a body sending the buffered frames, then the rest of the body.
+    fn replay(&self) -> TonicBody {
+        TonicBody::new(ReplayBody {
+            body: self.clone(),
+            position: 0,
+            trailers_sent: false,
+        })
+    }
+
+    /// Returns whether the whole body received so far is buffered.
+    fn is_replayable(&self) -> bool {
+        !self
+            .state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .overflowed
+    }
+}
+
+/// The body of an attempt, replaying a [`BufferedBody`].
+struct ReplayBody {
+    body: BufferedBody,
+    /// The index of the next buffered data frame to send.
+    position: usize,
+    trailers_sent: bool,
+}
+
+impl Body for ReplayBody {
+    type Data = Bytes;
+    type Error = tonic::Status;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, tonic::Status>>> {
+        let this = self.get_mut();
+        let mut state = this.body.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(data) = state.data.get(this.position) {
+            this.position += 1;
+            return Poll::Ready(Some(Ok(Frame::data(data.clone()))));
+        }
+        if this.position < state.pulled {
+            // Another attempt sent frames that were not buffered.
+            return Poll::Ready(Some(Err(tonic::Status::internal(
+                "request body outgrew the retry buffer",
+            ))));
+        }
+        if let Some(rest) = state.rest.as_mut() {
+            let frame = match ready!(Pin::new(rest).poll_frame(cx)) {
+                Some(Ok(frame)) => frame,
+                Some(Err(status)) => {
+                    state.rest = None;
+                    state.overflowed = true;
+                    return Poll::Ready(Some(Err(status)));
+                }
+                None => {
+                    state.rest = None;
+                    return Poll::Ready(None);
+                }
+            };
+            let frame = match frame.into_data() {
+                Ok(data) => {
+                    if !state.overflowed && state.size + data.len() <= this.body.limit {
+                        state.size += data.len();
+                        state.data.push(data.clone());
+                    } else {
+                        state.overflowed = true;
+                    }
+                    state.pulled += 1;
+                    this.position += 1;
+                    Frame::data(data)
+                }
+                Err(frame) => {
+                    state.trailers = frame.trailers_ref().cloned();
+                    state.rest = None;
+                    this.trailers_sent = true;
+                    frame
+                }
+            };
+            return Poll::Ready(Some(Ok(frame)));
+        }
+        match &state.trailers {
+            Some(trailers) if !this.trailers_sent => {
+                this.trailers_sent = true;
+                Poll::Ready(Some(Ok(Frame::trailers(trailers.clone()))))
+            }
+            _ => Poll::Ready(None),
+        }
+    }
+}
+
+/// Tower service retrying requests and enforcing their timeout, according to the retry
+/// policy and maximum stream duration decided by the routing layer.
+///
+/// The timeout bounds the attempts until response headers are received. It is also sent to
+/// the server in the `grpc-timeout` header, which bounds the rest of the request.
+#[derive(Clone)]
+pub(crate) struct RetryService<S> {
+    inner: S,
+    /// The maximum number of bytes of a request body buffered for retries.
+    buffer_size: usize,
+}
+
+impl<S, B> Service<Request<TonicBody>> for RetryService<S>
+where
+    S: Service<Request<TonicBody>, Response = http::Response<B>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut request: Request<TonicBody>) -> Self::Future {
+        let (retry_policy, max_stream_duration) = request
+            .extensions()
+            .get::<RouteDecision>()
+            .map(|decision| {
+                let retry_policy = decision
+                    .retry_policy()
+                    .filter(|policy| !policy.retry_on.is_empty() && policy.num_retries > 0)
+                    .cloned();
+                (retry_policy, decision.max_stream_duration())
+            })
+            .unwrap_or_default();
+        // The timeout of the application is lowered to the maximum of the route, if any.
+        let timeout = max_stream_duration
+            .map(|max| grpc_timeout(request.headers()).map_or(max, |timeout| timeout.min(max)));
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let Some(retry_policy) = retry_policy else {
+            if let Some(timeout) = timeout {
+                set_grpc_timeout(request.headers_mut(), timeout);
+            }
+            let future = self.inner.call(request);
+            return Box::pin(with_deadline(deadline, async move {
+                future.await.map_err(Into::into)
+            }));
+        };
+
+        // Call the inner service that was polled ready, leaving a clone in its place.
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+        let buffer_size = self.buffer_size;
+        Box::pin(with_deadline(
+            deadline,
+            send_with_retries(inner, request, retry_policy, deadline, buffer_size),
+        ))
+    }
+}
+
+/// Fails `future` with `DEADLINE_EXCEEDED` if it does not complete before the deadline.
+async fn with_deadline<T>(
+    deadline: Option<Instant>,
+    future: impl std::future::Future<Output = Result<T, BoxError>>,
+) -> Result<T, BoxError> {
+    let Some(deadline) = deadline else {
+        return future.await;
+    };
+    match tokio::time::timeout_at(deadline, future).await {
+        Ok(result) => result,
+        Err(_) => Err(tonic::Status::deadline_exceeded("request timed out").into()),
+    }
+}
+
+/// Sends a request with `inner`, which is ready, retrying failed attempts as allowed by the
+/// retry policy.
+async fn send_with_retries<S, B>(
+    mut inner: S,
+    request: Request<TonicBody>,
+    retry_policy: RetryPolicy,
+    deadline: Option<Instant>,
+    buffer_size: usize,
+) -> Result<http::Response<B>, BoxError>
+where
+    S: Service<Request<TonicBody>, Response = http::Response<B>>,
+    S::Error: Into<BoxError>,
+{
+    let (parts, body) = request.into_parts();
+    let body = BufferedBody::new(body, buffer_size);
+    let max_attempts = retry_policy.num_retries.saturating_add(1).min(MAX_ATTEMPTS);
+    let mut backoff = retry_policy.base_interval;
+    let mut attempt = 1;
+    loop {
+        let mut request = Request::from_parts(clone_parts(&parts), body.replay());
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            set_grpc_timeout(request.headers_mut(), remaining);
+        }
+        let result = inner.call(request).await.map_err(Into::into);
+        // Responses with headers are committed, unless they carry the status of the call.
+        let (code, pushback) = match &result {
+            Ok(response) => match response.headers().get("grpc-status") {
+                Some(status) => (
+                    status.to_str().ok().and_then(|s| s.parse::<i32>().ok()),
+                    response.headers().get(RETRY_PUSHBACK_HEADER).cloned(),
+                ),
+                None => (None, None),
+            },
+            Err(error) => match find_status(error) {
+                Some(status) => (
+                    Some(status.code() as i32),
+                    status
+                        .metadata()
+                        .get(RETRY_PUSHBACK_HEADER)
+                        .and_then(|value| HeaderValue::from_bytes(value.as_bytes()).ok()),
+                ),
+                // Errors without a status are failures to reach the endpoint.
+                None => (Some(tonic::Code::Unavailable as i32), None),
+            },
+        };
+        let retryable = code.is_some_and(|code| {
+            code != 0 && retry_policy.retry_on.iter().any(|&c| c as i32 == code)
+        });
+        if !retryable || attempt >= max_attempts || !body.is_replayable() {
+            return result;
+        }
+        let delay = match pushback {
+            // A malformed pushback asks not to retry.
+            Some(value) => match value.to_str().ok().and_then(|v| v.parse::<u64>().ok()) {
+                Some(millis) => {
+                    backoff = retry_policy.base_interval;
+                    Duration::from_millis(millis)
+                }
+                None => return result,
+            },
+            None => {
+                let delay = backoff.mul_f64(rand::random::<f64>());
+                backoff = (backoff * 2).min(retry_policy.max_interval);
+                delay
+            }
+        };
+        drop(result);
+        tokio::time::sleep(delay).await;
+        std::future::poll_fn(|cx| inner.poll_ready(cx))
+            .await
+            .map_err(Into::into)?;
+        attempt += 1;
+    }
+}
+
+/// Copies the head of a request.
+fn clone_parts(parts: &http::request::Parts) -> http::request::Parts {
+    let mut request = Request::new(());
+    *request.method_mut() = parts.method.clone();
+    *request.uri_mut() = parts.uri.clone();
+    *request.version_mut() = parts.version;
+    *request.headers_mut() = parts.headers.clone();
+    *request.extensions_mut() = parts.extensions.clone();
+    request.into_parts().0
+}
+
+/// Finds the gRPC status of an error, if any.
+fn find_status(error: &BoxError) -> Option<&tonic::Status> {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error.as_ref());
+    while let Some(error) = source {
+        if let Some(status) = error.downcast_ref::<tonic::Status>() {
+            return Some(status);
+        }
+        source = error.source();
+    }
+    None
+}
+
+/// Parses the `grpc-timeout` header of a request.
+fn grpc_timeout(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(GRPC_TIMEOUT_HEADER)?.to_str().ok()?;
+    let (amount, unit) = value.split_at(value.len().checked_sub(1)?);
+    if amount.is_empty() || amount.len() > 8 {
+        return None;
+    }
+    let amount: u64 = amount.parse().ok()?;
+    Some(match unit {
+        "H" => Duration::from_secs(amount * 3600),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    })
+}
+
+/// Sets the `grpc-timeout` header of a request, which has at most 8 digits.
+fn set_grpc_timeout(headers: &mut HeaderMap, timeout: Duration) {
+    let millis = timeout.as_millis();
+    let value = if millis <= 99_999_999 {
+        format!("{millis}m")
+    } else {
+        format!("{}S", timeout.as_secs().min(99_999_999))
+    };
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        headers.insert(GRPC_TIMEOUT_HEADER, value);
+    }
+}
+
+/// Tower layer retrying requests and enforcing their timeout, after the routing layer.
+#[derive(Clone)]
+pub(crate) struct RetryLayer {
+    buffer_size: usize,
+}
+
+impl RetryLayer {
+    /// Creates a new `RetryLayer`, buffering up to `buffer_size` bytes of each request body.
+    pub(crate) fn new(buffer_size: usize) -> Self {
+        Self { buffer_size }
+    }
+}
+
+impl<S> Layer<S> for RetryLayer {
+    type Service = RetryService<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        RetryService {
+            inner: service,
+            buffer_size: self.buffer_size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tower::ServiceExt;
+
+    fn retry_policy(num_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            retry_on: vec![tonic::Code::Unavailable as u32],
+            num_retries,
+            base_interval: Duration::from_millis(100),
+            max_interval: Duration::from_secs(1),
+        }
+    }
+
+    fn request(decision: RouteDecision, body: &'static str) -> Request<TonicBody> {
+        let mut request = Request::builder()
+            .uri("/svc/Method")
+            .body(TonicBody::new(http_body_util::Full::new(Bytes::from(body))))
+            .unwrap();
+        request.extensions_mut().insert(decision);
+        request
+    }
+
+    fn grpc_response(code: tonic::Code) -> http::Response<()> {
+        http::Response::builder()
+            .header("grpc-status", (code as i32).to_string())
+            .body(())
+            .unwrap()
+    }
+
+    /// A service failing the first `failures` requests with `UNAVAILABLE`, and recording the
+    /// number of attempts and the bodies received.
+    #[derive(Clone)]
+    struct Flaky {
+        failures: u32,
+        attempts: Arc<AtomicU32>,
+        bodies: Arc<Mutex<Vec<Bytes>>>,
+    }
+
+    impl Flaky {
+        fn new(failures: u32) -> Self {
+            Self {
+                failures,
+                attempts: Arc::default(),
+                bodies: Arc::default(),
+            }
+        }
+
+        fn service(
+            &self,
+        ) -> impl Service<
+            Request<TonicBody>,
+            Response = http::Response<()>,
+            Error = BoxError,
+            Future = BoxFuture<Result<http::Response<()>, BoxError>>,
+        > + Clone {
+            let this = self.clone();
+            tower::service_fn(move |request: Request<TonicBody>| {
+                let this = this.clone();
+                let future: BoxFuture<_> = Box::pin(async move {
+                    let body = request.into_body().collect().await?.to_bytes();
+                    this.bodies.lock().unwrap().push(body);
+                    let attempt = this.attempts.fetch_add(1, Ordering::SeqCst) + 1;
+                    if attempt <= this.failures {
+                        Ok(grpc_response(tonic::Code::Unavailable))
+                    } else {
+                        Ok(http::Response::new(()))
+                    }
+                });
+                future
+            })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry() {
+        let decision = RouteDecision::cluster("c").with_limits(Some(retry_policy(3)), None);
+
+        // Failed attempts are retried with the same body, with an exponential backoff.
+        let flaky = Flaky::new(2);
+        let service = RetryLayer::new(DEFAULT_RETRY_BUFFER_SIZE).layer(flaky.service());
+        let start = Instant::now();
+        let response = service
+            .oneshot(request(decision.clone(), "hello"))
+            .await
+            .unwrap();
+        assert!(response.headers().get("grpc-status").is_none());
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 3);
+        assert!(flaky
+            .bodies
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|body| body == "hello"));
+        assert!(start.elapsed() <= Duration::from_millis(300));
+
+        // Requests fail once the retries are exhausted.
+        let flaky = Flaky::new(10);
+        let service = RetryLayer::new(DEFAULT_RETRY_BUFFER_SIZE).layer(flaky.service());
+        let response = service
+            .oneshot(request(decision.clone(), "hello"))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["grpc-status"], "14");
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 4);
+
+        // Bodies larger than the buffer are not retried.
+        let flaky = Flaky::new(1);
+        let service = RetryLayer::new(4).layer(flaky.service());
+        let response = service.oneshot(request(decision, "hello")).await.unwrap();
+        assert_eq!(response.headers()["grpc-status"], "14");
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 1);
+
+        // Statuses the policy does not retry on are returned at once.
+        let service = RetryLayer::new(DEFAULT_RETRY_BUFFER_SIZE).layer(tower::service_fn(
+            |_: Request<TonicBody>| async {
+                Err::<http::Response<()>, BoxError>(tonic::Status::internal("boom").into())
+            },
+        ));
+        let decision = RouteDecision::cluster("c").with_limits(Some(retry_policy(3)), None);
+        let error = service.oneshot(request(decision, "")).await.unwrap_err();
+        let status = error.downcast::<tonic::Status>().unwrap();
+        assert_eq!(status.code(), tonic::Code::Internal);
+    }
+
+    #[tokio::test]
+    async fn test_replay_after_overflow() {
+        let frames =
+            ["ab", "cd", "ef"].map(|data| Ok::<_, Infallible>(Frame::data(Bytes::from(data))));
+        let body = TonicBody::new(http_body_util::StreamBody::new(tokio_stream::iter(frames)));
+        let body = BufferedBody::new(body, 4);
+        let mut stale = body.replay();
+        let latest = body.replay();
+        assert_eq!(
+            stale.frame().await.unwrap().unwrap().into_data().unwrap(),
+            "ab"
+        );
+
+        // The latest attempt outgrows the buffer.
+        assert_eq!(latest.collect().await.unwrap().to_bytes(), "abcdef");
+        assert!(!body.is_replayable());
+
+        // The stale attempt cannot send the frames that were not buffered.
+        assert_eq!(
+            stale.frame().await.unwrap().unwrap().into_data().unwrap(),
+            "cd"
+        );
+        let status = stale.frame().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Internal);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timeout() {
+        let timeout = Some(Duration::from_secs(1));
+        let service = RetryLayer::new(DEFAULT_RETRY_BUFFER_SIZE).layer(tower::service_fn(
+            |request: Request<TonicBody>| async move {
+                let timeout = grpc_timeout(request.headers());
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                Ok::<_, BoxError>(http::Response::new(timeout))
+            },
+        ));
+
+        // The route timeout applies, and is sent to the server.
+        let decision = RouteDecision::cluster("c").with_limits(None, timeout);
+        let start = Instant::now();
+        let error = service
+            .clone()
+            .oneshot(request(decision.clone(), ""))
+            .await
+            .unwrap_err();
+        let status = error.downcast::<tonic::Status>().unwrap();
+        assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        // A shorter timeout of the application is kept.
+        let mut short = request(decision.clone(), "");
+        set_grpc_timeout(short.headers_mut(), Duration::from_millis(500));
+        let start = Instant::now();
+        assert!(service.clone().oneshot(short).await.is_err());
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+
+        // Requests of routes without a timeout are not limited.
+        let unlimited = request(RouteDecision::cluster("c"), "");
+        let response = service.oneshot(unlimited).await.unwrap();
+        assert_eq!(*response.body(), None);
+    }
+
+    #[test]
+    fn test_grpc_timeout() {
+        let mut headers = HeaderMap::new();
+        for (value, timeout) in [
+            ("1H", Some(Duration::from_secs(3600))),
+            ("2S", Some(Duration::from_secs(2))),
+            ("300m", Some(Duration::from_millis(300))),
+            ("5n", Some(Duration::from_nanos(5))),
+            ("123456789S", None),
+            ("S", None),
+            ("1x", None),
+        ] {
+            headers.insert(GRPC_TIMEOUT_HEADER, HeaderValue::from_static(value));
+            assert_eq!(grpc_timeout(&headers), timeout, "{value}");
+        }
+        set_grpc_timeout(&mut headers, Duration::from_secs(3));
+        assert_eq!(headers[GRPC_TIMEOUT_HEADER], "3000m");
+        set_grpc_timeout(&mut headers, Duration::from_secs(1_000_000_000));
+        assert_eq!(headers[GRPC_TIMEOUT_HEADER], "99999999S");
+    }
+}
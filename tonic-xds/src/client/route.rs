@@ -4,7 +4,7 @@ use crate::xds::xds_manager::XdsRouter;
 use http::Request;
 use std::sync::Arc;
 use std::task::{Context, Poll};
-use tower::{Layer, Service};
+use tower::{BoxError, Layer, Service};
 
 /// Tower service for routing requests to the appropriate cluster based on the xDS routing configurations.
 /// Attaches routing decision as `RoutingDecision` to the request extensions.
@@ -21,14 +21,15 @@ impl<S, B> Service<Request<B>> for XdsRoutingService<S>
 where
     S: Service<Request<B>> + Clone + Send + 'static,
     B: Send + 'static,
+    S::Error: Into<BoxError>,
     S::Future: Send + 'static,
 {
     type Response = S::Response;
-    type Error = S::Error;
+    type Error = BoxError;
     type Future = BoxFuture<Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.inner.poll_ready(cx)
+        self.inner.poll_ready(cx).map_err(Into::into)
     }
 
     fn call(&mut self, mut request: Request<B>) -> Self::Future {
@@ -39,25 +40,31 @@ where
                 .uri()
                 .authority()
                 .map_or("", http::uri::Authority::as_str);
+            let path = request.uri().path();
             let headers = &request.headers();
-            let route_input = RouteInput { authority, headers };
-            let route_decision = xds_router.route(&route_input).await;
+            let route_input = RouteInput {
+                authority,
+                path,
+                headers,
+            };
+            let mut route_decision = xds_router.route(&route_input).await?;
+            // The cluster is picked before the retry layer, so that retries go to the same
+            // cluster.
+            route_decision.pick_cluster();
             request.extensions_mut().insert(route_decision);
-            inner_service.call(request).await
+            inner_service.call(request).await.map_err(Into::into)
         })
     }
 }
 
 /// Tower layer for routing requests to the appropriate cluster based on the `RouteConfiguration`.
 #[derive(Clone)]
-#[allow(dead_code)]
 pub(crate) struct XdsRoutingLayer {
     xds_router: Arc<dyn XdsRouter>,
 }
 
 impl XdsRoutingLayer {
     /// Creates a new `XdsRoutingLayer` with the given `XdsRouter`.
-    #[allow(dead_code)]
     pub(crate) fn new(xds_router: Arc<dyn XdsRouter>) -> Self {
         Self { xds_router }
     }
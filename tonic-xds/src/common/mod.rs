@@ -1 +1,2 @@
 pub(crate) mod async_util;
+pub(crate) mod xxhash;
@@ -16,6 +16,7 @@
 //!
 //! - LDS / RDS / CDS / EDS subscriptions via ADS stream.
 //! - Client-side P2C load balancing
+//! - xDS-enabled servers ([`XdsServer`]), configured by LDS filter chains.
 //! - Other features will be added in future releases.
 //!
 //! ## Example
@@ -23,30 +24,39 @@
 //! ```rust,no_run
 //! use tonic_xds::{XdsChannelBuilder, XdsChannelConfig, XdsChannelGrpc, XdsUri};
 //!
+//! # async fn example() {
 //! let target_uri = XdsUri::parse(
 //!   "xds:///myservice:50051"
 //! ).expect("fail to parse valid target URI");
 //!
+//! // The xDS client configuration is read from the file named by `GRPC_XDS_BOOTSTRAP`.
 //! let xds_channel = XdsChannelBuilder::with_config(
 //!   XdsChannelConfig::default().with_target_uri(target_uri)
-//! ).build_grpc_channel();
+//! ).build_grpc_channel().expect("fail to build xDS channel");
 //!
 //! // Use with your generated gRPC client
 //! // let client = MyServiceClient::new(xds_channel);
 //! // client.my_rpc_method(...).await;
+//! # }
 //! ```
 //!
 //! ## How it works
 //!
 //! [`XdsChannelGrpc`] connects to an xDS management server and subscribes to resource updates for
-//! listeners, routes, clusters, and endpoints. Requests are automatically routed and load-balanced
+//! listeners, routes, clusters, and endpoints, starting from the listener of the target. Requests are automatically routed and load-balanced
 //! in stacked [`tower::Service`]s that implement the [gRPC xDS features](https://github.com/grpc/grpc/blob/master/doc/grpc_xds_features.md).
 
 pub(crate) mod client;
 pub(crate) mod common;
+pub(crate) mod server;
 pub(crate) mod xds;
 
-pub use client::channel::{XdsChannel, XdsChannelBuilder, XdsChannelConfig, XdsChannelGrpc};
+pub use client::channel::{
+    XdsChannel, XdsChannelBuilder, XdsChannelConfig, XdsChannelError, XdsChannelGrpc,
+};
+pub use server::xds_server::{
+    XdsServer, XdsServerBuilder, XdsServerConfig, XdsServerError, XdsServingMode,
+};
 pub use xds::uri::{XdsUri, XdsUriError};
 
 #[cfg(test)]
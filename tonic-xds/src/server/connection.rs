@@ -0,0 +1,192 @@
+//! Connections accepted by an xDS-enabled server.
+//!
+//! Each accepted connection is matched against the filter chains of the listener being
+//! served, and goes through the TLS handshake of its filter chain. Connections are closed
+//! right away while the server is not serving, and are closed when it stops serving.
+
+use crate::server::serving::{FilterChainConfig, ServingState};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::server::Connected;
+
+/// How long to wait before accepting connections again after failing to accept one, such as
+/// when the process runs out of file descriptors.
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+/// Information about a connection, inserted into the extensions of its requests.
+#[derive(Clone, Debug)]
+pub(crate) struct XdsConnectInfo {
+    /// The local address of the connection.
+    pub(crate) local_addr: SocketAddr,
+    /// The remote address of the connection.
+    pub(crate) remote_addr: SocketAddr,
+    /// The configuration of the filter chain of the connection.
+    pub(crate) filter_chain: Arc<FilterChainConfig>,
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+/// A connection accepted by an xDS-enabled server.
+pub(crate) struct ServerIo {
+    stream: Stream,
+    info: XdsConnectInfo,
+    /// Completes when the server stops serving.
+    stopped: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+impl ServerIo {
+    /// Returns an error once the server has stopped serving, so that the connection is
+    /// closed.
+    fn check_stopped(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        match self.stopped.as_mut().poll(cx) {
+            Poll::Ready(()) => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "the xDS server stopped serving",
+            )),
+            Poll::Pending => Ok(()),
+        }
+    }
+}
+
+impl Connected for ServerIo {
+    type ConnectInfo = XdsConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.info.clone()
+    }
+}
+
+impl AsyncRead for ServerIo {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.check_stopped(cx)?;
+        match &mut self.stream {
+            Stream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ServerIo {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.check_stopped(cx)?;
+        match &mut self.stream {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.stream {
+            Stream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.stream {
+            Stream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Accepts the connections of `listener` in the background, returning the stream of
+/// connections to serve.
+///
+/// Accepting stops when the stream is dropped.
+pub(crate) fn incoming(
+    listener: TcpListener,
+    local_addr: SocketAddr,
+    state: watch::Receiver<ServingState>,
+) -> ReceiverStream<io::Result<ServerIo>> {
+    let (tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        loop {
+            let accepted = tokio::select! {
+                () = tx.closed() => return,
+                accepted = listener.accept() => accepted,
+            };
+            let (stream, remote_addr) = match accepted {
+                Ok(accepted) => accepted,
+                Err(_) => {
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+            };
+            let filter_chain = match &*state.borrow() {
+                ServingState::Serving(config) => config.select(local_addr, remote_addr),
+                ServingState::Pending | ServingState::NotServing(_) => None,
+            };
+            // The connection is closed if it matches no filter chain.
+            let Some(filter_chain) = filter_chain else {
+                continue;
+            };
+            let info = XdsConnectInfo {
+                local_addr,
+                remote_addr,
+                filter_chain,
+            };
+            tokio::spawn(handshake(stream, info, state.clone(), tx.clone()));
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+/// Performs the TLS handshake of a connection if its filter chain uses TLS, and sends it to
+/// be served.
+async fn handshake(
+    stream: TcpStream,
+    info: XdsConnectInfo,
+    mut state: watch::Receiver<ServingState>,
+    connections: mpsc::Sender<io::Result<ServerIo>>,
+) {
+    let config = match &info.filter_chain.tls {
+        None => None,
+        Some(tls) => match &*tls.borrow() {
+            Ok(config) => Some(config.clone()),
+            // The TLS configuration could not be loaded, so the connection cannot be secured.
+            Err(_) => return,
+        },
+    };
+    let stream = match config {
+        None => Stream::Tcp(stream),
+        Some(config) => match TlsAcceptor::from(config).accept(stream).await {
+            Ok(stream) => Stream::Tls(Box::new(stream)),
+            Err(_) => return,
+        },
+    };
+    let stopped = Box::pin(async move {
+        let _ = state
+            .wait_for(|state| matches!(state, ServingState::NotServing(_)))
+            .await;
+    });
+    let _ = connections
+        .send(Ok(ServerIo {
+            stream,
+            info,
+            stopped,
+        }))
+        .await;
+}
@@ -0,0 +1,5 @@
+pub(crate) mod connection;
+pub(crate) mod service;
+pub(crate) mod serving;
+pub(crate) mod tls;
+pub(crate) mod xds_server;
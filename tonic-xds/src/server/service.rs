@@ -0,0 +1,87 @@
+//! Routing of the requests received by an xDS-enabled server.
+
+use crate::common::async_util::BoxFuture;
+use crate::server::connection::XdsConnectInfo;
+use crate::server::serving::RouteState;
+use crate::xds::route::{RouteInput, RoutingError};
+use http::{Request, Response};
+use std::task::{Context, Poll};
+use tonic::body::Body;
+use tonic::transport::server::TcpConnectInfo;
+use tonic::Status;
+use tower::{Layer, Service};
+
+/// Tower service checking that requests match a route of the filter chain of their
+/// connection before passing them to the inner service.
+///
+/// Requests that do not match a non-forwarding route fail with `UNAVAILABLE`.
+#[derive(Clone, Debug)]
+pub(crate) struct XdsServerService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for XdsServerService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        if let Err(error) = check_route(&mut request) {
+            let status = Status::unavailable(error.to_string());
+            return Box::pin(async move { Ok(status.into_http()) });
+        }
+        Box::pin(self.inner.call(request))
+    }
+}
+
+/// Checks the route of a request with the routes of the filter chain of its connection.
+fn check_route(request: &mut Request<Body>) -> Result<(), RoutingError> {
+    let Some(info) = request.extensions().get::<XdsConnectInfo>().cloned() else {
+        return Err(RoutingError::Unavailable(
+            "request was not received by an xDS server".to_string(),
+        ));
+    };
+    // Lets services find the addresses of the connection with `Request::remote_addr`.
+    request.extensions_mut().insert(TcpConnectInfo {
+        local_addr: Some(info.local_addr),
+        remote_addr: Some(info.remote_addr),
+    });
+    let authority = request
+        .uri()
+        .authority()
+        .map_or("", http::uri::Authority::as_str);
+    let input = RouteInput {
+        authority,
+        path: request.uri().path(),
+        headers: request.headers(),
+    };
+    let routes = info.filter_chain.routes.borrow();
+    match &*routes {
+        RouteState::Ready(table) => table.check_server_route(authority, &input),
+        RouteState::Failed(error) => Err(RoutingError::Unavailable(error.clone())),
+        RouteState::Pending => Err(RoutingError::Unavailable(format!(
+            "no route configuration received for filter chain {}",
+            info.filter_chain.name
+        ))),
+    }
+}
+
+/// Tower layer applying [`XdsServerService`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct XdsServerLayer;
+
+impl<S> Layer<S> for XdsServerLayer {
+    type Service = XdsServerService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        XdsServerService { inner }
+    }
+}
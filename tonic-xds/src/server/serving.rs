@@ -0,0 +1,256 @@
+//! Watches the listener of an xDS-enabled server and the route configurations it names,
+//! deciding whether the server is serving and with which filter chains.
+//!
+//! A listener is only served once all of its route configurations have been received.
+//! Until then, the previous listener, if any, keeps being served.
+
+use crate::server::tls;
+use crate::xds::filter_chain::select_filter_chain;
+use crate::xds::route::RouteTable;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tokio_rustls::rustls::ServerConfig;
+use xds_client::resource::prost::filter_chain::{FilterChain, ServerListener};
+use xds_client::resource::prost::listener::{HttpFilterConfig, RouteSource};
+use xds_client::{
+    CertificateProviderConfig, Listener, ResourceEvent, RouteConfiguration, XdsClient,
+};
+
+/// The routes of a filter chain.
+#[derive(Debug)]
+pub(crate) enum RouteState {
+    /// The route configuration has not been received yet.
+    Pending,
+    /// The current route configuration.
+    Ready(Arc<RouteTable>),
+    /// The route configuration could not be obtained.
+    Failed(String),
+}
+
+/// The configuration of the connections of a filter chain.
+#[derive(Debug)]
+pub(crate) struct FilterChainConfig {
+    /// The filter chain name.
+    pub(crate) name: String,
+    /// The routes, updated when the route configuration changes.
+    pub(crate) routes: watch::Receiver<RouteState>,
+    /// The TLS configuration if connections use TLS, or the reason it could not be loaded,
+    /// updated when the certificates are reloaded.
+    pub(crate) tls: Option<watch::Receiver<Result<Arc<ServerConfig>, String>>>,
+}
+
+/// The filter chains of a listener being served.
+#[derive(Debug)]
+pub(crate) struct ServingConfig {
+    filter_chains: Vec<FilterChain>,
+    configs: Vec<Arc<FilterChainConfig>>,
+    default_config: Option<Arc<FilterChainConfig>>,
+}
+
+impl ServingConfig {
+    fn new(
+        listener: ServerListener,
+        rds: &HashMap<String, watch::Receiver<RouteState>>,
+        certificate_providers: &HashMap<String, CertificateProviderConfig>,
+    ) -> Self {
+        let config = |filter_chain: &FilterChain| {
+            let routes = match &filter_chain.http_connection_manager.route_config {
+                RouteSource::Rds(name) => rds[name].clone(),
+                RouteSource::Inline(config) => {
+                    let table = RouteTable::new(
+                        Arc::new(config.clone()),
+                        &filter_chain.http_connection_manager.http_filters,
+                    );
+                    watch::channel(RouteState::Ready(Arc::new(table))).1
+                }
+            };
+            Arc::new(FilterChainConfig {
+                name: filter_chain.name.clone(),
+                routes,
+                tls: filter_chain
+                    .tls
+                    .as_ref()
+                    .map(|tls| tls::watch_server_config(tls, certificate_providers)),
+            })
+        };
+        Self {
+            configs: listener.filter_chains.iter().map(config).collect(),
+            default_config: listener.default_filter_chain.as_ref().map(config),
+            filter_chains: listener.filter_chains,
+        }
+    }
+
+    /// Returns the configuration of a connection from `remote` to `local`, or `None` if the
+    /// connection matches no filter chain and must be closed.
+    pub(crate) fn select(
+        &self,
+        local: SocketAddr,
+        remote: SocketAddr,
+    ) -> Option<Arc<FilterChainConfig>> {
+        match select_filter_chain(&self.filter_chains, local, remote) {
+            Some(index) => Some(self.configs[index].clone()),
+            None => self.default_config.clone(),
+        }
+    }
+}
+
+/// Whether an xDS-enabled server is serving.
+#[derive(Clone, Debug)]
+pub(crate) enum ServingState {
+    /// No listener has been received yet.
+    Pending,
+    /// Connections are accepted with the configuration of the listener.
+    Serving(Arc<ServingConfig>),
+    /// Connections are closed, as there is no valid listener.
+    NotServing(String),
+}
+
+/// Watches the listener `listener_name` of a server listening on `local_addr`, until
+/// `state` is closed.
+pub(crate) async fn watch_listener(
+    client: XdsClient,
+    listener_name: String,
+    local_addr: SocketAddr,
+    state: watch::Sender<ServingState>,
+) {
+    let certificate_providers = client.config().certificate_providers.clone();
+    let mut listener = client.watch::<Listener>(listener_name);
+    // The route configurations of the pending and served listeners, keyed by name.
+    let mut rds: HashMap<String, watch::Receiver<RouteState>> = HashMap::new();
+    let (rds_updated_tx, mut rds_updated) = mpsc::unbounded_channel();
+    // The listener waiting for its route configurations.
+    let mut pending: Option<ServerListener> = None;
+    let mut served_rds_names = HashSet::new();
+    loop {
+        tokio::select! {
+            () = state.closed() => return,
+            Some(event) = listener.next() => match event {
+                ResourceEvent::ResourceChanged { resource, .. } => {
+                    match server_listener(&resource, local_addr) {
+                        Ok(server) => {
+                            for name in rds_names(&server) {
+                                rds.entry(name.to_string()).or_insert_with(|| {
+                                    let (tx, rx) = watch::channel(RouteState::Pending);
+                                    tokio::spawn(watch_routes(
+                                        client.clone(),
+                                        name.to_string(),
+                                        tx,
+                                        rds_updated_tx.clone(),
+                                    ));
+                                    rx
+                                });
+                            }
+                            pending = Some(server);
+                        }
+                        Err(error) => {
+                            pending = None;
+                            served_rds_names.clear();
+                            state.send_replace(ServingState::NotServing(error));
+                        }
+                    }
+                }
+                ResourceEvent::ResourceError { error, .. } => {
+                    pending = None;
+                    served_rds_names.clear();
+                    state.send_replace(ServingState::NotServing(error.to_string()));
+                }
+                ResourceEvent::AmbientError { .. } => {}
+            },
+            Some(()) = rds_updated.recv() => {}
+        }
+
+        if let Some(server) = pending.take() {
+            let ready =
+                rds_names(&server).all(|name| !matches!(*rds[name].borrow(), RouteState::Pending));
+            if ready {
+                served_rds_names = rds_names(&server).map(str::to_string).collect();
+                let config = ServingConfig::new(server, &rds, &certificate_providers);
+                state.send_replace(ServingState::Serving(Arc::new(config)));
+            } else {
+                pending = Some(server);
+            }
+        }
+        // Route configurations stay watched while connections use them.
+        rds.retain(|name, _| {
+            served_rds_names.contains(name)
+                || pending
+                    .as_ref()
+                    .is_some_and(|server| rds_names(server).any(|n| n == name))
+        });
+    }
+}
+
+/// Returns the server listener of `listener`, which must listen on `local_addr`.
+fn server_listener(listener: &Listener, local_addr: SocketAddr) -> Result<ServerListener, String> {
+    let server = listener
+        .server_listener()
+        .ok_or_else(|| format!("listener {} is not a server listener", listener.name))?;
+    if server.address != local_addr {
+        return Err(format!(
+            "listener {} has address {}, expected {local_addr}",
+            listener.name, server.address
+        ));
+    }
+    // Servers only support the router filter, so the route configurations fetched over RDS
+    // do not depend on the HTTP filters of the filter chains using them.
+    let filter_chains = server
+        .filter_chains
+        .iter()
+        .chain(&server.default_filter_chain);
+    for filter_chain in filter_chains {
+        let http_filters = &filter_chain.http_connection_manager.http_filters;
+        if let Some(filter) = http_filters
+            .iter()
+            .find(|filter| filter.config != HttpFilterConfig::Router)
+        {
+            return Err(format!(
+                "filter chain {} of listener {} has unsupported http filter {}",
+                filter_chain.name, listener.name, filter.name
+            ));
+        }
+    }
+    Ok(server.clone())
+}
+
+/// Returns the names of the route configurations fetched over RDS by a listener.
+fn rds_names(listener: &ServerListener) -> impl Iterator<Item = &str> {
+    listener
+        .filter_chains
+        .iter()
+        .chain(&listener.default_filter_chain)
+        .filter_map(
+            |filter_chain| match &filter_chain.http_connection_manager.route_config {
+                RouteSource::Rds(name) => Some(name.as_str()),
+                RouteSource::Inline(_) => None,
+            },
+        )
+}
+
+/// Watches a route configuration until `routes` is closed, notifying `updated` of changes.
+async fn watch_routes(
+    client: XdsClient,
+    name: String,
+    routes: watch::Sender<RouteState>,
+    updated: mpsc::UnboundedSender<()>,
+) {
+    let mut watcher = client.watch::<RouteConfiguration>(name);
+    loop {
+        let state = tokio::select! {
+            () = routes.closed() => return,
+            event = watcher.next() => match event {
+                Some(ResourceEvent::ResourceChanged { resource, .. }) => {
+                    RouteState::Ready(Arc::new(RouteTable::new(resource, &[])))
+                }
+                Some(ResourceEvent::ResourceError { error, .. }) => {
+                    RouteState::Failed(error.to_string())
+                }
+                Some(ResourceEvent::AmbientError { .. }) => continue,
+                None => return,
+            },
+        };
+        routes.send_replace(state);
+        let _ = updated.send(());
+    }
+}
@@ -0,0 +1,255 @@
+//! TLS configuration of filter chains, loaded from the certificate providers of the
+//! bootstrap configuration, see
+//! [gRFC A29](https://github.com/grpc/proposal/blob/master/A29-xds-tls-security.md).
+//!
+//! Only the `file_watcher` plugin is supported. Its files are read when the listener is
+//! received, and read again on the `refresh_interval` of the plugin instances. If they can
+//! no longer be read, the certificates previously read keep being used.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{crypto, RootCertStore, ServerConfig};
+use xds_client::resource::prost::filter_chain::{CertificateProviderInstance, DownstreamTls};
+use xds_client::{CertificateProviderConfig, Value};
+
+/// The name of the certificate provider plugin reading certificates from files.
+const FILE_WATCHER_PLUGIN: &str = "file_watcher";
+
+/// How often the files of a `file_watcher` plugin instance are read, unless it configures a
+/// `refresh_interval`.
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Builds the TLS configuration of a filter chain, and rebuilds it in the background on the
+/// refresh interval of its certificate providers until the returned receiver is dropped.
+pub(crate) fn watch_server_config(
+    tls: &DownstreamTls,
+    providers: &HashMap<String, CertificateProviderConfig>,
+) -> watch::Receiver<Result<Arc<ServerConfig>, String>> {
+    let (config, rx) = watch::channel(server_config(tls, providers));
+    let interval = std::iter::once(&tls.identity_certificate)
+        .chain(&tls.root_certificate)
+        .map(|instance| refresh_interval(instance, providers))
+        .min()
+        .unwrap_or(DEFAULT_REFRESH_INTERVAL);
+    let (tls, providers) = (tls.clone(), providers.clone());
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                () = config.closed() => return,
+                () = tokio::time::sleep(interval) => {}
+            }
+            match server_config(&tls, &providers) {
+                Ok(reloaded) => {
+                    let _ = config.send_replace(Ok(reloaded));
+                }
+                Err(error) => {
+                    config.send_if_modified(|current| match current {
+                        Ok(_) => false,
+                        Err(_) => {
+                            *current = Err(error);
+                            true
+                        }
+                    });
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Builds the TLS configuration of a filter chain.
+fn server_config(
+    tls: &DownstreamTls,
+    providers: &HashMap<String, CertificateProviderConfig>,
+) -> Result<Arc<ServerConfig>, String> {
+    let identity = provider_file_paths(&tls.identity_certificate, providers)?;
+    let (Some(certificate_file), Some(private_key_file)) = (
+        identity.get("certificate_file"),
+        identity.get("private_key_file"),
+    ) else {
+        return Err(format!(
+            "certificate provider {} has no identity certificate",
+            tls.identity_certificate.instance_name
+        ));
+    };
+    let certificates = CertificateDer::pem_file_iter(certificate_file)
+        .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("failed to read {certificate_file}: {e}"))?;
+    let private_key = PrivateKeyDer::from_pem_file(private_key_file)
+        .map_err(|e| format!("failed to read {private_key_file}: {e}"))?;
+
+    let provider = Arc::new(crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+    let builder = match &tls.root_certificate {
+        Some(instance) => {
+            let roots = provider_file_paths(instance, providers)?;
+            let Some(ca_certificate_file) = roots.get("ca_certificate_file") else {
+                return Err(format!(
+                    "certificate provider {} has no root certificates",
+                    instance.instance_name
+                ));
+            };
+            let mut root_store = RootCertStore::empty();
+            for certificate in CertificateDer::pem_file_iter(ca_certificate_file)
+                .map_err(|e| format!("failed to read {ca_certificate_file}: {e}"))?
+            {
+                let certificate = certificate
+                    .map_err(|e| format!("failed to read {ca_certificate_file}: {e}"))?;
+                root_store
+                    .add(certificate)
+                    .map_err(|e| format!("invalid root certificate: {e}"))?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(root_store), provider);
+            let verifier = if tls.require_client_certificate {
+                verifier.build()
+            } else {
+                verifier.allow_unauthenticated().build()
+            }
+            .map_err(|e| e.to_string())?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder
+        .with_single_cert(certificates, private_key)
+        .map_err(|e| format!("invalid identity certificate: {e}"))?;
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// Returns how often the files of a certificate provider are read.
+///
+/// The interval is a JSON duration, such as `"600s"`.
+fn refresh_interval(
+    instance: &CertificateProviderInstance,
+    providers: &HashMap<String, CertificateProviderConfig>,
+) -> Duration {
+    let Some(Some(Value::Struct(fields))) = providers
+        .get(&instance.instance_name)
+        .map(|provider| &provider.config)
+    else {
+        return DEFAULT_REFRESH_INTERVAL;
+    };
+    match fields.get("refresh_interval") {
+        Some(Value::String(interval)) => interval
+            .strip_suffix('s')
+            .and_then(|seconds| seconds.parse::<f64>().ok())
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .filter(|interval| !interval.is_zero())
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL),
+        _ => DEFAULT_REFRESH_INTERVAL,
+    }
+}
+
+/// Returns the file paths of the configuration of a `file_watcher` certificate provider.
+fn provider_file_paths<'a>(
+    instance: &CertificateProviderInstance,
+    providers: &'a HashMap<String, CertificateProviderConfig>,
+) -> Result<HashMap<&'a str, &'a str>, String> {
+    let provider = providers.get(&instance.instance_name).ok_or_else(|| {
+        format!(
+            "unknown certificate provider instance {}",
+            instance.instance_name
+        )
+    })?;
+    if provider.plugin_name != FILE_WATCHER_PLUGIN {
+        return Err(format!(
+            "unsupported certificate provider plugin {}",
+            provider.plugin_name
+        ));
+    }
+    let Some(Value::Struct(fields)) = &provider.config else {
+        return Ok(HashMap::new());
+    };
+    Ok(fields
+        .iter()
+        .filter_map(|(name, value)| match value {
+            Value::String(path) => Some((name.as_str(), path.as_str())),
+            _ => None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    const TLS_DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/data/tls");
+
+    fn file_watcher(fields: &[(&str, &str)]) -> HashMap<String, CertificateProviderConfig> {
+        let fields: BTreeMap<_, _> = fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), Value::String(value.to_string())))
+            .collect();
+        let config = CertificateProviderConfig {
+            plugin_name: FILE_WATCHER_PLUGIN.to_string(),
+            config: Some(Value::Struct(fields)),
+        };
+        HashMap::from([("default".to_string(), config)])
+    }
+
+    fn downstream_tls() -> DownstreamTls {
+        DownstreamTls {
+            identity_certificate: CertificateProviderInstance {
+                instance_name: "default".to_string(),
+                certificate_name: String::new(),
+            },
+            root_certificate: None,
+            require_client_certificate: false,
+        }
+    }
+
+    #[test]
+    fn test_refresh_interval() {
+        let instance = downstream_tls().identity_certificate;
+        let interval =
+            |value| refresh_interval(&instance, &file_watcher(&[("refresh_interval", value)]));
+        assert_eq!(interval("60s"), Duration::from_secs(60));
+        assert_eq!(interval("1.5s"), Duration::from_millis(1500));
+        assert_eq!(interval("0s"), DEFAULT_REFRESH_INTERVAL);
+        assert_eq!(interval("1m"), DEFAULT_REFRESH_INTERVAL);
+        assert_eq!(
+            refresh_interval(&instance, &HashMap::new()),
+            DEFAULT_REFRESH_INTERVAL
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    /// Tests that certificates are read again on the refresh interval, and that the
+    /// certificates previously read are kept when the files can no longer be read.
+    async fn test_certificates_reloaded() {
+        let dir = std::env::temp_dir().join(format!("tonic-xds-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| -> PathBuf { dir.join(name) };
+        let (certificate_file, private_key_file) = (path("server.pem"), path("server.key"));
+        let providers = file_watcher(&[
+            ("certificate_file", certificate_file.to_str().unwrap()),
+            ("private_key_file", private_key_file.to_str().unwrap()),
+            ("refresh_interval", "60s"),
+        ]);
+
+        let mut config = watch_server_config(&downstream_tls(), &providers);
+        assert!(config.borrow_and_update().is_err());
+
+        std::fs::copy(format!("{TLS_DATA}/server.pem"), &certificate_file).unwrap();
+        std::fs::copy(format!("{TLS_DATA}/server.key"), &private_key_file).unwrap();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert!(!config.has_changed().unwrap());
+        config.changed().await.unwrap();
+        let loaded = config.borrow_and_update().clone().unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        tokio::time::sleep(Duration::from_secs(120)).await;
+        assert!(Arc::ptr_eq(config.borrow().as_ref().unwrap(), &loaded));
+    }
+}
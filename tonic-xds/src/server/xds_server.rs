@@ -0,0 +1,363 @@
+use crate::server::connection::incoming;
+use crate::server::service::XdsServerLayer;
+use crate::server::serving::{watch_listener, ServingState};
+use std::future::{self, Future};
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tonic::service::Routes;
+use tonic::transport::Server;
+use xds_client::{ClientConfig, ProstCodec, TokioRuntime, TonicTransportBuilder, XdsClient};
+
+/// Configuration for building an [`XdsServer`].
+///
+/// Specifies, optionally, the configuration of the xDS client used to reach the xDS
+/// management server.
+#[derive(Clone, Debug, Default)]
+pub struct XdsServerConfig {
+    xds_client_config: Option<ClientConfig>,
+    xds_client: Option<XdsClient>,
+}
+
+impl XdsServerConfig {
+    /// Sets the configuration of the xDS client.
+    ///
+    /// If not set, the configuration is read from the bootstrap file named by the
+    /// `GRPC_XDS_BOOTSTRAP` environment variable, see [`ClientConfig::from_env`].
+    /// It must have a
+    /// [`server_listener_resource_name_template`](ClientConfig::server_listener_resource_name_template).
+    #[must_use]
+    pub fn with_xds_client_config(mut self, config: ClientConfig) -> Self {
+        self.xds_client_config = Some(config);
+        self
+    }
+
+    /// Sets the xDS client used to reach the xDS management server, so that it can be
+    /// shared with channels or with a [`CsdsService`](xds_client::CsdsService) reporting its
+    /// resources.
+    ///
+    /// The configuration of the client takes precedence over the one set by
+    /// [`with_xds_client_config`](Self::with_xds_client_config).
+    #[must_use]
+    pub fn with_xds_client(mut self, client: XdsClient) -> Self {
+        self.xds_client = Some(client);
+        self
+    }
+}
+
+/// Errors that can occur when building or running an [`XdsServer`].
+#[derive(Debug, thiserror::Error)]
+pub enum XdsServerError {
+    /// The xDS client could not be configured.
+    #[error("invalid xDS client configuration: {0}")]
+    XdsClient(#[from] xds_client::Error),
+    /// The xDS client configuration has no server listener resource name template.
+    #[error("no server_listener_resource_name_template in the xDS client configuration")]
+    MissingListenerTemplate,
+    /// The server address could not be bound.
+    #[error("failed to bind the server address: {0}")]
+    Bind(#[from] std::io::Error),
+    /// The server failed while serving.
+    #[error("server error: {0}")]
+    Transport(#[from] tonic::transport::Error),
+}
+
+/// Whether an [`XdsServer`] accepts connections.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum XdsServingMode {
+    /// The listener of the server has not been received yet. Connections are closed.
+    Starting,
+    /// Connections are accepted with the configuration of the listener.
+    Serving,
+    /// The listener of the server is invalid or does not exist. Connections are closed.
+    NotServing(String),
+}
+
+impl From<&ServingState> for XdsServingMode {
+    fn from(state: &ServingState) -> Self {
+        match state {
+            ServingState::Pending => Self::Starting,
+            ServingState::Serving(_) => Self::Serving,
+            ServingState::NotServing(error) => Self::NotServing(error.clone()),
+        }
+    }
+}
+
+/// Builder for creating an [`XdsServer`].
+#[derive(Clone, Debug)]
+pub struct XdsServerBuilder {
+    config: XdsServerConfig,
+}
+
+impl XdsServerBuilder {
+    /// Create a builder from a server configuration.
+    #[must_use]
+    pub fn with_config(config: XdsServerConfig) -> Self {
+        Self { config }
+    }
+
+    /// Binds the server to `addr`, and starts watching its listener resource.
+    ///
+    /// The name of the listener resource is the
+    /// [`server_listener_resource_name_template`](ClientConfig::server_listener_resource_name_template)
+    /// of the xDS client configuration, with `%s` replaced by the bound address, such as
+    /// `0.0.0.0:50051` or `[::]:50051`.
+    ///
+    /// This must be called from within a Tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the xDS client configuration cannot be loaded or has no server
+    /// listener resource name template, or if the address cannot be bound.
+    pub async fn bind(self, addr: SocketAddr) -> Result<XdsServer, XdsServerError> {
+        let client = match self.config.xds_client {
+            Some(client) => client,
+            None => {
+                let config = match self.config.xds_client_config {
+                    Some(config) => config,
+                    None => ClientConfig::from_env()?,
+                };
+                XdsClient::builder(config)
+                    .build(TonicTransportBuilder, ProstCodec, TokioRuntime)
+                    .await?
+            }
+        };
+        let template = client
+            .config()
+            .server_listener_resource_name_template
+            .as_deref()
+            .ok_or(XdsServerError::MissingListenerTemplate)?;
+
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let listener_name = template.replace("%s", &local_addr.to_string());
+        let (state_tx, state_rx) = watch::channel(ServingState::Pending);
+        tokio::spawn(watch_listener(
+            client,
+            listener_name.clone(),
+            local_addr,
+            state_tx,
+        ));
+        Ok(XdsServer {
+            listener,
+            local_addr,
+            listener_name,
+            state: state_rx,
+        })
+    }
+}
+
+/// A gRPC server configured by xDS, following
+/// [gRFC A36](https://github.com/grpc/proposal/blob/master/A36-xds-for-servers.md).
+///
+/// The server watches its listener resource on the xDS management server. Each connection
+/// is served with the filter chain of the listener that matches its addresses, which sets
+/// its TLS configuration and its routes. Requests must match a route with a non-forwarding
+/// action, and otherwise fail with `UNAVAILABLE`.
+///
+/// Connections are refused until a valid listener and its route configurations have been
+/// received, and while the listener is invalid or does not exist. When the server stops
+/// serving, its connections are closed.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use tonic::service::Routes;
+/// use tonic_xds::{XdsServerBuilder, XdsServerConfig};
+///
+/// # async fn example(routes: Routes) -> Result<(), tonic_xds::XdsServerError> {
+/// // The xDS client configuration is read from the file named by `GRPC_XDS_BOOTSTRAP`.
+/// let server = XdsServerBuilder::with_config(XdsServerConfig::default())
+///     .bind("0.0.0.0:50051".parse().unwrap())
+///     .await?;
+/// server.serve(routes).await
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct XdsServer {
+    listener: TcpListener,
+    local_addr: SocketAddr,
+    listener_name: String,
+    state: watch::Receiver<ServingState>,
+}
+
+impl XdsServer {
+    /// Returns the address the server is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Returns the name of the listener resource of the server.
+    pub fn listener_name(&self) -> &str {
+        &self.listener_name
+    }
+
+    /// Returns whether the server currently accepts connections.
+    pub fn serving_mode(&self) -> XdsServingMode {
+        XdsServingMode::from(&*self.state.borrow())
+    }
+
+    /// Waits until the serving mode changes, and returns the new mode.
+    pub async fn serving_mode_changed(&mut self) -> XdsServingMode {
+        // The sender lives as long as the server.
+        let _ = self.state.changed().await;
+        XdsServingMode::from(&*self.state.borrow_and_update())
+    }
+
+    /// Serves `routes` until the server fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying tonic server fails.
+    pub async fn serve(self, routes: Routes) -> Result<(), XdsServerError> {
+        self.serve_with_shutdown(routes, future::pending()).await
+    }
+
+    /// Serves `routes` until `signal` completes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying tonic server fails.
+    pub async fn serve_with_shutdown(
+        self,
+        routes: Routes,
+        signal: impl Future<Output = ()>,
+    ) -> Result<(), XdsServerError> {
+        let incoming = incoming(self.listener, self.local_addr, self.state);
+        Server::builder()
+            .layer(XdsServerLayer)
+            .add_routes(routes)
+            .serve_with_incoming_shutdown(incoming, signal)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::grpc::{greeter_routes, GreeterClient, HelloRequest};
+    use crate::testutil::xds::{
+        server_listener, server_route_configuration, spawn_xds_server, TestXdsServer,
+    };
+    use std::time::Duration;
+    use tonic::transport::Endpoint;
+    use xds_client::ServerConfig;
+
+    const TEMPLATE: &str = "grpc/server?xds.resource.listening_address=%s";
+
+    /// Returns a local address with a port that is free, as far as the OS knows.
+    fn free_addr() -> SocketAddr {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    /// Binds an xDS-enabled server to a free address, whose listener resource has address
+    /// `listener_addr` if set, and accepts requests with paths starting with `prefix`.
+    /// Waits until the server leaves the starting mode.
+    async fn start(listener_addr: Option<SocketAddr>, prefix: &str) -> (TestXdsServer, XdsServer) {
+        let addr = free_addr();
+        let listener_name = TEMPLATE.replace("%s", &addr.to_string());
+        let xds_server = spawn_xds_server(vec![
+            server_listener(&listener_name, listener_addr.unwrap_or(addr), "route-1"),
+            server_route_configuration("route-1", prefix),
+        ])
+        .await
+        .expect("Failed to spawn xDS server");
+
+        let mut client_config = ClientConfig::default()
+            .with_servers(vec![ServerConfig::new(xds_server.addr.to_string())]);
+        client_config.server_listener_resource_name_template = Some(TEMPLATE.to_string());
+        let mut server = XdsServerBuilder::with_config(
+            XdsServerConfig::default().with_xds_client_config(client_config),
+        )
+        .bind(addr)
+        .await
+        .expect("Failed to bind xDS server");
+        assert_eq!(server.listener_name(), listener_name);
+        assert_eq!(server.serving_mode(), XdsServingMode::Starting);
+        tokio::time::timeout(Duration::from_secs(10), server.serving_mode_changed())
+            .await
+            .expect("timed out waiting for the serving mode");
+        (xds_server, server)
+    }
+
+    async fn say_hello(addr: SocketAddr) -> Result<String, tonic::Status> {
+        let channel = Endpoint::from_shared(format!("http://{addr}"))
+            .unwrap()
+            .connect_lazy();
+        let response = GreeterClient::new(channel)
+            .say_hello(HelloRequest {
+                name: "test".to_string(),
+            })
+            .await?;
+        Ok(response.into_inner().message)
+    }
+
+    #[tokio::test]
+    /// Tests that a server serves requests once its listener and routes are received.
+    async fn test_xds_server_serving() {
+        let (xds_server, server) = start(None, "/helloworld.Greeter/SayHello").await;
+        assert_eq!(server.serving_mode(), XdsServingMode::Serving);
+
+        let addr = server.local_addr();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let handle = tokio::spawn(server.serve_with_shutdown(greeter_routes("server"), async {
+            let _ = shutdown_rx.await;
+        }));
+        assert_eq!(say_hello(addr).await.unwrap(), "server: test");
+
+        let _ = shutdown_tx.send(());
+        handle.await.unwrap().unwrap();
+        let _ = xds_server.shutdown.send(());
+        let _ = xds_server.handle.await;
+    }
+
+    #[tokio::test]
+    /// Tests that requests matching no non-forwarding route are rejected.
+    async fn test_xds_server_rejects_unrouted_requests() {
+        let (xds_server, server) = start(None, "/other.Service/").await;
+        assert_eq!(server.serving_mode(), XdsServingMode::Serving);
+
+        let addr = server.local_addr();
+        let handle = tokio::spawn(server.serve(greeter_routes("server")));
+        let status = say_hello(addr).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+
+        handle.abort();
+        let _ = xds_server.shutdown.send(());
+        let _ = xds_server.handle.await;
+    }
+
+    #[tokio::test]
+    /// Tests that a server does not serve when its listener has another address.
+    async fn test_xds_server_not_serving() {
+        let (xds_server, server) = start(Some(free_addr()), "").await;
+        let mode = server.serving_mode();
+        assert!(matches!(mode, XdsServingMode::NotServing(_)), "{mode:?}");
+
+        let addr = server.local_addr();
+        let handle = tokio::spawn(server.serve(greeter_routes("server")));
+        assert!(say_hello(addr).await.is_err());
+
+        handle.abort();
+        let _ = xds_server.shutdown.send(());
+        let _ = xds_server.handle.await;
+    }
+
+    #[tokio::test]
+    async fn test_bind_without_listener_template() {
+        let config = ClientConfig::default().with_servers(vec![ServerConfig::new("localhost:1")]);
+        let result = XdsServerBuilder::with_config(
+            XdsServerConfig::default().with_xds_client_config(config),
+        )
+        .bind(free_addr())
+        .await;
+        assert!(matches!(
+            result,
+            Err(XdsServerError::MissingListenerTemplate)
+        ));
+    }
+}
@@ -3,6 +3,7 @@ use std::error::Error;
 use std::net::SocketAddr;
 use tokio::{net::TcpListener, sync::oneshot};
 use tonic::server::NamedService;
+use tonic::service::Routes;
 use tonic::transport::{Channel, ClientTlsConfig, Endpoint, Server, ServerTlsConfig};
 use tonic::{Request, Response, Status};
 
@@ -26,6 +27,13 @@ impl Greeter for MyGreeter {
     }
 }
 
+/// Returns the routes of a greeter service whose replies start with `msg`.
+pub(crate) fn greeter_routes(msg: &str) -> Routes {
+    Routes::new(GreeterServer::new(MyGreeter {
+        msg: msg.to_string(),
+    }))
+}
+
 /// A test server that runs a gRPC service and provides a channel for clients to connect.
 pub(crate) struct TestServer {
     /// The gRPC channel for talking to the test server.
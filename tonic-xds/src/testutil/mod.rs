@@ -3,3 +3,6 @@ pub(crate) mod grpc;
 
 #[cfg(test)]
 pub(crate) mod proto;
+
+#[cfg(test)]
+pub(crate) mod xds;
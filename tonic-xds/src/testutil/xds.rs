@@ -0,0 +1,372 @@
+//! Test utilities for running a local xDS management server.
+use envoy_types::pb::envoy::config::cluster::v3 as cluster;
+use envoy_types::pb::envoy::config::core::v3 as core;
+use envoy_types::pb::envoy::config::endpoint::v3 as endpoint;
+use envoy_types::pb::envoy::config::listener::v3 as listener;
+use envoy_types::pb::envoy::config::route::v3 as route;
+use envoy_types::pb::envoy::extensions::filters::http::router::v3::Router;
+use envoy_types::pb::envoy::extensions::filters::network::http_connection_manager::v3 as hcm;
+use envoy_types::pb::envoy::service::discovery::v3::{
+    aggregated_discovery_service_server::{
+        AggregatedDiscoveryService, AggregatedDiscoveryServiceServer,
+    },
+    DeltaDiscoveryRequest, DeltaDiscoveryResponse, DiscoveryRequest, DiscoveryResponse,
+};
+use envoy_types::pb::envoy::service::load_stats::v3::{
+    load_reporting_service_server::{LoadReportingService, LoadReportingServiceServer},
+    LoadStatsRequest, LoadStatsResponse,
+};
+use envoy_types::pb::google::protobuf::{Any, Duration as ProtoDuration, UInt32Value};
+use prost::{Message, Name};
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::{net::TcpListener, sync::mpsc, sync::oneshot};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+/// The version of the resources served by the test server.
+const VERSION: &str = "1";
+
+type ResponseStream = Pin<Box<dyn Stream<Item = Result<DiscoveryResponse, Status>> + Send>>;
+
+/// An ADS server that serves a fixed set of resources, keyed by type URL.
+struct TestAds {
+    resources: Arc<HashMap<String, Vec<Any>>>,
+}
+
+#[tonic::async_trait]
+impl AggregatedDiscoveryService for TestAds {
+    type StreamAggregatedResourcesStream = ResponseStream;
+    type DeltaAggregatedResourcesStream =
+        Pin<Box<dyn Stream<Item = Result<DeltaDiscoveryResponse, Status>> + Send>>;
+
+    async fn stream_aggregated_resources(
+        &self,
+        request: Request<Streaming<DiscoveryRequest>>,
+    ) -> Result<Response<Self::StreamAggregatedResourcesStream>, Status> {
+        let mut requests = request.into_inner();
+        let resources = self.resources.clone();
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            while let Ok(Some(request)) = requests.message().await {
+                // All resources of a type are sent at once, so ACKs need no response.
+                if request.version_info == VERSION {
+                    continue;
+                }
+                let response = DiscoveryResponse {
+                    version_info: VERSION.to_string(),
+                    resources: resources
+                        .get(&request.type_url)
+                        .cloned()
+                        .unwrap_or_default(),
+                    nonce: VERSION.to_string(),
+                    type_url: request.type_url,
+                    ..Default::default()
+                };
+                if tx.send(Ok(response)).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn delta_aggregated_resources(
+        &self,
+        _request: Request<Streaming<DeltaDiscoveryRequest>>,
+    ) -> Result<Response<Self::DeltaAggregatedResourcesStream>, Status> {
+        Err(Status::unimplemented("delta ADS is not supported"))
+    }
+}
+
+/// An LRS server that asks for the load of all clusters every second, and forwards the
+/// load reports with cluster stats.
+struct TestLrs {
+    reports: mpsc::UnboundedSender<LoadStatsRequest>,
+}
+
+#[tonic::async_trait]
+impl LoadReportingService for TestLrs {
+    type StreamLoadStatsStream =
+        Pin<Box<dyn Stream<Item = Result<LoadStatsResponse, Status>> + Send>>;
+
+    async fn stream_load_stats(
+        &self,
+        request: Request<Streaming<LoadStatsRequest>>,
+    ) -> Result<Response<Self::StreamLoadStatsStream>, Status> {
+        let mut requests = request.into_inner();
+        let reports = self.reports.clone();
+        let (tx, rx) = mpsc::channel(1);
+        tokio::spawn(async move {
+            // The first request only carries the node.
+            if !matches!(requests.message().await, Ok(Some(_))) {
+                return;
+            }
+            let response = LoadStatsResponse {
+                send_all_clusters: true,
+                load_reporting_interval: Some(ProtoDuration {
+                    seconds: 1,
+                    nanos: 0,
+                }),
+                ..Default::default()
+            };
+            if tx.send(Ok(response)).await.is_err() {
+                return;
+            }
+            while let Ok(Some(request)) = requests.message().await {
+                if !request.cluster_stats.is_empty() && reports.send(request).is_err() {
+                    return;
+                }
+            }
+            // Keep the response stream open until the client closes the stream.
+            drop(tx);
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
+
+/// A local xDS management server for testing.
+pub(crate) struct TestXdsServer {
+    /// Signal the server to shutdown.
+    pub shutdown: oneshot::Sender<()>,
+    /// Handle to wait for server to exit.
+    pub handle: tokio::task::JoinHandle<()>,
+    /// Server address.
+    pub addr: SocketAddr,
+    /// The load reports received by the LRS service, if they have cluster stats.
+    pub load_reports: mpsc::UnboundedReceiver<LoadStatsRequest>,
+}
+
+/// Spawns an xDS management server serving the given resources.
+pub(crate) async fn spawn_xds_server(resources: Vec<Any>) -> Result<TestXdsServer, Box<dyn Error>> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let incoming = tokio_stream::wrappers::TcpListenerStream::new(listener);
+
+    let mut by_type: HashMap<String, Vec<Any>> = HashMap::new();
+    for resource in resources {
+        by_type
+            .entry(resource.type_url.clone())
+            .or_default()
+            .push(resource);
+    }
+    let svc = AggregatedDiscoveryServiceServer::new(TestAds {
+        resources: Arc::new(by_type),
+    });
+    let (reports_tx, load_reports) = mpsc::unbounded_channel();
+    let lrs = LoadReportingServiceServer::new(TestLrs {
+        reports: reports_tx,
+    });
+
+    let (tx, rx) = oneshot::channel();
+    let handle = tokio::spawn(async move {
+        let res = Server::builder()
+            .add_service(svc)
+            .add_service(lrs)
+            .serve_with_incoming_shutdown(incoming, async {
+                let _ = rx.await;
+            })
+            .await;
+        if let Err(e) = res {
+            eprintln!("xDS server error: {e}");
+        }
+    });
+
+    Ok(TestXdsServer {
+        shutdown: tx,
+        handle,
+        addr,
+        load_reports,
+    })
+}
+
+fn to_any<M: Message + Name>(message: &M) -> Any {
+    Any {
+        type_url: M::type_url(),
+        value: message.encode_to_vec(),
+    }
+}
+
+fn ads() -> core::ConfigSource {
+    core::ConfigSource {
+        config_source_specifier: Some(core::config_source::ConfigSourceSpecifier::Ads(
+            Default::default(),
+        )),
+        ..Default::default()
+    }
+}
+
+/// Returns an HTTP connection manager that fetches the route configuration `route_name`
+/// over RDS.
+fn rds_manager(route_name: &str) -> hcm::HttpConnectionManager {
+    hcm::HttpConnectionManager {
+        route_specifier: Some(hcm::http_connection_manager::RouteSpecifier::Rds(
+            hcm::Rds {
+                config_source: Some(ads()),
+                route_config_name: route_name.to_string(),
+            },
+        )),
+        http_filters: vec![hcm::HttpFilter {
+            name: "router".to_string(),
+            config_type: Some(hcm::http_filter::ConfigType::TypedConfig(to_any(
+                &Router::default(),
+            ))),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+fn socket_address(addr: SocketAddr) -> core::Address {
+    core::Address {
+        address: Some(core::address::Address::SocketAddress(core::SocketAddress {
+            address: addr.ip().to_string(),
+            port_specifier: Some(core::socket_address::PortSpecifier::PortValue(u32::from(
+                addr.port(),
+            ))),
+            ..Default::default()
+        })),
+    }
+}
+
+/// Returns a listener that fetches the route configuration `route_name` over RDS.
+pub(crate) fn listener(name: &str, route_name: &str) -> Any {
+    let manager = rds_manager(route_name);
+    to_any(&listener::Listener {
+        name: name.to_string(),
+        api_listener: Some(listener::ApiListener {
+            api_listener: Some(to_any(&manager)),
+        }),
+        ..Default::default()
+    })
+}
+
+/// Returns the listener of an xDS-enabled server listening on `addr`, whose single filter
+/// chain fetches the route configuration `route_name` over RDS.
+pub(crate) fn server_listener(name: &str, addr: SocketAddr, route_name: &str) -> Any {
+    let manager = rds_manager(route_name);
+    to_any(&listener::Listener {
+        name: name.to_string(),
+        address: Some(socket_address(addr)),
+        filter_chains: vec![listener::FilterChain {
+            name: "filter-chain-1".to_string(),
+            filters: vec![listener::Filter {
+                name: "hcm".to_string(),
+                config_type: Some(listener::filter::ConfigType::TypedConfig(to_any(&manager))),
+            }],
+            ..Default::default()
+        }],
+        ..Default::default()
+    })
+}
+
+/// Returns a route configuration for an xDS-enabled server that accepts requests whose
+/// path starts with `prefix`.
+pub(crate) fn server_route_configuration(name: &str, prefix: &str) -> Any {
+    to_any(&route::RouteConfiguration {
+        name: name.to_string(),
+        virtual_hosts: vec![route::VirtualHost {
+            name: "default".to_string(),
+            domains: vec!["*".to_string()],
+            routes: vec![route::Route {
+                r#match: Some(route::RouteMatch {
+                    path_specifier: Some(route::route_match::PathSpecifier::Prefix(
+                        prefix.to_string(),
+                    )),
+                    ..Default::default()
+                }),
+                action: Some(route::route::Action::NonForwardingAction(Default::default())),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+        ..Default::default()
+    })
+}
+
+/// Returns a route configuration that routes all requests to `cluster_name`.
+pub(crate) fn route_configuration(name: &str, cluster_name: &str) -> Any {
+    to_any(&route::RouteConfiguration {
+        name: name.to_string(),
+        virtual_hosts: vec![route::VirtualHost {
+            name: "default".to_string(),
+            domains: vec!["*".to_string()],
+            routes: vec![route::Route {
+                r#match: Some(route::RouteMatch {
+                    path_specifier: Some(route::route_match::PathSpecifier::Prefix(String::new())),
+                    ..Default::default()
+                }),
+                action: Some(route::route::Action::Route(route::RouteAction {
+                    cluster_specifier: Some(route::route_action::ClusterSpecifier::Cluster(
+                        cluster_name.to_string(),
+                    )),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+        ..Default::default()
+    })
+}
+
+fn eds_cluster(name: &str) -> cluster::Cluster {
+    cluster::Cluster {
+        name: name.to_string(),
+        cluster_discovery_type: Some(cluster::cluster::ClusterDiscoveryType::Type(
+            cluster::cluster::DiscoveryType::Eds as i32,
+        )),
+        eds_cluster_config: Some(cluster::cluster::EdsClusterConfig {
+            eds_config: Some(ads()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Returns an EDS cluster.
+pub(crate) fn cluster(name: &str) -> Any {
+    to_any(&eds_cluster(name))
+}
+
+/// Returns an EDS cluster whose load is reported to the xDS server.
+pub(crate) fn cluster_with_load_reporting(name: &str) -> Any {
+    to_any(&cluster::Cluster {
+        lrs_server: Some(core::ConfigSource {
+            config_source_specifier: Some(core::config_source::ConfigSourceSpecifier::Self_(
+                Default::default(),
+            )),
+            ..Default::default()
+        }),
+        ..eds_cluster(name)
+    })
+}
+
+/// Returns the load assignment of `cluster_name` with the given endpoints.
+pub(crate) fn cluster_load_assignment(cluster_name: &str, endpoints: &[SocketAddr]) -> Any {
+    let lb_endpoints = endpoints
+        .iter()
+        .map(|addr| endpoint::LbEndpoint {
+            host_identifier: Some(endpoint::lb_endpoint::HostIdentifier::Endpoint(
+                endpoint::Endpoint {
+                    address: Some(socket_address(*addr)),
+                    ..Default::default()
+                },
+            )),
+            ..Default::default()
+        })
+        .collect();
+    to_any(&endpoint::ClusterLoadAssignment {
+        cluster_name: cluster_name.to_string(),
+        endpoints: vec![endpoint::LocalityLbEndpoints {
+            load_balancing_weight: Some(UInt32Value { value: 1 }),
+            lb_endpoints,
+            ..Default::default()
+        }],
+        ..Default::default()
+    })
+}
@@ -0,0 +1,575 @@
+//! An [`XdsManager`](crate::xds::xds_manager::XdsManager) backed by an [`XdsClient`].
+//!
+//! The manager runs the LDS -> RDS -> CDS -> EDS cascade: the listener of the target
+//! names a route configuration, which is used to route requests to clusters. Each
+//! discovered cluster names the `ClusterLoadAssignment` its endpoints come from.
+
+use crate::client::circuit_breaker::RequestsCounter;
+use crate::client::endpoint::{
+    EndpointAddress, EndpointChannel, EndpointLocality, LocalizedEndpoint,
+};
+use crate::common::async_util::BoxFuture;
+use crate::xds::route::{RouteDecision, RouteInput, RouteTable, RoutingError};
+use crate::xds::uri::XdsUri;
+use crate::xds::xds_manager::{
+    BoxDiscover, ClusterConfig, ClusterDiscovery, XdsClusterDiscovery, XdsRouter,
+};
+use std::collections::HashMap;
+use std::future::{self, Future};
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tonic::transport::{Channel, Endpoint};
+use tower::{discover::Change, BoxError};
+use xds_client::resource::prost::listener::RouteSource;
+use xds_client::{
+    ClientConfig, Cluster, ClusterLoadAssignment, ClusterLoadStats, Listener, ProstCodec, Resource,
+    ResourceEvent, ResourceWatcher, RouteConfiguration, TokioRuntime, TonicTransportBuilder,
+    XdsClient,
+};
+
+type EndpointChange = Change<EndpointAddress, LocalizedEndpoint<EndpointChannel<Channel>>>;
+
+/// The endpoints sent to the load balancer, with the load report they record requests in.
+type CurrentEndpoints =
+    HashMap<EndpointAddress, (EndpointLocality, Option<ClusterLoadStats>, Channel)>;
+
+/// The route configuration of the target, as last received from the xDS server.
+#[derive(Clone, Debug)]
+enum RouteState {
+    /// No route configuration has been received yet.
+    Pending,
+    /// The current route configuration.
+    Ready(Arc<RouteTable>),
+    /// The listener or route configuration could not be obtained.
+    Failed(String),
+}
+
+/// Commands sent from the manager to its background task.
+enum ManagerCommand {
+    /// Start discovering the endpoints of a cluster.
+    DiscoverCluster {
+        name: String,
+        changes: mpsc::UnboundedSender<Result<EndpointChange, BoxError>>,
+        config: watch::Sender<ClusterConfig>,
+    },
+}
+
+/// An xDS manager that fetches resources for a target from the xDS management server.
+///
+/// The resources are watched by a background task, which stops when the manager is dropped.
+pub(crate) struct XdsClientManager {
+    /// The authority used for virtual host matching when the request has none.
+    target: String,
+    /// A random identifier of the channel, hashed by the channel id hash policy.
+    channel_id: u64,
+    routes: watch::Receiver<RouteState>,
+    commands: mpsc::UnboundedSender<ManagerCommand>,
+}
+
+impl XdsClientManager {
+    /// Starts watching the listener of `target` with a new xDS client.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub(crate) fn start(config: ClientConfig, target: &XdsUri) -> Result<Self, xds_client::Error> {
+        let listener_name =
+            config.listener_resource_name(target.authority.as_deref(), &target.target)?;
+        let client = async move {
+            XdsClient::builder(config)
+                .build(TonicTransportBuilder, ProstCodec, TokioRuntime)
+                .await
+        };
+        Ok(Self::spawn(client, listener_name, target))
+    }
+
+    /// Starts watching the listener of `target` with a shared xDS client.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub(crate) fn start_with_client(
+        client: XdsClient,
+        target: &XdsUri,
+    ) -> Result<Self, xds_client::Error> {
+        let listener_name = client
+            .config()
+            .listener_resource_name(target.authority.as_deref(), &target.target)?;
+        Ok(Self::spawn(
+            future::ready(Ok(client)),
+            listener_name,
+            target,
+        ))
+    }
+
+    /// Spawns the background task watching the listener with the client once it is built.
+    fn spawn(
+        client: impl Future<Output = Result<XdsClient, xds_client::Error>> + Send + 'static,
+        listener_name: String,
+        target: &XdsUri,
+    ) -> Self {
+        let (routes_tx, routes_rx) = watch::channel(RouteState::Pending);
+        let (commands_tx, commands_rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(client, listener_name, routes_tx, commands_rx));
+        Self {
+            target: target.target.clone(),
+            channel_id: rand::random(),
+            routes: routes_rx,
+            commands: commands_tx,
+        }
+    }
+}
+
+impl XdsRouter for XdsClientManager {
+    fn route(&self, input: &RouteInput<'_>) -> BoxFuture<Result<RouteDecision, RoutingError>> {
+        let authority = if input.authority.is_empty() {
+            self.target.as_str()
+        } else {
+            input.authority
+        };
+        // Requests are routed with the current configuration if there is one. Otherwise
+        // they wait for the first one.
+        let mut routes = self.routes.clone();
+        {
+            let state = routes.borrow_and_update();
+            if !matches!(*state, RouteState::Pending) {
+                let decision = decide(&state, authority, input, self.channel_id);
+                return Box::pin(async move { decision });
+            }
+        }
+        let authority = authority.to_string();
+        let path = input.path.to_string();
+        let headers = input.headers.clone();
+        let channel_id = self.channel_id;
+        Box::pin(async move {
+            let state = routes
+                .wait_for(|state| !matches!(state, RouteState::Pending))
+                .await
+                .map_err(|_| RoutingError::Unavailable("xDS manager stopped".to_string()))?;
+            let input = RouteInput {
+                authority: &authority,
+                path: &path,
+                headers: &headers,
+            };
+            decide(&state, &authority, &input, channel_id)
+        })
+    }
+}
+
+fn decide(
+    state: &RouteState,
+    authority: &str,
+    input: &RouteInput<'_>,
+    channel_id: u64,
+) -> Result<RouteDecision, RoutingError> {
+    match state {
+        RouteState::Ready(table) => table.route(authority, input, channel_id),
+        RouteState::Failed(error) => Err(RoutingError::Unavailable(error.clone())),
+        RouteState::Pending => Err(RoutingError::Unavailable(
+            "no route configuration received".to_string(),
+        )),
+    }
+}
+
+impl XdsClusterDiscovery<EndpointAddress, EndpointChannel<Channel>> for XdsClientManager {
+    fn discover_cluster(
+        &self,
+        cluster_name: &str,
+    ) -> ClusterDiscovery<BoxDiscover<EndpointAddress, EndpointChannel<Channel>>> {
+        let (changes_tx, changes_rx) = mpsc::unbounded_channel();
+        let (config_tx, config_rx) = watch::channel(ClusterConfig::default());
+        // If the background task has stopped, the stream ends right away.
+        let _ = self.commands.send(ManagerCommand::DiscoverCluster {
+            name: cluster_name.to_string(),
+            changes: changes_tx,
+            config: config_tx,
+        });
+        ClusterDiscovery {
+            endpoints: Box::pin(tokio_stream::wrappers::UnboundedReceiverStream::new(
+                changes_rx,
+            )),
+            config: config_rx,
+        }
+    }
+}
+
+/// The background task of an [`XdsClientManager`].
+async fn run(
+    client: impl Future<Output = Result<XdsClient, xds_client::Error>>,
+    listener_name: String,
+    routes: watch::Sender<RouteState>,
+    mut commands: mpsc::UnboundedReceiver<ManagerCommand>,
+) {
+    let client = match client.await {
+        Ok(client) => client,
+        Err(error) => {
+            routes.send_replace(RouteState::Failed(error.to_string()));
+            return;
+        }
+    };
+
+    let mut listener = client.watch::<Listener>(listener_name);
+    let mut route_config: Option<(String, ResourceWatcher<RouteConfiguration>)> = None;
+    // The HTTP filters of the listener, and the last routes received over RDS.
+    let mut http_filters = Vec::new();
+    let mut rds_routes: Option<Arc<RouteConfiguration>> = None;
+    loop {
+        tokio::select! {
+            Some(event) = listener.next() => match event {
+                ResourceEvent::ResourceChanged { resource, .. } => {
+                    let Some(manager) = resource.http_connection_manager() else {
+                        route_config = None;
+                        rds_routes = None;
+                        routes.send_replace(RouteState::Failed(format!(
+                            "listener {} is not an API listener",
+                            resource.name
+                        )));
+                        continue;
+                    };
+                    let filters_changed = http_filters != manager.http_filters;
+                    http_filters.clone_from(&manager.http_filters);
+                    match &manager.route_config {
+                        RouteSource::Rds(name) => {
+                            // Keep using the previous routes until the new ones arrive.
+                            if route_config.as_ref().map(|(n, _)| n) != Some(name) {
+                                route_config = Some((name.clone(), client.watch(name.as_str())));
+                            }
+                            if let Some(config) = rds_routes.as_ref().filter(|_| filters_changed) {
+                                let table = RouteTable::new(config.clone(), &http_filters);
+                                routes.send_replace(RouteState::Ready(Arc::new(table)));
+                            }
+                        }
+                        RouteSource::Inline(config) => {
+                            route_config = None;
+                            rds_routes = None;
+                            let table = RouteTable::new(Arc::new(config.clone()), &http_filters);
+                            routes.send_replace(RouteState::Ready(Arc::new(table)));
+                        }
+                    }
+                }
+                ResourceEvent::ResourceError { error, .. } => {
+                    route_config = None;
+                    rds_routes = None;
+                    routes.send_replace(RouteState::Failed(error.to_string()));
+                }
+                ResourceEvent::AmbientError { .. } => {}
+            },
+            Some(event) = next_event(&mut route_config) => match event {
+                ResourceEvent::ResourceChanged { resource, .. } => {
+                    let table = RouteTable::new(resource.clone(), &http_filters);
+                    rds_routes = Some(resource);
+                    routes.send_replace(RouteState::Ready(Arc::new(table)));
+                }
+                ResourceEvent::ResourceError { error, .. } => {
+                    rds_routes = None;
+                    routes.send_replace(RouteState::Failed(error.to_string()));
+                }
+                ResourceEvent::AmbientError { .. } => {}
+            },
+            command = commands.recv() => match command {
+                Some(ManagerCommand::DiscoverCluster { name, changes, config }) => {
+                    tokio::spawn(discover_cluster(client.clone(), name, changes, config));
+                }
+                // The manager has been dropped.
+                None => return,
+            },
+        }
+    }
+}
+
+/// Watches a cluster and its endpoints, sending its configuration and endpoint changes
+/// until `changes` is closed.
+async fn discover_cluster(
+    client: XdsClient,
+    cluster_name: String,
+    changes: mpsc::UnboundedSender<Result<EndpointChange, BoxError>>,
+    config: watch::Sender<ClusterConfig>,
+) {
+    let mut cluster = client.watch::<Cluster>(cluster_name.as_str());
+    let mut assignment: Option<(String, ResourceWatcher<ClusterLoadAssignment>)> = None;
+    let mut endpoints = CurrentEndpoints::new();
+    loop {
+        let update = tokio::select! {
+            () = changes.closed() => return,
+            Some(event) = cluster.next() => match event {
+                ResourceEvent::ResourceChanged { resource, .. } => {
+                    // gRPC only reports load to the server the cluster came from.
+                    let load_stats = if resource.lrs_server {
+                        let service_name = resource.discovery.eds_service_name();
+                        client.load_stats(&cluster_name, service_name).ok()
+                    } else {
+                        None
+                    };
+                    // Requests are counted together for the cluster of an xDS server.
+                    let requests = match client.config().servers_for(&cluster_name) {
+                        Ok(servers) => RequestsCounter::shared(
+                            &servers[0].server_uri,
+                            &cluster_name,
+                            resource.discovery.eds_service_name(),
+                        ),
+                        Err(_) => config.borrow().requests.clone(),
+                    };
+                    let load_stats_changed = config.borrow().load_stats != load_stats;
+                    update_config(&config, |current| {
+                        *current = ClusterConfig {
+                            drop_overloads: std::mem::take(&mut current.drop_overloads),
+                            load_stats,
+                            requests,
+                            ..ClusterConfig::from(resource.as_ref())
+                        };
+                    });
+                    match resource.discovery.eds_resource_name(&cluster_name) {
+                        Some(name) => {
+                            if assignment.as_ref().map(|(n, _)| n.as_str()) != Some(name) {
+                                assignment = Some((name.to_string(), client.watch(name)));
+                            }
+                            if !load_stats_changed {
+                                continue;
+                            }
+                            // Insert the endpoints again, recording their load in the new
+                            // load report.
+                            endpoints
+                                .iter()
+                                .map(|(address, (locality, _, _))| {
+                                    (address.clone(), locality.clone())
+                                })
+                                .collect()
+                        }
+                        // Only EDS clusters are supported.
+                        None => {
+                            assignment = None;
+                            HashMap::new()
+                        }
+                    }
+                }
+                ResourceEvent::ResourceError { .. } => {
+                    assignment = None;
+                    HashMap::new()
+                }
+                ResourceEvent::AmbientError { .. } => continue,
+            },
+            Some(event) = next_event(&mut assignment) => match event {
+                ResourceEvent::ResourceChanged { resource, .. } => {
+                    update_config(&config, |current| {
+                        current.drop_overloads.clone_from(&resource.drop_overloads);
+                    });
+                    usable_endpoints(&resource)
+                }
+                ResourceEvent::ResourceError { .. } => {
+                    update_config(&config, |current| current.drop_overloads.clear());
+                    HashMap::new()
+                }
+                ResourceEvent::AmbientError { .. } => continue,
+            },
+        };
+        let load_stats = config.borrow().load_stats.clone();
+        for change in endpoint_changes(&mut endpoints, update, load_stats.as_ref()) {
+            if changes.send(Ok(change)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Updates the configuration of a cluster, notifying the load balancer if it changed.
+fn update_config(config: &watch::Sender<ClusterConfig>, update: impl FnOnce(&mut ClusterConfig)) {
+    config.send_if_modified(|current| {
+        let previous = current.clone();
+        update(current);
+        *current != previous
+    });
+}
+
+/// Returns the next event of an optional watcher, or never completes if there is none.
+async fn next_event<T: Resource>(
+    watcher: &mut Option<(String, ResourceWatcher<T>)>,
+) -> Option<ResourceEvent<T>> {
+    match watcher {
+        Some((_, watcher)) => watcher.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Returns the endpoints that requests may be sent to, with their localities.
+fn usable_endpoints(
+    assignment: &ClusterLoadAssignment,
+) -> HashMap<EndpointAddress, EndpointLocality> {
+    let mut endpoints = HashMap::new();
+    for locality in &assignment.localities {
+        let endpoint_locality = EndpointLocality {
+            priority: locality.priority,
+            locality: locality.locality.clone(),
+            weight: locality.weight,
+        };
+        for endpoint in &locality.endpoints {
+            if endpoint.health_status.is_usable() {
+                endpoints
+                    .entry(EndpointAddress::new(&endpoint.address, endpoint.port))
+                    .or_insert_with(|| endpoint_locality.clone());
+            }
+        }
+    }
+    endpoints
+}
+
+/// Updates `current` to `next`, returning the changes to apply to the load balancer.
+///
+/// Endpoints whose locality or load report changed are inserted again, reusing their channel.
+fn endpoint_changes(
+    current: &mut CurrentEndpoints,
+    next: HashMap<EndpointAddress, EndpointLocality>,
+    load_stats: Option<&ClusterLoadStats>,
+) -> Vec<EndpointChange> {
+    let mut changes = Vec::new();
+    current.retain(|address, _| {
+        let keep = next.contains_key(address);
+        if !keep {
+            changes.push(Change::Remove(address.clone()));
+        }
+        keep
+    });
+    for (address, locality) in next {
+        let channel = match current.get(&address) {
+            Some((current_locality, current_load_stats, _))
+                if *current_locality == locality && current_load_stats.as_ref() == load_stats =>
+            {
+                continue
+            }
+            Some((_, _, channel)) => channel.clone(),
+            None => match Endpoint::from_shared(format!("http://{address}")) {
+                Ok(endpoint) => endpoint.connect_lazy(),
+                // Not a valid authority, such as a malformed hostname.
+                Err(_) => continue,
+            },
+        };
+        let mut service = EndpointChannel::new(channel.clone());
+        if let Some(load_stats) = load_stats {
+            service = service.with_load_stats(load_stats.locality(&locality.locality));
+        }
+        current.insert(
+            address.clone(),
+            (locality.clone(), load_stats.cloned(), channel),
+        );
+        changes.push(Change::Insert(
+            address,
+            LocalizedEndpoint { locality, service },
+        ));
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xds_client::resource::prost::endpoint::{Endpoint, HealthStatus, LocalityEndpoints};
+    use xds_client::Locality;
+
+    fn locality(
+        zone: &str,
+        priority: u32,
+        endpoints: &[(&str, HealthStatus)],
+    ) -> LocalityEndpoints {
+        LocalityEndpoints {
+            locality: Locality {
+                zone: zone.to_string(),
+                ..Default::default()
+            },
+            weight: 1,
+            priority,
+            endpoints: endpoints
+                .iter()
+                .map(|(address, health_status)| Endpoint {
+                    address: address.to_string(),
+                    port: 8080,
+                    health_status: *health_status,
+                    weight: 1,
+                })
+                .collect(),
+        }
+    }
+
+    fn assignment(localities: Vec<LocalityEndpoints>) -> ClusterLoadAssignment {
+        ClusterLoadAssignment {
+            cluster_name: "cluster-1".to_string(),
+            localities,
+            drop_overloads: Vec::new(),
+        }
+    }
+
+    fn summarize(changes: Vec<EndpointChange>) -> Vec<(String, Option<String>)> {
+        let mut summary: Vec<_> = changes
+            .into_iter()
+            .map(|change| match change {
+                Change::Insert(address, endpoint) => {
+                    (address.to_string(), Some(endpoint.locality.locality.zone))
+                }
+                Change::Remove(address) => (address.to_string(), None),
+            })
+            .collect();
+        summary.sort();
+        summary
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_changes() {
+        let mut current = HashMap::new();
+        let first = assignment(vec![locality(
+            "a",
+            0,
+            &[
+                ("10.0.0.1", HealthStatus::Healthy),
+                ("10.0.0.2", HealthStatus::Unknown),
+                ("10.0.0.3", HealthStatus::Unhealthy),
+            ],
+        )]);
+        assert_eq!(
+            summarize(endpoint_changes(
+                &mut current,
+                usable_endpoints(&first),
+                None
+            )),
+            vec![
+                ("10.0.0.1:8080".to_string(), Some("a".to_string())),
+                ("10.0.0.2:8080".to_string(), Some("a".to_string())),
+            ]
+        );
+
+        let second = assignment(vec![
+            locality(
+                "a",
+                0,
+                &[
+                    ("10.0.0.2", HealthStatus::Healthy),
+                    ("10.0.0.3", HealthStatus::Healthy),
+                ],
+            ),
+            locality("b", 1, &[("10.0.0.1", HealthStatus::Healthy)]),
+        ]);
+        assert_eq!(
+            summarize(endpoint_changes(
+                &mut current,
+                usable_endpoints(&second),
+                None
+            )),
+            vec![
+                // Moved to another locality.
+                ("10.0.0.1:8080".to_string(), Some("b".to_string())),
+                ("10.0.0.3:8080".to_string(), Some("a".to_string())),
+            ]
+        );
+        assert!(endpoint_changes(&mut current, usable_endpoints(&second), None).is_empty());
+
+        let third = assignment(vec![locality(
+            "a",
+            0,
+            &[("10.0.0.2", HealthStatus::Healthy)],
+        )]);
+        assert_eq!(
+            summarize(endpoint_changes(
+                &mut current,
+                usable_endpoints(&third),
+                None
+            )),
+            vec![
+                ("10.0.0.1:8080".to_string(), None),
+                ("10.0.0.3:8080".to_string(), None),
+            ]
+        );
+    }
+}
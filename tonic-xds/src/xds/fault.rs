@@ -0,0 +1,254 @@
+//! Fault injection decisions, following
+//! [gRFC A33](https://github.com/grpc/proposal/blob/master/A33-Fault-Injection.md).
+//!
+//! The faults of a request are decided when it is routed, as they depend on the route and
+//! the request headers. They are injected by the `FaultInjectionService` layer.
+
+use std::sync::Arc;
+use std::time::Duration;
+use xds_client::resource::prost::fault::{
+    FaultAbortStatus, FaultDelayKind, FaultInjection, FaultPercentage,
+};
+use xds_client::resource::prost::route::HeaderMatcher;
+
+/// Header with the delay in milliseconds of header-driven delays.
+const DELAY_HEADER: &str = "x-envoy-fault-delay-request";
+
+/// Header lowering the percentage of header-driven delays.
+const DELAY_PERCENTAGE_HEADER: &str = "x-envoy-fault-delay-request-percentage";
+
+/// Header with the gRPC status code of header-driven aborts.
+const ABORT_GRPC_HEADER: &str = "x-envoy-fault-abort-grpc-request";
+
+/// Header with the HTTP status of header-driven aborts.
+const ABORT_HTTP_HEADER: &str = "x-envoy-fault-abort-request";
+
+/// Header lowering the percentage of header-driven aborts.
+const ABORT_PERCENTAGE_HEADER: &str = "x-envoy-fault-abort-request-percentage";
+
+/// A fault injection filter, with its configuration for a route.
+#[derive(Debug, Clone)]
+pub(crate) struct FaultFilter {
+    /// The filter name, which scopes `max_active_faults`.
+    pub name: Arc<str>,
+    /// The filter configuration, possibly overridden by the route or virtual host.
+    pub config: Arc<FaultInjection>,
+}
+
+/// The faults to inject into a request by a fault injection filter.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct InjectedFault {
+    /// The name of the filter.
+    pub filter: Arc<str>,
+    /// The delay before the request is sent, if any.
+    pub delay: Option<Duration>,
+    /// The status the request is aborted with, if any.
+    pub abort: Option<tonic::Code>,
+    /// The maximum number of requests with an active fault of this filter, if limited.
+    pub max_active_faults: Option<u32>,
+}
+
+impl FaultFilter {
+    /// Decides the faults to inject into a request, if any.
+    ///
+    /// `matches_header` evaluates the header matchers of the filter against the request.
+    pub(crate) fn decide(
+        &self,
+        headers: &http::HeaderMap,
+        matches_header: impl Fn(&HeaderMatcher) -> bool,
+    ) -> Option<InjectedFault> {
+        let config = &self.config;
+        if !config.headers.iter().all(matches_header) {
+            return None;
+        }
+        let delay = config.delay.as_ref().and_then(|delay| {
+            let (duration, percentage) = match &delay.delay {
+                FaultDelayKind::Fixed(duration) => (*duration, delay.percentage),
+                FaultDelayKind::Header => (
+                    Duration::from_millis(header(headers, DELAY_HEADER)?),
+                    header_percentage(headers, DELAY_PERCENTAGE_HEADER, delay.percentage),
+                ),
+            };
+            roll(percentage).then_some(duration)
+        });
+        let abort = config.abort.as_ref().and_then(|abort| {
+            let (code, percentage) = match abort.status {
+                FaultAbortStatus::Http(status) => (http_to_grpc(status), abort.percentage),
+                FaultAbortStatus::Grpc(code) => (tonic::Code::from(code as i32), abort.percentage),
+                FaultAbortStatus::Header => {
+                    let code = match header::<i32>(headers, ABORT_GRPC_HEADER) {
+                        Some(code) => tonic::Code::from(code),
+                        None => http_to_grpc(header(headers, ABORT_HTTP_HEADER)?),
+                    };
+                    let percentage =
+                        header_percentage(headers, ABORT_PERCENTAGE_HEADER, abort.percentage);
+                    (code, percentage)
+                }
+            };
+            roll(percentage).then_some(code)
+        });
+        if delay.is_none() && abort.is_none() {
+            return None;
+        }
+        Some(InjectedFault {
+            filter: self.name.clone(),
+            delay,
+            abort,
+            max_active_faults: config.max_active_faults,
+        })
+    }
+}
+
+/// Parses the value of a header.
+fn header<T: std::str::FromStr>(headers: &http::HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// Returns the configured percentage, lowered to the numerator in the given header if any.
+fn header_percentage(
+    headers: &http::HeaderMap,
+    name: &str,
+    percentage: FaultPercentage,
+) -> FaultPercentage {
+    match header::<u32>(headers, name) {
+        Some(numerator) => FaultPercentage {
+            numerator: numerator.min(percentage.numerator),
+            ..percentage
+        },
+        None => percentage,
+    }
+}
+
+/// Returns true for the given share of calls.
+fn roll(percentage: FaultPercentage) -> bool {
+    rand::random_range(0..1_000_000) < percentage.per_million()
+}
+
+/// Converts an HTTP status to a gRPC status code, see
+/// [HTTP to gRPC status code mapping](https://github.com/grpc/grpc/blob/master/doc/http-grpc-status-mapping.md).
+fn http_to_grpc(status: u32) -> tonic::Code {
+    match status {
+        400 => tonic::Code::Internal,
+        401 => tonic::Code::Unauthenticated,
+        403 => tonic::Code::PermissionDenied,
+        404 => tonic::Code::Unimplemented,
+        429 | 502..=504 => tonic::Code::Unavailable,
+        _ => tonic::Code::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xds_client::resource::prost::fault::{FaultAbort, FaultDelay};
+    use xds_client::resource::prost::route::HeaderMatch;
+
+    const ALWAYS: FaultPercentage = FaultPercentage {
+        numerator: 100,
+        denominator: 100,
+    };
+
+    const NEVER: FaultPercentage = FaultPercentage {
+        numerator: 0,
+        denominator: 100,
+    };
+
+    fn filter(delay: Option<FaultDelay>, abort: Option<FaultAbort>) -> FaultFilter {
+        FaultFilter {
+            name: "fault".into(),
+            config: Arc::new(FaultInjection {
+                delay,
+                abort,
+                headers: Vec::new(),
+                max_active_faults: Some(5),
+            }),
+        }
+    }
+
+    fn header_map(headers: &[(&'static str, &'static str)]) -> http::HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    http::HeaderName::from_static(name),
+                    http::HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_decide() {
+        let fixed = filter(
+            Some(FaultDelay {
+                delay: FaultDelayKind::Fixed(Duration::from_secs(1)),
+                percentage: ALWAYS,
+            }),
+            Some(FaultAbort {
+                status: FaultAbortStatus::Http(503),
+                percentage: NEVER,
+            }),
+        );
+        assert_eq!(
+            fixed.decide(&header_map(&[]), |_| true),
+            Some(InjectedFault {
+                filter: "fault".into(),
+                delay: Some(Duration::from_secs(1)),
+                abort: None,
+                max_active_faults: Some(5),
+            })
+        );
+        // Faults are only injected into requests matching the headers.
+        let mut config = (*fixed.config).clone();
+        config.headers.push(HeaderMatcher {
+            name: "x-fault".to_string(),
+            matcher: HeaderMatch::Present(true),
+            invert_match: false,
+        });
+        let with_headers = FaultFilter {
+            config: Arc::new(config),
+            ..fixed
+        };
+        assert_eq!(with_headers.decide(&header_map(&[]), |_| false), None);
+        assert!(with_headers.decide(&header_map(&[]), |_| true).is_some());
+
+        let from_headers = filter(
+            Some(FaultDelay {
+                delay: FaultDelayKind::Header,
+                percentage: ALWAYS,
+            }),
+            Some(FaultAbort {
+                status: FaultAbortStatus::Header,
+                percentage: ALWAYS,
+            }),
+        );
+        assert_eq!(from_headers.decide(&header_map(&[]), |_| true), None);
+        let fault = from_headers
+            .decide(
+                &header_map(&[(DELAY_HEADER, "20"), (ABORT_HTTP_HEADER, "404")]),
+                |_| true,
+            )
+            .unwrap();
+        assert_eq!(fault.delay, Some(Duration::from_millis(20)));
+        assert_eq!(fault.abort, Some(tonic::Code::Unimplemented));
+        let fault = from_headers
+            .decide(
+                &header_map(&[(ABORT_HTTP_HEADER, "404"), (ABORT_GRPC_HEADER, "14")]),
+                |_| true,
+            )
+            .unwrap();
+        assert_eq!(fault.delay, None);
+        assert_eq!(fault.abort, Some(tonic::Code::Unavailable));
+
+        // Percentage headers can only lower the configured percentage.
+        let headers = header_map(&[
+            (DELAY_HEADER, "20"),
+            (DELAY_PERCENTAGE_HEADER, "0"),
+            (ABORT_GRPC_HEADER, "14"),
+            (ABORT_PERCENTAGE_HEADER, "1000"),
+        ]);
+        let fault = from_headers.decide(&headers, |_| true).unwrap();
+        assert_eq!(fault.delay, None);
+        assert_eq!(fault.abort, Some(tonic::Code::Unavailable));
+    }
+}
@@ -0,0 +1,190 @@
+//! Selection of the filter chain of a connection accepted by an xDS-enabled server, following
+//! [gRFC A36](https://github.com/grpc/proposal/blob/master/A36-xds-for-servers.md).
+
+use std::net::SocketAddr;
+use xds_client::resource::prost::filter_chain::{
+    CidrRange, ConnectionSourceType, FilterChain, FilterChainMatch,
+};
+
+/// Returns the specificity with which a filter chain match criterion matches a connection, or
+/// `None` if it does not match.
+type MatchStep<'a> = dyn Fn(&FilterChainMatch) -> Option<i32> + 'a;
+
+/// Returns the index of the filter chain of a connection from `remote` to `local`, or `None`
+/// if no filter chain matches and the default filter chain applies.
+///
+/// The criteria are applied in order: destination IP, source type, source IP and source
+/// port. At each step, only the filter chains with the most specific matching criterion
+/// are kept. Validated listeners have no overlapping filter chains, so at most one is left.
+pub(crate) fn select_filter_chain(
+    filter_chains: &[FilterChain],
+    local: SocketAddr,
+    remote: SocketAddr,
+) -> Option<usize> {
+    let is_local = remote.ip().is_loopback() || remote.ip() == local.ip();
+    let steps: [&MatchStep<'_>; 4] = [
+        &|m| prefix_specificity(&m.prefix_ranges, local),
+        &|m| match m.source_type {
+            ConnectionSourceType::Any => Some(0),
+            ConnectionSourceType::SameIpOrLoopback if is_local => Some(1),
+            ConnectionSourceType::External if !is_local => Some(1),
+            _ => None,
+        },
+        &|m| prefix_specificity(&m.source_prefix_ranges, remote),
+        &|m| match m.source_ports.as_slice() {
+            [] => Some(0),
+            ports if ports.contains(&remote.port()) => Some(1),
+            _ => None,
+        },
+    ];
+
+    let mut candidates: Vec<usize> = (0..filter_chains.len()).collect();
+    for specificity in steps {
+        let scores: Vec<_> = candidates
+            .iter()
+            .map(|&i| specificity(&filter_chains[i].filter_chain_match))
+            .collect();
+        let best = scores.iter().flatten().max().copied()?;
+        candidates = candidates
+            .into_iter()
+            .zip(scores)
+            .filter(|(_, score)| *score == Some(best))
+            .map(|(i, _)| i)
+            .collect();
+    }
+    candidates.first().copied()
+}
+
+/// Returns the length of the longest range containing the IP of `addr`, `-1` if there are
+/// no ranges, or `None` if no range contains it.
+fn prefix_specificity(ranges: &[CidrRange], addr: SocketAddr) -> Option<i32> {
+    if ranges.is_empty() {
+        return Some(-1);
+    }
+    ranges
+        .iter()
+        .filter(|range| range.contains(addr.ip()))
+        .map(|range| i32::from(range.prefix_len))
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xds_client::resource::prost::listener::{
+        HttpConnectionManager, HttpFilter, HttpFilterConfig, RouteSource,
+    };
+
+    fn cidr(range: &str) -> CidrRange {
+        let (address, prefix_len) = range.split_once('/').unwrap();
+        CidrRange {
+            address: address.parse().unwrap(),
+            prefix_len: prefix_len.parse().unwrap(),
+        }
+    }
+
+    fn filter_chain(name: &str, filter_chain_match: FilterChainMatch) -> FilterChain {
+        FilterChain {
+            name: name.to_string(),
+            filter_chain_match,
+            http_connection_manager: HttpConnectionManager {
+                route_config: RouteSource::Rds("route-1".to_string()),
+                http_filters: vec![HttpFilter {
+                    name: "router".to_string(),
+                    config: HttpFilterConfig::Router,
+                }],
+            },
+            tls: None,
+        }
+    }
+
+    fn select<'a>(filter_chains: &'a [FilterChain], local: &str, remote: &str) -> &'a str {
+        select_filter_chain(
+            filter_chains,
+            local.parse().unwrap(),
+            remote.parse().unwrap(),
+        )
+        .map_or("default", |i| filter_chains[i].name.as_str())
+    }
+
+    #[test]
+    fn test_destination_prefix() {
+        let filter_chains = [
+            filter_chain("any", FilterChainMatch::default()),
+            filter_chain(
+                "wide",
+                FilterChainMatch {
+                    prefix_ranges: vec![cidr("10.0.0.0/8")],
+                    ..Default::default()
+                },
+            ),
+            filter_chain(
+                "narrow",
+                FilterChainMatch {
+                    prefix_ranges: vec![cidr("10.1.0.0/16"), cidr("192.168.0.0/16")],
+                    ..Default::default()
+                },
+            ),
+        ];
+        assert_eq!(
+            select(&filter_chains, "10.1.2.3:8080", "10.9.9.9:1000"),
+            "narrow"
+        );
+        assert_eq!(
+            select(&filter_chains, "10.2.2.3:8080", "10.9.9.9:1000"),
+            "wide"
+        );
+        assert_eq!(select(&filter_chains, "[::1]:8080", "[::1]:1000"), "any");
+    }
+
+    #[test]
+    fn test_source_criteria() {
+        let filter_chains = [
+            filter_chain(
+                "local",
+                FilterChainMatch {
+                    source_type: ConnectionSourceType::SameIpOrLoopback,
+                    ..Default::default()
+                },
+            ),
+            filter_chain(
+                "external",
+                FilterChainMatch {
+                    source_type: ConnectionSourceType::External,
+                    source_prefix_ranges: vec![cidr("192.168.0.0/16")],
+                    ..Default::default()
+                },
+            ),
+            filter_chain(
+                "external-port",
+                FilterChainMatch {
+                    source_type: ConnectionSourceType::External,
+                    source_prefix_ranges: vec![cidr("192.168.0.0/16")],
+                    source_ports: vec![443],
+                    ..Default::default()
+                },
+            ),
+        ];
+        assert_eq!(
+            select(&filter_chains, "10.0.0.1:8080", "127.0.0.1:1000"),
+            "local"
+        );
+        assert_eq!(
+            select(&filter_chains, "10.0.0.1:8080", "10.0.0.1:1000"),
+            "local"
+        );
+        assert_eq!(
+            select(&filter_chains, "10.0.0.1:8080", "192.168.1.1:1000"),
+            "external"
+        );
+        assert_eq!(
+            select(&filter_chains, "10.0.0.1:8080", "192.168.1.1:443"),
+            "external-port"
+        );
+        // No filter chain matches external connections from other ranges.
+        assert_eq!(
+            select(&filter_chains, "10.0.0.1:8080", "172.16.0.1:443"),
+            "default"
+        );
+    }
+}
@@ -1,3 +1,6 @@
+pub(crate) mod client_manager;
+pub(crate) mod fault;
+pub(crate) mod filter_chain;
 pub(crate) mod route;
 pub(crate) mod uri;
 pub(crate) mod xds_manager;
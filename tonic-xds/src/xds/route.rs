@@ -1,16 +1,1041 @@
-#![allow(dead_code)]
+use crate::common::xxhash::xxh64;
+use crate::xds::fault::{FaultFilter, InjectedFault};
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use xds_client::resource::prost::listener::{HttpFilter, HttpFilterConfig};
+use xds_client::resource::prost::route::{
+    ClusterSpecifier, ClusterSpecifierPlugin, ClusterWeight, HashPolicy, HashPolicyKind,
+    HeaderMatch, HeaderMatcher, PathMatcher, RetryPolicy, RouteAction, RouteConfiguration,
+    RouteMatch, StringMatcher, StringPattern, VirtualHost,
+};
+
 /// Represents the input for xDS routing decisions.
 pub(crate) struct RouteInput<'a> {
     /// The authority (host) of the request URI. This is used for sending LDS request to
     /// fetch the routing configurations from xDS server.
     pub authority: &'a str,
+    /// The path of the request URI, e.g. `/package.Service/Method` for gRPC requests.
+    pub path: &'a str,
     /// The HTTP headers of the request. These can be used for header-based routing decisions.
     pub headers: &'a http::HeaderMap,
 }
 
 /// Represents the routing decision made by the xDS routing layer.
-#[derive(Clone)]
+///
+/// A route forwards requests to one cluster, or splits them across weighted clusters.
+/// The cluster of each request is chosen by [`RouteDecision::pick_cluster`], unless the
+/// route delegates the choice to a cluster specifier plugin.
+#[derive(Clone, Debug)]
 pub(crate) struct RouteDecision {
-    /// The name of the cluster to which the request should be routed.
-    pub cluster: String,
+    /// The clusters to which the request may be routed, with non-zero weights.
+    clusters: Arc<[ClusterWeight]>,
+    /// The cluster specifier plugin picking the cluster of the request, if any.
+    plugin: Option<Arc<ClusterSpecifierPlugin>>,
+    /// The sum of the cluster weights.
+    total_weight: u64,
+    /// The hash of the request for consistent hashing load balancing, if computed.
+    request_hash: Option<u64>,
+    /// The faults to inject into the request, one per fault injection filter.
+    faults: Vec<InjectedFault>,
+    /// The retry policy of the route, if any.
+    retry_policy: Option<Arc<RetryPolicy>>,
+    /// The maximum duration of the request, if limited by the route.
+    max_stream_duration: Option<Duration>,
+    /// The index of the cluster picked for the request, once picked.
+    picked: Option<usize>,
+}
+
+impl RouteDecision {
+    /// Creates a decision that routes requests to a single cluster.
+    pub(crate) fn cluster(name: impl Into<String>) -> Self {
+        Self::weighted_clusters(vec![ClusterWeight {
+            name: name.into(),
+            weight: 1,
+        }])
+    }
+
+    /// Creates a decision that splits requests across clusters in proportion to their weights.
+    pub(crate) fn weighted_clusters(clusters: Vec<ClusterWeight>) -> Self {
+        let total_weight = clusters.iter().map(|c| u64::from(c.weight)).sum();
+        Self {
+            clusters: clusters.into(),
+            plugin: None,
+            total_weight,
+            request_hash: None,
+            faults: Vec::new(),
+            retry_policy: None,
+            max_stream_duration: None,
+            picked: None,
+        }
+    }
+
+    /// Creates a decision that leaves the choice of the cluster to a cluster specifier
+    /// plugin.
+    pub(crate) fn plugin(plugin: ClusterSpecifierPlugin) -> Self {
+        Self {
+            plugin: Some(Arc::new(plugin)),
+            ..Self::weighted_clusters(Vec::new())
+        }
+    }
+
+    /// Sets the retry policy and the maximum request duration of the route.
+    pub(crate) fn with_limits(
+        mut self,
+        retry_policy: Option<RetryPolicy>,
+        max_stream_duration: Option<Duration>,
+    ) -> Self {
+        self.retry_policy = retry_policy.map(Arc::new);
+        self.max_stream_duration = max_stream_duration;
+        self
+    }
+
+    /// Returns the hash of the request, used by the `RING_HASH` and `MAGLEV` cluster
+    /// load balancing policies.
+    pub(crate) fn request_hash(&self) -> Option<u64> {
+        self.request_hash
+    }
+
+    /// Returns the faults to inject into the request.
+    pub(crate) fn faults(&self) -> &[InjectedFault] {
+        &self.faults
+    }
+
+    /// Returns the retry policy of the request, if any.
+    pub(crate) fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_deref()
+    }
+
+    /// Returns the maximum duration of the request, if limited by the route.
+    pub(crate) fn max_stream_duration(&self) -> Option<Duration> {
+        self.max_stream_duration
+    }
+
+    /// Returns the cluster specifier plugin picking the cluster of the request, if any.
+    pub(crate) fn cluster_specifier_plugin(&self) -> Option<&ClusterSpecifierPlugin> {
+        self.plugin.as_deref()
+    }
+
+    /// Picks the cluster for a request, at random in proportion to the cluster weights.
+    ///
+    /// The cluster is picked once: every attempt of the request goes to the same cluster.
+    pub(crate) fn pick_cluster(&mut self) -> &str {
+        let picked = *self.picked.get_or_insert_with(|| {
+            let mut pick = match self.clusters.len() {
+                1 => 0,
+                _ => rand::random_range(0..self.total_weight.max(1)),
+            };
+            for (index, cluster) in self.clusters.iter().enumerate() {
+                let weight = u64::from(cluster.weight);
+                if pick < weight {
+                    return index;
+                }
+                pick -= weight;
+            }
+            // Only reached if there are no clusters with a weight.
+            self.clusters.len().saturating_sub(1)
+        });
+        self.clusters
+            .get(picked)
+            .map_or("", |cluster| &cluster.name)
+    }
+}
+
+/// Errors that can occur while routing a request.
+#[derive(Debug, Clone, thiserror::Error)]
+pub(crate) enum RoutingError {
+    #[error("xDS routing configuration is unavailable: {0}")]
+    Unavailable(String),
+    #[error("no virtual host matches authority {0}")]
+    NoMatchingVirtualHost(String),
+    #[error("no route matches the request")]
+    NoMatchingRoute,
+    #[error("matched route is not a forwarding route")]
+    NonForwardingAction,
+    #[error("matched route is not a non-forwarding route")]
+    ForwardingAction,
+}
+
+/// A `RouteConfiguration` prepared for routing requests, following
+/// [gRFC A28](https://github.com/grpc/proposal/blob/master/A28-xds-traffic-splitting-and-routing.md).
+#[derive(Debug)]
+pub(crate) struct RouteTable {
+    config: Arc<RouteConfiguration>,
+    /// The decisions of the routes of each virtual host, `None` for non-forwarding routes.
+    decisions: Vec<Vec<Option<RouteDecision>>>,
+    /// The fault injection filters of the routes of each virtual host.
+    faults: Vec<Vec<Vec<FaultFilter>>>,
+    /// The compiled regular expressions of the route configuration, keyed by pattern.
+    regexes: HashMap<String, Regex>,
+    /// The compiled regular expressions of the hash policy header rewrites, keyed by pattern.
+    /// Unlike matchers, these are not anchored.
+    rewrites: HashMap<String, Regex>,
+}
+
+impl RouteTable {
+    /// Creates a route table, compiling the regular expressions of the route configuration.
+    ///
+    /// `http_filters` are the HTTP filters of the listener, whose configurations may be
+    /// overridden by virtual hosts and routes.
+    pub(crate) fn new(config: Arc<RouteConfiguration>, http_filters: &[HttpFilter]) -> Self {
+        let faults: Vec<Vec<Vec<FaultFilter>>> = config
+            .virtual_hosts
+            .iter()
+            .map(|vh| {
+                vh.routes
+                    .iter()
+                    .map(|route| fault_filters(http_filters, vh, &route.filter_overrides))
+                    .collect()
+            })
+            .collect();
+
+        let mut regexes = HashMap::new();
+        let routes = config.virtual_hosts.iter().flat_map(|vh| &vh.routes);
+        let fault_headers = faults
+            .iter()
+            .flatten()
+            .flatten()
+            .flat_map(|filter| &filter.config.headers);
+        let patterns = routes
+            .flat_map(|route| {
+                let route_match = &route.route_match;
+                let path = match &route_match.path {
+                    PathMatcher::Regex(pattern) => Some(pattern),
+                    _ => None,
+                };
+                path.into_iter()
+                    .chain(route_match.headers.iter().filter_map(header_regex))
+            })
+            .chain(fault_headers.filter_map(header_regex));
+        for pattern in patterns {
+            if regexes.contains_key(pattern) {
+                continue;
+            }
+            // The patterns were validated when the resource was decoded. Should one fail
+            // to compile anyway, its matchers never match.
+            if let Ok(regex) = Regex::new(&format!("^(?:{pattern})$")) {
+                regexes.insert(pattern.clone(), regex);
+            }
+        }
+        let mut rewrites = HashMap::new();
+        let policies = config
+            .virtual_hosts
+            .iter()
+            .flat_map(|vh| &vh.routes)
+            .filter_map(|route| match &route.action {
+                RouteAction::Forward(action) => Some(&action.hash_policies),
+                _ => None,
+            })
+            .flatten();
+        for policy in policies {
+            if let HashPolicyKind::Header {
+                regex_rewrite: Some(rewrite),
+                ..
+            } = &policy.kind
+            {
+                if let Ok(regex) = Regex::new(&rewrite.pattern) {
+                    rewrites.insert(rewrite.pattern.clone(), regex);
+                }
+            }
+        }
+        let decisions = config
+            .virtual_hosts
+            .iter()
+            .map(|vh| {
+                vh.routes
+                    .iter()
+                    .map(|route| match &route.action {
+                        RouteAction::Forward(action) => {
+                            let decision = match &action.cluster {
+                                ClusterSpecifier::Cluster(name) => {
+                                    RouteDecision::cluster(name.clone())
+                                }
+                                ClusterSpecifier::WeightedClusters(clusters) => {
+                                    RouteDecision::weighted_clusters(clusters.clone())
+                                }
+                                ClusterSpecifier::Plugin(plugin) => {
+                                    RouteDecision::plugin(plugin.clone())
+                                }
+                            };
+                            Some(decision.with_limits(
+                                action.retry_policy.clone(),
+                                action.max_stream_duration,
+                            ))
+                        }
+                        RouteAction::NonForwarding | RouteAction::Unsupported => None,
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            config,
+            decisions,
+            faults,
+            regexes,
+            rewrites,
+        }
+    }
+
+    /// Routes a request.
+    ///
+    /// The virtual host is selected by matching `authority` against its domains. The first
+    /// route of the virtual host that matches the request is used. `channel_id` identifies
+    /// the channel sending the request, for the channel id hash policy.
+    pub(crate) fn route(
+        &self,
+        authority: &str,
+        input: &RouteInput<'_>,
+        channel_id: u64,
+    ) -> Result<RouteDecision, RoutingError> {
+        let vh_index = find_virtual_host(&self.config.virtual_hosts, authority)
+            .ok_or_else(|| RoutingError::NoMatchingVirtualHost(authority.to_string()))?;
+        let routes = &self.config.virtual_hosts[vh_index].routes;
+        let route_index = routes
+            .iter()
+            .position(|route| self.matches(&route.route_match, input))
+            .ok_or(RoutingError::NoMatchingRoute)?;
+        let mut decision = self.decisions[vh_index][route_index]
+            .clone()
+            .ok_or(RoutingError::NonForwardingAction)?;
+        if let RouteAction::Forward(action) = &routes[route_index].action {
+            decision.request_hash = Some(self.hash(&action.hash_policies, input, channel_id));
+        }
+        decision.faults = self.faults[vh_index][route_index]
+            .iter()
+            .filter_map(|filter| {
+                filter.decide(input.headers, |header| {
+                    self.matches_header(header, input.headers)
+                })
+            })
+            .collect();
+        Ok(decision)
+    }
+
+    /// Checks that a request received by an xDS-enabled server may be processed.
+    ///
+    /// Servers only process requests whose route has a non-forwarding action, see
+    /// [gRFC A36](https://github.com/grpc/proposal/blob/master/A36-xds-for-servers.md).
+    pub(crate) fn check_server_route(
+        &self,
+        authority: &str,
+        input: &RouteInput<'_>,
+    ) -> Result<(), RoutingError> {
+        let vh_index = find_virtual_host(&self.config.virtual_hosts, authority)
+            .ok_or_else(|| RoutingError::NoMatchingVirtualHost(authority.to_string()))?;
+        let route = self.config.virtual_hosts[vh_index]
+            .routes
+            .iter()
+            .find(|route| self.matches(&route.route_match, input))
+            .ok_or(RoutingError::NoMatchingRoute)?;
+        match route.action {
+            RouteAction::NonForwarding => Ok(()),
+            RouteAction::Forward(_) | RouteAction::Unsupported => {
+                Err(RoutingError::ForwardingAction)
+            }
+        }
+    }
+
+    /// Computes the hash of a request from the hash policies of its route.
+    ///
+    /// The hashes of the policies are combined in order, until a terminal policy produces a
+    /// hash. If no policy produces a hash, a random one is used.
+    fn hash(&self, policies: &[HashPolicy], input: &RouteInput<'_>, channel_id: u64) -> u64 {
+        let mut hash = None;
+        for policy in policies {
+            let policy_hash = match &policy.kind {
+                HashPolicyKind::Header {
+                    name,
+                    regex_rewrite,
+                } => {
+                    let Some(value) = header_value(input.headers, name) else {
+                        continue;
+                    };
+                    let value = match regex_rewrite
+                        .as_ref()
+                        .and_then(|rewrite| Some((self.rewrites.get(&rewrite.pattern)?, rewrite)))
+                    {
+                        Some((regex, rewrite)) => regex
+                            .replace_all(&value, substitution(&rewrite.substitution))
+                            .into_owned(),
+                        None => value,
+                    };
+                    xxh64(value.as_bytes(), 0)
+                }
+                HashPolicyKind::ChannelId => channel_id,
+            };
+            hash = Some(hash.map_or(policy_hash, |hash: u64| hash.rotate_left(1) ^ policy_hash));
+            if policy.terminal {
+                break;
+            }
+        }
+        hash.unwrap_or_else(rand::random)
+    }
+
+    fn matches(&self, route_match: &RouteMatch, input: &RouteInput<'_>) -> bool {
+        self.matches_path(route_match, input.path)
+            && route_match
+                .headers
+                .iter()
+                .all(|header| self.matches_header(header, input.headers))
+            && route_match
+                .fraction_per_million
+                .map_or(true, |fraction| rand::random_range(0..1_000_000) < fraction)
+    }
+
+    fn matches_path(&self, route_match: &RouteMatch, path: &str) -> bool {
+        let case_sensitive = route_match.case_sensitive;
+        match &route_match.path {
+            PathMatcher::Prefix(prefix) if case_sensitive => path.starts_with(prefix.as_str()),
+            PathMatcher::Prefix(prefix) => path
+                .get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix)),
+            PathMatcher::Path(exact) if case_sensitive => path == exact,
+            PathMatcher::Path(exact) => path.eq_ignore_ascii_case(exact),
+            // `case_sensitive` does not apply to regular expressions.
+            PathMatcher::Regex(pattern) => self.matches_regex(pattern, path),
+        }
+    }
+
+    fn matches_header(&self, header: &HeaderMatcher, headers: &http::HeaderMap) -> bool {
+        let value = header_value(headers, &header.name);
+        let matched = match (&header.matcher, value) {
+            (HeaderMatch::Present(present), value) => *present == value.is_some(),
+            // Other matchers never match an absent header, even when inverted.
+            (_, None) => return false,
+            (HeaderMatch::Range { start, end }, Some(value)) => value
+                .parse::<i64>()
+                .is_ok_and(|value| *start <= value && value < *end),
+            (HeaderMatch::String(matcher), Some(value)) => self.matches_string(matcher, &value),
+        };
+        matched != header.invert_match
+    }
+
+    fn matches_string(&self, matcher: &StringMatcher, value: &str) -> bool {
+        let fold = |s| fold_case(s, matcher.ignore_case);
+        match &matcher.pattern {
+            StringPattern::Exact(pattern) => fold(value) == fold(pattern),
+            StringPattern::Prefix(pattern) => fold(value).starts_with(fold(pattern).as_ref()),
+            StringPattern::Suffix(pattern) => fold(value).ends_with(fold(pattern).as_ref()),
+            StringPattern::Contains(pattern) => fold(value).contains(fold(pattern).as_ref()),
+            // `ignore_case` does not apply to regular expressions.
+            StringPattern::Regex(pattern) => self.matches_regex(pattern, value),
+        }
+    }
+
+    fn matches_regex(&self, pattern: &str, value: &str) -> bool {
+        self.regexes
+            .get(pattern)
+            .is_some_and(|regex| regex.is_match(value))
+    }
+}
+
+/// Returns the regular expression of a header matcher, if it has one.
+fn header_regex(header: &HeaderMatcher) -> Option<&String> {
+    match &header.matcher {
+        HeaderMatch::String(StringMatcher {
+            pattern: StringPattern::Regex(pattern),
+            ..
+        }) => Some(pattern),
+        _ => None,
+    }
+}
+
+/// Returns the fault injection filters of a route, with their configurations overridden by
+/// the route or else its virtual host.
+fn fault_filters(
+    http_filters: &[HttpFilter],
+    virtual_host: &VirtualHost,
+    route_overrides: &HashMap<String, HttpFilterConfig>,
+) -> Vec<FaultFilter> {
+    http_filters
+        .iter()
+        .filter_map(|filter| {
+            let HttpFilterConfig::Fault(config) = &filter.config else {
+                return None;
+            };
+            let config = [route_overrides, &virtual_host.filter_overrides]
+                .into_iter()
+                .find_map(|overrides| match overrides.get(&filter.name) {
+                    Some(HttpFilterConfig::Fault(config)) => Some(config),
+                    _ => None,
+                })
+                .unwrap_or(config);
+            Some(FaultFilter {
+                name: filter.name.as_str().into(),
+                config: Arc::new(config.clone()),
+            })
+        })
+        .collect()
+}
+
+/// Lowercases `s` if matching ignores case.
+fn fold_case(s: &str, ignore_case: bool) -> Cow<'_, str> {
+    if ignore_case {
+        Cow::Owned(s.to_ascii_lowercase())
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+/// Returns the value of a header for matching, joining multiple values with commas.
+///
+/// Binary headers are treated as absent.
+fn header_value(headers: &http::HeaderMap, name: &str) -> Option<String> {
+    if name.ends_with("-bin") {
+        return None;
+    }
+    let mut values = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .peekable();
+    values.peek()?;
+    Some(values.collect::<Vec<_>>().join(","))
+}
+
+/// Converts an RE2 substitution, where `\N` refers to a capture group, to the syntax of
+/// [`Regex::replace_all`].
+fn substitution(re2: &str) -> String {
+    let mut replacement = String::with_capacity(re2.len());
+    let mut chars = re2.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(digit)) if digit.is_ascii_digit() => {
+                replacement.push_str(&format!("${{{digit}}}"));
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                replacement.push('\\');
+                chars.next();
+            }
+            ('$', _) => replacement.push_str("$$"),
+            (c, _) => replacement.push(c),
+        }
+    }
+    replacement
+}
+
+/// How a virtual host domain matches a host, in increasing order of preference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum DomainMatch {
+    /// `*`
+    Universal,
+    /// `foo.*`
+    PrefixWildcard,
+    /// `*.foo.com`
+    SuffixWildcard,
+    /// `foo.com`
+    Exact,
+}
+
+/// Returns how `domain` matches `host`, if it does.
+fn match_domain(domain: &str, host: &str) -> Option<DomainMatch> {
+    let domain = domain.to_ascii_lowercase();
+    if domain == "*" {
+        return Some(DomainMatch::Universal);
+    }
+    if let Some(suffix) = domain.strip_prefix('*') {
+        return host
+            .ends_with(suffix)
+            .then_some(DomainMatch::SuffixWildcard);
+    }
+    if let Some(prefix) = domain.strip_suffix('*') {
+        return host
+            .starts_with(prefix)
+            .then_some(DomainMatch::PrefixWildcard);
+    }
+    (domain == host).then_some(DomainMatch::Exact)
+}
+
+/// Finds the index of the virtual host for `authority`.
+///
+/// Exact domains are preferred over suffix wildcards, then prefix wildcards, then `*`.
+/// Among wildcards of the same kind, the longest domain wins.
+fn find_virtual_host(virtual_hosts: &[VirtualHost], authority: &str) -> Option<usize> {
+    let host = authority.to_ascii_lowercase();
+    let mut best: Option<((DomainMatch, usize), usize)> = None;
+    for (index, virtual_host) in virtual_hosts.iter().enumerate() {
+        for domain in &virtual_host.domains {
+            let Some(domain_match) = match_domain(domain, &host) else {
+                continue;
+            };
+            let rank = (domain_match, domain.len());
+            if best.map_or(true, |(best_rank, _)| rank > best_rank) {
+                best = Some((rank, index));
+            }
+        }
+    }
+    best.map(|(_, index)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xds_client::resource::prost::route::{ForwardingAction, Route};
+
+    fn path_match(path: PathMatcher) -> RouteMatch {
+        RouteMatch {
+            path,
+            case_sensitive: true,
+            headers: Vec::new(),
+            fraction_per_million: None,
+        }
+    }
+
+    fn prefix(prefix: &str) -> RouteMatch {
+        path_match(PathMatcher::Prefix(prefix.to_string()))
+    }
+
+    fn forward(route_match: RouteMatch, cluster: &str) -> Route {
+        Route {
+            route_match,
+            action: RouteAction::Forward(ForwardingAction {
+                cluster: ClusterSpecifier::Cluster(cluster.to_string()),
+                hash_policies: Vec::new(),
+                retry_policy: None,
+                max_stream_duration: None,
+            }),
+            filter_overrides: HashMap::new(),
+        }
+    }
+
+    fn virtual_host(domains: &[&str], routes: Vec<Route>) -> VirtualHost {
+        VirtualHost {
+            name: domains[0].to_string(),
+            domains: domains.iter().map(|d| d.to_string()).collect(),
+            routes,
+            filter_overrides: HashMap::new(),
+        }
+    }
+
+    fn route_table(virtual_hosts: Vec<VirtualHost>) -> RouteTable {
+        RouteTable::new(
+            Arc::new(RouteConfiguration {
+                name: "route-1".to_string(),
+                virtual_hosts,
+            }),
+            &[],
+        )
+    }
+
+    fn header_map(headers: &[(&str, &str)]) -> http::HeaderMap {
+        let mut header_map = http::HeaderMap::new();
+        for (name, value) in headers {
+            header_map.append(
+                http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        header_map
+    }
+
+    fn decide(
+        table: &RouteTable,
+        authority: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<String, RoutingError> {
+        let input = RouteInput {
+            authority,
+            path,
+            headers: &header_map(headers),
+        };
+        table
+            .route(authority, &input, 0)
+            .map(|mut decision| decision.pick_cluster().to_string())
+    }
+
+    fn header(name: &str, matcher: HeaderMatch, invert_match: bool) -> HeaderMatcher {
+        HeaderMatcher {
+            name: name.to_string(),
+            matcher,
+            invert_match,
+        }
+    }
+
+    fn string(pattern: StringPattern, ignore_case: bool) -> HeaderMatch {
+        HeaderMatch::String(StringMatcher {
+            pattern,
+            ignore_case,
+        })
+    }
+
+    #[test]
+    fn test_virtual_host_domain_matching() {
+        let table = route_table(vec![
+            virtual_host(&["*"], vec![forward(prefix(""), "universal")]),
+            virtual_host(&["foo.*"], vec![forward(prefix(""), "prefix")]),
+            virtual_host(&["*.example.com"], vec![forward(prefix(""), "suffix")]),
+            virtual_host(
+                &["*.api.example.com"],
+                vec![forward(prefix(""), "longer-suffix")],
+            ),
+            virtual_host(&["Foo.Example.com"], vec![forward(prefix(""), "exact")]),
+        ]);
+        let cases = [
+            ("foo.example.com", "exact"),
+            ("FOO.example.COM", "exact"),
+            ("bar.example.com", "suffix"),
+            ("v1.api.example.com", "longer-suffix"),
+            ("foo.test", "prefix"),
+            ("other", "universal"),
+        ];
+        for (authority, expected) in cases {
+            assert_eq!(
+                decide(&table, authority, "/", &[]).unwrap(),
+                expected,
+                "{authority}"
+            );
+        }
+
+        let table = route_table(vec![virtual_host(&["myservice"], Vec::new())]);
+        assert!(matches!(
+            decide(&table, "other", "/", &[]),
+            Err(RoutingError::NoMatchingVirtualHost(_))
+        ));
+        assert!(matches!(
+            decide(&table, "myservice", "/", &[]),
+            Err(RoutingError::NoMatchingRoute)
+        ));
+    }
+
+    #[test]
+    fn test_path_matching() {
+        let mut case_insensitive = prefix("/PKG.Svc/");
+        case_insensitive.case_sensitive = false;
+        let table = route_table(vec![virtual_host(
+            &["*"],
+            vec![
+                forward(
+                    path_match(PathMatcher::Path("/pkg.Svc/Exact".to_string())),
+                    "exact",
+                ),
+                forward(
+                    path_match(PathMatcher::Regex("/pkg\\.Svc/Re.*".to_string())),
+                    "regex",
+                ),
+                forward(case_insensitive, "prefix"),
+                forward(prefix("/"), "default"),
+            ],
+        )]);
+        let cases = [
+            ("/pkg.Svc/Exact", "exact"),
+            ("/pkg.Svc/exact", "prefix"),
+            ("/pkg.Svc/Regex", "regex"),
+            // Regular expressions must match the whole path.
+            ("/x/pkg.Svc/Regex", "default"),
+            ("/PKG.svc/Other", "prefix"),
+            ("/other.Svc/Method", "default"),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(
+                decide(&table, "svc", path, &[]).unwrap(),
+                expected,
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_header_matching() {
+        let route = |matcher: HeaderMatcher, cluster: &str| {
+            let mut route_match = prefix("");
+            route_match.headers.push(matcher);
+            forward(route_match, cluster)
+        };
+        let table = route_table(vec![virtual_host(
+            &["*"],
+            vec![
+                route(
+                    header(
+                        "x-exact",
+                        string(StringPattern::Exact("A".into()), true),
+                        false,
+                    ),
+                    "exact",
+                ),
+                route(
+                    header(
+                        "x-prefix",
+                        string(StringPattern::Prefix("pre".into()), false),
+                        false,
+                    ),
+                    "prefix",
+                ),
+                route(
+                    header(
+                        "x-suffix",
+                        string(StringPattern::Suffix("fix".into()), false),
+                        false,
+                    ),
+                    "suffix",
+                ),
+                route(
+                    header(
+                        "x-regex",
+                        string(StringPattern::Regex("[0-9]+".into()), false),
+                        false,
+                    ),
+                    "regex",
+                ),
+                route(
+                    header("x-range", HeaderMatch::Range { start: 10, end: 20 }, false),
+                    "range",
+                ),
+                route(
+                    header("x-present", HeaderMatch::Present(true), false),
+                    "present",
+                ),
+                route(header("x-bin", HeaderMatch::Present(true), false), "binary"),
+                route(
+                    header(
+                        "x-inverted",
+                        string(StringPattern::Exact("no".into()), false),
+                        true,
+                    ),
+                    "inverted",
+                ),
+                forward(prefix(""), "default"),
+            ],
+        )]);
+        let cases: [(&[(&str, &str)], &str); 11] = [
+            (&[("x-exact", "a")], "exact"),
+            (&[("x-prefix", "prefixed")], "prefix"),
+            (&[("x-suffix", "suffix")], "suffix"),
+            (&[("x-regex", "123")], "regex"),
+            (&[("x-regex", "123a")], "default"),
+            (&[("x-range", "15")], "range"),
+            (&[("x-range", "20")], "default"),
+            (&[("x-present", "")], "present"),
+            // Multiple values are joined with commas.
+            (&[("x-prefix", "a"), ("x-prefix", "pre")], "default"),
+            (&[("x-bin", "value"), ("x-inverted", "no")], "default"),
+            (&[("x-inverted", "yes")], "inverted"),
+        ];
+        for (headers, expected) in cases {
+            assert_eq!(
+                decide(&table, "svc", "/", headers).unwrap(),
+                expected,
+                "{headers:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_runtime_fraction_and_actions() {
+        let mut never = prefix("");
+        never.fraction_per_million = Some(0);
+        let mut always = prefix("/always");
+        always.fraction_per_million = Some(1_000_000);
+        let table = route_table(vec![virtual_host(
+            &["*"],
+            vec![
+                forward(never, "never"),
+                forward(always, "always"),
+                Route {
+                    route_match: prefix("/non-forwarding"),
+                    action: RouteAction::NonForwarding,
+                    filter_overrides: HashMap::new(),
+                },
+                forward(prefix(""), "default"),
+            ],
+        )]);
+        for _ in 0..100 {
+            assert_eq!(decide(&table, "svc", "/", &[]).unwrap(), "default");
+            assert_eq!(decide(&table, "svc", "/always", &[]).unwrap(), "always");
+        }
+        assert!(matches!(
+            decide(&table, "svc", "/non-forwarding", &[]),
+            Err(RoutingError::NonForwardingAction)
+        ));
+    }
+
+    #[test]
+    fn test_check_server_route() {
+        let table = route_table(vec![virtual_host(
+            &["*"],
+            vec![
+                Route {
+                    route_match: prefix("/pkg.Svc/"),
+                    action: RouteAction::NonForwarding,
+                    filter_overrides: HashMap::new(),
+                },
+                forward(prefix("/forward"), "cluster"),
+            ],
+        )]);
+        let check = |path| {
+            let headers = http::HeaderMap::new();
+            let input = RouteInput {
+                authority: "svc",
+                path,
+                headers: &headers,
+            };
+            table.check_server_route("svc", &input)
+        };
+        assert!(check("/pkg.Svc/Method").is_ok());
+        assert!(matches!(
+            check("/forward"),
+            Err(RoutingError::ForwardingAction)
+        ));
+        assert!(matches!(
+            check("/other"),
+            Err(RoutingError::NoMatchingRoute)
+        ));
+    }
+
+    #[test]
+    fn test_weighted_clusters() {
+        let decision = RouteDecision::weighted_clusters(vec![
+            ClusterWeight {
+                name: "a".to_string(),
+                weight: 1,
+            },
+            ClusterWeight {
+                name: "b".to_string(),
+                weight: 3,
+            },
+        ]);
+        let table = route_table(vec![virtual_host(
+            &["*"],
+            vec![Route {
+                route_match: prefix(""),
+                action: RouteAction::Forward(ForwardingAction {
+                    cluster: ClusterSpecifier::WeightedClusters(decision.clusters.to_vec()),
+                    hash_policies: Vec::new(),
+                    retry_policy: None,
+                    max_stream_duration: None,
+                }),
+                filter_overrides: HashMap::new(),
+            }],
+        )]);
+
+        let num_picks = 10_000;
+        let mut counts = HashMap::new();
+        for _ in 0..num_picks {
+            let cluster = decide(&table, "svc", "/", &[]).unwrap();
+            *counts.entry(cluster).or_insert(0) += 1;
+        }
+        let b = counts["b"] as f64 / num_picks as f64;
+        assert!((0.7..0.8).contains(&b), "{counts:?}");
+        assert_eq!(counts.len(), 2);
+        assert_eq!(RouteDecision::cluster("c").pick_cluster(), "c");
+
+        // Every attempt of a request goes to the cluster picked first.
+        let mut decision = decision;
+        let picked = decision.pick_cluster().to_string();
+        for _ in 0..100 {
+            assert_eq!(decision.clone().pick_cluster(), picked);
+        }
+    }
+
+    #[test]
+    fn test_cluster_specifier_plugin() {
+        use xds_client::resource::prost::route::ClusterSpecifierPluginConfig;
+
+        let plugin = ClusterSpecifierPlugin {
+            name: "rls".to_string(),
+            config: ClusterSpecifierPluginConfig::RouteLookup(Default::default()),
+        };
+        let mut plugin_route = forward(prefix("/plugin"), "");
+        if let RouteAction::Forward(action) = &mut plugin_route.action {
+            action.cluster = ClusterSpecifier::Plugin(plugin.clone());
+        }
+        let table = route_table(vec![virtual_host(
+            &["*"],
+            vec![plugin_route, forward(prefix("/"), "c")],
+        )]);
+        let headers = http::HeaderMap::new();
+        let route = |path| {
+            let input = RouteInput {
+                authority: "svc",
+                path,
+                headers: &headers,
+            };
+            table.route("svc", &input, 0).unwrap()
+        };
+
+        assert_eq!(route("/plugin").cluster_specifier_plugin(), Some(&plugin));
+        let mut decision = route("/other");
+        assert_eq!(decision.cluster_specifier_plugin(), None);
+        assert_eq!(decision.pick_cluster(), "c");
+    }
+
+    #[test]
+    fn test_request_hash() {
+        use xds_client::resource::prost::route::RegexRewrite;
+
+        let hash_policy = |kind: HashPolicyKind, terminal: bool| HashPolicy { kind, terminal };
+        let header_policy = |name: &str, terminal: bool| {
+            hash_policy(
+                HashPolicyKind::Header {
+                    name: name.to_string(),
+                    regex_rewrite: None,
+                },
+                terminal,
+            )
+        };
+        let route = |path: &str, hash_policies: Vec<HashPolicy>| Route {
+            route_match: prefix(path),
+            action: RouteAction::Forward(ForwardingAction {
+                cluster: ClusterSpecifier::Cluster("c".to_string()),
+                hash_policies,
+                retry_policy: None,
+                max_stream_duration: None,
+            }),
+            filter_overrides: HashMap::new(),
+        };
+        let rewrite = HashPolicyKind::Header {
+            name: "x-session".to_string(),
+            regex_rewrite: Some(RegexRewrite {
+                pattern: "v(\\d+)".to_string(),
+                substitution: "n\\1".to_string(),
+            }),
+        };
+        let table = route_table(vec![virtual_host(
+            &["*"],
+            vec![
+                route(
+                    "/terminal",
+                    vec![header_policy("x-a", true), header_policy("x-b", false)],
+                ),
+                route(
+                    "/combined",
+                    vec![
+                        header_policy("x-a", false),
+                        hash_policy(HashPolicyKind::ChannelId, false),
+                    ],
+                ),
+                route("/rewrite", vec![hash_policy(rewrite, false)]),
+                route("/", Vec::new()),
+            ],
+        )]);
+        let hash = |path: &str, headers: &[(&str, &str)]| {
+            let input = RouteInput {
+                authority: "svc",
+                path,
+                headers: &header_map(headers),
+            };
+            table
+                .route("svc", &input, 7)
+                .unwrap()
+                .request_hash()
+                .unwrap()
+        };
+
+        let a = xxh64(b"a", 0);
+        let b = xxh64(b"b", 0);
+        assert_eq!(hash("/terminal", &[("x-a", "a"), ("x-b", "b")]), a);
+        // A terminal policy without a hash does not stop the next ones.
+        assert_eq!(hash("/terminal", &[("x-b", "b")]), b);
+        assert_eq!(hash("/combined", &[("x-a", "a")]), a.rotate_left(1) ^ 7);
+        assert_eq!(hash("/combined", &[]), 7);
+        assert_eq!(
+            hash("/rewrite", &[("x-session", "v1-v22")]),
+            xxh64(b"n1-n22", 0)
+        );
+        // Without a hash from the policies, requests are hashed at random.
+        assert_ne!(hash("/", &[]), hash("/", &[]));
+    }
 }
@@ -12,9 +12,14 @@ pub enum XdsUriError {
     InvalidUri(#[from] url::ParseError),
 }
 
-/// An xDS target URI (e.g., `xds:///my-service`).
+/// An xDS target URI (e.g., `xds:///my-service` or `xds://my-authority/my-service`).
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct XdsUri {
+    /// The xDS authority of the target, if the URI names one.
+    ///
+    /// The authority selects the `authorities` entry of the bootstrap configuration used to
+    /// resolve the target, see [gRFC A47](https://github.com/grpc/proposal/blob/master/A47-xds-federation.md).
+    pub authority: Option<String>,
     /// The target service name extracted from the URI.
     pub target: String,
 }
@@ -36,6 +41,11 @@ impl XdsUri {
     /// use tonic_xds::XdsUri;
     ///
     /// let uri = XdsUri::parse("xds:///my-service").expect("Failed to parse valid xDS URI");
+    /// assert_eq!(uri.authority, None);
+    /// assert_eq!(uri.target, "my-service");
+    ///
+    /// let uri = XdsUri::parse("xds://xds.example.com/my-service").expect("Failed to parse valid xDS URI");
+    /// assert_eq!(uri.authority.as_deref(), Some("xds.example.com"));
     /// assert_eq!(uri.target, "my-service");
     ///
     /// let invalid_uri = XdsUri::parse("http:///my-service");
@@ -49,8 +59,15 @@ impl XdsUri {
             return Err(XdsUriError::InvalidScheme(uri.scheme().to_string()));
         }
 
+        let authority =
+            uri.host_str()
+                .filter(|host| !host.is_empty())
+                .map(|host| match uri.port() {
+                    Some(port) => format!("{host}:{port}"),
+                    None => host.to_string(),
+                });
         let target = uri.path().trim_start_matches('/').to_string();
 
-        Ok(Self { target })
+        Ok(Self { authority, target })
     }
 }
@@ -1,25 +1,87 @@
+use crate::client::circuit_breaker::RequestsCounter;
+use crate::client::endpoint::LocalizedEndpoint;
 use crate::common::async_util::BoxFuture;
 use std::pin::Pin;
+use tokio::sync::watch;
 use tower::{discover::Change, BoxError};
+use xds_client::resource::prost::cluster::{LbPolicy, OutlierDetection};
+use xds_client::resource::prost::endpoint::DropOverload;
+use xds_client::ClusterLoadStats;
 
-use crate::xds::route::{RouteDecision, RouteInput};
+use crate::xds::route::{RouteDecision, RouteInput, RoutingError};
 
-pub(crate) type BoxDiscover<Endpoint, S> =
-    Pin<Box<dyn futures_core::Stream<Item = Result<Change<Endpoint, S>, BoxError>> + Send>>;
+/// A stream of endpoint changes of a cluster. Inserted endpoints carry their locality.
+pub(crate) type BoxDiscover<Endpoint, S> = Pin<
+    Box<
+        dyn futures_core::Stream<Item = Result<Change<Endpoint, LocalizedEndpoint<S>>, BoxError>>
+            + Send,
+    >,
+>;
+
+/// The endpoints of a cluster and how requests are load balanced across them.
+pub(crate) struct ClusterDiscovery<D> {
+    /// The endpoint changes of the cluster.
+    pub endpoints: D,
+    /// The configuration of the cluster, updated when the cluster changes.
+    pub config: watch::Receiver<ClusterConfig>,
+}
+
+/// The configuration of a cluster that applies to the requests sent to its endpoints.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ClusterConfig {
+    /// The load balancing policy across endpoints.
+    pub lb_policy: LbPolicy,
+    /// The outlier detection of the endpoints, if enabled.
+    pub outlier_detection: Option<OutlierDetection>,
+    /// The maximum number of concurrent requests to the cluster.
+    pub max_requests: u32,
+    /// The categories of requests to drop, from the load assignment of the cluster.
+    pub drop_overloads: Vec<DropOverload>,
+    /// The load of the cluster reported to the LRS server, if enabled.
+    pub load_stats: Option<ClusterLoadStats>,
+    /// The requests in flight to the cluster, limited by `max_requests`.
+    pub requests: RequestsCounter,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            lb_policy: LbPolicy::RoundRobin,
+            outlier_detection: None,
+            // The default of gRFC A32, until the cluster is received.
+            max_requests: 1024,
+            drop_overloads: Vec::new(),
+            load_stats: None,
+            requests: RequestsCounter::default(),
+        }
+    }
+}
+
+/// The configuration of a cluster resource, before its load assignment is received and
+/// without load reporting.
+impl From<&xds_client::Cluster> for ClusterConfig {
+    fn from(cluster: &xds_client::Cluster) -> Self {
+        Self {
+            lb_policy: cluster.lb_policy.clone(),
+            outlier_detection: cluster.outlier_detection.clone(),
+            max_requests: cluster.max_requests,
+            ..Default::default()
+        }
+    }
+}
 
 /// Trait for routing requests to clusters based on xDS routing configurations.
 pub(crate) trait XdsRouter: Send + Sync + 'static {
-    fn route(&self, input: &RouteInput<'_>) -> BoxFuture<RouteDecision>;
+    fn route(&self, input: &RouteInput<'_>) -> BoxFuture<Result<RouteDecision, RoutingError>>;
 }
 
 /// Trait for discovering cluster endpoints based on xDS cluster configurations.
 pub(crate) trait XdsClusterDiscovery<Endpoint, S>: Send + Sync + 'static {
-    fn discover_cluster(&self, cluster_name: &str) -> BoxDiscover<Endpoint, S>;
+    fn discover_cluster(&self, cluster_name: &str) -> ClusterDiscovery<BoxDiscover<Endpoint, S>>;
 }
 
 /// Combined trait for xDS management (routing + load balancing).
 /// Automatically implemented for any type that implements both `XdsRouter` and `XdsClusterDiscovery`.
-#[allow(dead_code)]
 pub(crate) trait XdsManager<Endpoint, S>:
     XdsRouter + XdsClusterDiscovery<Endpoint, S>
 {
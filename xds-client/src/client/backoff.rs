@@ -0,0 +1,81 @@
+//! Exponential backoff between ADS stream connection attempts.
+//!
+//! The parameters match gRPC connection backoff, see
+//! <https://github.com/grpc/grpc/blob/master/doc/connection-backoff.md>.
+
+use rand::Rng;
+use std::time::Duration;
+
+/// The amount of time to backoff after the first failure.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// The factor with which to multiply backoffs after a failed attempt.
+const MULTIPLIER: f64 = 1.6;
+
+/// The factor with which backoffs are randomized.
+const JITTER: f64 = 0.2;
+
+/// The upper bound of backoff delay.
+const MAX_DELAY: Duration = Duration::from_secs(120);
+
+/// Jittered exponential backoff.
+#[derive(Debug)]
+pub(crate) struct ExponentialBackoff {
+    /// The delay for the next attempt, without the random jitter. Stored as f64
+    /// to avoid rounding errors.
+    next_delay_secs: f64,
+}
+
+impl ExponentialBackoff {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_delay_secs: BASE_DELAY.as_secs_f64(),
+        }
+    }
+
+    /// Reset the delay to the base delay, after a successful attempt.
+    pub(crate) fn reset(&mut self) {
+        self.next_delay_secs = BASE_DELAY.as_secs_f64();
+    }
+
+    /// Returns the delay before the next attempt and increases the following one.
+    pub(crate) fn backoff_duration(&mut self) -> Duration {
+        let next_delay = self.next_delay_secs;
+        let cur_delay = next_delay * (1.0 + JITTER * rand::rng().random_range(-1.0..1.0));
+        self.next_delay_secs = MAX_DELAY.as_secs_f64().min(next_delay * MULTIPLIER);
+        Duration::from_secs_f64(cur_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_jittered(delay: Duration, expected_secs: f64) {
+        let secs = delay.as_secs_f64();
+        assert!(
+            secs >= expected_secs * (1.0 - JITTER) && secs <= expected_secs * (1.0 + JITTER),
+            "{secs} is not within jitter of {expected_secs}"
+        );
+    }
+
+    #[test]
+    fn test_backoff_grows_up_to_max_delay() {
+        let mut backoff = ExponentialBackoff::new();
+        let mut expected = BASE_DELAY.as_secs_f64();
+        for _ in 0..20 {
+            assert_jittered(backoff.backoff_duration(), expected);
+            expected = (expected * MULTIPLIER).min(MAX_DELAY.as_secs_f64());
+        }
+        assert_eq!(expected, MAX_DELAY.as_secs_f64());
+    }
+
+    #[test]
+    fn test_backoff_reset() {
+        let mut backoff = ExponentialBackoff::new();
+        backoff.backoff_duration();
+        backoff.backoff_duration();
+        backoff.reset();
+        assert_jittered(backoff.backoff_duration(), BASE_DELAY.as_secs_f64());
+    }
+}
@@ -0,0 +1,370 @@
+//! Parsing of the gRPC xDS bootstrap JSON.
+//!
+//! The JSON is first deserialized into the raw types below, which mirror the file
+//! format, and then validated and converted into a [`ClientConfig`].
+
+use crate::client::config::{
+    AuthorityConfig, CertificateProviderConfig, ChannelCredentialsConfig, ClientConfig,
+    ServerConfig,
+};
+use crate::error::{Error, Result};
+use crate::message::{Locality, Node, Value};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize)]
+struct RawBootstrap {
+    #[serde(default)]
+    xds_servers: Vec<RawServer>,
+    node: Option<RawNode>,
+    #[serde(default)]
+    authorities: HashMap<String, RawAuthority>,
+    client_default_listener_resource_name_template: Option<String>,
+    server_listener_resource_name_template: Option<String>,
+    #[serde(default)]
+    certificate_providers: HashMap<String, RawCertificateProvider>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawServer {
+    server_uri: Option<String>,
+    #[serde(default)]
+    channel_creds: Vec<RawChannelCreds>,
+    #[serde(default)]
+    server_features: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawChannelCreds {
+    #[serde(rename = "type")]
+    type_name: String,
+    config: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawNode {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    cluster: String,
+    #[serde(default)]
+    metadata: serde_json::Map<String, serde_json::Value>,
+    locality: Option<RawLocality>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawLocality {
+    #[serde(default)]
+    region: String,
+    #[serde(default)]
+    zone: String,
+    #[serde(default)]
+    sub_zone: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawAuthority {
+    client_listener_resource_name_template: Option<String>,
+    xds_servers: Option<Vec<RawServer>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawCertificateProvider {
+    plugin_name: String,
+    config: Option<serde_json::Value>,
+}
+
+/// Parse and validate bootstrap JSON.
+pub(crate) fn parse(json: &str) -> Result<ClientConfig> {
+    let raw: RawBootstrap =
+        serde_json::from_str(json).map_err(|e| Error::Bootstrap(e.to_string()))?;
+
+    if raw.xds_servers.is_empty() {
+        return Err(Error::Bootstrap(
+            "xds_servers must not be empty".to_string(),
+        ));
+    }
+    let servers = convert_servers(raw.xds_servers)?;
+
+    let node = raw.node.map(convert_node).unwrap_or_default();
+
+    let authorities = raw
+        .authorities
+        .into_iter()
+        .map(|(name, authority)| {
+            let authority = convert_authority(&name, authority)?;
+            Ok((name, authority))
+        })
+        .collect::<Result<_>>()?;
+
+    let certificate_providers = raw
+        .certificate_providers
+        .into_iter()
+        .map(|(name, provider)| {
+            let provider = CertificateProviderConfig {
+                plugin_name: provider.plugin_name,
+                config: provider.config.map(convert_value),
+            };
+            (name, provider)
+        })
+        .collect();
+
+    let mut config = ClientConfig::new(node).with_servers(servers);
+    config.authorities = authorities;
+    if let Some(template) = raw.client_default_listener_resource_name_template {
+        config.client_default_listener_resource_name_template = template;
+    }
+    config.server_listener_resource_name_template = raw.server_listener_resource_name_template;
+    config.certificate_providers = certificate_providers;
+    Ok(config)
+}
+
+fn convert_servers(servers: Vec<RawServer>) -> Result<Vec<ServerConfig>> {
+    servers.into_iter().map(convert_server).collect()
+}
+
+fn convert_server(server: RawServer) -> Result<ServerConfig> {
+    let server_uri = server
+        .server_uri
+        .filter(|uri| !uri.is_empty())
+        .ok_or_else(|| Error::Bootstrap("server_uri must be set".to_string()))?;
+    if server.channel_creds.is_empty() {
+        return Err(Error::Bootstrap(format!(
+            "channel_creds must not be empty for server {server_uri}"
+        )));
+    }
+    let channel_creds = server
+        .channel_creds
+        .into_iter()
+        .map(|creds| ChannelCredentialsConfig {
+            type_name: creds.type_name,
+            config: creds.config.map(convert_value),
+        })
+        .collect();
+    Ok(ServerConfig {
+        server_uri,
+        channel_creds,
+        server_features: server.server_features,
+    })
+}
+
+fn convert_node(node: RawNode) -> Node {
+    Node {
+        id: node.id,
+        cluster: node.cluster,
+        metadata: node
+            .metadata
+            .into_iter()
+            .map(|(name, value)| (name, convert_value(value)))
+            .collect(),
+        locality: node.locality.map(|locality| Locality {
+            region: locality.region,
+            zone: locality.zone,
+            sub_zone: locality.sub_zone,
+        }),
+    }
+}
+
+fn convert_authority(name: &str, authority: RawAuthority) -> Result<AuthorityConfig> {
+    let prefix = format!("xdstp://{name}/");
+    let mut config = AuthorityConfig::new(name)
+        .with_servers(convert_servers(authority.xds_servers.unwrap_or_default())?);
+    match authority.client_listener_resource_name_template {
+        Some(template) if !template.starts_with(&prefix) => {
+            return Err(Error::Bootstrap(format!(
+                "client_listener_resource_name_template of authority {name} must start with {prefix}"
+            )));
+        }
+        Some(template) => config.client_listener_resource_name_template = template,
+        None => {}
+    }
+    Ok(config)
+}
+
+fn convert_value(value: serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Bool(b),
+        serde_json::Value::Number(n) => Value::Number(n.as_f64().unwrap_or_default()),
+        serde_json::Value::String(s) => Value::String(s),
+        serde_json::Value::Array(values) => {
+            Value::List(values.into_iter().map(convert_value).collect())
+        }
+        serde_json::Value::Object(fields) => Value::Struct(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name, convert_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    const FULL_BOOTSTRAP: &str = r#"{
+        "xds_servers": [
+            {
+                "server_uri": "xds.example.com:443",
+                "channel_creds": [{"type": "google_default"}, {"type": "insecure"}],
+                "server_features": ["xds_v3", "ignore_resource_deletion"]
+            },
+            {
+                "server_uri": "fallback.example.com:443",
+                "channel_creds": [{"type": "tls", "config": {"ca_certificate_file": "/ca.pem"}}]
+            }
+        ],
+        "node": {
+            "id": "node-1",
+            "cluster": "cluster-1",
+            "metadata": {"TRAFFICDIRECTOR_NETWORK_NAME": "default", "weight": 2},
+            "locality": {"region": "us-west", "zone": "us-west-1a", "sub_zone": "rack-1"}
+        },
+        "authorities": {
+            "region-a": {
+                "xds_servers": [
+                    {"server_uri": "region-a.example.com:443", "channel_creds": [{"type": "insecure"}]}
+                ]
+            },
+            "region-b": {
+                "client_listener_resource_name_template": "xdstp://region-b/envoy.config.listener.v3.Listener/svc/%s"
+            }
+        },
+        "client_default_listener_resource_name_template": "xdstp://region-a/envoy.config.listener.v3.Listener/%s",
+        "server_listener_resource_name_template": "grpc/server?xds.resource.listening_address=%s",
+        "certificate_providers": {
+            "default": {"plugin_name": "file_watcher", "config": {"refresh_interval": "60s"}}
+        },
+        "unknown_field": true
+    }"#;
+
+    #[test]
+    fn test_parse_full_bootstrap() {
+        let config = parse(FULL_BOOTSTRAP).unwrap();
+
+        assert_eq!(config.servers.len(), 2);
+        let server = &config.servers[0];
+        assert_eq!(server.server_uri, "xds.example.com:443");
+        assert_eq!(server.channel_creds[0].type_name, "google_default");
+        assert_eq!(server.channel_creds[1].type_name, "insecure");
+        assert!(server.has_feature("ignore_resource_deletion"));
+        assert!(!config.servers[1].has_feature("ignore_resource_deletion"));
+        assert_eq!(
+            config.servers[1].channel_creds[0].config,
+            Some(Value::Struct(BTreeMap::from([(
+                "ca_certificate_file".to_string(),
+                Value::String("/ca.pem".to_string())
+            )])))
+        );
+
+        assert_eq!(config.node.id, "node-1");
+        assert_eq!(config.node.cluster, "cluster-1");
+        assert_eq!(
+            config.node.metadata["TRAFFICDIRECTOR_NETWORK_NAME"],
+            Value::String("default".to_string())
+        );
+        assert_eq!(config.node.metadata["weight"], Value::Number(2.0));
+        let locality = config.node.locality.unwrap();
+        assert_eq!(locality.region, "us-west");
+        assert_eq!(locality.zone, "us-west-1a");
+        assert_eq!(locality.sub_zone, "rack-1");
+
+        let region_a = &config.authorities["region-a"];
+        assert_eq!(
+            region_a.client_listener_resource_name_template,
+            "xdstp://region-a/envoy.config.listener.v3.Listener/%s"
+        );
+        assert_eq!(
+            region_a.xds_servers[0].server_uri,
+            "region-a.example.com:443"
+        );
+        let region_b = &config.authorities["region-b"];
+        assert_eq!(
+            region_b.client_listener_resource_name_template,
+            "xdstp://region-b/envoy.config.listener.v3.Listener/svc/%s"
+        );
+        assert!(region_b.xds_servers.is_empty());
+
+        assert_eq!(
+            config.client_default_listener_resource_name_template,
+            "xdstp://region-a/envoy.config.listener.v3.Listener/%s"
+        );
+        assert_eq!(
+            config.server_listener_resource_name_template.as_deref(),
+            Some("grpc/server?xds.resource.listening_address=%s")
+        );
+        assert_eq!(
+            config.certificate_providers["default"].plugin_name,
+            "file_watcher"
+        );
+    }
+
+    #[test]
+    fn test_parse_minimal_bootstrap() {
+        let config = parse(
+            r#"{"xds_servers": [{"server_uri": "localhost:10000", "channel_creds": [{"type": "insecure"}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(config.servers, vec![ServerConfig::new("localhost:10000")]);
+        assert_eq!(config.node.id, "");
+        assert!(config.authorities.is_empty());
+        assert_eq!(config.client_default_listener_resource_name_template, "%s");
+        assert!(config.server_listener_resource_name_template.is_none());
+    }
+
+    #[test]
+    fn test_parse_invalid_bootstrap() {
+        let cases = [
+            ("not json", "expected"),
+            (r#"{"xds_servers": []}"#, "xds_servers must not be empty"),
+            (
+                r#"{"xds_servers": [{"channel_creds": [{"type": "insecure"}]}]}"#,
+                "server_uri must be set",
+            ),
+            (
+                r#"{"xds_servers": [{"server_uri": "localhost:10000"}]}"#,
+                "channel_creds must not be empty",
+            ),
+            (
+                r#"{
+                    "xds_servers": [{"server_uri": "localhost:10000", "channel_creds": [{"type": "insecure"}]}],
+                    "authorities": {"a": {"client_listener_resource_name_template": "xdstp://b/%s"}}
+                }"#,
+                "must start with xdstp://a/",
+            ),
+        ];
+        for (json, expected) in cases {
+            let error = parse(json).unwrap_err().to_string();
+            assert!(error.contains(expected), "{error} for {json}");
+        }
+    }
+
+    #[test]
+    fn test_from_bootstrap_sources() {
+        assert!(ClientConfig::from_bootstrap_sources(None, None).is_err());
+
+        let config =
+            ClientConfig::from_bootstrap_sources(None, Some(FULL_BOOTSTRAP.to_string())).unwrap();
+        assert_eq!(config.node.id, "node-1");
+
+        // The file takes precedence over the inline configuration.
+        let path = std::env::temp_dir().join(format!("xds-bootstrap-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"xds_servers": [{"server_uri": "localhost:10000", "channel_creds": [{"type": "insecure"}]}], "node": {"id": "from-file"}}"#,
+        )
+        .unwrap();
+        let config = ClientConfig::from_bootstrap_sources(
+            Some(path.clone().into()),
+            Some(FULL_BOOTSTRAP.to_string()),
+        )
+        .unwrap();
+        assert_eq!(config.node.id, "from-file");
+        std::fs::remove_file(&path).unwrap();
+
+        let error = ClientConfig::from_bootstrap_sources(Some(path.into()), None).unwrap_err();
+        assert!(error.to_string().contains("failed to read"));
+    }
+}
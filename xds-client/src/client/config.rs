@@ -1,7 +1,331 @@
 //! Configuration for the xDS client.
+//!
+//! A [`ClientConfig`] is usually loaded from a gRPC xDS bootstrap file, see
+//! [`ClientConfig::from_env`].
+
+use crate::client::bootstrap;
+use crate::error::{Error, Result};
+use crate::message::{Node, Value};
+use crate::resource::name;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::Path;
+use std::time::Duration;
+
+/// Default time to wait for a requested resource before reporting it as not existing.
+const DEFAULT_RESOURCE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Environment variable holding the path of the bootstrap file.
+pub const BOOTSTRAP_PATH_ENV: &str = "GRPC_XDS_BOOTSTRAP";
+
+/// Environment variable holding the bootstrap configuration itself.
+pub const BOOTSTRAP_CONFIG_ENV: &str = "GRPC_XDS_BOOTSTRAP_CONFIG";
 
 /// Configuration for the xDS client.
 #[derive(Debug, Clone)]
 pub struct ClientConfig {
-    // TODO: Add fields as needed
+    /// Node identification sent to the xDS server in the first request of each ADS stream.
+    pub node: Node,
+    /// How long to wait for a requested resource before reporting it as not existing.
+    ///
+    /// Defaults to 15 seconds.
+    pub resource_timeout: Duration,
+    /// The xDS servers to use for resources without an authority, in order of preference.
+    pub servers: Vec<ServerConfig>,
+    /// Authorities for `xdstp://` resource names, keyed by authority name.
+    pub authorities: HashMap<String, AuthorityConfig>,
+    /// Template for the Listener resource name of a client channel target without an authority.
+    ///
+    /// `%s` is replaced by the target's service name. Defaults to `%s`.
+    pub client_default_listener_resource_name_template: String,
+    /// Template for the Listener resource name of an xDS-enabled server.
+    ///
+    /// `%s` is replaced by the server's listening address.
+    pub server_listener_resource_name_template: Option<String>,
+    /// Certificate provider plugin instances, keyed by instance name.
+    pub certificate_providers: HashMap<String, CertificateProviderConfig>,
+}
+
+impl ClientConfig {
+    /// Create a new configuration for the given node, without any servers.
+    pub fn new(node: Node) -> Self {
+        Self {
+            node,
+            resource_timeout: DEFAULT_RESOURCE_TIMEOUT,
+            servers: Vec::new(),
+            authorities: HashMap::new(),
+            client_default_listener_resource_name_template: "%s".to_string(),
+            server_listener_resource_name_template: None,
+            certificate_providers: HashMap::new(),
+        }
+    }
+
+    /// Load the configuration from the bootstrap named by the environment.
+    ///
+    /// The bootstrap is read from the file at [`GRPC_XDS_BOOTSTRAP`](BOOTSTRAP_PATH_ENV)
+    /// if set, and otherwise from the contents of
+    /// [`GRPC_XDS_BOOTSTRAP_CONFIG`](BOOTSTRAP_CONFIG_ENV).
+    pub fn from_env() -> Result<Self> {
+        Self::from_bootstrap_sources(
+            std::env::var_os(BOOTSTRAP_PATH_ENV),
+            std::env::var(BOOTSTRAP_CONFIG_ENV).ok(),
+        )
+    }
+
+    /// Load the configuration from the values of the bootstrap environment variables.
+    pub(crate) fn from_bootstrap_sources(
+        path: Option<OsString>,
+        json: Option<String>,
+    ) -> Result<Self> {
+        if let Some(path) = path {
+            return Self::from_bootstrap_file(path);
+        }
+        if let Some(json) = json {
+            return Self::from_bootstrap_json(&json);
+        }
+        Err(Error::Bootstrap(format!(
+            "neither {BOOTSTRAP_PATH_ENV} nor {BOOTSTRAP_CONFIG_ENV} is set"
+        )))
+    }
+
+    /// Load the configuration from a bootstrap file.
+    pub fn from_bootstrap_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| Error::Bootstrap(format!("failed to read {}: {e}", path.display())))?;
+        Self::from_bootstrap_json(&json)
+    }
+
+    /// Parse the configuration from bootstrap JSON.
+    ///
+    /// See [gRFC A27](https://github.com/grpc/proposal/blob/master/A27-xds-global-load-balancing.md#xdsclient-and-bootstrap-file)
+    /// for the format.
+    pub fn from_bootstrap_json(json: &str) -> Result<Self> {
+        bootstrap::parse(json)
+    }
+
+    /// Set the time to wait for a requested resource before reporting it as not existing.
+    pub fn with_resource_timeout(mut self, timeout: Duration) -> Self {
+        self.resource_timeout = timeout;
+        self
+    }
+
+    /// Set the xDS servers to use for resources without an authority.
+    pub fn with_servers(mut self, servers: Vec<ServerConfig>) -> Self {
+        self.servers = servers;
+        self
+    }
+
+    /// Add an authority for `xdstp://` resource names.
+    pub fn with_authority(mut self, name: impl Into<String>, authority: AuthorityConfig) -> Self {
+        self.authorities.insert(name.into(), authority);
+        self
+    }
+
+    /// Returns the Listener resource name for a client channel target.
+    ///
+    /// The template of the given authority is used, or
+    /// [`client_default_listener_resource_name_template`](Self::client_default_listener_resource_name_template)
+    /// if the target has no authority. For `xdstp://` templates the service name is
+    /// percent-encoded.
+    pub fn listener_resource_name(&self, authority: Option<&str>, service: &str) -> Result<String> {
+        let template = match authority {
+            Some(authority) => {
+                &self
+                    .authorities
+                    .get(authority)
+                    .ok_or_else(|| Error::UnknownAuthority(authority.to_string()))?
+                    .client_listener_resource_name_template
+            }
+            None => &self.client_default_listener_resource_name_template,
+        };
+        if template.starts_with("xdstp:") {
+            Ok(template.replace("%s", &name::percent_encode_path(service)))
+        } else {
+            Ok(template.replace("%s", service))
+        }
+    }
+
+    /// Returns the xDS servers responsible for the given resource name, in order of
+    /// preference.
+    pub fn servers_for(&self, resource_name: &str) -> Result<&[ServerConfig]> {
+        let servers = match name::authority(resource_name) {
+            Some(authority) => {
+                let config = self
+                    .authorities
+                    .get(authority)
+                    .ok_or_else(|| Error::UnknownAuthority(authority.to_string()))?;
+                if config.xds_servers.is_empty() {
+                    &self.servers
+                } else {
+                    &config.xds_servers
+                }
+            }
+            None => &self.servers,
+        };
+        if servers.is_empty() {
+            return Err(Error::Bootstrap("no xDS servers configured".to_string()));
+        }
+        Ok(servers)
+    }
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self::new(Node::default())
+    }
+}
+
+/// Server feature selecting the incremental (delta) variant of the ADS protocol for a
+/// server, instead of the state-of-the-world variant.
+pub const DELTA_ADS_FEATURE: &str = "delta_ads";
+
+/// Server feature keeping Listeners and Clusters in use when the server stops sending them
+/// in state-of-the-world responses, instead of treating them as deleted.
+pub const IGNORE_RESOURCE_DELETION_FEATURE: &str = "ignore_resource_deletion";
+
+/// Configuration of an xDS management server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    /// The URI of the server.
+    pub server_uri: String,
+    /// Channel credentials to connect with. The first supported type should be used.
+    pub channel_creds: Vec<ChannelCredentialsConfig>,
+    /// Server features, such as [`IGNORE_RESOURCE_DELETION_FEATURE`] or [`DELTA_ADS_FEATURE`].
+    pub server_features: Vec<String>,
+}
+
+impl ServerConfig {
+    /// Create a configuration for a server reached with insecure credentials.
+    pub fn new(server_uri: impl Into<String>) -> Self {
+        Self {
+            server_uri: server_uri.into(),
+            channel_creds: vec![ChannelCredentialsConfig {
+                type_name: "insecure".to_string(),
+                config: None,
+            }],
+            server_features: Vec::new(),
+        }
+    }
+
+    /// Returns whether the server supports the given feature.
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.server_features.iter().any(|f| f == feature)
+    }
+}
+
+/// Channel credentials for connecting to an xDS server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelCredentialsConfig {
+    /// The credentials type, such as `insecure`, `google_default` or `tls`.
+    pub type_name: String,
+    /// Type-specific configuration.
+    pub config: Option<Value>,
+}
+
+/// Configuration of an authority for `xdstp://` resource names.
+///
+/// Resources of each authority are fetched over their own ADS stream, unless
+/// another authority uses the same servers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorityConfig {
+    /// Template for the Listener resource name of a client channel target with this authority.
+    ///
+    /// `%s` is replaced by the target's service name.
+    pub client_listener_resource_name_template: String,
+    /// The xDS servers for this authority. If empty, the top-level servers are used.
+    pub xds_servers: Vec<ServerConfig>,
+}
+
+impl AuthorityConfig {
+    /// Create a configuration for the given authority that uses the top-level servers.
+    pub fn new(authority: &str) -> Self {
+        Self {
+            client_listener_resource_name_template: format!(
+                "xdstp://{authority}/envoy.config.listener.v3.Listener/%s"
+            ),
+            xds_servers: Vec::new(),
+        }
+    }
+
+    /// Set the xDS servers for this authority.
+    pub fn with_servers(mut self, servers: Vec<ServerConfig>) -> Self {
+        self.xds_servers = servers;
+        self
+    }
+}
+
+/// Configuration of a certificate provider plugin instance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateProviderConfig {
+    /// The plugin name, such as `file_watcher`.
+    pub plugin_name: String,
+    /// Plugin-specific configuration.
+    pub config: Option<Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn federated_config() -> ClientConfig {
+        ClientConfig::default()
+            .with_servers(vec![ServerConfig::new("default.example.com:443")])
+            .with_authority(
+                "a.example.com",
+                AuthorityConfig::new("a.example.com")
+                    .with_servers(vec![ServerConfig::new("a.example.com:443")]),
+            )
+            .with_authority("b.example.com", AuthorityConfig::new("b.example.com"))
+    }
+
+    #[test]
+    fn test_servers_for() {
+        let config = federated_config();
+        let uri = |name| config.servers_for(name).unwrap()[0].server_uri.as_str();
+
+        assert_eq!(uri("listener-1"), "default.example.com:443");
+        assert_eq!(
+            uri("xdstp://a.example.com/t/listener-1"),
+            "a.example.com:443"
+        );
+        // An authority without servers falls back to the top-level servers.
+        assert_eq!(
+            uri("xdstp://b.example.com/t/listener-1"),
+            "default.example.com:443"
+        );
+        assert!(matches!(
+            config.servers_for("xdstp://c.example.com/t/listener-1"),
+            Err(Error::UnknownAuthority(authority)) if authority == "c.example.com"
+        ));
+        assert!(matches!(
+            ClientConfig::default().servers_for("listener-1"),
+            Err(Error::Bootstrap(_))
+        ));
+    }
+
+    #[test]
+    fn test_listener_resource_name() {
+        let mut config = federated_config();
+        assert_eq!(
+            config.listener_resource_name(None, "my service").unwrap(),
+            "my service"
+        );
+        assert_eq!(
+            config
+                .listener_resource_name(Some("a.example.com"), "my service")
+                .unwrap(),
+            "xdstp://a.example.com/envoy.config.listener.v3.Listener/my%20service"
+        );
+        assert!(config
+            .listener_resource_name(Some("c.example.com"), "my-service")
+            .is_err());
+
+        config.client_default_listener_resource_name_template =
+            "xdstp://default/envoy.config.listener.v3.Listener/%s".to_string();
+        assert_eq!(
+            config.listener_resource_name(None, "my-service").unwrap(),
+            "xdstp://default/envoy.config.listener.v3.Listener/my-service"
+        );
+    }
 }
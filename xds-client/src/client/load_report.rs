@@ -0,0 +1,316 @@
+//! Load statistics reported to LRS servers, see
+//! [gRFC A27](https://github.com/grpc/proposal/blob/master/A27-xds-global-load-balancing.md).
+//!
+//! The load of a cluster is recorded through a [`ClusterLoadStats`] handle, obtained from
+//! [`XdsClient::load_stats`](crate::XdsClient::load_stats). A background worker reports the
+//! load to the LRS server at the interval the server asks for, as long as handles are alive.
+
+use crate::message::{ClusterStats, DroppedRequests, Locality, UpstreamLocalityStats};
+use futures_channel::oneshot;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
+
+/// Locks a mutex, ignoring poisoning as the counters stay consistent.
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The requests sent to a locality since the last report.
+#[derive(Debug, Default)]
+struct LocalityCounters {
+    issued: AtomicU64,
+    succeeded: AtomicU64,
+    errored: AtomicU64,
+    in_progress: AtomicU64,
+}
+
+/// The load of a cluster since the last report.
+#[derive(Debug)]
+struct ClusterCounters {
+    cluster_name: String,
+    service_name: Option<String>,
+    localities: Mutex<HashMap<Locality, Arc<LocalityCounters>>>,
+    /// The number of requests dropped, keyed by drop category.
+    drops: Mutex<HashMap<String, u64>>,
+    /// The number of requests dropped without a category.
+    uncategorized_drops: AtomicU64,
+    last_report: Mutex<Instant>,
+}
+
+impl ClusterCounters {
+    /// Returns the load since the last report, or `None` if there is nothing to report.
+    fn snapshot(&self, now: Instant) -> Option<ClusterStats> {
+        let mut upstream_locality_stats = Vec::new();
+        lock(&self.localities).retain(|locality, counters| {
+            let stats = UpstreamLocalityStats {
+                locality: locality.clone(),
+                total_successful_requests: counters.succeeded.swap(0, Ordering::Relaxed),
+                total_requests_in_progress: counters.in_progress.load(Ordering::Relaxed),
+                total_error_requests: counters.errored.swap(0, Ordering::Relaxed),
+                total_issued_requests: counters.issued.swap(0, Ordering::Relaxed),
+            };
+            let idle = stats.total_issued_requests == 0
+                && stats.total_requests_in_progress == 0
+                && stats.total_successful_requests == 0
+                && stats.total_error_requests == 0;
+            if !idle {
+                upstream_locality_stats.push(stats);
+            }
+            // Idle localities without handles are forgotten.
+            !idle || Arc::strong_count(counters) > 1
+        });
+        let dropped_requests: Vec<_> = lock(&self.drops)
+            .drain()
+            .map(|(category, dropped_count)| DroppedRequests {
+                category,
+                dropped_count,
+            })
+            .collect();
+        let total_dropped_requests = self.uncategorized_drops.swap(0, Ordering::Relaxed)
+            + dropped_requests
+                .iter()
+                .map(|dropped| dropped.dropped_count)
+                .sum::<u64>();
+        if upstream_locality_stats.is_empty() && total_dropped_requests == 0 {
+            return None;
+        }
+        let last_report = std::mem::replace(&mut *lock(&self.last_report), now);
+        Some(ClusterStats {
+            cluster_name: self.cluster_name.clone(),
+            cluster_service_name: self.service_name.clone().unwrap_or_default(),
+            upstream_locality_stats,
+            total_dropped_requests,
+            dropped_requests,
+            load_report_interval: now.saturating_duration_since(last_report),
+        })
+    }
+}
+
+/// Records the load of a cluster, for reporting to an LRS server.
+///
+/// Cloning this handle creates a new reference to the same statistics. The load of the
+/// cluster is reported while handles are alive.
+#[derive(Clone)]
+pub struct ClusterLoadStats {
+    counters: Arc<ClusterCounters>,
+    /// Keeps the load reporting worker running.
+    _store: Arc<LoadStore>,
+}
+
+impl fmt::Debug for ClusterLoadStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClusterLoadStats")
+            .field("cluster_name", &self.counters.cluster_name)
+            .field("service_name", &self.counters.service_name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handles are equal if they record the load of the same cluster to the same server.
+impl PartialEq for ClusterLoadStats {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.counters, &other.counters)
+    }
+}
+
+impl ClusterLoadStats {
+    /// Returns a handle recording the requests sent to the endpoints of a locality.
+    pub fn locality(&self, locality: &Locality) -> LocalityLoadStats {
+        let counters = lock(&self.counters.localities)
+            .entry(locality.clone())
+            .or_default()
+            .clone();
+        LocalityLoadStats { counters }
+    }
+
+    /// Records a request dropped by the client, in the given drop category if any.
+    ///
+    /// Requests dropped by circuit breaking have no category.
+    pub fn record_drop(&self, category: Option<&str>) {
+        match category {
+            Some(category) => {
+                *lock(&self.counters.drops)
+                    .entry(category.to_string())
+                    .or_default() += 1;
+            }
+            None => {
+                self.counters
+                    .uncategorized_drops
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Records the requests sent to the endpoints of a locality of a cluster.
+#[derive(Clone, Debug)]
+pub struct LocalityLoadStats {
+    counters: Arc<LocalityCounters>,
+}
+
+impl LocalityLoadStats {
+    /// Records a request issued to an endpoint of the locality.
+    ///
+    /// Each call must be followed by a call to [`end_request`](Self::end_request).
+    pub fn start_request(&self) {
+        self.counters.issued.fetch_add(1, Ordering::Relaxed);
+        self.counters.in_progress.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the completion of a request started with
+    /// [`start_request`](Self::start_request).
+    pub fn end_request(&self, success: bool) {
+        self.counters.in_progress.fetch_sub(1, Ordering::Relaxed);
+        let counter = if success {
+            &self.counters.succeeded
+        } else {
+            &self.counters.errored
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Identifies the load of a cluster by cluster name and EDS service name.
+type ClusterKey = (String, Option<String>);
+
+/// The load statistics of the clusters reported to an LRS server.
+///
+/// The load reporting worker of the server holds a weak reference, and stops once the store
+/// is dropped.
+#[derive(Debug)]
+pub(crate) struct LoadStore {
+    /// The counters of each cluster, keyed by cluster and EDS service name.
+    clusters: Mutex<HashMap<ClusterKey, Weak<ClusterCounters>>>,
+    /// Dropped with the store, which signals the worker to stop.
+    _stop: oneshot::Sender<()>,
+}
+
+impl LoadStore {
+    /// Creates a store, and the receiver completing once it is dropped.
+    pub(crate) fn new() -> (Self, oneshot::Receiver<()>) {
+        let (stop, stopped) = oneshot::channel();
+        let store = Self {
+            clusters: Mutex::new(HashMap::new()),
+            _stop: stop,
+        };
+        (store, stopped)
+    }
+
+    /// Returns a handle recording the load of a cluster, shared with the other handles of
+    /// the same cluster.
+    pub(crate) fn cluster(
+        self: &Arc<Self>,
+        cluster_name: &str,
+        service_name: Option<&str>,
+    ) -> ClusterLoadStats {
+        let mut clusters = lock(&self.clusters);
+        clusters.retain(|_, counters| counters.strong_count() > 0);
+        let key = (cluster_name.to_string(), service_name.map(str::to_string));
+        let counters = match clusters.get(&key).and_then(Weak::upgrade) {
+            Some(counters) => counters,
+            None => {
+                let counters = Arc::new(ClusterCounters {
+                    cluster_name: key.0.clone(),
+                    service_name: key.1.clone(),
+                    localities: Mutex::new(HashMap::new()),
+                    drops: Mutex::new(HashMap::new()),
+                    uncategorized_drops: AtomicU64::new(0),
+                    last_report: Mutex::new(Instant::now()),
+                });
+                clusters.insert(key, Arc::downgrade(&counters));
+                counters
+            }
+        };
+        ClusterLoadStats {
+            counters,
+            _store: self.clone(),
+        }
+    }
+
+    /// Returns the load of the given clusters since their last report, or of all clusters if
+    /// `clusters` is `None`. Clusters without any load are omitted.
+    pub(crate) fn snapshot(&self, clusters: Option<&HashSet<String>>) -> Vec<ClusterStats> {
+        let now = Instant::now();
+        lock(&self.clusters)
+            .iter()
+            .filter(|((name, _), _)| clusters.map_or(true, |clusters| clusters.contains(name)))
+            .filter_map(|(_, counters)| counters.upgrade()?.snapshot(now))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(zone: &str) -> Locality {
+        Locality {
+            zone: zone.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let (store, _stopped) = LoadStore::new();
+        let store = Arc::new(store);
+        let stats = store.cluster("cluster-1", Some("eds-1"));
+        assert_eq!(stats, store.cluster("cluster-1", Some("eds-1")));
+        let other = store.cluster("cluster-2", None);
+        assert_ne!(stats, other);
+        assert!(store.snapshot(None).is_empty());
+
+        let a = stats.locality(&zone("a"));
+        for success in [true, true, false] {
+            a.start_request();
+            a.end_request(success);
+        }
+        a.start_request();
+        stats.record_drop(Some("throttle"));
+        stats.record_drop(None);
+        other.record_drop(None);
+
+        let all = store.snapshot(None);
+        assert_eq!(all.len(), 2);
+        let report = all
+            .into_iter()
+            .find(|stats| stats.cluster_name == "cluster-1")
+            .unwrap();
+        assert_eq!(report.cluster_service_name, "eds-1");
+        assert_eq!(
+            report.upstream_locality_stats,
+            vec![UpstreamLocalityStats {
+                locality: zone("a"),
+                total_successful_requests: 2,
+                total_requests_in_progress: 1,
+                total_error_requests: 1,
+                total_issued_requests: 4,
+            }]
+        );
+        assert_eq!(report.total_dropped_requests, 2);
+        assert_eq!(
+            report.dropped_requests,
+            vec![DroppedRequests {
+                category: "throttle".to_string(),
+                dropped_count: 1,
+            }]
+        );
+
+        // Requests in progress are reported until they complete.
+        let only_first = HashSet::from(["cluster-1".to_string()]);
+        let report = store.snapshot(Some(&only_first));
+        let stats = &report[0].upstream_locality_stats[0];
+        assert_eq!(stats.total_requests_in_progress, 1);
+        assert_eq!(stats.total_issued_requests, 0);
+        a.end_request(true);
+        let report = store.snapshot(Some(&only_first));
+        assert_eq!(
+            report[0].upstream_locality_stats[0].total_successful_requests,
+            1
+        );
+        assert!(store.snapshot(None).is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+//! LRS worker that reports the load of clusters to an LRS server.
+//!
+//! The worker runs as a background task for each server load is reported to, and reads the
+//! load from the [`LoadStore`] shared with the [`ClusterLoadStats`] handles of the server.
+//!
+//! [`ClusterLoadStats`]: crate::client::load_report::ClusterLoadStats
+
+use crate::client::backoff::ExponentialBackoff;
+use crate::client::config::ServerConfig;
+use crate::client::load_report::LoadStore;
+use crate::codec::XdsCodec;
+use crate::error::{Error, Result};
+use crate::message::{LoadStatsRequest, Node};
+use crate::runtime::Runtime;
+use crate::transport::{Transport, TransportBuilder, TransportStream};
+use bytes::Bytes;
+use futures_channel::oneshot;
+use futures_util::future::{pending, select, Either};
+use std::collections::HashSet;
+use std::pin::pin;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+/// The lower bound of the interval between load reports.
+const MIN_LOAD_REPORTING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The clusters to report and how often, as last requested by the server.
+#[derive(Debug)]
+struct ReportSchedule {
+    /// The clusters to report, or `None` for all clusters.
+    clusters: Option<HashSet<String>>,
+    interval: Duration,
+}
+
+/// Events observed while driving an LRS stream.
+enum StreamEvent {
+    Response(Result<Option<Bytes>>),
+    Report,
+    Stopped,
+}
+
+/// The LRS worker manages the LRS stream to a server.
+///
+/// It handles:
+/// - Sending the node on a new stream and waiting for the server to ask for reports
+/// - Reporting the load of the requested clusters at the requested interval
+/// - Reconnecting with exponential backoff
+///
+/// It stops once the [`LoadStore`] is dropped, i.e. when no load is recorded anymore.
+#[derive(Debug)]
+pub(crate) struct LrsWorker<B: TransportBuilder, C, R> {
+    transport_builder: Arc<B>,
+    server: ServerConfig,
+    /// Transport to the server, created on the first connection attempt.
+    transport: Option<B::Transport>,
+    backoff: ExponentialBackoff,
+    codec: C,
+    runtime: R,
+    node: Node,
+    store: Weak<LoadStore>,
+    stopped: oneshot::Receiver<()>,
+    /// Whether a response has been received on the current stream.
+    received_response: bool,
+}
+
+impl<B: TransportBuilder, C: XdsCodec, R: Runtime> LrsWorker<B, C, R> {
+    /// Create a new worker reporting the load of `store` to `server`.
+    pub(crate) fn new(
+        transport_builder: Arc<B>,
+        server: ServerConfig,
+        codec: C,
+        runtime: R,
+        node: Node,
+        store: Weak<LoadStore>,
+        stopped: oneshot::Receiver<()>,
+    ) -> Self {
+        Self {
+            transport_builder,
+            server,
+            transport: None,
+            backoff: ExponentialBackoff::new(),
+            codec,
+            runtime,
+            node,
+            store,
+            stopped,
+            received_response: false,
+        }
+    }
+
+    /// Run the worker until the load store is dropped.
+    pub(crate) async fn run(mut self) {
+        loop {
+            self.received_response = false;
+            if self.run_stream().await.is_ok() {
+                return;
+            }
+            // Reconnect right away if the stream was healthy.
+            if self.received_response {
+                self.backoff.reset();
+                continue;
+            }
+            let delay = self.backoff.backoff_duration();
+            let sleep = pin!(self.runtime.sleep(delay));
+            if let Either::Right(_) = select(sleep, &mut self.stopped).await {
+                return;
+            }
+        }
+    }
+
+    /// Drive a single LRS stream.
+    ///
+    /// Returns `Ok(())` when the load store is dropped, or the error that ended the stream.
+    async fn run_stream(&mut self) -> Result<()> {
+        if self.transport.is_none() {
+            self.transport = Some(self.transport_builder.build(&self.server)?);
+        }
+        let transport = self.transport.as_ref().expect("transport was just created");
+        let mut stream = transport.new_load_stats_stream().await?;
+        let request = LoadStatsRequest {
+            node: Some(self.node.clone()),
+            cluster_stats: Vec::new(),
+        };
+        stream
+            .send(self.codec.encode_load_stats_request(&request)?)
+            .await?;
+
+        // Nothing is reported until the server asks for it.
+        let mut schedule: Option<ReportSchedule> = None;
+        loop {
+            let event = {
+                let runtime = self.runtime.clone();
+                let interval = schedule.as_ref().map(|s| s.interval);
+                let report = pin!(async move {
+                    match interval {
+                        Some(interval) => runtime.sleep(interval).await,
+                        None => pending().await,
+                    }
+                });
+                let response = pin!(stream.recv());
+                match select(select(response, report), &mut self.stopped).await {
+                    Either::Left((Either::Left((response, _)), _)) => {
+                        StreamEvent::Response(response)
+                    }
+                    Either::Left((Either::Right(_), _)) => StreamEvent::Report,
+                    Either::Right(_) => StreamEvent::Stopped,
+                }
+            };
+
+            match event {
+                StreamEvent::Response(Ok(Some(bytes))) => {
+                    self.received_response = true;
+                    let response = self.codec.decode_load_stats_response(bytes)?;
+                    schedule = Some(ReportSchedule {
+                        clusters: (!response.send_all_clusters)
+                            .then(|| response.clusters.into_iter().collect()),
+                        interval: response
+                            .load_reporting_interval
+                            .max(MIN_LOAD_REPORTING_INTERVAL),
+                    });
+                }
+                StreamEvent::Response(Ok(None)) => return Err(Error::StreamClosed),
+                StreamEvent::Response(Err(error)) => return Err(error),
+                StreamEvent::Report => {
+                    let Some(store) = self.store.upgrade() else {
+                        return Ok(());
+                    };
+                    let clusters = schedule.as_ref().and_then(|s| s.clusters.as_ref());
+                    let request = LoadStatsRequest {
+                        node: None,
+                        cluster_stats: store.snapshot(clusters),
+                    };
+                    drop(store);
+                    stream
+                        .send(self.codec.encode_load_stats_request(&request)?)
+                        .await?;
+                }
+                StreamEvent::Stopped => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::testutil::mock_transport;
+    use crate::{ClientConfig, Locality, Node, ProstCodec, ServerConfig, TokioRuntime, XdsClient};
+    use envoy_types::pb::envoy::service::load_stats::v3 as load_stats;
+    use envoy_types::pb::google::protobuf::Duration as ProtoDuration;
+
+    async fn new_client() -> (XdsClient, crate::testutil::MockServer) {
+        let config = ClientConfig::new(Node {
+            id: "node-1".to_string(),
+            ..Default::default()
+        })
+        .with_servers(vec![ServerConfig::new("default-server")]);
+        let (transport, server) = mock_transport();
+        let client = XdsClient::builder(config)
+            .build(transport, ProstCodec, TokioRuntime)
+            .await
+            .unwrap();
+        (client, server)
+    }
+
+    fn response(clusters: &[&str], send_all_clusters: bool) -> load_stats::LoadStatsResponse {
+        load_stats::LoadStatsResponse {
+            clusters: clusters.iter().map(|c| c.to_string()).collect(),
+            send_all_clusters,
+            load_reporting_interval: Some(ProtoDuration {
+                seconds: 1,
+                nanos: 0,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_load_reporting() {
+        let (client, mut server) = new_client().await;
+        let stats = client.load_stats("cluster-1", Some("eds-1")).unwrap();
+        let other = client.load_stats("cluster-2", None).unwrap();
+        let mut stream = server.next_stream().await;
+        assert!(stream.load_stats);
+
+        let request = stream.recv_load_stats_request().await;
+        let node = request.node.unwrap();
+        assert_eq!(node.id, "node-1");
+        assert_eq!(
+            node.client_features,
+            vec!["envoy.lrs.supports_send_all_clusters"]
+        );
+        assert!(request.cluster_stats.is_empty());
+
+        let locality = stats.locality(&Locality {
+            zone: "a".to_string(),
+            ..Default::default()
+        });
+        locality.start_request();
+        locality.end_request(false);
+        stats.record_drop(Some("throttle"));
+        other.record_drop(None);
+
+        stream.send_load_stats_response(response(&["cluster-1"], false));
+        let report = stream.recv_load_stats_request().await;
+        assert!(report.node.is_none());
+        assert_eq!(report.cluster_stats.len(), 1);
+        let cluster_stats = &report.cluster_stats[0];
+        assert_eq!(cluster_stats.cluster_name, "cluster-1");
+        assert_eq!(cluster_stats.cluster_service_name, "eds-1");
+        assert_eq!(cluster_stats.total_dropped_requests, 1);
+        let locality_stats = &cluster_stats.upstream_locality_stats[0];
+        assert_eq!(locality_stats.locality.as_ref().unwrap().zone, "a");
+        assert_eq!(locality_stats.total_issued_requests, 1);
+        assert_eq!(locality_stats.total_error_requests, 1);
+
+        // Reports continue at the interval, with the load since the last report.
+        stream.send_load_stats_response(response(&[], true));
+        let report = stream.recv_load_stats_request().await;
+        assert_eq!(report.cluster_stats.len(), 1);
+        assert_eq!(report.cluster_stats[0].cluster_name, "cluster-2");
+        let report = stream.recv_load_stats_request().await;
+        assert!(report.cluster_stats.is_empty());
+
+        // The stream stops once no load is recorded anymore.
+        drop((stats, other, locality));
+        stream.closed().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect() {
+        let (client, mut server) = new_client().await;
+        let stats = client.load_stats("cluster-1", None).unwrap();
+        // Handles for other clusters share the stream.
+        let _other = client.load_stats("cluster-2", None).unwrap();
+        let mut stream = server.next_stream().await;
+        stream.recv_load_stats_request().await;
+        stream.send_load_stats_response(response(&["cluster-1"], false));
+        stream.send_error(Error::StreamClosed);
+
+        let mut stream = server.next_stream().await;
+        assert!(stream.load_stats);
+        assert!(stream.recv_load_stats_request().await.node.is_some());
+        stats.record_drop(None);
+        stream.send_load_stats_response(response(&["cluster-1"], false));
+        let report = stream.recv_load_stats_request().await;
+        assert_eq!(report.cluster_stats[0].total_dropped_requests, 1);
+    }
+}
@@ -1,42 +1,168 @@
 //! Client interface through which the user can watch and receive updates for xDS resources.
 
-use crate::client::config::ClientConfig;
-use crate::client::watch::ResourceWatcher;
+use crate::client::config::{ClientConfig, ServerConfig};
+use crate::client::load_report::{ClusterLoadStats, LoadStore};
+use crate::client::lrs::LrsWorker;
+use crate::client::watch::{ProcessingDone, ResourceWatcher, WatcherEvent};
+use crate::client::worker::{AdsWorker, WorkerCommand};
+use crate::codec::XdsCodec;
 use crate::error::Result;
-use crate::resource::Resource;
+use crate::message::GenericXdsConfig;
+use crate::resource::{decode_erased, name, Resource};
+use crate::runtime::Runtime;
+use crate::transport::TransportBuilder;
+use futures_channel::{mpsc, oneshot};
+use futures_util::future::join_all;
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
 
+pub(crate) mod backoff;
+pub(crate) mod bootstrap;
 pub mod config;
+pub mod load_report;
+pub(crate) mod lrs;
 pub mod watch;
-pub mod worker;
+pub(crate) mod worker;
 
 /// Builder for [`XdsClient`].
-/// TODO: parameterize with transport and runtime, default to tonic/tokio stack.
 #[derive(Debug)]
 pub struct XdsClientBuilder {
-    _config: ClientConfig,
+    config: ClientConfig,
 }
 
 impl XdsClientBuilder {
     /// Create a new builder with the given configuration.
     pub fn new(config: ClientConfig) -> Self {
-        Self { _config: config }
+        Self { config }
     }
 
-    /// Build the client with the given transport and runtime.
+    /// Build the client with the given transport builder, codec and runtime.
     ///
-    /// This starts the background worker that manages the ADS stream.
-    pub async fn build(self) -> Result<XdsClient> {
-        todo!()
+    /// Background workers managing the ADS streams are started on demand: one for
+    /// each distinct set of servers that watched resources are fetched from. Likewise,
+    /// one LRS worker is started for each server that load is reported to.
+    pub async fn build<B, C, R>(
+        self,
+        transport_builder: B,
+        codec: C,
+        runtime: R,
+    ) -> Result<XdsClient>
+    where
+        B: TransportBuilder,
+        C: XdsCodec + Clone,
+        R: Runtime,
+    {
+        let config = Arc::new(self.config);
+        let transport_builder = Arc::new(transport_builder);
+        let spawn_config = config.clone();
+        let lrs_transport_builder = transport_builder.clone();
+        let lrs_codec = codec.clone();
+        let lrs_runtime = runtime.clone();
+        let node = config.node.clone();
+        let spawn_worker = move |servers| {
+            let (commands_tx, commands_rx) = mpsc::unbounded();
+            let worker = AdsWorker::new(
+                transport_builder.clone(),
+                servers,
+                codec.clone(),
+                runtime.clone(),
+                &spawn_config,
+                commands_rx,
+            );
+            runtime.spawn(worker.run());
+            commands_tx
+        };
+        let spawn_lrs_worker = move |server| {
+            let (store, stopped) = LoadStore::new();
+            let store = Arc::new(store);
+            let worker = LrsWorker::new(
+                lrs_transport_builder.clone(),
+                server,
+                lrs_codec.clone(),
+                lrs_runtime.clone(),
+                node.clone(),
+                Arc::downgrade(&store),
+                stopped,
+            );
+            lrs_runtime.spawn(worker.run());
+            store
+        };
+        Ok(XdsClient {
+            inner: Arc::new(ClientInner {
+                config,
+                workers: Mutex::new(Vec::new()),
+                spawn_worker: Box::new(spawn_worker),
+                load_stores: Mutex::new(Vec::new()),
+                spawn_lrs_worker: Box::new(spawn_lrs_worker),
+            }),
+        })
     }
 }
 
+/// Starts a worker for the given servers and returns its command sender.
+type SpawnWorker =
+    Box<dyn Fn(Vec<ServerConfig>) -> mpsc::UnboundedSender<WorkerCommand> + Send + Sync>;
+
+/// Starts an LRS worker for the given server and returns the store it reports.
+type SpawnLrsWorker = Box<dyn Fn(ServerConfig) -> Arc<LoadStore> + Send + Sync>;
+
 /// The xDS client.
 ///
-/// This is a handle to the background worker that manages the ADS stream.
-/// Cloning this handle creates a new reference to the same worker.
+/// This is a handle to the background workers that manage the ADS streams.
+/// Cloning this handle creates a new reference to the same workers.
+/// A worker stops once all handles and its watchers are dropped.
 #[derive(Clone, Debug)]
 pub struct XdsClient {
-    // TODO: add fields as needed
+    inner: Arc<ClientInner>,
+}
+
+struct ClientInner {
+    config: Arc<ClientConfig>,
+    /// Command senders of the running workers, keyed by the servers they talk to.
+    workers: Mutex<Vec<(Vec<ServerConfig>, mpsc::UnboundedSender<WorkerCommand>)>>,
+    spawn_worker: SpawnWorker,
+    /// Load stores of the running LRS workers, keyed by the server they report to.
+    load_stores: Mutex<Vec<(ServerConfig, Weak<LoadStore>)>>,
+    spawn_lrs_worker: SpawnLrsWorker,
+}
+
+impl fmt::Debug for ClientInner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientInner")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ClientInner {
+    /// Returns the commands sender of the worker responsible for the resource name,
+    /// starting the worker if needed.
+    fn worker_for(&self, resource_name: &str) -> Result<mpsc::UnboundedSender<WorkerCommand>> {
+        let servers = self.config.servers_for(resource_name)?;
+        let mut workers = self.workers.lock().unwrap();
+        if let Some((_, commands)) = workers.iter().find(|(s, _)| s == servers) {
+            return Ok(commands.clone());
+        }
+        let commands = (self.spawn_worker)(servers.to_vec());
+        workers.push((servers.to_vec(), commands.clone()));
+        Ok(commands)
+    }
+
+    /// Returns the load store reported to the server, starting its LRS worker if needed.
+    fn load_store_for(&self, server: &ServerConfig) -> Arc<LoadStore> {
+        let mut load_stores = self.load_stores.lock().unwrap();
+        load_stores.retain(|(_, store)| store.strong_count() > 0);
+        if let Some(store) = load_stores
+            .iter()
+            .find(|(s, _)| s == server)
+            .and_then(|(_, store)| store.upgrade())
+        {
+            return store;
+        }
+        let store = (self.spawn_lrs_worker)(server.clone());
+        load_stores.push((server.clone(), Arc::downgrade(&store)));
+        store
+    }
 }
 
 impl XdsClient {
@@ -45,30 +171,108 @@ impl XdsClient {
         XdsClientBuilder::new(config)
     }
 
+    /// Returns the configuration of the client.
+    pub fn config(&self) -> &ClientConfig {
+        &self.inner.config
+    }
+
     /// Watch a resource by name.
     ///
     /// Returns a [`ResourceWatcher`] that receives events for this resource.
+    /// If the resource is already cached, the watcher receives it right away.
     /// Dropping the watcher automatically unsubscribes.
     ///
+    /// `xdstp://` names are fetched from the servers of their authority. If the
+    /// authority is not configured, the watcher receives a resource error.
+    ///
     /// # Example
     ///
     /// ```ignore
     /// let mut watcher = client.watch::<Listener>("my-listener");
     /// while let Some(event) = watcher.next().await {
     ///     match event {
-    ///         ResourceEvent::ResourceChanged(resource) => {
+    ///         ResourceEvent::ResourceChanged { resource, .. } => {
     ///             println!("Listener changed: {}", resource.name());
     ///         }
-    ///         ResourceEvent::ResourceError(error) => {
+    ///         ResourceEvent::ResourceError { error, .. } => {
     ///             println!("Error watching listener: {}", error);
     ///         }
-    ///         ResourceEvent::AmbientError(error) => {
+    ///         ResourceEvent::AmbientError { error, .. } => {
     ///             println!("Ambient error: {}", error);
     ///         }
     ///     }
     /// }
     /// ```
-    pub fn watch<T: Resource>(&self, _name: impl Into<String>) -> ResourceWatcher<T> {
-        todo!()
+    pub fn watch<T: Resource>(&self, name: impl Into<String>) -> ResourceWatcher<T> {
+        let name = name::canonicalize(&name.into());
+        let (watcher_tx, watcher_rx) = mpsc::unbounded();
+        let commands = match self.inner.worker_for(&name) {
+            Ok(commands) => commands,
+            Err(error) => {
+                let (done, _) = ProcessingDone::channel();
+                let _ = watcher_tx.unbounded_send(WatcherEvent::ResourceError { error, done });
+                // There is no subscription to remove when the watcher is dropped.
+                let (commands, _) = mpsc::unbounded();
+                return ResourceWatcher::new(name, watcher_rx, commands);
+            }
+        };
+        // If the worker has stopped, the watcher sender is dropped and the
+        // watcher yields `None` right away.
+        let _ = commands.unbounded_send(WorkerCommand::Watch {
+            type_url: T::TYPE_URL,
+            name: name.clone(),
+            decoder: decode_erased::<T>,
+            all_resources_required_in_sotw: T::ALL_RESOURCES_REQUIRED_IN_SOTW,
+            watcher: watcher_tx,
+        });
+        ResourceWatcher::new(name, watcher_rx, commands)
+    }
+
+    /// Returns a handle recording the load of a cluster, for reporting to an LRS server.
+    ///
+    /// The load is reported to the primary server of the cluster's authority, which is
+    /// the server the cluster resource is fetched from when its `lrs_server` is `self`.
+    /// `service_name` is the EDS service name of the cluster, if any.
+    ///
+    /// The load is reported at the interval the server asks for, as long as handles to
+    /// the server's load are alive. Handles for the same cluster share their statistics.
+    pub fn load_stats(
+        &self,
+        cluster_name: &str,
+        service_name: Option<&str>,
+    ) -> Result<ClusterLoadStats> {
+        let servers = self.inner.config.servers_for(cluster_name)?;
+        let store = self.inner.load_store_for(&servers[0]);
+        Ok(store.cluster(cluster_name, service_name))
+    }
+
+    /// Returns the state of every resource watched through this client, sorted by type
+    /// URL and name.
+    ///
+    /// This is what the client status discovery service (CSDS) reports to operators
+    /// comparing the configuration of the client with that of the xDS server.
+    pub async fn dump_resources(&self) -> Vec<GenericXdsConfig> {
+        let replies: Vec<_> = {
+            let workers = self.inner.workers.lock().unwrap();
+            workers
+                .iter()
+                .filter_map(|(_, commands)| {
+                    let (reply, rx) = oneshot::channel();
+                    commands
+                        .unbounded_send(WorkerCommand::Dump { reply })
+                        .ok()
+                        .map(|()| rx)
+                })
+                .collect()
+        };
+        // Stopped workers drop the reply sender and have nothing to report.
+        let mut configs: Vec<_> = join_all(replies)
+            .await
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect();
+        configs.sort_by(|a, b| (&a.type_url, &a.name).cmp(&(&b.type_url, &b.name)));
+        configs
     }
 }
@@ -1,8 +1,12 @@
 //! Resource watcher types.
 
+use crate::client::worker::WorkerCommand;
 use crate::error::Error;
-use crate::resource::Resource;
-use futures_channel::oneshot;
+use crate::resource::{DecodedResource, Resource};
+use futures_channel::{mpsc, oneshot};
+use futures_util::StreamExt;
+use std::marker::PhantomData;
+use std::sync::Arc;
 
 /// A signal to indicate that processing of a resource event is complete.
 ///
@@ -42,7 +46,6 @@ impl ProcessingDone {
     ///
     /// Returns the `ProcessingDone` sender and a receiver future that resolves
     /// when `complete()` is called or the sender is dropped.
-    #[allow(dead_code)] // TODO: remove once used by XdsClient worker
     pub(crate) fn channel() -> (Self, oneshot::Receiver<()>) {
         let (tx, rx) = oneshot::channel();
         (Self(Some(tx)), rx)
@@ -72,11 +75,14 @@ pub enum ResourceEvent<T> {
     /// Indicates a new version of the resource is available.
     ResourceChanged {
         /// The updated resource.
-        resource: T,
+        ///
+        /// Shared between all watchers of the same resource.
+        resource: Arc<T>,
         /// Signal when processing is complete.
         done: ProcessingDone,
     },
-    /// Indicates an error occurred while trying to fetch or decode the resource.
+    /// Indicates an error occurred while trying to fetch the resource, such as the
+    /// resource not existing or the stream failing before the resource was received.
     ResourceError {
         /// The error that occurred.
         error: Error,
@@ -98,14 +104,30 @@ pub enum ResourceEvent<T> {
 /// A watcher for resources of type `T`.
 ///
 /// Call [`next()`](Self::next) to receive resource events.
-/// Dropping the watcher unsubscribes from the resource.
+/// Watchers of the same resource share a single subscription, which is
+/// removed from the ADS stream when the last of them is dropped.
 #[derive(Debug)]
 pub struct ResourceWatcher<T: Resource> {
-    // TODO: replace with proper implementation
-    _marker: std::marker::PhantomData<T>,
+    name: String,
+    events: mpsc::UnboundedReceiver<WatcherEvent>,
+    commands: mpsc::UnboundedSender<WorkerCommand>,
+    _marker: PhantomData<T>,
 }
 
 impl<T: Resource> ResourceWatcher<T> {
+    pub(crate) fn new(
+        name: String,
+        events: mpsc::UnboundedReceiver<WatcherEvent>,
+        commands: mpsc::UnboundedSender<WorkerCommand>,
+    ) -> Self {
+        Self {
+            name,
+            events,
+            commands,
+            _marker: PhantomData,
+        }
+    }
+
     /// Returns the next resource event.
     ///
     /// Returns `None` when the subscription is closed.
@@ -132,6 +154,60 @@ impl<T: Resource> ResourceWatcher<T> {
     /// }
     /// ```
     pub async fn next(&mut self) -> Option<ResourceEvent<T>> {
-        todo!()
+        let event = match self.events.next().await? {
+            WatcherEvent::ResourceChanged { resource, done } => {
+                match resource.value.downcast::<T>() {
+                    Ok(resource) => ResourceEvent::ResourceChanged { resource, done },
+                    // Two resource types registered under the same type URL.
+                    Err(_) => ResourceEvent::ResourceError {
+                        error: Error::Validation(format!(
+                            "resource {} is not of the watched type for {}",
+                            resource.name,
+                            T::TYPE_URL.as_str()
+                        )),
+                        done,
+                    },
+                }
+            }
+            WatcherEvent::ResourceError { error, done } => {
+                ResourceEvent::ResourceError { error, done }
+            }
+            WatcherEvent::AmbientError { error, done } => {
+                ResourceEvent::AmbientError { error, done }
+            }
+        };
+        Some(event)
     }
 }
+
+impl<T: Resource> Drop for ResourceWatcher<T> {
+    fn drop(&mut self) {
+        // Close the channel first, so the worker sees this watcher as gone
+        // when it handles the command.
+        self.events.close();
+        let _ = self.commands.unbounded_send(WorkerCommand::Unwatch {
+            type_url: T::TYPE_URL,
+            name: std::mem::take(&mut self.name),
+        });
+    }
+}
+
+/// Type-erased [`ResourceEvent`] sent from the worker to a [`ResourceWatcher`].
+#[derive(Debug)]
+pub(crate) enum WatcherEvent {
+    ResourceChanged {
+        resource: DecodedResource,
+        done: ProcessingDone,
+    },
+    ResourceError {
+        error: Error,
+        done: ProcessingDone,
+    },
+    AmbientError {
+        error: Error,
+        done: ProcessingDone,
+    },
+}
+
+/// Sending half of a [`ResourceWatcher`], held by the worker.
+pub(crate) type WatcherSender = mpsc::UnboundedSender<WatcherEvent>;
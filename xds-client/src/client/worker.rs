@@ -1,116 +1,1859 @@
 //! ADS worker that manages the xDS stream.
 //!
-//! This is a skeleton implementation showing how the transport and codec
-//! integrate. Full subscription management will be added in future PRs.
+//! The worker runs as a background task and owns all subscription state.
+//! [`XdsClient`](crate::XdsClient) handles talk to it through [`WorkerCommand`]s,
+//! and it dispatches the resources received on the ADS stream to the watchers.
 
+use crate::client::backoff::ExponentialBackoff;
+use crate::client::config::{
+    ClientConfig, ServerConfig, DELTA_ADS_FEATURE, IGNORE_RESOURCE_DELETION_FEATURE,
+};
+use crate::client::watch::{ProcessingDone, WatcherEvent, WatcherSender};
 use crate::codec::XdsCodec;
-use crate::error::Result;
-use crate::message::{DiscoveryRequest, DiscoveryResponse, Node};
-use crate::transport::TransportStream;
+use crate::error::{Error, Result};
+use crate::message::{
+    ClientResourceStatus, DeltaDiscoveryRequest, DeltaDiscoveryResponse, DiscoveryRequest,
+    DiscoveryResponse, ErrorDetail, GenericXdsConfig, Node, ResourceAny, UpdateFailureState,
+};
+use crate::resource::{DecodeFn, DecodedResource, TypeUrl};
+use crate::runtime::Runtime;
+use crate::transport::{Transport, TransportBuilder, TransportStream};
+use bytes::Bytes;
+use futures_channel::{mpsc, oneshot};
+use futures_util::future::{join_all, pending, select, BoxFuture, Either};
+use futures_util::StreamExt;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-/// The ADS worker manages a single xDS stream.
+/// gRPC status code sent in the `error_detail` of a NACK.
+const INVALID_ARGUMENT: i32 = 3;
+
+/// The ADS stream type of the transports created by `B`.
+type AdsStream<B> = <<B as TransportBuilder>::Transport as Transport>::Stream;
+
+/// Commands sent from [`XdsClient`](crate::XdsClient) handles and watchers to the worker.
+#[derive(Debug)]
+pub(crate) enum WorkerCommand {
+    /// Start watching a resource.
+    Watch {
+        type_url: TypeUrl,
+        name: String,
+        decoder: DecodeFn,
+        /// See [`Resource::ALL_RESOURCES_REQUIRED_IN_SOTW`](crate::Resource::ALL_RESOURCES_REQUIRED_IN_SOTW).
+        all_resources_required_in_sotw: bool,
+        watcher: WatcherSender,
+    },
+    /// A watcher of the resource was dropped.
+    Unwatch { type_url: TypeUrl, name: String },
+    /// Report the state of all subscribed resources.
+    Dump {
+        reply: oneshot::Sender<Vec<GenericXdsConfig>>,
+    },
+}
+
+/// Fired by a does-not-exist timer that was not cancelled in time.
+#[derive(Debug)]
+struct ResourceTimeout {
+    type_url: String,
+    name: String,
+    /// Identifies the timer, so that a timeout racing with a cancellation is ignored.
+    id: u64,
+}
+
+/// A running does-not-exist timer. Dropping it cancels the timer.
+#[derive(Debug)]
+struct ResourceTimer {
+    id: u64,
+    _cancel: oneshot::Sender<()>,
+}
+
+/// Events observed while driving an ADS stream.
+enum StreamEvent<S> {
+    Response(Result<Option<Bytes>>),
+    Command(Option<WorkerCommand>),
+    Timeout(Option<ResourceTimeout>),
+    /// An attempt to reconnect to the primary server completed.
+    Primary(Result<S>),
+}
+
+/// A pending attempt to reconnect to the primary server while connected to a fallback server.
+struct PrimaryProbe<S>(BoxFuture<'static, Result<S>>);
+
+impl<S> fmt::Debug for PrimaryProbe<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrimaryProbe").finish_non_exhaustive()
+    }
+}
+
+/// Opens an ADS stream, using the delta variant of the protocol if `delta` is set.
+async fn open_stream<T: Transport>(transport: Arc<T>, delta: bool) -> Result<T::Stream> {
+    if delta {
+        transport.new_delta_stream().await
+    } else {
+        transport.new_stream().await
+    }
+}
+
+/// Sends an event to a watcher.
+///
+/// Returns the receiver signaled once the watcher is done processing it.
+fn send_event(
+    watcher: &WatcherSender,
+    event: impl FnOnce(ProcessingDone) -> WatcherEvent,
+) -> Option<oneshot::Receiver<()>> {
+    let (done, rx) = ProcessingDone::channel();
+    watcher.unbounded_send(event(done)).ok().map(|_| rx)
+}
+
+/// Cached state of a single subscribed resource.
+#[derive(Debug, Default)]
+struct ResourceState {
+    /// Watchers sharing the subscription.
+    watchers: Vec<WatcherSender>,
+    /// The most recently received resource and its serialized form.
+    cached: Option<(DecodedResource, Bytes)>,
+    /// The version_info of the response the cached resource was last received in.
+    version_info: String,
+    /// When the cached resource was last received.
+    last_updated: Option<SystemTime>,
+    /// The most recent rejected update, until the resource is accepted again.
+    failure: Option<UpdateFailureState>,
+    /// The last error reported while the resource is not cached.
+    error: Option<Error>,
+    /// The does-not-exist timer, running while the resource is requested but not received.
+    timer: Option<ResourceTimer>,
+    /// Whether the server deleted the cached resource but the deletion is ignored, until
+    /// the resource is received again.
+    deletion_ignored: bool,
+}
+
+impl ResourceState {
+    /// Sends the current state of the resource to a new watcher.
+    ///
+    /// There is no ACK to hold back, so processing is not waited for.
+    fn notify(&self, watcher: &WatcherSender) {
+        if let Some((resource, _)) = &self.cached {
+            send_event(watcher, |done| WatcherEvent::ResourceChanged {
+                resource: resource.clone(),
+                done,
+            });
+        } else if let Some(error) = &self.error {
+            send_event(watcher, |done| WatcherEvent::ResourceError {
+                error: error.clone(),
+                done,
+            });
+        }
+    }
+
+    /// Sends an event to all watchers.
+    ///
+    /// Returns the receivers signaled once the watchers are done processing it.
+    fn broadcast(
+        &mut self,
+        event: impl Fn(ProcessingDone) -> WatcherEvent,
+    ) -> Vec<oneshot::Receiver<()>> {
+        self.watchers.retain(|watcher| !watcher.is_closed());
+        self.watchers
+            .iter()
+            .filter_map(|watcher| send_event(watcher, &event))
+            .collect()
+    }
+
+    /// Records a rejected update of the resource and notifies the watchers.
+    ///
+    /// Per gRFC A88, the error is ambient if the previous version of the resource remains
+    /// in use. Returns the receivers signaled once the watchers are done processing it.
+    fn reject(
+        &mut self,
+        version_info: &str,
+        details: &str,
+        error: Error,
+    ) -> Vec<oneshot::Receiver<()>> {
+        // The server did send the resource, so it exists.
+        self.timer = None;
+        self.failure = Some(UpdateFailureState {
+            version_info: version_info.to_string(),
+            details: details.to_string(),
+            last_update_attempt: SystemTime::now(),
+        });
+        if self.cached.is_some() {
+            self.broadcast(|done| WatcherEvent::AmbientError {
+                error: error.clone(),
+                done,
+            })
+        } else {
+            self.error = Some(error.clone());
+            self.broadcast(|done| WatcherEvent::ResourceError {
+                error: error.clone(),
+                done,
+            })
+        }
+    }
+
+    /// Drops the resource after the server removed it, and notifies the watchers that it
+    /// does not exist.
+    ///
+    /// Returns the receivers signaled once the watchers are done processing it.
+    fn remove(&mut self, name: &str) -> Vec<oneshot::Receiver<()>> {
+        if self.cached.is_none() && self.does_not_exist() {
+            return Vec::new();
+        }
+        self.timer = None;
+        self.cached = None;
+        self.deletion_ignored = false;
+        self.version_info.clear();
+        self.last_updated = None;
+        self.failure = None;
+        let error = Error::ResourceDoesNotExist(name.to_string());
+        self.error = Some(error.clone());
+        self.broadcast(|done| WatcherEvent::ResourceError {
+            error: error.clone(),
+            done,
+        })
+    }
+
+    /// Whether the resource has been reported as not existing.
+    fn does_not_exist(&self) -> bool {
+        matches!(self.error, Some(Error::ResourceDoesNotExist(_)))
+    }
+
+    /// Returns the state of the resource reported by CSDS.
+    fn dump(&self, type_url: &str, name: &str) -> GenericXdsConfig {
+        let client_status = if self.failure.is_some() {
+            ClientResourceStatus::Nacked
+        } else if self.cached.is_some() {
+            ClientResourceStatus::Acked
+        } else if self.does_not_exist() {
+            ClientResourceStatus::DoesNotExist
+        } else {
+            ClientResourceStatus::Requested
+        };
+        GenericXdsConfig {
+            type_url: type_url.to_string(),
+            name: name.to_string(),
+            version_info: self.version_info.clone(),
+            xds_config: self.cached.as_ref().map(|(_, raw)| ResourceAny {
+                type_url: type_url.to_string(),
+                value: raw.clone(),
+            }),
+            last_updated: self.last_updated,
+            client_status,
+            error_state: self.failure.clone(),
+        }
+    }
+}
+
+/// Subscription state for a single resource type.
+#[derive(Debug)]
+struct TypeState {
+    /// Decoder for resources of this type.
+    decoder: DecodeFn,
+    /// Whether a subscribed resource missing from a state-of-the-world response was deleted.
+    all_resources_required_in_sotw: bool,
+    /// The version_info of the most recently ACKed response.
+    version_info: String,
+    /// The nonce of the most recent response on the current stream.
+    nonce: String,
+    /// Subscribed resources keyed by name.
+    resources: BTreeMap<String, ResourceState>,
+    /// The resource names subscribed to on the current delta stream.
+    delta_subscribed: BTreeSet<String>,
+}
+
+impl TypeState {
+    fn new(decoder: DecodeFn, all_resources_required_in_sotw: bool) -> Self {
+        Self {
+            decoder,
+            all_resources_required_in_sotw,
+            version_info: String::new(),
+            nonce: String::new(),
+            resources: BTreeMap::new(),
+            delta_subscribed: BTreeSet::new(),
+        }
+    }
+
+    /// Returns the currently subscribed resource names.
+    fn resource_names(&self) -> Vec<String> {
+        self.resources.keys().cloned().collect()
+    }
+
+    /// Decodes a received resource of this type and updates its state, notifying the
+    /// watchers if it changed.
+    ///
+    /// The name of the resource is added to `received`, which is reset to `None` if the
+    /// resource cannot be decoded far enough to know its name. Returns why the resource was
+    /// rejected, to be included in the NACK.
+    fn update(
+        &mut self,
+        type_url: &str,
+        version_info: &str,
+        any: ResourceAny,
+        received: &mut Option<HashSet<String>>,
+        pending: &mut Vec<oneshot::Receiver<()>>,
+    ) -> std::result::Result<(), String> {
+        if any.type_url != type_url {
+            *received = None;
+            return Err(format!(
+                "resource type {} does not match response type {type_url}",
+                any.type_url
+            ));
+        }
+        let decoded = match (self.decoder)(any.value.clone()) {
+            Ok(decoded) => decoded,
+            Err(error) => {
+                if let Error::InvalidResource { name, message } = &error {
+                    if let Some(received) = received {
+                        received.insert(name.clone());
+                    }
+                    if let Some(resource) = self.resources.get_mut(name) {
+                        pending.extend(resource.reject(version_info, message, error.clone()));
+                    }
+                } else {
+                    *received = None;
+                }
+                return Err(error.to_string());
+            }
+        };
+        if let Some(received) = received {
+            received.insert(decoded.name.clone());
+        }
+        let Some(resource) = self.resources.get_mut(&decoded.name) else {
+            // Not subscribed, e.g. unsubscribed while the response was in flight.
+            return Ok(());
+        };
+        resource.timer = None;
+        resource.error = None;
+        resource.failure = None;
+        resource.deletion_ignored = false;
+        resource.version_info = version_info.to_string();
+        resource.last_updated = Some(SystemTime::now());
+        if matches!(&resource.cached, Some((_, raw)) if *raw == any.value) {
+            // Unchanged, so the watchers already have it.
+            return Ok(());
+        }
+        resource.cached = Some((decoded.clone(), any.value));
+        pending.extend(resource.broadcast(|done| WatcherEvent::ResourceChanged {
+            resource: decoded.clone(),
+            done,
+        }));
+        Ok(())
+    }
+}
+
+/// The ADS worker manages the xDS stream.
 ///
 /// It handles:
-/// - Sending discovery requests (subscriptions)
-/// - Receiving discovery responses
+/// - Sending discovery requests (subscriptions), with the state-of-the-world or the
+///   incremental (delta) variant of the protocol as configured for the server
+/// - Receiving discovery responses, caching resources and fanning them out to watchers
 /// - Version/nonce tracking for ACK/NACK
+/// - Re-subscribing to all resources on reconnect, with exponential backoff
+/// - Falling back to lower priority servers while resources are missing ([gRFC A71])
+/// - Does-not-exist timers for requested resources ([gRFC A88])
+///
+/// [gRFC A71]: https://github.com/grpc/proposal/blob/master/A71-xds-fallback.md
+/// [gRFC A88]: https://github.com/grpc/proposal/blob/master/A88-xds-data-error-handling.md
 #[derive(Debug)]
-pub struct AdsWorker<S, C> {
-    stream: S,
+pub(crate) struct AdsWorker<B: TransportBuilder, C, R> {
+    transport_builder: Arc<B>,
+    /// The servers this worker fetches resources from, in order of preference.
+    servers: Vec<ServerConfig>,
+    /// Transports to the servers, created on the first connection attempt to each.
+    transports: Vec<Option<Arc<B::Transport>>>,
+    /// Index of the server the worker is connected or connecting to.
+    server_index: usize,
+    backoff: ExponentialBackoff,
+    /// Reconnection attempt to the primary server, while connected to a fallback server.
+    primary_probe: Option<PrimaryProbe<AdsStream<B>>>,
     codec: C,
-    node: Option<Node>,
-    // TODO: Add subscription tracking
-    // subscriptions: HashMap<String, HashSet<String>>,  // type_url -> resource_names
-    // TODO: Add version/nonce tracking
-    // versions: HashMap<String, String>,  // type_url -> version_info
-    // nonces: HashMap<String, String>,    // type_url -> response_nonce
+    runtime: R,
+    node: Node,
+    resource_timeout: Duration,
+    commands: mpsc::UnboundedReceiver<WorkerCommand>,
+    timeouts_tx: mpsc::UnboundedSender<ResourceTimeout>,
+    timeouts: mpsc::UnboundedReceiver<ResourceTimeout>,
+    next_timer_id: u64,
+    /// Subscription state keyed by type URL.
+    types: HashMap<String, TypeState>,
+    /// The error that ended the last stream, until a new stream is established.
+    stream_error: Option<Error>,
+    /// Whether the node has been sent on the current stream.
+    node_sent: bool,
+    /// Whether a response has been received on the current stream.
+    received_response: bool,
 }
 
-impl<S: TransportStream, C: XdsCodec> AdsWorker<S, C> {
-    /// Create a new worker with the given stream and codec.
-    pub fn new(stream: S, codec: C, node: Option<Node>) -> Self {
+impl<B: TransportBuilder, C: XdsCodec, R: Runtime> AdsWorker<B, C, R> {
+    /// Create a new worker for the given servers.
+    pub(crate) fn new(
+        transport_builder: Arc<B>,
+        servers: Vec<ServerConfig>,
+        codec: C,
+        runtime: R,
+        config: &ClientConfig,
+        commands: mpsc::UnboundedReceiver<WorkerCommand>,
+    ) -> Self {
+        let (timeouts_tx, timeouts) = mpsc::unbounded();
         Self {
-            stream,
+            transport_builder,
+            transports: servers.iter().map(|_| None).collect(),
+            servers,
+            server_index: 0,
+            backoff: ExponentialBackoff::new(),
+            primary_probe: None,
             codec,
-            node,
+            runtime,
+            node: config.node.clone(),
+            resource_timeout: config.resource_timeout,
+            commands,
+            timeouts_tx,
+            timeouts,
+            next_timer_id: 0,
+            types: HashMap::new(),
+            stream_error: None,
+            node_sent: false,
+            received_response: false,
+        }
+    }
+
+    /// Run the worker event loop until all [`XdsClient`](crate::XdsClient) handles
+    /// and watchers are dropped.
+    pub(crate) async fn run(mut self) {
+        loop {
+            let error = match self.new_stream(self.server_index).await {
+                Ok(mut stream) => match self.run_stream(&mut stream).await {
+                    Ok(()) => return,
+                    Err(error) => error,
+                },
+                Err(error) => {
+                    self.received_response = false;
+                    error
+                }
+            };
+            self.primary_probe = None;
+
+            // Reconnect to the same server right away if the stream was healthy, so
+            // that a server closing streams periodically does not delay updates.
+            if self.received_response {
+                self.backoff.reset();
+                self.report_stream_error(error);
+                continue;
+            }
+
+            // Try the next server without reporting the error, but only while some
+            // watched resource has not been received. Otherwise the cached resources
+            // stay in use until a higher priority server is reachable again.
+            if self.server_index + 1 < self.servers.len() && self.has_uncached_resources() {
+                self.server_index += 1;
+                self.cancel_timers();
+                continue;
+            }
+
+            self.report_stream_error(error);
+            self.server_index = 0;
+            let delay = self.backoff.backoff_duration();
+            if !self.wait(delay).await {
+                return;
+            }
+        }
+    }
+
+    /// Returns the transport to the server at `index`, creating it on first use.
+    fn transport(&mut self, index: usize) -> Result<Arc<B::Transport>> {
+        if let Some(transport) = &self.transports[index] {
+            return Ok(transport.clone());
+        }
+        let transport = Arc::new(self.transport_builder.build(&self.servers[index])?);
+        self.transports[index] = Some(transport.clone());
+        Ok(transport)
+    }
+
+    /// Whether the server at `index` is configured to use the delta protocol.
+    fn uses_delta(&self, index: usize) -> bool {
+        self.servers[index].has_feature(DELTA_ADS_FEATURE)
+    }
+
+    /// Open a new ADS stream to the server at `index`.
+    async fn new_stream(&mut self, index: usize) -> Result<AdsStream<B>> {
+        let delta = self.uses_delta(index);
+        open_stream(self.transport(index)?, delta).await
+    }
+
+    /// Start an attempt to reconnect to the primary server after a backoff delay.
+    fn probe_primary(&mut self) {
+        let transport = self.transport(0);
+        let delta = self.uses_delta(0);
+        let runtime = self.runtime.clone();
+        let delay = self.backoff.backoff_duration();
+        self.primary_probe = Some(PrimaryProbe(Box::pin(async move {
+            runtime.sleep(delay).await;
+            open_stream(transport?, delta).await
+        })));
+    }
+
+    /// Drive a single ADS stream.
+    ///
+    /// Returns `Ok(())` when all client handles and watchers are dropped, or the error that
+    /// ended the stream. While connected to a fallback server, the stream is replaced by one
+    /// to the primary server as soon as it can be established.
+    async fn run_stream(&mut self, stream: &mut AdsStream<B>) -> Result<()> {
+        self.resubscribe(stream).await?;
+        // New watches are requested on this stream rather than failing right away.
+        self.stream_error = None;
+        if self.server_index > 0 {
+            self.probe_primary();
+        }
+
+        loop {
+            let event = {
+                let response = pin!(stream.recv());
+                let primary = self.primary_probe.as_mut();
+                let primary = pin!(async move {
+                    match primary {
+                        Some(PrimaryProbe(probe)) => probe.await,
+                        None => pending().await,
+                    }
+                });
+                let command_or_timeout = select(self.commands.next(), self.timeouts.next());
+                match select(select(response, primary), command_or_timeout).await {
+                    Either::Left((Either::Left((response, _)), _)) => {
+                        StreamEvent::Response(response)
+                    }
+                    Either::Left((Either::Right((primary, _)), _)) => StreamEvent::Primary(primary),
+                    Either::Right((Either::Left((command, _)), _)) => StreamEvent::Command(command),
+                    Either::Right((Either::Right((timeout, _)), _)) => {
+                        StreamEvent::Timeout(timeout)
+                    }
+                }
+            };
+
+            match event {
+                StreamEvent::Response(Ok(Some(bytes))) => {
+                    self.received_response = true;
+                    if self.uses_delta(self.server_index) {
+                        match self.codec.decode_delta_response(bytes) {
+                            Ok(response) => self.handle_delta_response(stream, response).await?,
+                            Err(error) => self.reject_undecodable(stream, error).await?,
+                        }
+                    } else {
+                        match self.codec.decode_response(bytes) {
+                            Ok(response) => self.handle_response(stream, response).await?,
+                            Err(error) => self.reject_undecodable(stream, error).await?,
+                        }
+                    }
+                }
+                StreamEvent::Response(Ok(None)) => return Err(Error::StreamClosed),
+                StreamEvent::Response(Err(error)) => return Err(error),
+                StreamEvent::Command(Some(command)) => {
+                    if let Some(type_url) = self.handle_command(command) {
+                        self.send_request(stream, &type_url, None).await?;
+                    }
+                }
+                StreamEvent::Command(None) => return Ok(()),
+                StreamEvent::Timeout(Some(timeout)) => self.handle_timeout(timeout),
+                // The worker holds a sender, so the channel never closes.
+                StreamEvent::Timeout(None) => {}
+                StreamEvent::Primary(Ok(primary)) => {
+                    // Dropping the fallback stream closes it. Cached resources stay valid
+                    // until the primary server sends its own versions.
+                    self.primary_probe = None;
+                    self.server_index = 0;
+                    self.backoff.reset();
+                    *stream = primary;
+                    self.resubscribe(stream).await?;
+                }
+                StreamEvent::Primary(Err(_)) => self.probe_primary(),
+            }
         }
     }
 
-    /// Send a discovery request.
+    /// Re-subscribe to everything on a new stream.
+    async fn resubscribe(&mut self, stream: &mut AdsStream<B>) -> Result<()> {
+        self.node_sent = false;
+        self.received_response = false;
+
+        // Types without resources are skipped, as an empty first request would
+        // be a wildcard subscription.
+        let mut type_urls = Vec::new();
+        for (type_url, state) in &mut self.types {
+            state.nonce.clear();
+            state.delta_subscribed.clear();
+            if !state.resources.is_empty() {
+                type_urls.push(type_url.clone());
+            }
+        }
+        for type_url in type_urls {
+            self.send_request(stream, &type_url, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Apply a command to the subscription state.
     ///
-    /// The codec serializes the request to bytes, then the transport sends it.
-    pub async fn send_request(&mut self, request: DiscoveryRequest) -> Result<()> {
-        let bytes = self.codec.encode_request(&request)?;
-        self.stream.send(bytes).await
+    /// Returns the type URL whose subscribed resource names changed, if any.
+    fn handle_command(&mut self, command: WorkerCommand) -> Option<String> {
+        match command {
+            WorkerCommand::Watch {
+                type_url,
+                name,
+                decoder,
+                all_resources_required_in_sotw,
+                watcher,
+            } => {
+                let state = self
+                    .types
+                    .entry(type_url.as_str().to_string())
+                    .or_insert_with(|| TypeState::new(decoder, all_resources_required_in_sotw));
+                let is_new = !state.resources.contains_key(&name);
+                let resource = state.resources.entry(name).or_default();
+                if is_new {
+                    // While disconnected, new resources fail right away.
+                    resource.error = self.stream_error.clone();
+                }
+                resource.notify(&watcher);
+                resource.watchers.push(watcher);
+                is_new.then(|| type_url.as_str().to_string())
+            }
+            WorkerCommand::Unwatch { type_url, name } => {
+                let state = self.types.get_mut(type_url.as_str())?;
+                let resource = state.resources.get_mut(&name)?;
+                resource.watchers.retain(|watcher| !watcher.is_closed());
+                if !resource.watchers.is_empty() {
+                    return None;
+                }
+                // The last watcher is gone: drop the subscription, the cached
+                // resource and the timer.
+                state.resources.remove(&name);
+                Some(type_url.as_str().to_string())
+            }
+            WorkerCommand::Dump { reply } => {
+                let configs = self
+                    .types
+                    .iter()
+                    .flat_map(|(type_url, state)| {
+                        state
+                            .resources
+                            .iter()
+                            .map(move |(name, resource)| resource.dump(type_url, name))
+                    })
+                    .collect();
+                let _ = reply.send(configs);
+                None
+            }
+        }
     }
 
-    /// Receive a discovery response.
+    /// Dispatch a response to the watchers, then ACK or NACK it.
     ///
-    /// The transport receives bytes, then the codec deserializes them.
-    pub async fn recv_response(&mut self) -> Result<Option<DiscoveryResponse>> {
-        let bytes = match self.stream.recv().await? {
-            Some(b) => b,
-            None => return Ok(None),
+    /// For types whose resources are all required in state-of-the-world responses, the
+    /// subscribed resources missing from the response were deleted by the server. They are
+    /// reported as not existing, or with an ambient error if the server has the
+    /// [`IGNORE_RESOURCE_DELETION_FEATURE`].
+    async fn handle_response(
+        &mut self,
+        stream: &mut AdsStream<B>,
+        response: DiscoveryResponse,
+    ) -> Result<()> {
+        let Some(state) = self.types.get_mut(&response.type_url) else {
+            // Not subscribed to this type, nothing to dispatch or ACK.
+            return Ok(());
         };
-        let response = self.codec.decode_response(bytes)?;
-        Ok(Some(response))
+
+        let mut errors = Vec::new();
+        let mut pending = Vec::new();
+        let mut received = Some(HashSet::new());
+        for any in response.resources {
+            if let Err(error) = state.update(
+                &response.type_url,
+                &response.version_info,
+                any,
+                &mut received,
+                &mut pending,
+            ) {
+                errors.push(error);
+            }
+        }
+        // Deletions cannot be told apart from resources whose name could not be decoded.
+        if let Some(received) = received.filter(|_| state.all_resources_required_in_sotw) {
+            let ignore_deletion =
+                self.servers[self.server_index].has_feature(IGNORE_RESOURCE_DELETION_FEATURE);
+            for (name, resource) in &mut state.resources {
+                // Resources waiting for their does-not-exist timer may not have been
+                // requested yet when the server sent the response.
+                if received.contains(name) || resource.timer.is_some() {
+                    continue;
+                }
+                if !ignore_deletion {
+                    pending.extend(resource.remove(name));
+                } else if resource.cached.is_some() && !resource.deletion_ignored {
+                    resource.deletion_ignored = true;
+                    let error = Error::ResourceDoesNotExist(name.clone());
+                    pending.extend(resource.broadcast(|done| WatcherEvent::AmbientError {
+                        error: error.clone(),
+                        done,
+                    }));
+                }
+            }
+        }
+        self.acknowledge(
+            stream,
+            response.type_url,
+            response.version_info,
+            response.nonce,
+            errors,
+            pending,
+        )
+        .await
     }
 
-    /// Subscribe to resources of a given type.
-    pub async fn subscribe(&mut self, type_url: &str, resource_names: Vec<String>) -> Result<()> {
-        // TODO: Track subscription in self.subscriptions
-        // TODO: Get current version/nonce from tracking state
-        let (version_info, response_nonce) = self.get_version_nonce(type_url);
+    /// Dispatch a delta response to the watchers, then ACK or NACK it.
+    ///
+    /// Removed resources are reported as not existing.
+    async fn handle_delta_response(
+        &mut self,
+        stream: &mut AdsStream<B>,
+        response: DeltaDiscoveryResponse,
+    ) -> Result<()> {
+        let Some(state) = self.types.get_mut(&response.type_url) else {
+            // Not subscribed to this type, nothing to dispatch or ACK.
+            return Ok(());
+        };
 
-        let request = DiscoveryRequest {
-            node: self.node.clone(),
-            type_url: type_url.to_string(),
-            resource_names,
-            version_info,
-            response_nonce,
-            error_detail: None,
+        let mut errors = Vec::new();
+        let mut pending = Vec::new();
+        for resource in response.resources {
+            // Heartbeats carry no resource, the client already has it.
+            let Some(any) = resource.resource else {
+                continue;
+            };
+            if let Err(error) = state.update(
+                &response.type_url,
+                &resource.version,
+                any,
+                &mut None,
+                &mut pending,
+            ) {
+                errors.push(error);
+            }
+        }
+        for name in &response.removed_resources {
+            if let Some(resource) = state.resources.get_mut(name) {
+                pending.extend(resource.remove(name));
+            }
+        }
+        self.acknowledge(
+            stream,
+            response.type_url,
+            response.system_version_info,
+            response.nonce,
+            errors,
+            pending,
+        )
+        .await
+    }
+
+    /// Wait for the watchers to process a response, then ACK it, or NACK it if some
+    /// of its resources were rejected.
+    async fn acknowledge(
+        &mut self,
+        stream: &mut AdsStream<B>,
+        type_url: String,
+        version_info: String,
+        nonce: String,
+        errors: Vec<String>,
+        pending: Vec<oneshot::Receiver<()>>,
+    ) -> Result<()> {
+        // Watchers may add cascading subscriptions while processing, so that
+        // they are included in the ACK.
+        let mut changed = self.wait_for_processing(pending).await;
+
+        let Some(state) = self.types.get_mut(&type_url) else {
+            return Ok(());
+        };
+        state.nonce = nonce;
+        let error_detail = if errors.is_empty() {
+            state.version_info = version_info;
+            None
+        } else {
+            Some(ErrorDetail {
+                code: INVALID_ARGUMENT,
+                message: errors.join("; "),
+            })
+        };
+        self.send_request(stream, &type_url, error_detail).await?;
+
+        changed.remove(&type_url);
+        for type_url in changed {
+            self.send_request(stream, &type_url, None).await?;
+        }
+        Ok(())
+    }
+
+    /// NACK a response that could not be decoded.
+    ///
+    /// Its type and nonce are unknown, so every subscribed type is NACKed with its last
+    /// ACKed version. The stream stays open, as a new stream would likely get the same
+    /// response again.
+    async fn reject_undecodable(&mut self, stream: &mut AdsStream<B>, error: Error) -> Result<()> {
+        let error_detail = ErrorDetail {
+            code: INVALID_ARGUMENT,
+            message: format!("failed to decode response: {error}"),
+        };
+        let type_urls: Vec<String> = self
+            .types
+            .iter()
+            .filter(|(_, state)| !state.resources.is_empty())
+            .map(|(type_url, _)| type_url.clone())
+            .collect();
+        for type_url in type_urls {
+            self.send_request(stream, &type_url, Some(error_detail.clone()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Report a resource that was not received before its timer fired as not existing.
+    fn handle_timeout(&mut self, timeout: ResourceTimeout) {
+        let Some(resource) = self
+            .types
+            .get_mut(&timeout.type_url)
+            .and_then(|state| state.resources.get_mut(&timeout.name))
+        else {
+            return;
         };
+        if resource.timer.as_ref().map(|timer| timer.id) != Some(timeout.id) {
+            // The timer was cancelled after it fired.
+            return;
+        }
+        resource.timer = None;
+        let error = Error::ResourceDoesNotExist(timeout.name);
+        resource.error = Some(error.clone());
+        resource.broadcast(|done| WatcherEvent::ResourceError {
+            error: error.clone(),
+            done,
+        });
+    }
 
-        self.send_request(request).await
+    /// Wait until the watchers signal [`ProcessingDone`], while still accepting commands.
+    ///
+    /// Returns the type URLs whose subscribed resource names changed meanwhile.
+    async fn wait_for_processing(
+        &mut self,
+        pending: Vec<oneshot::Receiver<()>>,
+    ) -> HashSet<String> {
+        let mut changed = HashSet::new();
+        let mut pending = pin!(join_all(pending));
+        loop {
+            let command = match select(pending.as_mut(), self.commands.next()).await {
+                Either::Left(_) => break,
+                Either::Right((command, _)) => command,
+            };
+            match command {
+                Some(command) => changed.extend(self.handle_command(command)),
+                // The stream loop observes the shutdown on its next iteration.
+                None => return changed,
+            }
+        }
+        // Watches added right before signaling done may still be queued.
+        while let Ok(command) = self.commands.try_recv() {
+            changed.extend(self.handle_command(command));
+        }
+        changed
     }
 
-    /// Send ACK for a received response.
-    pub async fn ack(&mut self, response: &DiscoveryResponse) -> Result<()> {
-        // TODO: Update version/nonce tracking state
-        // TODO: Get currently subscribed resource names for this type_url
-        let resource_names = self.get_subscribed_names(&response.type_url);
+    /// Wait for `delay` while still accepting commands.
+    ///
+    /// Returns `false` if all client handles and watchers were dropped.
+    async fn wait(&mut self, delay: Duration) -> bool {
+        let runtime = self.runtime.clone();
+        let mut sleep = pin!(runtime.sleep(delay));
+        loop {
+            let command = match select(sleep.as_mut(), self.commands.next()).await {
+                Either::Left(_) => return true,
+                Either::Right((command, _)) => command,
+            };
+            match command {
+                Some(command) => {
+                    // Subscriptions are sent when the next stream is established.
+                    self.handle_command(command);
+                }
+                None => return false,
+            }
+        }
+    }
 
-        let request = DiscoveryRequest {
-            node: None,
-            type_url: response.type_url.clone(),
-            resource_names,
-            version_info: response.version_info.clone(),
-            response_nonce: response.nonce.clone(),
-            error_detail: None,
+    /// Send a discovery request for `type_url` with the current subscription state.
+    ///
+    /// This is used for subscribing, ACKing and, with `error_detail` set, NACKing.
+    /// On a delta stream, only the changes to the subscription are sent.
+    /// Does-not-exist timers are started for the requested resources that have not
+    /// been received yet.
+    async fn send_request(
+        &mut self,
+        stream: &mut AdsStream<B>,
+        type_url: &str,
+        error_detail: Option<ErrorDetail>,
+    ) -> Result<()> {
+        let delta = self.uses_delta(self.server_index);
+        let Some(state) = self.types.get_mut(type_url) else {
+            return Ok(());
         };
+        // The node only needs to be sent in the first request on a stream.
+        let node = (!self.node_sent).then(|| self.node.clone());
+        if delta {
+            let names: BTreeSet<String> = state.resources.keys().cloned().collect();
+            let request = DeltaDiscoveryRequest {
+                node,
+                type_url: type_url.to_string(),
+                resource_names_subscribe: names
+                    .difference(&state.delta_subscribed)
+                    .cloned()
+                    .collect(),
+                resource_names_unsubscribe: state
+                    .delta_subscribed
+                    .difference(&names)
+                    .cloned()
+                    .collect(),
+                // Cached resources not subscribed to on this stream were received on a
+                // previous one. Their versions let the server skip sending them again.
+                initial_resource_versions: state
+                    .resources
+                    .iter()
+                    .filter(|(name, resource)| {
+                        resource.cached.is_some() && !state.delta_subscribed.contains(*name)
+                    })
+                    .map(|(name, resource)| (name.clone(), resource.version_info.clone()))
+                    .collect(),
+                response_nonce: state.nonce.clone(),
+                error_detail,
+            };
+            let bytes = self.codec.encode_delta_request(&request)?;
+            stream.send(bytes).await?;
+            state.delta_subscribed = names;
+        } else {
+            let request = DiscoveryRequest {
+                node,
+                type_url: type_url.to_string(),
+                resource_names: state.resource_names(),
+                version_info: state.version_info.clone(),
+                response_nonce: state.nonce.clone(),
+                error_detail,
+            };
+            let bytes = self.codec.encode_request(&request)?;
+            stream.send(bytes).await?;
+        }
+        self.node_sent = true;
+
+        for (name, resource) in &mut state.resources {
+            if resource.cached.is_some()
+                || resource.timer.is_some()
+                || resource.failure.is_some()
+                || resource.does_not_exist()
+            {
+                continue;
+            }
+            let id = self.next_timer_id;
+            self.next_timer_id += 1;
+            let (cancel, cancelled) = oneshot::channel::<()>();
+            let timeout = ResourceTimeout {
+                type_url: type_url.to_string(),
+                name: name.clone(),
+                id,
+            };
+            let runtime = self.runtime.clone();
+            let duration = self.resource_timeout;
+            let timeouts = self.timeouts_tx.clone();
+            self.runtime.spawn(async move {
+                let sleep = pin!(runtime.sleep(duration));
+                if let Either::Left(_) = select(sleep, cancelled).await {
+                    let _ = timeouts.unbounded_send(timeout);
+                }
+            });
+            resource.timer = Some(ResourceTimer {
+                id,
+                _cancel: cancel,
+            });
+            // Requested again on a healthy stream, so a stream error no longer applies.
+            resource.error = None;
+        }
+        Ok(())
+    }
+
+    /// Notify all watchers that the stream failed.
+    ///
+    /// Per gRFC A88, the error is ambient for cached resources, which remain valid.
+    /// Watchers of resources that have not been received get a resource error.
+    fn report_stream_error(&mut self, error: Error) {
+        self.cancel_timers();
+        for state in self.types.values_mut() {
+            for resource in state.resources.values_mut() {
+                if resource.cached.is_some() {
+                    resource.broadcast(|done| WatcherEvent::AmbientError {
+                        error: error.clone(),
+                        done,
+                    });
+                } else {
+                    resource.error = Some(error.clone());
+                    resource.broadcast(|done| WatcherEvent::ResourceError {
+                        error: error.clone(),
+                        done,
+                    });
+                }
+            }
+        }
+        self.stream_error = Some(error);
+    }
+
+    /// Cancel all does-not-exist timers, as they only run while the resources are
+    /// requested on a stream.
+    fn cancel_timers(&mut self) {
+        for state in self.types.values_mut() {
+            for resource in state.resources.values_mut() {
+                resource.timer = None;
+            }
+        }
+    }
+
+    /// Returns whether any watched resource has not been received.
+    fn has_uncached_resources(&self) -> bool {
+        self.types
+            .values()
+            .flat_map(|state| state.resources.values())
+            .any(|resource| resource.cached.is_none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::config::{DELTA_ADS_FEATURE, IGNORE_RESOURCE_DELETION_FEATURE};
+    use crate::client::watch::{ResourceEvent, ResourceWatcher};
+    use crate::error::Error;
+    use crate::message::ClientResourceStatus;
+    use crate::resource::Resource;
+    use crate::testutil::{
+        mock_transport, other_test_response, test_delta_response, test_response, MockServer,
+        MockServerStream, OtherTestResource, TestResource,
+    };
+    use crate::{
+        AuthorityConfig, ClientConfig, Node, ProstCodec, ServerConfig, TokioRuntime, XdsClient,
+    };
+    use bytes::Bytes;
+    use std::collections::HashMap;
+    use std::time::Duration;
 
-        self.send_request(request).await
+    fn test_config() -> ClientConfig {
+        ClientConfig::new(Node {
+            id: "node-1".to_string(),
+            ..Default::default()
+        })
+        .with_servers(vec![ServerConfig::new("default-server")])
     }
 
-    /// Get the current version and nonce for a type_url.
-    fn get_version_nonce(&self, _type_url: &str) -> (String, String) {
-        // TODO: Look up from self.versions and self.nonces
-        todo!("implement version/nonce tracking")
+    async fn new_client() -> (XdsClient, MockServer) {
+        new_client_with_config(test_config()).await
     }
 
-    /// Get the currently subscribed resource names for a type_url.
-    fn get_subscribed_names(&self, _type_url: &str) -> Vec<String> {
-        // TODO: Look up from self.subscriptions
-        todo!("implement subscription tracking")
+    async fn new_client_with_config(config: ClientConfig) -> (XdsClient, MockServer) {
+        let (transport, server) = mock_transport();
+        let client = XdsClient::builder(config)
+            .build(transport, ProstCodec, TokioRuntime)
+            .await
+            .unwrap();
+        (client, server)
     }
 
-    /// Run the worker event loop.
-    pub async fn run(&mut self) -> Result<()> {
-        // TODO: Implement the main event loop that:
-        // - Receives responses from the stream
-        // - Dispatches resources to watchers
-        // - Sends ACK/NACK
-        // - Handles reconnection
-        todo!("implement worker event loop")
+    #[tokio::test]
+    async fn test_subscribe_and_ack() {
+        let (client, mut server) = new_client().await;
+        let mut watcher = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+
+        let request = stream.recv_request().await;
+        assert_eq!(request.node.unwrap().id, "node-1");
+        assert_eq!(request.type_url, TestResource::TYPE_URL.as_str());
+        assert_eq!(request.resource_names, vec!["foo"]);
+        assert_eq!(request.version_info, "");
+        assert_eq!(request.response_nonce, "");
+
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        match watcher.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { resource, .. } => {
+                assert_eq!(*resource, TestResource::new("foo", "a"));
+            }
+            event => panic!("unexpected event: {event:?}"),
+        }
+
+        let ack = stream.recv_request().await;
+        assert!(ack.node.is_none());
+        assert_eq!(ack.resource_names, vec!["foo"]);
+        assert_eq!(ack.version_info, "1");
+        assert_eq!(ack.response_nonce, "nonce-1");
+        assert!(ack.error_detail.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_nack_keeps_previous_version() {
+        let (client, mut server) = new_client().await;
+        let mut watcher = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        watcher.next().await.unwrap();
+        stream.recv_request().await;
+
+        let mut response = test_response("2", "nonce-2", &[]);
+        response.resources.push(TestResource::malformed_any());
+        stream.send_response(response);
+
+        let nack = stream.recv_request().await;
+        assert_eq!(nack.version_info, "1");
+        assert_eq!(nack.response_nonce, "nonce-2");
+        assert_eq!(nack.resource_names, vec!["foo"]);
+        let error = nack.error_detail.unwrap();
+        assert_eq!(error.code, 3);
+        assert!(error.message.contains("malformed"));
+    }
+
+    #[tokio::test]
+    async fn test_undecodable_response_nacked_on_same_stream() {
+        let (client, mut server) = new_client().await;
+        let mut watcher = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        watcher.next().await.unwrap();
+        stream.recv_request().await;
+
+        stream.send_raw(Bytes::from_static(b"\xff\xff\xff"));
+        let nack = stream.recv_request().await;
+        assert_eq!(nack.version_info, "1");
+        assert_eq!(nack.response_nonce, "nonce-1");
+        assert_eq!(nack.resource_names, vec!["foo"]);
+        let error = nack.error_detail.unwrap();
+        assert_eq!(error.code, 3);
+        assert!(error.message.contains("failed to decode response"));
+
+        // The stream stays open and keeps being used.
+        stream.send_response(test_response(
+            "2",
+            "nonce-2",
+            &[TestResource::new("foo", "b")],
+        ));
+        match watcher.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { resource, .. } => {
+                assert_eq!(*resource, TestResource::new("foo", "b"));
+            }
+            event => panic!("unexpected event: {event:?}"),
+        }
+        let ack = stream.recv_request().await;
+        assert_eq!(ack.version_info, "2");
+        assert!(ack.error_detail.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_resource_notifies_watchers() {
+        let (client, mut server) = new_client().await;
+        let mut foo = client.watch::<TestResource>("foo");
+        let mut bar = client.watch::<TestResource>("bar");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+        stream.recv_request().await;
+
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        foo.next().await.unwrap();
+        stream.recv_request().await;
+
+        // The previous version of foo remains in use, bar has none.
+        stream.send_response(test_response(
+            "2",
+            "nonce-2",
+            &[TestResource::new("foo", ""), TestResource::new("bar", "")],
+        ));
+        match foo.next().await.unwrap() {
+            ResourceEvent::AmbientError {
+                error: Error::InvalidResource { name, .. },
+                ..
+            } => assert_eq!(name, "foo"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+        match bar.next().await.unwrap() {
+            ResourceEvent::ResourceError {
+                error: Error::InvalidResource { name, .. },
+                ..
+            } => assert_eq!(name, "bar"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+
+        let nack = stream.recv_request().await;
+        assert_eq!(nack.version_info, "1");
+        let error = nack.error_detail.unwrap();
+        assert!(error.message.contains("foo: empty value"));
+        assert!(error.message.contains("bar: empty value"));
+    }
+
+    #[tokio::test]
+    async fn test_cascading_watch_included_in_ack() {
+        let (client, mut server) = new_client().await;
+        let mut watcher = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        let mut done = match watcher.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { done, .. } => done,
+            event => panic!("unexpected event: {event:?}"),
+        };
+
+        // The ACK is held back until processing is done.
+        let _bar = client.watch::<TestResource>("bar");
+        assert!(stream.try_recv_request().is_none());
+        done.complete();
+
+        let ack = stream.recv_request().await;
+        assert_eq!(ack.resource_names, vec!["bar", "foo"]);
+        assert_eq!(ack.version_info, "1");
+        assert_eq!(ack.response_nonce, "nonce-1");
+    }
+
+    #[tokio::test]
+    async fn test_resubscribe_on_reconnect() {
+        let (client, mut server) = new_client().await;
+        let mut watcher = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        watcher.next().await.unwrap();
+        stream.recv_request().await;
+
+        stream.send_error(Error::StreamClosed);
+        match watcher.next().await.unwrap() {
+            ResourceEvent::AmbientError { .. } => {}
+            event => panic!("unexpected event: {event:?}"),
+        }
+
+        let mut stream = server.next_stream().await;
+        let request = stream.recv_request().await;
+        assert_eq!(request.node.unwrap().id, "node-1");
+        assert_eq!(request.resource_names, vec!["foo"]);
+        assert_eq!(request.version_info, "1");
+        assert_eq!(request.response_nonce, "");
+    }
+
+    #[tokio::test]
+    async fn test_watch_after_reconnect_is_requested() {
+        let (client, mut server) = new_client().await;
+        let mut foo = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+
+        stream.send_error(Error::StreamClosed);
+        match foo.next().await.unwrap() {
+            ResourceEvent::ResourceError { .. } => {}
+            event => panic!("unexpected event: {event:?}"),
+        }
+
+        // The error of the previous stream does not apply to watches made once a new
+        // stream is established, even before it gets a response.
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+        let mut bar = client.watch::<TestResource>("bar");
+        let request = stream.recv_request().await;
+        assert_eq!(request.resource_names, vec!["bar", "foo"]);
+
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("bar", "b")],
+        ));
+        match bar.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { resource, .. } => {
+                assert_eq!(*resource, TestResource::new("bar", "b"));
+            }
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multiple_types_share_stream() {
+        let (client, mut server) = new_client().await;
+        let _foo = client.watch::<TestResource>("foo");
+        let _other = client.watch::<OtherTestResource>("other");
+        let mut stream = server.next_stream().await;
+
+        let first = stream.recv_request().await;
+        let second = stream.recv_request().await;
+        assert!(first.node.is_some());
+        assert!(second.node.is_none());
+        assert_ne!(first.type_url, second.type_url);
+    }
+
+    #[tokio::test]
+    async fn test_watchers_share_subscription_and_cache() {
+        let (client, mut server) = new_client().await;
+        let mut first = client.watch::<TestResource>("foo");
+        let mut second = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+
+        let request = stream.recv_request().await;
+        assert_eq!(request.resource_names, vec!["foo"]);
+        assert!(stream.try_recv_request().is_none());
+
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        for watcher in [&mut first, &mut second] {
+            match watcher.next().await.unwrap() {
+                ResourceEvent::ResourceChanged { resource, .. } => {
+                    assert_eq!(resource.value, "a");
+                }
+                event => panic!("unexpected event: {event:?}"),
+            }
+        }
+        stream.recv_request().await;
+
+        // A new watcher gets the cached resource without a new subscription.
+        let mut third = client.watch::<TestResource>("foo");
+        match third.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { resource, .. } => {
+                assert_eq!(resource.value, "a");
+            }
+            event => panic!("unexpected event: {event:?}"),
+        }
+        assert!(stream.try_recv_request().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_resource_not_redelivered() {
+        let (client, mut server) = new_client().await;
+        let mut watcher = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        watcher.next().await.unwrap();
+        stream.recv_request().await;
+
+        stream.send_response(test_response(
+            "2",
+            "nonce-2",
+            &[TestResource::new("foo", "a")],
+        ));
+        let ack = stream.recv_request().await;
+        assert_eq!(ack.version_info, "2");
+
+        stream.send_response(test_response(
+            "3",
+            "nonce-3",
+            &[TestResource::new("foo", "b")],
+        ));
+        match watcher.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { resource, .. } => {
+                assert_eq!(resource.value, "b");
+            }
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_when_last_watcher_dropped() {
+        let (client, mut server) = new_client().await;
+        let _foo = client.watch::<TestResource>("foo");
+        let bar_1 = client.watch::<TestResource>("bar");
+        let bar_2 = client.watch::<TestResource>("bar");
+        let mut stream = server.next_stream().await;
+
+        let mut request = stream.recv_request().await;
+        while request.resource_names != vec!["bar", "foo"] {
+            request = stream.recv_request().await;
+        }
+
+        drop(bar_1);
+        let _baz = client.watch::<TestResource>("baz");
+        let request = stream.recv_request().await;
+        assert_eq!(request.resource_names, vec!["bar", "baz", "foo"]);
+
+        drop(bar_2);
+        let request = stream.recv_request().await;
+        assert_eq!(request.resource_names, vec!["baz", "foo"]);
+    }
+
+    #[tokio::test]
+    async fn test_resource_does_not_exist() {
+        let config = test_config().with_resource_timeout(Duration::from_millis(50));
+        let (client, mut server) = new_client_with_config(config).await;
+        let mut watcher = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+
+        match watcher.next().await.unwrap() {
+            ResourceEvent::ResourceError {
+                error: Error::ResourceDoesNotExist(name),
+                ..
+            } => assert_eq!(name, "foo"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+
+        // New watchers learn that the resource does not exist right away.
+        let mut second = client.watch::<TestResource>("foo");
+        match second.next().await.unwrap() {
+            ResourceEvent::ResourceError {
+                error: Error::ResourceDoesNotExist(_),
+                ..
+            } => {}
+            event => panic!("unexpected event: {event:?}"),
+        }
+
+        // The resource can still show up later.
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        match watcher.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { resource, .. } => {
+                assert_eq!(resource.value, "a");
+            }
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timer_cancelled_when_resource_received() {
+        let config = test_config().with_resource_timeout(Duration::from_millis(50));
+        let (client, mut server) = new_client_with_config(config).await;
+        let mut watcher = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        watcher.next().await.unwrap();
+        stream.recv_request().await;
+
+        let next = tokio::time::timeout(Duration::from_millis(200), watcher.next()).await;
+        assert!(next.is_err(), "unexpected event: {next:?}");
+    }
+
+    #[tokio::test]
+    async fn test_stream_error_before_received_is_resource_error() {
+        let (client, mut server) = new_client().await;
+        let mut watcher = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+
+        stream.send_error(Error::StreamClosed);
+        match watcher.next().await.unwrap() {
+            ResourceEvent::ResourceError {
+                error: Error::StreamClosed,
+                ..
+            } => {}
+            event => panic!("unexpected event: {event:?}"),
+        }
+
+        // New watchers fail right away while the client is disconnected.
+        let mut other = client.watch::<TestResource>("bar");
+        match other.next().await.unwrap() {
+            ResourceEvent::ResourceError {
+                error: Error::StreamClosed,
+                ..
+            } => {}
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_federation_uses_authority_servers() {
+        let config = test_config()
+            .with_authority(
+                "auth-a",
+                AuthorityConfig::new("auth-a").with_servers(vec![ServerConfig::new("a-server")]),
+            )
+            .with_authority("auth-b", AuthorityConfig::new("auth-b"));
+        let (client, mut server) = new_client_with_config(config).await;
+
+        let mut watcher = client.watch::<TestResource>("xdstp://auth-a/test.Resource/foo?b=2&a=1");
+        let mut a_stream = server.next_stream().await;
+        assert_eq!(a_stream.server_uri, "a-server");
+        let request = a_stream.recv_request().await;
+        assert_eq!(request.node.unwrap().id, "node-1");
+        assert_eq!(
+            request.resource_names,
+            vec!["xdstp://auth-a/test.Resource/foo?a=1&b=2"]
+        );
+
+        // An authority without servers shares the stream of the top-level servers.
+        let _default = client.watch::<TestResource>("bar");
+        let mut default_stream = server.next_stream().await;
+        assert_eq!(default_stream.server_uri, "default-server");
+        default_stream.recv_request().await;
+        let _other = client.watch::<TestResource>("xdstp://auth-b/test.Resource/baz");
+        let request = default_stream.recv_request().await;
+        assert_eq!(
+            request.resource_names,
+            vec!["bar", "xdstp://auth-b/test.Resource/baz"]
+        );
+
+        let name = "xdstp://auth-a/test.Resource/foo?a=1&b=2";
+        a_stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new(name, "a")],
+        ));
+        match watcher.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { resource, .. } => assert_eq!(resource.value, "a"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_authority_is_resource_error() {
+        let (client, _server) = new_client().await;
+        let mut watcher = client.watch::<TestResource>("xdstp://unknown/test.Resource/foo");
+        match watcher.next().await.unwrap() {
+            ResourceEvent::ResourceError {
+                error: Error::UnknownAuthority(authority),
+                ..
+            } => assert_eq!(authority, "unknown"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+        assert!(watcher.next().await.is_none());
+    }
+
+    fn fallback_config() -> ClientConfig {
+        test_config().with_servers(vec![
+            ServerConfig::new("primary"),
+            ServerConfig::new("fallback"),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_fallback_while_resources_missing() {
+        let (client, mut server) = new_client_with_config(fallback_config()).await;
+        let mut watcher = client.watch::<TestResource>("foo");
+        let mut primary = server.next_stream().await;
+        assert_eq!(primary.server_uri, "primary");
+        primary.recv_request().await;
+
+        // The primary fails before sending anything, so the fallback is tried right away
+        // and the error is not reported to the watcher.
+        primary.send_error(Error::StreamClosed);
+        let mut fallback = server.next_stream().await;
+        assert_eq!(fallback.server_uri, "fallback");
+        let request = fallback.recv_request().await;
+        assert_eq!(request.node.unwrap().id, "node-1");
+        assert_eq!(request.resource_names, vec!["foo"]);
+
+        fallback.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        match watcher.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { resource, .. } => assert_eq!(resource.value, "a"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+        fallback.recv_request().await;
+
+        // The client switches back once the primary is reachable again.
+        let mut primary = server.next_stream().await;
+        assert_eq!(primary.server_uri, "primary");
+        let request = primary.recv_request().await;
+        assert!(request.node.is_some());
+        assert_eq!(request.resource_names, vec!["foo"]);
+        primary.send_response(test_response(
+            "2",
+            "nonce-2",
+            &[TestResource::new("foo", "b")],
+        ));
+        match watcher.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { resource, .. } => assert_eq!(resource.value, "b"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_fallback_when_resources_cached() {
+        let (client, mut server) = new_client_with_config(fallback_config()).await;
+        let mut watcher = client.watch::<TestResource>("foo");
+        let mut primary = server.next_stream().await;
+        primary.recv_request().await;
+        primary.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        watcher.next().await.unwrap();
+        primary.recv_request().await;
+
+        // A healthy stream is re-established right away.
+        primary.send_error(Error::StreamClosed);
+        let mut primary = server.next_stream().await;
+        assert_eq!(primary.server_uri, "primary");
+        primary.recv_request().await;
+        match watcher.next().await.unwrap() {
+            ResourceEvent::AmbientError { .. } => {}
+            event => panic!("unexpected event: {event:?}"),
+        }
+
+        // All resources are cached, so the client keeps retrying the primary after a backoff.
+        primary.send_error(Error::StreamClosed);
+        match watcher.next().await.unwrap() {
+            ResourceEvent::AmbientError { .. } => {}
+            event => panic!("unexpected event: {event:?}"),
+        }
+        let primary = server.next_stream().await;
+        assert_eq!(primary.server_uri, "primary");
+    }
+
+    /// Subscribes to the `OtherTestResource`s `a` and `b` and receives both.
+    async fn receive_other_resources(
+        client: &XdsClient,
+        server: &mut MockServer,
+    ) -> (
+        ResourceWatcher<OtherTestResource>,
+        ResourceWatcher<OtherTestResource>,
+        MockServerStream,
+    ) {
+        let mut a = client.watch::<OtherTestResource>("a");
+        let mut b = client.watch::<OtherTestResource>("b");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+        stream.recv_request().await;
+
+        stream.send_response(other_test_response("1", "nonce-1", &["a", "b"]));
+        a.next().await.unwrap();
+        b.next().await.unwrap();
+        stream.recv_request().await;
+        (a, b, stream)
+    }
+
+    #[tokio::test]
+    async fn test_sotw_missing_resource_does_not_exist() {
+        let (client, mut server) = new_client().await;
+        let (_a, mut b, mut stream) = receive_other_resources(&client, &mut server).await;
+
+        stream.send_response(other_test_response("2", "nonce-2", &["a"]));
+        match b.next().await.unwrap() {
+            ResourceEvent::ResourceError {
+                error: Error::ResourceDoesNotExist(name),
+                ..
+            } => assert_eq!(name, "b"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+        let ack = stream.recv_request().await;
+        assert_eq!(ack.version_info, "2");
+        assert!(ack.error_detail.is_none());
+
+        // The resource can still show up later.
+        stream.send_response(other_test_response("3", "nonce-3", &["a", "b"]));
+        match b.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { resource, .. } => assert_eq!(resource.name, "b"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sotw_missing_resource_kept_with_ignore_resource_deletion() {
+        let mut server = ServerConfig::new("default-server");
+        server.server_features = vec![IGNORE_RESOURCE_DELETION_FEATURE.to_string()];
+        let config = test_config().with_servers(vec![server]);
+        let (client, mut server) = new_client_with_config(config).await;
+        let (_a, mut b, mut stream) = receive_other_resources(&client, &mut server).await;
+
+        stream.send_response(other_test_response("2", "nonce-2", &["a"]));
+        match b.next().await.unwrap() {
+            ResourceEvent::AmbientError {
+                error: Error::ResourceDoesNotExist(name),
+                ..
+            } => assert_eq!(name, "b"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+        stream.recv_request().await;
+
+        // New watchers still get the cached resource.
+        let mut second = client.watch::<OtherTestResource>("b");
+        match second.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { resource, .. } => assert_eq!(resource.name, "b"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sotw_missing_resource_kept_if_not_required() {
+        let (client, mut server) = new_client().await;
+        let mut foo = client.watch::<TestResource>("foo");
+        let _bar = client.watch::<TestResource>("bar");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+        stream.recv_request().await;
+
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        foo.next().await.unwrap();
+        stream.recv_request().await;
+
+        stream.send_response(test_response("2", "nonce-2", &[]));
+        stream.recv_request().await;
+        let foo = client
+            .dump_resources()
+            .await
+            .into_iter()
+            .find(|config| config.name == "foo")
+            .unwrap();
+        assert_eq!(foo.client_status, ClientResourceStatus::Acked);
+    }
+
+    fn delta_config() -> ClientConfig {
+        let mut server = ServerConfig::new("delta-server");
+        server.server_features = vec![DELTA_ADS_FEATURE.to_string()];
+        test_config().with_servers(vec![server])
+    }
+
+    #[tokio::test]
+    async fn test_delta_subscribe_ack_and_unsubscribe() {
+        let (client, mut server) = new_client_with_config(delta_config()).await;
+        let mut foo = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+        assert!(stream.delta);
+
+        let request = stream.recv_delta_request().await;
+        assert_eq!(request.node.unwrap().id, "node-1");
+        assert_eq!(request.type_url, TestResource::TYPE_URL.as_str());
+        assert_eq!(request.resource_names_subscribe, vec!["foo"]);
+        assert!(request.resource_names_unsubscribe.is_empty());
+        assert_eq!(request.response_nonce, "");
+
+        stream.send_delta_response(test_delta_response(
+            "v1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+            &[],
+        ));
+        match foo.next().await.unwrap() {
+            ResourceEvent::ResourceChanged { resource, .. } => {
+                assert_eq!(*resource, TestResource::new("foo", "a"));
+            }
+            event => panic!("unexpected event: {event:?}"),
+        }
+        let ack = stream.recv_delta_request().await;
+        assert!(ack.node.is_none());
+        assert_eq!(ack.response_nonce, "nonce-1");
+        assert!(ack.resource_names_subscribe.is_empty());
+        assert!(ack.resource_names_unsubscribe.is_empty());
+        assert!(ack.error_detail.is_none());
+
+        // Only the changes to the subscription are sent.
+        let _bar = client.watch::<TestResource>("bar");
+        let request = stream.recv_delta_request().await;
+        assert_eq!(request.resource_names_subscribe, vec!["bar"]);
+        assert!(request.resource_names_unsubscribe.is_empty());
+
+        drop(foo);
+        let request = stream.recv_delta_request().await;
+        assert!(request.resource_names_subscribe.is_empty());
+        assert_eq!(request.resource_names_unsubscribe, vec!["foo"]);
+    }
+
+    #[tokio::test]
+    async fn test_delta_removed_resource_does_not_exist() {
+        let (client, mut server) = new_client_with_config(delta_config()).await;
+        let mut watcher = client.watch::<TestResource>("foo");
+        let mut stream = server.next_stream().await;
+        stream.recv_delta_request().await;
+
+        stream.send_delta_response(test_delta_response(
+            "v1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+            &[],
+        ));
+        watcher.next().await.unwrap();
+        stream.recv_delta_request().await;
+
+        stream.send_delta_response(test_delta_response("", "nonce-2", &[], &["foo"]));
+        match watcher.next().await.unwrap() {
+            ResourceEvent::ResourceError {
+                error: Error::ResourceDoesNotExist(name),
+                ..
+            } => assert_eq!(name, "foo"),
+            event => panic!("unexpected event: {event:?}"),
+        }
+        let ack = stream.recv_delta_request().await;
+        assert_eq!(ack.response_nonce, "nonce-2");
+        assert!(ack.error_detail.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delta_resubscribe_with_initial_versions() {
+        let (client, mut server) = new_client_with_config(delta_config()).await;
+        let mut foo = client.watch::<TestResource>("foo");
+        let _bar = client.watch::<TestResource>("bar");
+        let mut stream = server.next_stream().await;
+        stream.recv_delta_request().await;
+        stream.recv_delta_request().await;
+
+        stream.send_delta_response(test_delta_response(
+            "v1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+            &[],
+        ));
+        foo.next().await.unwrap();
+        stream.recv_delta_request().await;
+
+        stream.send_error(Error::StreamClosed);
+        foo.next().await.unwrap();
+
+        let mut stream = server.next_stream().await;
+        let request = stream.recv_delta_request().await;
+        assert_eq!(request.node.unwrap().id, "node-1");
+        assert_eq!(request.resource_names_subscribe, vec!["bar", "foo"]);
+        assert_eq!(
+            request.initial_resource_versions,
+            HashMap::from([("foo".to_string(), "v1".to_string())])
+        );
+        assert_eq!(request.response_nonce, "");
     }
 }
@@ -1,12 +1,17 @@
 //! Codec for encoding/decoding xDS messages.
 //!
 //! The codec layer converts between crate-owned message types
-//! ([`DiscoveryRequest`], [`DiscoveryResponse`]) and serialized bytes.
+//! ([`DiscoveryRequest`], [`DiscoveryResponse`], [`DeltaDiscoveryRequest`],
+//! [`DeltaDiscoveryResponse`], [`LoadStatsRequest`], [`LoadStatsResponse`])
+//! and serialized bytes.
 //! This abstraction allows different protobuf implementations
 //! (prost, google-protobuf) to be used with the same xDS client logic.
 
 use crate::error::Result;
-use crate::message::{DiscoveryRequest, DiscoveryResponse};
+use crate::message::{
+    DeltaDiscoveryRequest, DeltaDiscoveryResponse, DiscoveryRequest, DiscoveryResponse,
+    LoadStatsRequest, LoadStatsResponse,
+};
 use bytes::Bytes;
 
 #[cfg(feature = "codegen-prost")]
@@ -22,4 +27,16 @@ pub trait XdsCodec: Send + Sync + 'static {
 
     /// Decode bytes into a [`DiscoveryResponse`].
     fn decode_response(&self, bytes: Bytes) -> Result<DiscoveryResponse>;
+
+    /// Encode a [`DeltaDiscoveryRequest`] to bytes.
+    fn encode_delta_request(&self, request: &DeltaDiscoveryRequest) -> Result<Bytes>;
+
+    /// Decode bytes into a [`DeltaDiscoveryResponse`].
+    fn decode_delta_response(&self, bytes: Bytes) -> Result<DeltaDiscoveryResponse>;
+
+    /// Encode a [`LoadStatsRequest`] to bytes.
+    fn encode_load_stats_request(&self, request: &LoadStatsRequest) -> Result<Bytes>;
+
+    /// Decode bytes into a [`LoadStatsResponse`].
+    fn decode_load_stats_response(&self, bytes: Bytes) -> Result<LoadStatsResponse>;
 }
@@ -2,9 +2,92 @@
 
 use crate::codec::XdsCodec;
 use crate::error::{Error, Result};
-use crate::message::{DiscoveryRequest, DiscoveryResponse, ResourceAny};
+use crate::message::{
+    DeltaDiscoveryRequest, DeltaDiscoveryResponse, DeltaResource, DiscoveryRequest,
+    DiscoveryResponse, ErrorDetail, LoadStatsRequest, LoadStatsResponse, Locality, Node,
+    ResourceAny, Value,
+};
 use bytes::Bytes;
+use envoy_types::pb::envoy::config::core::v3 as core;
+use envoy_types::pb::google::protobuf as pb;
 use prost::Message;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Client feature advertising support for `send_all_clusters` in LRS responses.
+const LRS_SEND_ALL_CLUSTERS: &str = "envoy.lrs.supports_send_all_clusters";
+
+/// Converts a [`Node`] to its proto.
+pub(crate) fn encode_node(node: &Node) -> core::Node {
+    core::Node {
+        id: node.id.clone(),
+        cluster: node.cluster.clone(),
+        metadata: (!node.metadata.is_empty()).then(|| encode_struct(&node.metadata)),
+        locality: node.locality.as_ref().map(encode_locality),
+        ..Default::default()
+    }
+}
+
+/// Converts a [`Locality`] to its proto.
+fn encode_locality(locality: &Locality) -> core::Locality {
+    core::Locality {
+        region: locality.region.clone(),
+        zone: locality.zone.clone(),
+        sub_zone: locality.sub_zone.clone(),
+    }
+}
+
+/// Converts an [`ErrorDetail`] to a `google.rpc.Status`.
+fn encode_error_detail(error: &ErrorDetail) -> envoy_types::pb::google::rpc::Status {
+    envoy_types::pb::google::rpc::Status {
+        code: error.code,
+        message: error.message.clone(),
+        details: vec![],
+    }
+}
+
+/// Converts an `Any` proto to a [`ResourceAny`].
+fn decode_any(any: pb::Any) -> ResourceAny {
+    ResourceAny {
+        type_url: any.type_url,
+        value: any.value.into(),
+    }
+}
+
+/// Converts a duration to its proto. Durations beyond the proto range are clamped.
+fn encode_duration(duration: Duration) -> pb::Duration {
+    pb::Duration {
+        seconds: i64::try_from(duration.as_secs()).unwrap_or(i64::MAX),
+        nanos: duration.subsec_nanos() as i32,
+    }
+}
+
+/// Converts metadata fields to a `google.protobuf.Struct`.
+fn encode_struct(fields: &BTreeMap<String, Value>) -> pb::Struct {
+    pb::Struct {
+        fields: fields
+            .iter()
+            .map(|(name, value)| (name.clone(), encode_value(value)))
+            .collect(),
+    }
+}
+
+/// Converts a [`Value`] to a `google.protobuf.Value`.
+fn encode_value(value: &Value) -> pb::Value {
+    use pb::value::Kind;
+
+    let kind = match value {
+        Value::Null => Kind::NullValue(pb::NullValue::NullValue as i32),
+        Value::Bool(b) => Kind::BoolValue(*b),
+        Value::Number(n) => Kind::NumberValue(*n),
+        Value::String(s) => Kind::StringValue(s.clone()),
+        Value::List(values) => Kind::ListValue(pb::ListValue {
+            values: values.iter().map(encode_value).collect(),
+        }),
+        Value::Struct(fields) => Kind::StructValue(encode_struct(fields)),
+    };
+    pb::Value { kind: Some(kind) }
+}
 
 /// A codec that uses prost/envoy-types for serialization.
 #[derive(Debug, Clone, Copy, Default)]
@@ -12,30 +95,15 @@ pub struct ProstCodec;
 
 impl XdsCodec for ProstCodec {
     fn encode_request(&self, request: &DiscoveryRequest) -> Result<Bytes> {
-        use envoy_types::pb::envoy::config::core::v3 as core;
         use envoy_types::pb::envoy::service::discovery::v3 as discovery;
-        use envoy_types::pb::google::rpc::Status;
 
         let proto_request = discovery::DiscoveryRequest {
             version_info: request.version_info.clone(),
-            node: request.node.as_ref().map(|n| core::Node {
-                id: n.id.clone(),
-                cluster: n.cluster.clone(),
-                locality: n.locality.as_ref().map(|l| core::Locality {
-                    region: l.region.clone(),
-                    zone: l.zone.clone(),
-                    sub_zone: l.sub_zone.clone(),
-                }),
-                ..Default::default()
-            }),
+            node: request.node.as_ref().map(encode_node),
             resource_names: request.resource_names.clone(),
             type_url: request.type_url.clone(),
             response_nonce: request.response_nonce.clone(),
-            error_detail: request.error_detail.as_ref().map(|e| Status {
-                code: e.code,
-                message: e.message.clone(),
-                details: vec![],
-            }),
+            error_detail: request.error_detail.as_ref().map(encode_error_detail),
             ..Default::default()
         };
 
@@ -52,21 +120,124 @@ impl XdsCodec for ProstCodec {
             resources: proto_response
                 .resources
                 .into_iter()
-                .map(|any| ResourceAny {
-                    type_url: any.type_url,
-                    value: any.value.into(),
+                .map(decode_any)
+                .collect(),
+            type_url: proto_response.type_url,
+            nonce: proto_response.nonce,
+        })
+    }
+
+    fn encode_delta_request(&self, request: &DeltaDiscoveryRequest) -> Result<Bytes> {
+        use envoy_types::pb::envoy::service::discovery::v3 as discovery;
+
+        let proto_request = discovery::DeltaDiscoveryRequest {
+            node: request.node.as_ref().map(encode_node),
+            type_url: request.type_url.clone(),
+            resource_names_subscribe: request.resource_names_subscribe.clone(),
+            resource_names_unsubscribe: request.resource_names_unsubscribe.clone(),
+            initial_resource_versions: request
+                .initial_resource_versions
+                .iter()
+                .map(|(name, version)| (name.clone(), version.clone()))
+                .collect(),
+            response_nonce: request.response_nonce.clone(),
+            error_detail: request.error_detail.as_ref().map(encode_error_detail),
+            ..Default::default()
+        };
+
+        Ok(proto_request.encode_to_vec().into())
+    }
+
+    fn decode_delta_response(&self, bytes: Bytes) -> Result<DeltaDiscoveryResponse> {
+        use envoy_types::pb::envoy::service::discovery::v3 as discovery;
+
+        let proto_response =
+            discovery::DeltaDiscoveryResponse::decode(bytes).map_err(Error::Decode)?;
+
+        Ok(DeltaDiscoveryResponse {
+            system_version_info: proto_response.system_version_info,
+            resources: proto_response
+                .resources
+                .into_iter()
+                .map(|resource| DeltaResource {
+                    name: resource.name,
+                    version: resource.version,
+                    resource: resource.resource.map(decode_any),
                 })
                 .collect(),
             type_url: proto_response.type_url,
+            removed_resources: proto_response.removed_resources,
             nonce: proto_response.nonce,
         })
     }
+
+    fn encode_load_stats_request(&self, request: &LoadStatsRequest) -> Result<Bytes> {
+        use envoy_types::pb::envoy::config::endpoint::v3 as endpoint;
+        use envoy_types::pb::envoy::service::load_stats::v3 as load_stats;
+
+        let cluster_stats = request
+            .cluster_stats
+            .iter()
+            .map(|stats| endpoint::ClusterStats {
+                cluster_name: stats.cluster_name.clone(),
+                cluster_service_name: stats.cluster_service_name.clone(),
+                upstream_locality_stats: stats
+                    .upstream_locality_stats
+                    .iter()
+                    .map(|locality| endpoint::UpstreamLocalityStats {
+                        locality: Some(encode_locality(&locality.locality)),
+                        total_successful_requests: locality.total_successful_requests,
+                        total_requests_in_progress: locality.total_requests_in_progress,
+                        total_error_requests: locality.total_error_requests,
+                        total_issued_requests: locality.total_issued_requests,
+                        ..Default::default()
+                    })
+                    .collect(),
+                total_dropped_requests: stats.total_dropped_requests,
+                dropped_requests: stats
+                    .dropped_requests
+                    .iter()
+                    .map(|dropped| endpoint::cluster_stats::DroppedRequests {
+                        category: dropped.category.clone(),
+                        dropped_count: dropped.dropped_count,
+                    })
+                    .collect(),
+                load_report_interval: Some(encode_duration(stats.load_report_interval)),
+            })
+            .collect();
+        let node = request.node.as_ref().map(|node| core::Node {
+            client_features: vec![LRS_SEND_ALL_CLUSTERS.to_string()],
+            ..encode_node(node)
+        });
+        let proto_request = load_stats::LoadStatsRequest {
+            node,
+            cluster_stats,
+        };
+        Ok(proto_request.encode_to_vec().into())
+    }
+
+    fn decode_load_stats_response(&self, bytes: Bytes) -> Result<LoadStatsResponse> {
+        use envoy_types::pb::envoy::service::load_stats::v3 as load_stats;
+
+        let proto_response = load_stats::LoadStatsResponse::decode(bytes).map_err(Error::Decode)?;
+        // Negative intervals are treated as zero.
+        let interval = proto_response.load_reporting_interval.unwrap_or_default();
+        let load_reporting_interval = Duration::new(
+            u64::try_from(interval.seconds).unwrap_or(0),
+            u32::try_from(interval.nanos).unwrap_or(0).min(999_999_999),
+        );
+        Ok(LoadStatsResponse {
+            clusters: proto_response.clusters,
+            send_all_clusters: proto_response.send_all_clusters,
+            load_reporting_interval,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::message::{ErrorDetail, Locality, Node};
+    use crate::message::{ClusterStats, DroppedRequests, ErrorDetail, UpstreamLocalityStats};
 
     #[test]
     fn test_encode_request_minimal() {
@@ -100,6 +271,13 @@ mod tests {
                     zone: "us-west-1a".to_string(),
                     sub_zone: "rack-1".to_string(),
                 }),
+                metadata: BTreeMap::from([(
+                    "labels".to_string(),
+                    Value::Struct(BTreeMap::from([(
+                        "env".to_string(),
+                        Value::String("prod".to_string()),
+                    )])),
+                )]),
             }),
             ..Default::default()
         };
@@ -115,6 +293,14 @@ mod tests {
         assert_eq!(locality.region, "us-west");
         assert_eq!(locality.zone, "us-west-1a");
         assert_eq!(locality.sub_zone, "rack-1");
+        let labels = &node.metadata.unwrap().fields["labels"];
+        let Some(pb::value::Kind::StructValue(labels)) = &labels.kind else {
+            panic!("unexpected labels: {labels:?}");
+        };
+        assert_eq!(
+            labels.fields["env"].kind,
+            Some(pb::value::Kind::StringValue("prod".to_string()))
+        );
     }
 
     #[test]
@@ -179,4 +365,130 @@ mod tests {
         assert_eq!(error.code, 3);
         assert_eq!(error.message, "validation failed");
     }
+
+    #[test]
+    fn test_delta_roundtrip() {
+        use envoy_types::pb::envoy::service::discovery::v3 as discovery;
+        use envoy_types::pb::google::protobuf::Any;
+
+        let codec = ProstCodec;
+
+        let request = DeltaDiscoveryRequest {
+            type_url: "type.googleapis.com/test.Resource".to_string(),
+            resource_names_subscribe: vec!["res-1".to_string()],
+            resource_names_unsubscribe: vec!["res-2".to_string()],
+            initial_resource_versions: BTreeMap::from([("res-1".to_string(), "7".to_string())]),
+            response_nonce: "nonce-abc".to_string(),
+            ..Default::default()
+        };
+        let proto_request =
+            discovery::DeltaDiscoveryRequest::decode(codec.encode_delta_request(&request).unwrap())
+                .unwrap();
+        assert_eq!(proto_request.resource_names_subscribe, vec!["res-1"]);
+        assert_eq!(proto_request.resource_names_unsubscribe, vec!["res-2"]);
+        assert_eq!(proto_request.initial_resource_versions["res-1"], "7");
+        assert_eq!(proto_request.response_nonce, "nonce-abc");
+        assert!(proto_request.error_detail.is_none());
+
+        let proto_response = discovery::DeltaDiscoveryResponse {
+            type_url: "type.googleapis.com/test.Resource".to_string(),
+            nonce: "nonce-1".to_string(),
+            resources: vec![discovery::Resource {
+                name: "res-1".to_string(),
+                version: "8".to_string(),
+                resource: Some(Any {
+                    type_url: "type.googleapis.com/test.Resource".to_string(),
+                    value: b"res-1=a".to_vec(),
+                }),
+                ..Default::default()
+            }],
+            removed_resources: vec!["res-3".to_string()],
+            ..Default::default()
+        };
+        let response = codec
+            .decode_delta_response(proto_response.encode_to_vec().into())
+            .unwrap();
+        assert_eq!(response.nonce, "nonce-1");
+        assert_eq!(response.resources.len(), 1);
+        assert_eq!(response.resources[0].name, "res-1");
+        assert_eq!(response.resources[0].version, "8");
+        assert_eq!(
+            response.resources[0]
+                .resource
+                .as_ref()
+                .unwrap()
+                .value
+                .as_ref(),
+            b"res-1=a"
+        );
+        assert_eq!(response.removed_resources, vec!["res-3"]);
+    }
+
+    #[test]
+    fn test_load_stats_roundtrip() {
+        use envoy_types::pb::envoy::service::load_stats::v3 as load_stats;
+
+        let codec = ProstCodec;
+        let locality = Locality {
+            region: "us-west".to_string(),
+            zone: "us-west-1a".to_string(),
+            sub_zone: String::new(),
+        };
+        let request = LoadStatsRequest {
+            node: Some(Node {
+                id: "node-1".to_string(),
+                ..Default::default()
+            }),
+            cluster_stats: vec![ClusterStats {
+                cluster_name: "cluster-1".to_string(),
+                cluster_service_name: "eds-1".to_string(),
+                upstream_locality_stats: vec![UpstreamLocalityStats {
+                    locality: locality.clone(),
+                    total_successful_requests: 3,
+                    total_requests_in_progress: 1,
+                    total_error_requests: 2,
+                    total_issued_requests: 6,
+                }],
+                total_dropped_requests: 5,
+                dropped_requests: vec![DroppedRequests {
+                    category: "throttle".to_string(),
+                    dropped_count: 4,
+                }],
+                load_report_interval: Duration::from_millis(1500),
+            }],
+        };
+        let bytes = codec.encode_load_stats_request(&request).unwrap();
+        let proto_request = load_stats::LoadStatsRequest::decode(bytes).unwrap();
+        assert_eq!(proto_request.node.unwrap().id, "node-1");
+        let stats = &proto_request.cluster_stats[0];
+        assert_eq!(stats.cluster_service_name, "eds-1");
+        assert_eq!(stats.total_dropped_requests, 5);
+        assert_eq!(stats.dropped_requests[0].dropped_count, 4);
+        assert_eq!(
+            stats.load_report_interval,
+            Some(pb::Duration {
+                seconds: 1,
+                nanos: 500_000_000,
+            })
+        );
+        let locality_stats = &stats.upstream_locality_stats[0];
+        assert_eq!(locality_stats.locality, Some(encode_locality(&locality)));
+        assert_eq!(locality_stats.total_issued_requests, 6);
+        assert_eq!(locality_stats.total_requests_in_progress, 1);
+
+        let proto_response = load_stats::LoadStatsResponse {
+            clusters: vec!["cluster-1".to_string()],
+            load_reporting_interval: Some(pb::Duration {
+                seconds: 10,
+                nanos: 0,
+            }),
+            ..Default::default()
+        };
+        let response = codec
+            .decode_load_stats_response(proto_response.encode_to_vec().into())
+            .unwrap();
+        assert_eq!(response.clusters, vec!["cluster-1".to_string()]);
+        assert!(!response.send_all_clusters);
+        assert_eq!(response.load_reporting_interval, Duration::from_secs(10));
+    }
 }
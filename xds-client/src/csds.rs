@@ -0,0 +1,281 @@
+//! Client status discovery service (CSDS).
+//!
+//! [`CsdsService`] implements `envoy.service.status.v3.ClientStatusDiscoveryService`,
+//! reporting every resource known to one or more [`XdsClient`]s with its version, status,
+//! last update time and the details of its last rejected update, see
+//! [gRFC A40](https://github.com/grpc/proposal/blob/master/A40-csds-support.md).
+//!
+//! # Example
+//!
+//! ```ignore
+//! use xds_client::CsdsService;
+//!
+//! tonic::transport::Server::builder()
+//!     .add_service(CsdsService::new(client).into_server())
+//!     .serve(addr)
+//!     .await?;
+//! ```
+
+use crate::client::XdsClient;
+use crate::codec::prost::encode_node;
+use crate::message::{ClientResourceStatus, GenericXdsConfig, ResourceAny, UpdateFailureState};
+use envoy_types::pb::envoy::admin::v3 as admin;
+use envoy_types::pb::envoy::service::status::v3 as status;
+use envoy_types::pb::envoy::service::status::v3::client_status_discovery_service_server::{
+    ClientStatusDiscoveryService, ClientStatusDiscoveryServiceServer,
+};
+use envoy_types::pb::google::protobuf as pb;
+use std::pin::Pin;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status, Streaming};
+
+/// A CSDS server reporting the resources of [`XdsClient`]s.
+///
+/// Each client is reported as a separate `ClientConfig` with the node of its
+/// configuration. Node matchers in requests are ignored.
+#[derive(Clone, Debug)]
+pub struct CsdsService {
+    clients: Vec<XdsClient>,
+}
+
+impl CsdsService {
+    /// Create a service reporting the resources of `client`.
+    pub fn new(client: XdsClient) -> Self {
+        Self {
+            clients: vec![client],
+        }
+    }
+
+    /// Also report the resources of `client`.
+    #[must_use]
+    pub fn with_client(mut self, client: XdsClient) -> Self {
+        self.clients.push(client);
+        self
+    }
+
+    /// Wraps the service in a tonic server, to be added to a
+    /// [`Server`](tonic::transport::Server).
+    pub fn into_server(self) -> ClientStatusDiscoveryServiceServer<Self> {
+        ClientStatusDiscoveryServiceServer::new(self)
+    }
+
+    /// Dumps the resources of all clients.
+    async fn client_status(
+        &self,
+        request: &status::ClientStatusRequest,
+    ) -> status::ClientStatusResponse {
+        let mut config = Vec::with_capacity(self.clients.len());
+        for client in &self.clients {
+            let generic_xds_configs = client
+                .dump_resources()
+                .await
+                .into_iter()
+                .map(|resource| encode_config(resource, request.exclude_resource_contents))
+                .collect();
+            config.push(status::ClientConfig {
+                node: Some(encode_node(&client.config().node)),
+                generic_xds_configs,
+                ..Default::default()
+            });
+        }
+        status::ClientStatusResponse { config }
+    }
+}
+
+type ClientStatusStream =
+    Pin<Box<dyn Stream<Item = Result<status::ClientStatusResponse, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl ClientStatusDiscoveryService for CsdsService {
+    type StreamClientStatusStream = ClientStatusStream;
+
+    async fn stream_client_status(
+        &self,
+        request: Request<Streaming<status::ClientStatusRequest>>,
+    ) -> Result<Response<Self::StreamClientStatusStream>, Status> {
+        let mut requests = request.into_inner();
+        let service = self.clone();
+        let (tx, rx) = mpsc::channel(1);
+        // Each request is answered with the current status of the clients.
+        tokio::spawn(async move {
+            while let Ok(Some(request)) = requests.message().await {
+                let response = service.client_status(&request).await;
+                if tx.send(Ok(response)).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn fetch_client_status(
+        &self,
+        request: Request<status::ClientStatusRequest>,
+    ) -> Result<Response<status::ClientStatusResponse>, Status> {
+        Ok(Response::new(self.client_status(request.get_ref()).await))
+    }
+}
+
+/// Converts the state of a resource to its proto, without the resource itself if
+/// `exclude_contents` is set.
+fn encode_config(
+    config: GenericXdsConfig,
+    exclude_contents: bool,
+) -> status::client_config::GenericXdsConfig {
+    status::client_config::GenericXdsConfig {
+        type_url: config.type_url,
+        name: config.name,
+        version_info: config.version_info,
+        xds_config: config
+            .xds_config
+            .filter(|_| !exclude_contents)
+            .map(encode_any),
+        last_updated: config.last_updated.map(encode_timestamp),
+        client_status: encode_status(config.client_status) as i32,
+        error_state: config.error_state.map(encode_failure),
+        ..Default::default()
+    }
+}
+
+fn encode_any(any: ResourceAny) -> pb::Any {
+    pb::Any {
+        type_url: any.type_url,
+        value: any.value.to_vec(),
+    }
+}
+
+fn encode_status(status: ClientResourceStatus) -> admin::ClientResourceStatus {
+    match status {
+        ClientResourceStatus::Requested => admin::ClientResourceStatus::Requested,
+        ClientResourceStatus::DoesNotExist => admin::ClientResourceStatus::DoesNotExist,
+        ClientResourceStatus::Acked => admin::ClientResourceStatus::Acked,
+        ClientResourceStatus::Nacked => admin::ClientResourceStatus::Nacked,
+    }
+}
+
+fn encode_failure(failure: UpdateFailureState) -> admin::UpdateFailureState {
+    admin::UpdateFailureState {
+        last_update_attempt: Some(encode_timestamp(failure.last_update_attempt)),
+        details: failure.details,
+        version_info: failure.version_info,
+        ..Default::default()
+    }
+}
+
+/// Converts a time to its proto. Times before the Unix epoch are clamped to it.
+fn encode_timestamp(time: SystemTime) -> pb::Timestamp {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    pb::Timestamp {
+        seconds: i64::try_from(since_epoch.as_secs()).unwrap_or(i64::MAX),
+        nanos: since_epoch.subsec_nanos() as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{mock_transport, test_response, TestResource};
+    use crate::{ClientConfig, Node, ProstCodec, Resource, ServerConfig, TokioRuntime};
+
+    async fn fetch(service: &CsdsService) -> Vec<status::client_config::GenericXdsConfig> {
+        let response = service
+            .fetch_client_status(Request::new(status::ClientStatusRequest::default()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.config.len(), 1);
+        let config = response.config.into_iter().next().unwrap();
+        assert_eq!(config.node.unwrap().id, "node-1");
+        config.generic_xds_configs
+    }
+
+    #[tokio::test]
+    async fn test_client_status() {
+        let config = ClientConfig::new(Node {
+            id: "node-1".to_string(),
+            ..Default::default()
+        })
+        .with_servers(vec![ServerConfig::new("default-server")]);
+        let (transport, mut server) = mock_transport();
+        let client = XdsClient::builder(config)
+            .build(transport, ProstCodec, TokioRuntime)
+            .await
+            .unwrap();
+        let service = CsdsService::new(client.clone());
+
+        let mut foo = client.watch::<TestResource>("foo");
+        let _bar = client.watch::<TestResource>("bar");
+        let mut stream = server.next_stream().await;
+        stream.recv_request().await;
+        stream.recv_request().await;
+
+        stream.send_response(test_response(
+            "1",
+            "nonce-1",
+            &[TestResource::new("foo", "a")],
+        ));
+        foo.next().await.unwrap();
+        stream.recv_request().await;
+
+        let configs = fetch(&service).await;
+        let names: Vec<_> = configs.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["bar", "foo"]);
+        let (bar_config, foo_config) = (&configs[0], &configs[1]);
+        assert_eq!(
+            bar_config.client_status,
+            admin::ClientResourceStatus::Requested as i32
+        );
+        assert!(bar_config.xds_config.is_none());
+        assert_eq!(
+            foo_config.client_status,
+            admin::ClientResourceStatus::Acked as i32
+        );
+        assert_eq!(foo_config.type_url, TestResource::TYPE_URL.as_str());
+        assert_eq!(foo_config.version_info, "1");
+        assert_eq!(
+            foo_config.xds_config,
+            Some(pb::Any {
+                type_url: TestResource::TYPE_URL.as_str().to_string(),
+                value: b"foo=a".to_vec(),
+            })
+        );
+        assert!(foo_config.last_updated.is_some());
+
+        // A rejected update keeps the accepted version.
+        stream.send_response(test_response(
+            "2",
+            "nonce-2",
+            &[TestResource::new("foo", "")],
+        ));
+        foo.next().await.unwrap();
+        stream.recv_request().await;
+
+        let configs = fetch(&service).await;
+        let foo_config = &configs[1];
+        assert_eq!(
+            foo_config.client_status,
+            admin::ClientResourceStatus::Nacked as i32
+        );
+        assert_eq!(foo_config.version_info, "1");
+        let error_state = foo_config.error_state.as_ref().unwrap();
+        assert_eq!(error_state.version_info, "2");
+        assert_eq!(error_state.details, "empty value");
+        assert!(error_state.last_update_attempt.is_some());
+
+        let response = service
+            .fetch_client_status(Request::new(status::ClientStatusRequest {
+                exclude_resource_contents: true,
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(response.config[0]
+            .generic_xds_configs
+            .iter()
+            .all(|config| config.xds_config.is_none()));
+    }
+}
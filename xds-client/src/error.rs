@@ -3,7 +3,7 @@
 use thiserror::Error;
 
 /// Error type for the xDS client.
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum Error {
     /// Failed to connect to the xDS server.
     #[error("failed to connect: {0}")]
@@ -14,10 +14,41 @@ pub enum Error {
     #[error("stream error: {0}")]
     Stream(#[from] tonic::Status),
 
+    /// The bootstrap configuration is missing or invalid.
+    #[error("invalid bootstrap configuration: {0}")]
+    Bootstrap(String),
+
+    /// An `xdstp://` resource name refers to an authority missing from the configuration.
+    #[error("authority {0} is not configured")]
+    UnknownAuthority(String),
+
     /// The stream was closed unexpectedly.
     #[error("stream closed unexpectedly")]
     StreamClosed,
 
+    /// The resource was not received within the configured timeout, so it is
+    /// considered not to exist.
+    #[error("resource {0} does not exist")]
+    ResourceDoesNotExist(String),
+
+    /// A resource failed validation.
+    ///
+    /// The message is included in the NACK's `error_detail`.
+    #[error("validation error: {0}")]
+    Validation(String),
+
+    /// A resource whose name could be decoded failed validation.
+    ///
+    /// The message is included in the NACK's `error_detail`, and the resource is
+    /// reported as NACKed in the client status.
+    #[error("validation error: {name}: {message}")]
+    InvalidResource {
+        /// The name of the resource.
+        name: String,
+        /// Why the resource is invalid.
+        message: String,
+    },
+
     /// Failed to decode a protobuf message.
     #[cfg(feature = "codegen-prost")]
     #[error("decode error: {0}")]
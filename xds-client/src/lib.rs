@@ -4,10 +4,16 @@
 //! - ADS stream management (connection, reconnection, etc.)
 //! - Resource subscription and watching
 //! - Version/nonce tracking and ACK/NACK
+//! - The state-of-the-world and incremental (delta) variants of the ADS protocol
+//! - Federation: `xdstp://` resource names are fetched from the servers of their authority
+//! - Load reporting to LRS servers
+//! - Client status discovery (CSDS), reporting the state of every watched resource
+//!
+//! With the `codegen-prost` feature it also provides the LDS, RDS, CDS and EDS
+//! resource types, validated the way gRPC validates them.
 //!
 //! It does NOT contain gRPC-specific logic such as:
 //! - LDS -> RDS -> CDS -> EDS cascading
-//! - gRPC-specific resource validation
 //! - Service config generation
 //!
 //! Instead a gRPC library can use this crate to build these features.
@@ -15,11 +21,12 @@
 //! # Example
 //!
 //! ```ignore
-//! use xds_client::{XdsClient, ClientConfig, Resource};
+//! use xds_client::{ClientConfig, ProstCodec, TokioRuntime, TonicTransportBuilder, XdsClient};
 //!
-//! let config = ClientConfig::new("http://localhost:10000", "my-node");
+//! // Reads the bootstrap file named by GRPC_XDS_BOOTSTRAP.
+//! let config = ClientConfig::from_env()?;
 //! let client = XdsClient::builder(config)
-//!     .build(transport, runtime)
+//!     .build(TonicTransportBuilder, ProstCodec, TokioRuntime)
 //!     .await?;
 //!
 //! let mut watcher = client.watch::<Listener>("my-listener");
@@ -50,22 +57,34 @@
 
 pub mod client;
 pub mod codec;
+#[cfg(all(feature = "transport-tonic", feature = "codegen-prost"))]
+pub mod csds;
 pub mod error;
 pub mod message;
 pub mod resource;
 pub mod runtime;
 pub mod transport;
 
-pub use client::config::ClientConfig;
+#[cfg(test)]
+pub(crate) mod testutil;
+
+pub use client::config::{
+    AuthorityConfig, CertificateProviderConfig, ChannelCredentialsConfig, ClientConfig,
+    ServerConfig,
+};
+pub use client::load_report::{ClusterLoadStats, LocalityLoadStats};
 pub use client::watch::{ProcessingDone, ResourceEvent, ResourceWatcher};
-pub use client::worker::AdsWorker;
 pub use client::{XdsClient, XdsClientBuilder};
 pub use codec::XdsCodec;
 pub use error::{Error, Result};
-pub use message::{DiscoveryRequest, DiscoveryResponse, ErrorDetail, Locality, Node, ResourceAny};
+pub use message::{
+    ClientResourceStatus, DeltaDiscoveryRequest, DeltaDiscoveryResponse, DeltaResource,
+    DiscoveryRequest, DiscoveryResponse, ErrorDetail, GenericXdsConfig, Locality, Node,
+    ResourceAny, UpdateFailureState, Value,
+};
 pub use resource::Resource;
 pub use runtime::Runtime;
-pub use transport::{Transport, TransportStream};
+pub use transport::{Transport, TransportBuilder, TransportStream};
 
 // Tokio runtime
 #[cfg(feature = "rt-tokio")]
@@ -73,8 +92,16 @@ pub use runtime::tokio::TokioRuntime;
 
 // Tonic transport
 #[cfg(feature = "transport-tonic")]
-pub use transport::tonic::TonicTransport;
+pub use transport::tonic::{TonicTransport, TonicTransportBuilder};
+
+// Client status discovery service
+#[cfg(all(feature = "transport-tonic", feature = "codegen-prost"))]
+pub use csds::CsdsService;
 
 // Prost codec
 #[cfg(feature = "codegen-prost")]
 pub use codec::prost::ProstCodec;
+
+// Prost resources
+#[cfg(feature = "codegen-prost")]
+pub use resource::prost::{Cluster, ClusterLoadAssignment, Listener, RouteConfiguration};
@@ -5,6 +5,8 @@
 //! to/from the wire format (e.g., prost/envoy-types or google-protobuf).
 
 use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
 
 /// A discovery request to send to the xDS server.
 #[derive(Debug, Clone, Default)]
@@ -38,6 +40,55 @@ pub struct DiscoveryResponse {
     pub nonce: String,
 }
 
+/// An incremental (delta) discovery request to send to the xDS server.
+///
+/// Unlike a [`DiscoveryRequest`], it only carries the changes to the subscribed
+/// resource names since the previous request on the stream.
+#[derive(Debug, Clone, Default)]
+pub struct DeltaDiscoveryRequest {
+    /// The node making the request.
+    pub node: Option<Node>,
+    /// Type URL of the resources being requested.
+    pub type_url: String,
+    /// Resource names to add to the subscription.
+    pub resource_names_subscribe: Vec<String>,
+    /// Resource names to remove from the subscription.
+    pub resource_names_unsubscribe: Vec<String>,
+    /// The versions of the resources the client already has, keyed by name. Only sent
+    /// in the first request of a type on a stream.
+    pub initial_resource_versions: BTreeMap<String, String>,
+    /// The nonce from the most recent response, or empty for the first request.
+    pub response_nonce: String,
+    /// Error details if this is a NACK (negative acknowledgment).
+    pub error_detail: Option<ErrorDetail>,
+}
+
+/// An incremental (delta) discovery response from the xDS server.
+#[derive(Debug, Clone, Default)]
+pub struct DeltaDiscoveryResponse {
+    /// The version of the server's state, for debugging only.
+    pub system_version_info: String,
+    /// The added or updated resources.
+    pub resources: Vec<DeltaResource>,
+    /// Type URL of the resources.
+    pub type_url: String,
+    /// Names of the resources that have been removed.
+    pub removed_resources: Vec<String>,
+    /// Nonce for this response, to be echoed back in the next request.
+    pub nonce: String,
+}
+
+/// A resource in a [`DeltaDiscoveryResponse`].
+#[derive(Debug, Clone)]
+pub struct DeltaResource {
+    /// The resource name.
+    pub name: String,
+    /// The version of the resource.
+    pub version: String,
+    /// The resource, or `None` for a heartbeat of a resource the client already has.
+    pub resource: Option<ResourceAny>,
+}
+
 /// A resource wrapped as google.protobuf.Any.
 #[derive(Debug, Clone)]
 pub struct ResourceAny {
@@ -54,12 +105,14 @@ pub struct Node {
     pub id: String,
     /// The cluster the node belongs to.
     pub cluster: String,
+    /// Opaque metadata extending the node identifier.
+    pub metadata: BTreeMap<String, Value>,
     /// Locality specifying where the node is running.
     pub locality: Option<Locality>,
 }
 
-/// Locality information identifying where a node is running.
-#[derive(Debug, Clone, Default)]
+/// Locality information identifying where a node or endpoint is running.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub struct Locality {
     /// Region the node is in.
     pub region: String,
@@ -69,6 +122,23 @@ pub struct Locality {
     pub sub_zone: String,
 }
 
+/// A dynamically typed value, equivalent to `google.protobuf.Value` or a JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A null value.
+    Null,
+    /// A boolean value.
+    Bool(bool),
+    /// A number, which like in JSON is always a double.
+    Number(f64),
+    /// A string value.
+    String(String),
+    /// A list of values.
+    List(Vec<Value>),
+    /// A structured value with named fields.
+    Struct(BTreeMap<String, Value>),
+}
+
 /// Error details for NACK responses.
 #[derive(Debug, Clone)]
 pub struct ErrorDetail {
@@ -77,3 +147,110 @@ pub struct ErrorDetail {
     /// Error message.
     pub message: String,
 }
+
+/// A load report sent to the LRS server, see
+/// [gRFC A27](https://github.com/grpc/proposal/blob/master/A27-xds-global-load-balancing.md).
+#[derive(Debug, Clone, Default)]
+pub struct LoadStatsRequest {
+    /// The node making the request, only sent in the first request of a stream.
+    pub node: Option<Node>,
+    /// The load of each cluster since its last report.
+    pub cluster_stats: Vec<ClusterStats>,
+}
+
+/// The load of a cluster since its last report.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClusterStats {
+    /// The cluster name.
+    pub cluster_name: String,
+    /// The EDS service name of the cluster, if any.
+    pub cluster_service_name: String,
+    /// The requests sent to each locality.
+    pub upstream_locality_stats: Vec<UpstreamLocalityStats>,
+    /// The number of requests dropped, including those without a category.
+    pub total_dropped_requests: u64,
+    /// The number of requests dropped by each drop category.
+    pub dropped_requests: Vec<DroppedRequests>,
+    /// The time since the last report of the cluster.
+    pub load_report_interval: Duration,
+}
+
+/// The requests sent to the endpoints of a locality.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpstreamLocalityStats {
+    /// The locality.
+    pub locality: Locality,
+    /// The number of requests completed successfully.
+    pub total_successful_requests: u64,
+    /// The number of requests in progress when the report is sent.
+    pub total_requests_in_progress: u64,
+    /// The number of requests that failed.
+    pub total_error_requests: u64,
+    /// The number of requests issued.
+    pub total_issued_requests: u64,
+}
+
+/// The number of requests dropped by a drop category.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DroppedRequests {
+    /// The drop category.
+    pub category: String,
+    /// The number of requests dropped.
+    pub dropped_count: u64,
+}
+
+/// A response of the LRS server, asking for load reports.
+#[derive(Debug, Clone, Default)]
+pub struct LoadStatsResponse {
+    /// The clusters to report the load of.
+    pub clusters: Vec<String>,
+    /// Whether to report the load of all clusters, ignoring `clusters`.
+    pub send_all_clusters: bool,
+    /// The interval between load reports.
+    pub load_reporting_interval: Duration,
+}
+
+/// The status of a resource in the xDS client, as reported by the client status
+/// discovery service (CSDS).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClientResourceStatus {
+    /// The resource has been requested but not received yet.
+    Requested,
+    /// The resource was not received within the does-not-exist timeout.
+    DoesNotExist,
+    /// The resource has been received and accepted.
+    Acked,
+    /// The most recent update of the resource was rejected.
+    Nacked,
+}
+
+/// A rejected update of a resource.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateFailureState {
+    /// The version_info of the rejected response.
+    pub version_info: String,
+    /// Why the update was rejected.
+    pub details: String,
+    /// When the update was rejected.
+    pub last_update_attempt: SystemTime,
+}
+
+/// The state of a resource known to the xDS client, as reported by CSDS.
+#[derive(Debug, Clone)]
+pub struct GenericXdsConfig {
+    /// Type URL of the resource.
+    pub type_url: String,
+    /// The resource name.
+    pub name: String,
+    /// The version_info of the response the accepted resource was received in, or
+    /// empty if no version has been accepted.
+    pub version_info: String,
+    /// The accepted resource, if any.
+    pub xds_config: Option<ResourceAny>,
+    /// When the accepted resource was received.
+    pub last_updated: Option<SystemTime>,
+    /// The status of the resource.
+    pub client_status: ClientResourceStatus,
+    /// The most recent rejected update, if the resource is NACKed.
+    pub error_state: Option<UpdateFailureState>,
+}
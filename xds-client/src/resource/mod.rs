@@ -2,7 +2,10 @@
 
 use crate::error::Result;
 use bytes::Bytes;
+use std::any::Any;
+use std::sync::Arc;
 
+pub(crate) mod name;
 #[cfg(feature = "codegen-prost")]
 pub mod prost;
 
@@ -57,6 +60,12 @@ pub trait Resource: Sized + Send + Sync + 'static {
     /// The xDS type URL for this resource type.
     const TYPE_URL: TypeUrl;
 
+    /// Whether state-of-the-world responses of this type contain all the subscribed
+    /// resources, as for Listeners and Clusters.
+    ///
+    /// If so, a subscribed resource missing from such a response was deleted by the server.
+    const ALL_RESOURCES_REQUIRED_IN_SOTW: bool = false;
+
     /// Decode and validate a resource from its serialized bytes.
     ///
     /// Returns `Err` if parsing fails or validation fails.
@@ -67,3 +76,28 @@ pub trait Resource: Sized + Send + Sync + 'static {
     /// The resource name combined with the type URL uniquely identifies a resource.
     fn name(&self) -> &str;
 }
+
+/// A decoded resource with its concrete type erased.
+///
+/// The ADS worker handles all resource types uniformly; the concrete type is
+/// recovered by the [`ResourceWatcher`](crate::ResourceWatcher) of that type.
+#[derive(Debug, Clone)]
+pub(crate) struct DecodedResource {
+    /// The resource name.
+    pub(crate) name: String,
+    /// The decoded resource.
+    pub(crate) value: Arc<dyn Any + Send + Sync>,
+}
+
+/// Type-erased [`Resource::decode`], registered with the worker by the first
+/// watch of a resource type.
+pub(crate) type DecodeFn = fn(Bytes) -> Result<DecodedResource>;
+
+/// Decodes a resource of type `T` and erases its type.
+pub(crate) fn decode_erased<T: Resource>(bytes: Bytes) -> Result<DecodedResource> {
+    let resource = T::decode(bytes)?;
+    Ok(DecodedResource {
+        name: resource.name().to_string(),
+        value: Arc::new(resource),
+    })
+}
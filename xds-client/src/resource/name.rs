@@ -0,0 +1,115 @@
+//! Parsing of xDS resource names.
+//!
+//! Resource names are either old-style opaque names, or `xdstp://` names of the form
+//! `xdstp://{authority}/{resource type}/{id}?{context parameters}` introduced by
+//! [gRFC A47](https://github.com/grpc/proposal/blob/master/A47-xds-federation.md).
+
+/// Scheme prefix of federated resource names.
+const XDSTP_PREFIX: &str = "xdstp://";
+
+/// Returns the authority of an `xdstp://` resource name, or `None` for an old-style name.
+pub(crate) fn authority(name: &str) -> Option<&str> {
+    let rest = name.strip_prefix(XDSTP_PREFIX)?;
+    let end = rest.find(['/', '?']).unwrap_or(rest.len());
+    Some(&rest[..end])
+}
+
+/// Returns the canonical form of a resource name.
+///
+/// The context parameters of an `xdstp://` name are sorted by key, so that names which
+/// differ only in parameter order refer to the same resource. Old-style names are
+/// returned unchanged.
+pub(crate) fn canonicalize(name: &str) -> String {
+    if !name.starts_with(XDSTP_PREFIX) {
+        return name.to_string();
+    }
+    let Some((path, query)) = name.split_once('?') else {
+        return name.to_string();
+    };
+    let mut params: Vec<&str> = query.split('&').filter(|p| !p.is_empty()).collect();
+    params.sort_by_key(|p| p.split_once('=').map_or(*p, |(key, _)| key));
+    if params.is_empty() {
+        path.to_string()
+    } else {
+        format!("{path}?{}", params.join("&"))
+    }
+}
+
+/// Percent-encodes a string for use in the path of an `xdstp://` resource name.
+///
+/// Characters allowed in a URI path, including `/`, are kept as is.
+pub(crate) fn percent_encode_path(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'!'
+            | b'$'
+            | b'&'
+            | b'\''
+            | b'('
+            | b')'
+            | b'*'
+            | b'+'
+            | b','
+            | b';'
+            | b'='
+            | b':'
+            | b'@'
+            | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authority() {
+        assert_eq!(authority("listener-1"), None);
+        assert_eq!(
+            authority("xdstp://xds.example.com/envoy.config.listener.v3.Listener/foo"),
+            Some("xds.example.com")
+        );
+        assert_eq!(
+            authority("xdstp:///envoy.config.listener.v3.Listener/foo"),
+            Some("")
+        );
+        assert_eq!(
+            authority("xdstp://xds.example.com"),
+            Some("xds.example.com")
+        );
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        assert_eq!(canonicalize("foo?b=2&a=1"), "foo?b=2&a=1");
+        assert_eq!(
+            canonicalize("xdstp://auth/type/foo?b=2&a=1"),
+            "xdstp://auth/type/foo?a=1&b=2"
+        );
+        assert_eq!(
+            canonicalize("xdstp://auth/type/foo?"),
+            "xdstp://auth/type/foo"
+        );
+        assert_eq!(
+            canonicalize("xdstp://auth/type/foo"),
+            "xdstp://auth/type/foo"
+        );
+    }
+
+    #[test]
+    fn test_percent_encode_path() {
+        assert_eq!(percent_encode_path("my-service:80/a"), "my-service:80/a");
+        assert_eq!(percent_encode_path("a b?c#d%"), "a%20b%3Fc%23d%25");
+    }
+}
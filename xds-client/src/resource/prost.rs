@@ -1 +0,0 @@
-//! `prost` codec-specific resources.
@@ -0,0 +1,653 @@
+//! CDS resource: `Cluster`.
+
+use crate::error::Result;
+use crate::resource::prost::endpoint::socket_address;
+use crate::resource::prost::{convert_duration, decode_any, invalid, is_ads_config_source};
+use crate::resource::{Resource, TypeUrl};
+use bytes::Bytes;
+use envoy_types::pb::envoy::config::cluster::v3 as cluster;
+use envoy_types::pb::envoy::extensions::clusters::aggregate::v3::ClusterConfig as AggregateClusterConfig;
+use prost::Message;
+use std::time::Duration;
+
+/// Largest ring size accepted for ring hash load balancing.
+const MAX_RING_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Default minimum ring size for ring hash load balancing.
+const DEFAULT_MIN_RING_SIZE: u64 = 1024;
+
+/// Largest table size accepted for Maglev load balancing.
+const MAX_MAGLEV_TABLE_SIZE: u64 = 5_000_011;
+
+/// Default table size for Maglev load balancing.
+const DEFAULT_MAGLEV_TABLE_SIZE: u64 = 65_537;
+
+/// Default number of endpoints sampled by least request load balancing.
+const DEFAULT_CHOICE_COUNT: u32 = 2;
+
+/// Default outlier detection interval.
+const DEFAULT_OUTLIER_DETECTION_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Default base ejection time of outlier detection.
+const DEFAULT_BASE_EJECTION_TIME: Duration = Duration::from_secs(30);
+
+/// Default maximum ejection time of outlier detection.
+const DEFAULT_MAX_EJECTION_TIME: Duration = Duration::from_secs(300);
+
+/// Default maximum number of concurrent requests to a cluster.
+const DEFAULT_MAX_REQUESTS: u32 = 1024;
+
+/// Extension name of aggregate clusters.
+const AGGREGATE_CLUSTER: &str = "envoy.clusters.aggregate";
+
+/// A validated `envoy.config.cluster.v3.Cluster`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cluster {
+    /// The cluster name.
+    pub name: String,
+    /// How the endpoints of the cluster are discovered.
+    pub discovery: ClusterDiscovery,
+    /// The load balancing policy across endpoints.
+    pub lb_policy: LbPolicy,
+    /// The outlier detection of the endpoints, if enabled.
+    pub outlier_detection: Option<OutlierDetection>,
+    /// The maximum number of concurrent requests to the cluster, from the circuit breaker
+    /// thresholds of the default routing priority.
+    pub max_requests: u32,
+    /// Whether the load of the cluster is reported to the LRS server. gRPC only supports
+    /// reporting to the server the cluster was fetched from.
+    pub lrs_server: bool,
+}
+
+/// How the endpoints of a [`Cluster`] are discovered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClusterDiscovery {
+    /// Endpoints are fetched over EDS.
+    Eds {
+        /// The `ClusterLoadAssignment` name, if different from the cluster name.
+        service_name: Option<String>,
+    },
+    /// Endpoints are resolved from a DNS name.
+    LogicalDns {
+        /// The host name to resolve.
+        hostname: String,
+        /// The port of the endpoints.
+        port: u16,
+    },
+    /// The cluster is a prioritized list of other clusters, see
+    /// [gRFC A37](https://github.com/grpc/proposal/blob/master/A37-xds-aggregate-and-logical-dns-clusters.md).
+    Aggregate {
+        /// The underlying clusters, in order of priority.
+        clusters: Vec<String>,
+    },
+}
+
+impl ClusterDiscovery {
+    /// Returns the name of the EDS resource of an EDS cluster with the given name.
+    pub fn eds_resource_name<'a>(&'a self, cluster_name: &'a str) -> Option<&'a str> {
+        match self {
+            Self::Eds { service_name } => Some(service_name.as_deref().unwrap_or(cluster_name)),
+            _ => None,
+        }
+    }
+
+    /// Returns the EDS service name of an EDS cluster, if it has one.
+    pub fn eds_service_name(&self) -> Option<&str> {
+        match self {
+            Self::Eds { service_name } => service_name.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// A load balancing policy.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LbPolicy {
+    /// Round robin across endpoints.
+    RoundRobin,
+    /// Pick the endpoint with the fewest outstanding requests out of `choice_count`
+    /// random ones.
+    LeastRequest {
+        /// The number of endpoints sampled, at least 2.
+        choice_count: u32,
+    },
+    /// Consistent hashing on a ring, see
+    /// [gRFC A42](https://github.com/grpc/proposal/blob/master/A42-xds-ring-hash-lb-policy.md).
+    RingHash {
+        /// The minimum number of ring entries.
+        minimum_ring_size: u64,
+        /// The maximum number of ring entries.
+        maximum_ring_size: u64,
+    },
+    /// Consistent hashing with a Maglev lookup table.
+    Maglev {
+        /// The number of table entries, a prime number.
+        table_size: u64,
+    },
+}
+
+/// Ejection of endpoints with abnormal failure rates, see
+/// [gRFC A50](https://github.com/grpc/proposal/blob/master/A50-xds-outlier-detection.md).
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlierDetection {
+    /// The time between ejection sweeps.
+    pub interval: Duration,
+    /// The ejection time of an endpoint, multiplied by the number of times it was ejected.
+    pub base_ejection_time: Duration,
+    /// The maximum ejection time, unless lower than `base_ejection_time`.
+    pub max_ejection_time: Duration,
+    /// The maximum percentage of endpoints ejected at once.
+    pub max_ejection_percent: u32,
+    /// Ejection based on success rate statistics, if enabled.
+    pub success_rate_ejection: Option<SuccessRateEjection>,
+    /// Ejection based on failure percentage, if enabled.
+    pub failure_percentage_ejection: Option<FailurePercentageEjection>,
+}
+
+/// Ejection of endpoints whose success rate is too far below the mean of the cluster.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuccessRateEjection {
+    /// An endpoint is ejected if its success rate is below the mean by more than the
+    /// standard deviation times `stdev_factor / 1000`.
+    pub stdev_factor: u32,
+    /// The chance, in percent, that an outlier is ejected.
+    pub enforcement_percentage: u32,
+    /// The minimum number of endpoints with `request_volume` requests in an interval.
+    pub minimum_hosts: u32,
+    /// The minimum number of requests in an interval for an endpoint to be considered.
+    pub request_volume: u32,
+}
+
+/// Ejection of endpoints whose percentage of failed requests is above a threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailurePercentageEjection {
+    /// The failure percentage above which an endpoint is an outlier.
+    pub threshold: u32,
+    /// The chance, in percent, that an outlier is ejected.
+    pub enforcement_percentage: u32,
+    /// The minimum number of endpoints with `request_volume` requests in an interval.
+    pub minimum_hosts: u32,
+    /// The minimum number of requests in an interval for an endpoint to be considered.
+    pub request_volume: u32,
+}
+
+impl Resource for Cluster {
+    const TYPE_URL: TypeUrl = TypeUrl::new("type.googleapis.com/envoy.config.cluster.v3.Cluster");
+    const ALL_RESOURCES_REQUIRED_IN_SOTW: bool = true;
+
+    fn decode(bytes: Bytes) -> Result<Self> {
+        let proto = cluster::Cluster::decode(bytes)?;
+        let name = proto.name.clone();
+        let discovery = convert_discovery(&proto).map_err(|e| invalid(&name, e))?;
+        let lb_policy = convert_lb_policy(&proto).map_err(|e| invalid(&name, e))?;
+        let outlier_detection = proto
+            .outlier_detection
+            .as_ref()
+            .map(convert_outlier_detection)
+            .transpose()
+            .map_err(|e| invalid(&name, e))?;
+        let lrs_server = convert_lrs_server(&proto).map_err(|e| invalid(&name, e))?;
+        Ok(Self {
+            name,
+            discovery,
+            lb_policy,
+            outlier_detection,
+            max_requests: convert_max_requests(&proto),
+            lrs_server,
+        })
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+fn convert_discovery(proto: &cluster::Cluster) -> std::result::Result<ClusterDiscovery, String> {
+    use cluster::cluster::{ClusterDiscoveryType, DiscoveryType};
+
+    match &proto.cluster_discovery_type {
+        Some(ClusterDiscoveryType::Type(t)) if *t == DiscoveryType::Eds as i32 => {
+            let eds = proto.eds_cluster_config.as_ref();
+            if !is_ads_config_source(eds.and_then(|eds| eds.eds_config.as_ref())) {
+                return Err("eds config source must be ads or self".to_string());
+            }
+            let service_name = eds
+                .map(|eds| eds.service_name.clone())
+                .filter(|name| !name.is_empty());
+            if service_name.is_none() && proto.name.starts_with("xdstp:") {
+                return Err("eds service_name must be set for xdstp cluster names".to_string());
+            }
+            Ok(ClusterDiscovery::Eds { service_name })
+        }
+        Some(ClusterDiscoveryType::Type(t)) if *t == DiscoveryType::LogicalDns as i32 => {
+            let endpoint = proto
+                .load_assignment
+                .as_ref()
+                .and_then(|assignment| match assignment.endpoints.as_slice() {
+                    [locality] => match locality.lb_endpoints.as_slice() {
+                        [endpoint] => Some(endpoint),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .ok_or("logical dns cluster must have exactly one endpoint")?;
+            let (hostname, port) = socket_address(endpoint)?;
+            Ok(ClusterDiscovery::LogicalDns { hostname, port })
+        }
+        Some(ClusterDiscoveryType::ClusterType(custom)) if custom.name == AGGREGATE_CLUSTER => {
+            let config = custom
+                .typed_config
+                .as_ref()
+                .ok_or("aggregate cluster has no typed_config")?;
+            let config = decode_any::<AggregateClusterConfig>(config).map_err(|e| e.to_string())?;
+            if config.clusters.is_empty() {
+                return Err("aggregate cluster has no clusters".to_string());
+            }
+            Ok(ClusterDiscovery::Aggregate {
+                clusters: config.clusters,
+            })
+        }
+        _ => Err("unsupported cluster discovery type".to_string()),
+    }
+}
+
+fn convert_lb_policy(proto: &cluster::Cluster) -> std::result::Result<LbPolicy, String> {
+    use cluster::cluster::ring_hash_lb_config::HashFunction;
+    use cluster::cluster::{LbConfig, LbPolicy as ProtoLbPolicy};
+
+    match ProtoLbPolicy::try_from(proto.lb_policy) {
+        Ok(ProtoLbPolicy::RoundRobin) => Ok(LbPolicy::RoundRobin),
+        Ok(ProtoLbPolicy::LeastRequest) => {
+            let choice_count = match &proto.lb_config {
+                Some(LbConfig::LeastRequestLbConfig(config)) => config
+                    .choice_count
+                    .map_or(DEFAULT_CHOICE_COUNT, |count| count.value),
+                _ => DEFAULT_CHOICE_COUNT,
+            };
+            if choice_count < 2 {
+                return Err(format!(
+                    "least request choice_count {choice_count} is less than 2"
+                ));
+            }
+            Ok(LbPolicy::LeastRequest { choice_count })
+        }
+        Ok(ProtoLbPolicy::RingHash) => {
+            let (minimum_ring_size, maximum_ring_size) = match &proto.lb_config {
+                Some(LbConfig::RingHashLbConfig(config)) => {
+                    if config.hash_function != HashFunction::XxHash as i32 {
+                        return Err("ring hash function must be XX_HASH".to_string());
+                    }
+                    (
+                        config
+                            .minimum_ring_size
+                            .map_or(DEFAULT_MIN_RING_SIZE, |size| size.value),
+                        config
+                            .maximum_ring_size
+                            .map_or(MAX_RING_SIZE, |size| size.value),
+                    )
+                }
+                _ => (DEFAULT_MIN_RING_SIZE, MAX_RING_SIZE),
+            };
+            if maximum_ring_size > MAX_RING_SIZE || minimum_ring_size > maximum_ring_size {
+                return Err(format!(
+                    "invalid ring sizes: minimum {minimum_ring_size}, maximum {maximum_ring_size}"
+                ));
+            }
+            Ok(LbPolicy::RingHash {
+                minimum_ring_size,
+                maximum_ring_size,
+            })
+        }
+        Ok(ProtoLbPolicy::Maglev) => {
+            let table_size = match &proto.lb_config {
+                Some(LbConfig::MaglevLbConfig(config)) => config
+                    .table_size
+                    .map_or(DEFAULT_MAGLEV_TABLE_SIZE, |size| size.value),
+                _ => DEFAULT_MAGLEV_TABLE_SIZE,
+            };
+            if table_size > MAX_MAGLEV_TABLE_SIZE || !is_prime(table_size) {
+                return Err(format!("invalid maglev table size {table_size}"));
+            }
+            Ok(LbPolicy::Maglev { table_size })
+        }
+        _ => Err(format!("unsupported lb_policy {}", proto.lb_policy)),
+    }
+}
+
+fn convert_max_requests(proto: &cluster::Cluster) -> u32 {
+    use envoy_types::pb::envoy::config::core::v3::RoutingPriority;
+
+    proto
+        .circuit_breakers
+        .iter()
+        .flat_map(|circuit_breakers| &circuit_breakers.thresholds)
+        .find(|thresholds| thresholds.priority == RoutingPriority::Default as i32)
+        .and_then(|thresholds| thresholds.max_requests)
+        .map_or(DEFAULT_MAX_REQUESTS, |max_requests| max_requests.value)
+}
+
+fn convert_lrs_server(proto: &cluster::Cluster) -> std::result::Result<bool, String> {
+    use envoy_types::pb::envoy::config::core::v3::config_source::ConfigSourceSpecifier;
+
+    match &proto.lrs_server {
+        None => Ok(false),
+        Some(source) => match source.config_source_specifier {
+            Some(ConfigSourceSpecifier::Self_(_)) => Ok(true),
+            _ => Err("lrs_server config source must be self".to_string()),
+        },
+    }
+}
+
+fn convert_outlier_detection(
+    proto: &cluster::OutlierDetection,
+) -> std::result::Result<OutlierDetection, String> {
+    let duration = |duration: Option<&_>, default| match duration {
+        Some(duration) => convert_duration(duration),
+        None => Ok(default),
+    };
+    let percent = |name: &str, value: Option<u32>, default: u32| {
+        let value = value.unwrap_or(default);
+        if value > 100 {
+            return Err(format!("outlier detection {name} {value} exceeds 100"));
+        }
+        Ok(value)
+    };
+    let value = |value: Option<u32>, default: u32| value.unwrap_or(default);
+
+    let enforcing_success_rate = percent(
+        "enforcing_success_rate",
+        proto.enforcing_success_rate.map(|v| v.value),
+        100,
+    )?;
+    let enforcing_failure_percentage = percent(
+        "enforcing_failure_percentage",
+        proto.enforcing_failure_percentage.map(|v| v.value),
+        0,
+    )?;
+    let failure_percentage_threshold = percent(
+        "failure_percentage_threshold",
+        proto.failure_percentage_threshold.map(|v| v.value),
+        85,
+    )?;
+    Ok(OutlierDetection {
+        interval: duration(proto.interval.as_ref(), DEFAULT_OUTLIER_DETECTION_INTERVAL)?,
+        base_ejection_time: duration(
+            proto.base_ejection_time.as_ref(),
+            DEFAULT_BASE_EJECTION_TIME,
+        )?,
+        max_ejection_time: duration(proto.max_ejection_time.as_ref(), DEFAULT_MAX_EJECTION_TIME)?,
+        max_ejection_percent: percent(
+            "max_ejection_percent",
+            proto.max_ejection_percent.map(|v| v.value),
+            10,
+        )?,
+        // Each algorithm is disabled if it is never enforced.
+        success_rate_ejection: (enforcing_success_rate > 0).then(|| SuccessRateEjection {
+            stdev_factor: value(proto.success_rate_stdev_factor.map(|v| v.value), 1900),
+            enforcement_percentage: enforcing_success_rate,
+            minimum_hosts: value(proto.success_rate_minimum_hosts.map(|v| v.value), 5),
+            request_volume: value(proto.success_rate_request_volume.map(|v| v.value), 100),
+        }),
+        failure_percentage_ejection: (enforcing_failure_percentage > 0).then(|| {
+            FailurePercentageEjection {
+                threshold: failure_percentage_threshold,
+                enforcement_percentage: enforcing_failure_percentage,
+                minimum_hosts: value(proto.failure_percentage_minimum_hosts.map(|v| v.value), 5),
+                request_volume: value(proto.failure_percentage_request_volume.map(|v| v.value), 50),
+            }
+        }),
+    })
+}
+
+fn is_prime(n: u64) -> bool {
+    n >= 2 && (2..).take_while(|d| d * d <= n).all(|d| n % d != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::prost::to_any;
+    use envoy_types::pb::envoy::config::core::v3 as core;
+    use envoy_types::pb::google::protobuf::{UInt32Value, UInt64Value};
+
+    fn eds_cluster(name: &str, service_name: &str) -> cluster::Cluster {
+        cluster::Cluster {
+            name: name.to_string(),
+            cluster_discovery_type: Some(cluster::cluster::ClusterDiscoveryType::Type(
+                cluster::cluster::DiscoveryType::Eds as i32,
+            )),
+            eds_cluster_config: Some(cluster::cluster::EdsClusterConfig {
+                eds_config: Some(core::ConfigSource {
+                    config_source_specifier: Some(core::config_source::ConfigSourceSpecifier::Ads(
+                        Default::default(),
+                    )),
+                    ..Default::default()
+                }),
+                service_name: service_name.to_string(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn decode(proto: cluster::Cluster) -> Result<Cluster> {
+        Cluster::decode(proto.encode_to_vec().into())
+    }
+
+    #[test]
+    fn test_decode_eds_cluster() {
+        let cluster = decode(eds_cluster("cluster-1", "")).unwrap();
+        assert_eq!(cluster.name(), "cluster-1");
+        assert_eq!(
+            cluster.discovery,
+            ClusterDiscovery::Eds { service_name: None }
+        );
+        assert_eq!(
+            cluster.discovery.eds_resource_name(&cluster.name),
+            Some("cluster-1")
+        );
+        assert_eq!(cluster.lb_policy, LbPolicy::RoundRobin);
+
+        let mut proto = eds_cluster("cluster-1", "service-1");
+        proto.lb_policy = cluster::cluster::LbPolicy::RingHash as i32;
+        proto.lb_config = Some(cluster::cluster::LbConfig::RingHashLbConfig(
+            cluster::cluster::RingHashLbConfig {
+                minimum_ring_size: Some(UInt64Value { value: 10 }),
+                ..Default::default()
+            },
+        ));
+        let cluster = decode(proto).unwrap();
+        assert_eq!(
+            cluster.discovery.eds_resource_name(&cluster.name),
+            Some("service-1")
+        );
+        assert_eq!(cluster.discovery.eds_service_name(), Some("service-1"));
+        assert_eq!(
+            cluster.lb_policy,
+            LbPolicy::RingHash {
+                minimum_ring_size: 10,
+                maximum_ring_size: MAX_RING_SIZE,
+            }
+        );
+
+        assert_eq!(cluster.outlier_detection, None);
+        assert_eq!(cluster.max_requests, DEFAULT_MAX_REQUESTS);
+        assert!(!cluster.lrs_server);
+
+        let mut proto = eds_cluster("cluster-1", "");
+        proto.lrs_server = Some(core::ConfigSource {
+            config_source_specifier: Some(core::config_source::ConfigSourceSpecifier::Self_(
+                Default::default(),
+            )),
+            ..Default::default()
+        });
+        assert!(decode(proto).unwrap().lrs_server);
+
+        let mut proto = eds_cluster("cluster-1", "");
+        proto.lb_policy = cluster::cluster::LbPolicy::Maglev as i32;
+        assert_eq!(
+            decode(proto).unwrap().lb_policy,
+            LbPolicy::Maglev {
+                table_size: DEFAULT_MAGLEV_TABLE_SIZE,
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_max_requests() {
+        use cluster::circuit_breakers::Thresholds;
+        use cluster::CircuitBreakers;
+        use envoy_types::pb::envoy::config::core::v3::RoutingPriority;
+        use envoy_types::pb::google::protobuf::UInt32Value;
+
+        let thresholds = |priority: RoutingPriority, max_requests| Thresholds {
+            priority: priority as i32,
+            max_requests: Some(UInt32Value {
+                value: max_requests,
+            }),
+            ..Default::default()
+        };
+        let mut proto = eds_cluster("cluster-1", "");
+        proto.circuit_breakers = Some(CircuitBreakers {
+            thresholds: vec![
+                thresholds(RoutingPriority::High, 10),
+                thresholds(RoutingPriority::Default, 100),
+            ],
+            ..Default::default()
+        });
+        assert_eq!(decode(proto).unwrap().max_requests, 100);
+
+        let mut proto = eds_cluster("cluster-1", "");
+        proto.circuit_breakers = Some(CircuitBreakers {
+            thresholds: vec![thresholds(RoutingPriority::High, 10)],
+            ..Default::default()
+        });
+        assert_eq!(decode(proto).unwrap().max_requests, DEFAULT_MAX_REQUESTS);
+    }
+
+    #[test]
+    fn test_decode_outlier_detection() {
+        use envoy_types::pb::google::protobuf::Duration as ProtoDuration;
+
+        let mut proto = eds_cluster("cluster-1", "");
+        proto.outlier_detection = Some(cluster::OutlierDetection::default());
+        assert_eq!(
+            decode(proto.clone()).unwrap().outlier_detection,
+            Some(OutlierDetection {
+                interval: DEFAULT_OUTLIER_DETECTION_INTERVAL,
+                base_ejection_time: DEFAULT_BASE_EJECTION_TIME,
+                max_ejection_time: DEFAULT_MAX_EJECTION_TIME,
+                max_ejection_percent: 10,
+                success_rate_ejection: Some(SuccessRateEjection {
+                    stdev_factor: 1900,
+                    enforcement_percentage: 100,
+                    minimum_hosts: 5,
+                    request_volume: 100,
+                }),
+                failure_percentage_ejection: None,
+            })
+        );
+
+        proto.outlier_detection = Some(cluster::OutlierDetection {
+            interval: Some(ProtoDuration {
+                seconds: 1,
+                nanos: 0,
+            }),
+            enforcing_success_rate: Some(UInt32Value { value: 0 }),
+            enforcing_failure_percentage: Some(UInt32Value { value: 50 }),
+            failure_percentage_threshold: Some(UInt32Value { value: 20 }),
+            failure_percentage_request_volume: Some(UInt32Value { value: 10 }),
+            ..Default::default()
+        });
+        let outlier_detection = decode(proto).unwrap().outlier_detection.unwrap();
+        assert_eq!(outlier_detection.interval, Duration::from_secs(1));
+        assert_eq!(outlier_detection.success_rate_ejection, None);
+        assert_eq!(
+            outlier_detection.failure_percentage_ejection,
+            Some(FailurePercentageEjection {
+                threshold: 20,
+                enforcement_percentage: 50,
+                minimum_hosts: 5,
+                request_volume: 10,
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_aggregate_cluster() {
+        let config = AggregateClusterConfig {
+            clusters: vec!["a".to_string(), "b".to_string()],
+        };
+        let proto = cluster::Cluster {
+            name: "aggregate".to_string(),
+            cluster_discovery_type: Some(cluster::cluster::ClusterDiscoveryType::ClusterType(
+                cluster::cluster::CustomClusterType {
+                    name: AGGREGATE_CLUSTER.to_string(),
+                    typed_config: Some(to_any(&config)),
+                },
+            )),
+            ..Default::default()
+        };
+        assert_eq!(
+            decode(proto).unwrap().discovery,
+            ClusterDiscovery::Aggregate {
+                clusters: vec!["a".to_string(), "b".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_cluster() {
+        let mut non_ads = eds_cluster("cluster-1", "");
+        non_ads.eds_cluster_config.as_mut().unwrap().eds_config = None;
+        let mut static_cluster = eds_cluster("cluster-1", "");
+        static_cluster.cluster_discovery_type = Some(cluster::cluster::ClusterDiscoveryType::Type(
+            cluster::cluster::DiscoveryType::Static as i32,
+        ));
+        let mut random = eds_cluster("cluster-1", "");
+        random.lb_policy = cluster::cluster::LbPolicy::Random as i32;
+        let mut ring_too_large = eds_cluster("cluster-1", "");
+        ring_too_large.lb_policy = cluster::cluster::LbPolicy::RingHash as i32;
+        ring_too_large.lb_config = Some(cluster::cluster::LbConfig::RingHashLbConfig(
+            cluster::cluster::RingHashLbConfig {
+                maximum_ring_size: Some(UInt64Value {
+                    value: MAX_RING_SIZE + 1,
+                }),
+                ..Default::default()
+            },
+        ));
+        let mut maglev_not_prime = eds_cluster("cluster-1", "");
+        maglev_not_prime.lb_policy = cluster::cluster::LbPolicy::Maglev as i32;
+        maglev_not_prime.lb_config = Some(cluster::cluster::LbConfig::MaglevLbConfig(
+            cluster::cluster::MaglevLbConfig {
+                table_size: Some(UInt64Value { value: 65_536 }),
+            },
+        ));
+        let mut ejection_percent_too_large = eds_cluster("cluster-1", "");
+        ejection_percent_too_large.outlier_detection = Some(cluster::OutlierDetection {
+            max_ejection_percent: Some(UInt32Value { value: 101 }),
+            ..Default::default()
+        });
+        let mut lrs_over_ads = eds_cluster("cluster-1", "");
+        lrs_over_ads.lrs_server = lrs_over_ads
+            .eds_cluster_config
+            .as_ref()
+            .unwrap()
+            .eds_config
+            .clone();
+        let xdstp_without_service =
+            eds_cluster("xdstp://auth/envoy.config.cluster.v3.Cluster/c", "");
+
+        for proto in [
+            non_ads,
+            static_cluster,
+            random,
+            ring_too_large,
+            maglev_not_prime,
+            ejection_percent_too_large,
+            lrs_over_ads,
+            xdstp_without_service,
+        ] {
+            assert!(decode(proto).is_err());
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! EDS resource: `ClusterLoadAssignment`.
+
+use crate::error::Result;
+use crate::message::Locality;
+use crate::resource::prost::{convert_locality, invalid, parts_per_million};
+use crate::resource::{Resource, TypeUrl};
+use bytes::Bytes;
+use envoy_types::pb::envoy::config::core::v3 as core;
+use envoy_types::pb::envoy::config::endpoint::v3 as endpoint;
+use prost::Message;
+use std::collections::{BTreeSet, HashSet};
+
+/// A validated `envoy.config.endpoint.v3.ClusterLoadAssignment`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterLoadAssignment {
+    /// The name of the cluster, or the EDS service name of the cluster.
+    pub cluster_name: String,
+    /// The localities with a non-zero weight. Priorities are contiguous, starting at 0.
+    pub localities: Vec<LocalityEndpoints>,
+    /// Categories of requests the client should drop.
+    pub drop_overloads: Vec<DropOverload>,
+}
+
+/// The endpoints of a locality.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalityEndpoints {
+    /// The locality.
+    pub locality: Locality,
+    /// The load balancing weight of the locality, greater than zero.
+    pub weight: u32,
+    /// The priority of the locality. 0 is the highest priority.
+    pub priority: u32,
+    /// The endpoints of the locality.
+    pub endpoints: Vec<Endpoint>,
+}
+
+/// An upstream endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    /// The IP address or host name of the endpoint.
+    pub address: String,
+    /// The port of the endpoint.
+    pub port: u16,
+    /// The health status reported by the control plane.
+    pub health_status: HealthStatus,
+    /// The load balancing weight of the endpoint, greater than zero.
+    pub weight: u32,
+}
+
+/// Health status of an [`Endpoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthStatus {
+    /// The health is unknown. Treated as healthy.
+    Unknown,
+    /// The endpoint is healthy.
+    Healthy,
+    /// The endpoint is unhealthy.
+    Unhealthy,
+    /// The endpoint is draining connections.
+    Draining,
+    /// Health checking timed out.
+    Timeout,
+    /// The endpoint is degraded.
+    Degraded,
+}
+
+impl HealthStatus {
+    /// Returns whether requests may be sent to the endpoint.
+    pub fn is_usable(self) -> bool {
+        matches!(self, Self::Unknown | Self::Healthy)
+    }
+}
+
+/// A category of requests the client should drop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DropOverload {
+    /// The drop category, reported in load reports.
+    pub category: String,
+    /// The fraction of requests to drop, in parts per million.
+    pub parts_per_million: u32,
+}
+
+impl Resource for ClusterLoadAssignment {
+    const TYPE_URL: TypeUrl =
+        TypeUrl::new("type.googleapis.com/envoy.config.endpoint.v3.ClusterLoadAssignment");
+
+    fn decode(bytes: Bytes) -> Result<Self> {
+        let proto = endpoint::ClusterLoadAssignment::decode(bytes)?;
+        let cluster_name = proto.cluster_name;
+        let localities =
+            convert_localities(proto.endpoints).map_err(|e| invalid(&cluster_name, e))?;
+        let drop_overloads = proto
+            .policy
+            .map(|policy| policy.drop_overloads)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|drop| DropOverload {
+                category: drop.category,
+                parts_per_million: drop.drop_percentage.map_or(0, |p| parts_per_million(&p)),
+            })
+            .collect();
+        Ok(Self {
+            cluster_name,
+            localities,
+            drop_overloads,
+        })
+    }
+
+    fn name(&self) -> &str {
+        &self.cluster_name
+    }
+}
+
+fn convert_localities(
+    localities: Vec<endpoint::LocalityLbEndpoints>,
+) -> std::result::Result<Vec<LocalityEndpoints>, String> {
+    let mut seen = HashSet::new();
+    let mut priorities = BTreeSet::new();
+    let mut converted = Vec::with_capacity(localities.len());
+    for locality in localities {
+        // Localities without a weight are ignored.
+        let weight = locality.load_balancing_weight.map_or(0, |w| w.value);
+        if weight == 0 {
+            continue;
+        }
+        let priority = locality.priority;
+        let proto_locality = convert_locality(locality.locality.unwrap_or_default());
+        if !seen.insert((priority, proto_locality.clone())) {
+            return Err(format!(
+                "duplicate locality {proto_locality:?} in priority {priority}"
+            ));
+        }
+        let mut total_weight: u64 = 0;
+        let mut endpoints = Vec::with_capacity(locality.lb_endpoints.len());
+        for lb_endpoint in &locality.lb_endpoints {
+            let endpoint = convert_endpoint(lb_endpoint)?;
+            total_weight += u64::from(endpoint.weight);
+            endpoints.push(endpoint);
+        }
+        if total_weight > u64::from(u32::MAX) {
+            return Err(format!(
+                "total endpoint weight {total_weight} exceeds uint32"
+            ));
+        }
+        priorities.insert(priority);
+        converted.push(LocalityEndpoints {
+            locality: proto_locality,
+            weight,
+            priority,
+            endpoints,
+        });
+    }
+    // Priorities must be contiguous, starting at 0.
+    if priorities.iter().enumerate().any(|(i, p)| *p as usize != i) {
+        return Err(format!("priorities {priorities:?} are not contiguous"));
+    }
+    Ok(converted)
+}
+
+fn convert_endpoint(lb_endpoint: &endpoint::LbEndpoint) -> std::result::Result<Endpoint, String> {
+    let (address, port) = socket_address(lb_endpoint)?;
+    let weight = lb_endpoint.load_balancing_weight.map_or(1, |w| w.value);
+    if weight == 0 {
+        return Err(format!("endpoint {address}:{port} has a weight of 0"));
+    }
+    let health_status = match core::HealthStatus::try_from(lb_endpoint.health_status) {
+        Ok(core::HealthStatus::Healthy) => HealthStatus::Healthy,
+        Ok(core::HealthStatus::Unhealthy) => HealthStatus::Unhealthy,
+        Ok(core::HealthStatus::Draining) => HealthStatus::Draining,
+        Ok(core::HealthStatus::Timeout) => HealthStatus::Timeout,
+        Ok(core::HealthStatus::Degraded) => HealthStatus::Degraded,
+        Ok(core::HealthStatus::Unknown) | Err(_) => HealthStatus::Unknown,
+    };
+    Ok(Endpoint {
+        address,
+        port,
+        health_status,
+        weight,
+    })
+}
+
+/// Returns the address and port of an endpoint, which must be a socket address.
+pub(crate) fn socket_address(
+    lb_endpoint: &endpoint::LbEndpoint,
+) -> std::result::Result<(String, u16), String> {
+    use core::address::Address;
+    use core::socket_address::PortSpecifier;
+    use endpoint::lb_endpoint::HostIdentifier;
+
+    let address = match &lb_endpoint.host_identifier {
+        Some(HostIdentifier::Endpoint(endpoint)) => endpoint
+            .address
+            .as_ref()
+            .and_then(|address| address.address.as_ref()),
+        _ => None,
+    };
+    let Some(Address::SocketAddress(address)) = address else {
+        return Err("endpoint has no socket address".to_string());
+    };
+    let port = match address.port_specifier {
+        Some(PortSpecifier::PortValue(port)) => u16::try_from(port).ok(),
+        _ => None,
+    }
+    .ok_or_else(|| format!("endpoint {} has an invalid port", address.address))?;
+    Ok((address.address.clone(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use envoy_types::pb::envoy::r#type::v3::{fractional_percent, FractionalPercent};
+    use envoy_types::pb::google::protobuf::UInt32Value;
+
+    fn lb_endpoint(address: &str, port: u32) -> endpoint::LbEndpoint {
+        endpoint::LbEndpoint {
+            host_identifier: Some(endpoint::lb_endpoint::HostIdentifier::Endpoint(
+                endpoint::Endpoint {
+                    address: Some(core::Address {
+                        address: Some(core::address::Address::SocketAddress(core::SocketAddress {
+                            address: address.to_string(),
+                            port_specifier: Some(core::socket_address::PortSpecifier::PortValue(
+                                port,
+                            )),
+                            ..Default::default()
+                        })),
+                    }),
+                    ..Default::default()
+                },
+            )),
+            ..Default::default()
+        }
+    }
+
+    fn locality(zone: &str, weight: u32, priority: u32) -> endpoint::LocalityLbEndpoints {
+        endpoint::LocalityLbEndpoints {
+            locality: Some(core::Locality {
+                zone: zone.to_string(),
+                ..Default::default()
+            }),
+            load_balancing_weight: Some(UInt32Value { value: weight }),
+            priority,
+            lb_endpoints: vec![lb_endpoint("10.0.0.1", 8080)],
+            ..Default::default()
+        }
+    }
+
+    fn decode(localities: Vec<endpoint::LocalityLbEndpoints>) -> Result<ClusterLoadAssignment> {
+        let proto = endpoint::ClusterLoadAssignment {
+            cluster_name: "cluster-1".to_string(),
+            endpoints: localities,
+            policy: Some(endpoint::cluster_load_assignment::Policy {
+                drop_overloads: vec![endpoint::cluster_load_assignment::policy::DropOverload {
+                    category: "throttle".to_string(),
+                    drop_percentage: Some(FractionalPercent {
+                        numerator: 10,
+                        denominator: fractional_percent::DenominatorType::Hundred as i32,
+                    }),
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        ClusterLoadAssignment::decode(proto.encode_to_vec().into())
+    }
+
+    #[test]
+    fn test_decode_cluster_load_assignment() {
+        let mut unhealthy = locality("b", 2, 1);
+        unhealthy.lb_endpoints[0].health_status = core::HealthStatus::Unhealthy as i32;
+        let assignment = decode(vec![locality("a", 1, 0), unhealthy, locality("c", 0, 5)]).unwrap();
+
+        assert_eq!(assignment.name(), "cluster-1");
+        assert_eq!(
+            assignment.drop_overloads,
+            vec![DropOverload {
+                category: "throttle".to_string(),
+                parts_per_million: 100_000,
+            }]
+        );
+        // The zero-weight locality is ignored, so priorities are contiguous.
+        assert_eq!(assignment.localities.len(), 2);
+        assert_eq!(assignment.localities[0].locality.zone, "a");
+        assert_eq!(
+            assignment.localities[0].endpoints,
+            vec![Endpoint {
+                address: "10.0.0.1".to_string(),
+                port: 8080,
+                health_status: HealthStatus::Unknown,
+                weight: 1,
+            }]
+        );
+        assert_eq!(assignment.localities[1].priority, 1);
+        assert!(!assignment.localities[1].endpoints[0]
+            .health_status
+            .is_usable());
+    }
+
+    #[test]
+    fn test_invalid_cluster_load_assignment() {
+        let mut bad_port = locality("a", 1, 0);
+        bad_port.lb_endpoints = vec![lb_endpoint("10.0.0.1", 70000)];
+        let mut zero_weight = locality("a", 1, 0);
+        zero_weight.lb_endpoints[0].load_balancing_weight = Some(UInt32Value { value: 0 });
+
+        for localities in [
+            vec![locality("a", 1, 0), locality("a", 1, 0)],
+            vec![locality("a", 1, 0), locality("b", 1, 2)],
+            vec![bad_port],
+            vec![zero_weight],
+        ] {
+            let error = decode(localities).unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .starts_with("validation error: cluster-1: "),
+                "{error}"
+            );
+        }
+    }
+}
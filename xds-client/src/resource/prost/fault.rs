@@ -0,0 +1,257 @@
+//! The fault injection HTTP filter, see
+//! [gRFC A33](https://github.com/grpc/proposal/blob/master/A33-Fault-Injection.md).
+
+use crate::resource::prost::convert_duration;
+use crate::resource::prost::route::{convert_header_matcher, HeaderMatcher};
+use envoy_types::pb::envoy::extensions::filters::common::fault::v3 as common_fault;
+use envoy_types::pb::envoy::extensions::filters::http::fault::v3 as fault;
+use envoy_types::pb::envoy::r#type::v3::{fractional_percent, FractionalPercent};
+use std::time::Duration;
+
+/// A validated `envoy.extensions.filters.http.fault.v3.HTTPFault`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultInjection {
+    /// The delay injected before requests are sent, if any.
+    pub delay: Option<FaultDelay>,
+    /// The abort injected instead of sending requests, if any.
+    pub abort: Option<FaultAbort>,
+    /// Header matchers, all of which must match for a fault to be injected.
+    pub headers: Vec<HeaderMatcher>,
+    /// The maximum number of requests with an active fault, unlimited if `None`.
+    pub max_active_faults: Option<u32>,
+}
+
+/// A delay injected into requests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultDelay {
+    /// The duration of the delay.
+    pub delay: FaultDelayKind,
+    /// The share of requests delayed.
+    pub percentage: FaultPercentage,
+}
+
+/// The duration of a [`FaultDelay`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum FaultDelayKind {
+    /// A fixed delay.
+    Fixed(Duration),
+    /// A delay in milliseconds taken from the `x-envoy-fault-delay-request` header. The
+    /// `x-envoy-fault-delay-request-percentage` header may lower the percentage of delayed
+    /// requests.
+    Header,
+}
+
+/// An abort injected into requests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultAbort {
+    /// The status of aborted requests.
+    pub status: FaultAbortStatus,
+    /// The share of requests aborted.
+    pub percentage: FaultPercentage,
+}
+
+/// The status of a [`FaultAbort`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum FaultAbortStatus {
+    /// An HTTP status, converted to a gRPC status code.
+    Http(u32),
+    /// A gRPC status code.
+    Grpc(u32),
+    /// A gRPC status code from the `x-envoy-fault-abort-grpc-request` header, or an HTTP
+    /// status from the `x-envoy-fault-abort-request` header. The
+    /// `x-envoy-fault-abort-request-percentage` header may lower the percentage of aborted
+    /// requests.
+    Header,
+}
+
+/// The share of requests a fault is injected into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultPercentage {
+    /// The numerator of the share.
+    pub numerator: u32,
+    /// The denominator of the share: 100, 10 000 or 1 000 000.
+    pub denominator: u32,
+}
+
+impl FaultPercentage {
+    /// Returns the share in parts per million.
+    pub fn per_million(&self) -> u32 {
+        let multiplier = 1_000_000 / self.denominator.max(1);
+        self.numerator.saturating_mul(multiplier).min(1_000_000)
+    }
+}
+
+impl From<&FractionalPercent> for FaultPercentage {
+    fn from(fraction: &FractionalPercent) -> Self {
+        use fractional_percent::DenominatorType;
+
+        let denominator = match DenominatorType::try_from(fraction.denominator) {
+            Ok(DenominatorType::Hundred) => 100,
+            Ok(DenominatorType::TenThousand) => 10_000,
+            Ok(DenominatorType::Million) | Err(_) => 1_000_000,
+        };
+        Self {
+            numerator: fraction.numerator,
+            denominator,
+        }
+    }
+}
+
+/// Validates and converts a fault injection filter config.
+pub(crate) fn convert_fault_injection(
+    proto: fault::HttpFault,
+) -> std::result::Result<FaultInjection, String> {
+    use common_fault::fault_delay::FaultDelaySecifier;
+    use fault::fault_abort::ErrorType;
+
+    let percentage = |fraction: Option<&FractionalPercent>| {
+        fraction.map_or(
+            FaultPercentage {
+                numerator: 0,
+                denominator: 100,
+            },
+            FaultPercentage::from,
+        )
+    };
+    let delay = match proto.delay {
+        Some(delay) => {
+            let kind = match &delay.fault_delay_secifier {
+                Some(FaultDelaySecifier::FixedDelay(duration)) => {
+                    FaultDelayKind::Fixed(convert_duration(duration)?)
+                }
+                Some(FaultDelaySecifier::HeaderDelay(_)) => FaultDelayKind::Header,
+                None => return Err("fault delay has no delay".to_string()),
+            };
+            Some(FaultDelay {
+                delay: kind,
+                percentage: percentage(delay.percentage.as_ref()),
+            })
+        }
+        None => None,
+    };
+    let abort = match proto.abort {
+        Some(abort) => {
+            let status = match abort.error_type {
+                Some(ErrorType::HttpStatus(status)) if (200..600).contains(&status) => {
+                    FaultAbortStatus::Http(status)
+                }
+                Some(ErrorType::HttpStatus(status)) => {
+                    return Err(format!("invalid fault abort http status {status}"))
+                }
+                Some(ErrorType::GrpcStatus(code)) => FaultAbortStatus::Grpc(code),
+                Some(ErrorType::HeaderAbort(_)) => FaultAbortStatus::Header,
+                None => return Err("fault abort has no status".to_string()),
+            };
+            Some(FaultAbort {
+                status,
+                percentage: percentage(abort.percentage.as_ref()),
+            })
+        }
+        None => None,
+    };
+    let headers = proto
+        .headers
+        .into_iter()
+        .map(convert_header_matcher)
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(FaultInjection {
+        delay,
+        abort,
+        headers,
+        max_active_faults: proto.max_active_faults.map(|max| max.value),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::prost::route::HeaderMatch;
+    use envoy_types::pb::envoy::config::route::v3 as route;
+    use envoy_types::pb::google::protobuf::{Duration as ProtoDuration, UInt32Value};
+
+    fn fraction(
+        numerator: u32,
+        denominator: fractional_percent::DenominatorType,
+    ) -> FractionalPercent {
+        FractionalPercent {
+            numerator,
+            denominator: denominator as i32,
+        }
+    }
+
+    #[test]
+    fn test_convert_fault_injection() {
+        use fractional_percent::DenominatorType;
+
+        let proto = fault::HttpFault {
+            delay: Some(common_fault::FaultDelay {
+                percentage: Some(fraction(50, DenominatorType::Hundred)),
+                fault_delay_secifier: Some(
+                    common_fault::fault_delay::FaultDelaySecifier::FixedDelay(ProtoDuration {
+                        seconds: 1,
+                        nanos: 0,
+                    }),
+                ),
+            }),
+            abort: Some(fault::FaultAbort {
+                percentage: Some(fraction(25, DenominatorType::TenThousand)),
+                error_type: Some(fault::fault_abort::ErrorType::HeaderAbort(
+                    Default::default(),
+                )),
+                ..Default::default()
+            }),
+            headers: vec![route::HeaderMatcher {
+                name: "x-fault".to_string(),
+                header_match_specifier: Some(
+                    route::header_matcher::HeaderMatchSpecifier::PresentMatch(true),
+                ),
+                ..Default::default()
+            }],
+            max_active_faults: Some(UInt32Value { value: 10 }),
+            ..Default::default()
+        };
+        let fault = convert_fault_injection(proto).unwrap();
+        assert_eq!(
+            fault.delay,
+            Some(FaultDelay {
+                delay: FaultDelayKind::Fixed(Duration::from_secs(1)),
+                percentage: FaultPercentage {
+                    numerator: 50,
+                    denominator: 100,
+                },
+            })
+        );
+        let abort = fault.abort.unwrap();
+        assert_eq!(abort.status, FaultAbortStatus::Header);
+        assert_eq!(abort.percentage.per_million(), 2_500);
+        assert_eq!(fault.headers[0].matcher, HeaderMatch::Present(true));
+        assert_eq!(fault.max_active_faults, Some(10));
+
+        let fault = convert_fault_injection(fault::HttpFault::default()).unwrap();
+        assert_eq!(fault.delay, None);
+        assert_eq!(fault.abort, None);
+        assert_eq!(fault.max_active_faults, None);
+    }
+
+    #[test]
+    fn test_invalid_fault_injection() {
+        let no_delay = fault::HttpFault {
+            delay: Some(common_fault::FaultDelay::default()),
+            ..Default::default()
+        };
+        let no_abort_status = fault::HttpFault {
+            abort: Some(fault::FaultAbort::default()),
+            ..Default::default()
+        };
+        let invalid_http_status = fault::HttpFault {
+            abort: Some(fault::FaultAbort {
+                error_type: Some(fault::fault_abort::ErrorType::HttpStatus(100)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        for proto in [no_delay, no_abort_status, invalid_http_status] {
+            assert!(convert_fault_injection(proto).is_err());
+        }
+    }
+}
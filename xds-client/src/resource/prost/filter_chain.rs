@@ -0,0 +1,689 @@
+//! The filter chains of an LDS `Listener` for an xDS-enabled server, see
+//! [gRFC A36](https://github.com/grpc/proposal/blob/master/A36-xds-for-servers.md).
+//!
+//! The TLS configuration of filter chains follows
+//! [gRFC A29](https://github.com/grpc/proposal/blob/master/A29-xds-tls-security.md).
+
+use crate::resource::prost::decode_any;
+use crate::resource::prost::listener::{convert_http_connection_manager, HttpConnectionManager};
+use envoy_types::pb::envoy::config::core::v3 as core;
+use envoy_types::pb::envoy::config::listener::v3 as listener;
+use envoy_types::pb::envoy::extensions::filters::network::http_connection_manager::v3 as hcm;
+use envoy_types::pb::envoy::extensions::transport_sockets::tls::v3 as tls;
+use prost::Name;
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// The name of the TLS transport socket.
+const TLS_TRANSPORT_SOCKET: &str = "envoy.transport_sockets.tls";
+
+/// A validated server `Listener`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerListener {
+    /// The address the server listens on.
+    pub address: SocketAddr,
+    /// The filter chains, one of which is selected for each connection.
+    pub filter_chains: Vec<FilterChain>,
+    /// The filter chain of connections that match none of the filter chains.
+    ///
+    /// Connections are closed if there is none.
+    pub default_filter_chain: Option<FilterChain>,
+}
+
+/// A validated `FilterChain`, the configuration of the connections it matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterChain {
+    /// The filter chain name.
+    pub name: String,
+    /// The connections the filter chain applies to.
+    pub filter_chain_match: FilterChainMatch,
+    /// The HTTP connection manager, the only network filter.
+    pub http_connection_manager: HttpConnectionManager,
+    /// The TLS configuration, if connections use TLS.
+    pub tls: Option<DownstreamTls>,
+}
+
+/// The criteria of a [`FilterChain`] on the addresses of a connection.
+///
+/// Empty criteria match any connection.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilterChainMatch {
+    /// The ranges the destination IP address must be in.
+    pub prefix_ranges: Vec<CidrRange>,
+    /// The kind of source of the connection.
+    pub source_type: ConnectionSourceType,
+    /// The ranges the source IP address must be in.
+    pub source_prefix_ranges: Vec<CidrRange>,
+    /// The allowed source ports.
+    pub source_ports: Vec<u16>,
+}
+
+/// A range of IP addresses, in CIDR notation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CidrRange {
+    /// The first address of the range, with the bits after the prefix cleared.
+    pub address: IpAddr,
+    /// The length of the prefix.
+    pub prefix_len: u8,
+}
+
+impl CidrRange {
+    /// Returns whether `ip` is in the range.
+    ///
+    /// IPv4-mapped IPv6 addresses are treated as IPv4 addresses.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, ip.to_canonical()) {
+            (IpAddr::V4(address), IpAddr::V4(ip)) => {
+                mask(u32::from(ip).into(), 32, self.prefix_len) == u32::from(address).into()
+            }
+            (IpAddr::V6(address), IpAddr::V6(ip)) => {
+                mask(u128::from(ip), 128, self.prefix_len) == u128::from(address)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Clears the bits of an address of `bits` bits after its first `prefix_len` bits.
+fn mask(address: u128, bits: u32, prefix_len: u8) -> u128 {
+    let host_bits = bits - u32::from(prefix_len).min(bits);
+    if host_bits >= 128 {
+        0
+    } else {
+        address >> host_bits << host_bits
+    }
+}
+
+/// The kind of source of a connection matched by a [`FilterChainMatch`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ConnectionSourceType {
+    /// Any connection.
+    #[default]
+    Any,
+    /// Connections from the local host: the source IP is a loopback address or the
+    /// destination IP.
+    SameIpOrLoopback,
+    /// Connections from other hosts.
+    External,
+}
+
+/// The server TLS configuration of a [`FilterChain`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownstreamTls {
+    /// The certificate provider of the server identity certificate and key.
+    pub identity_certificate: CertificateProviderInstance,
+    /// The certificate provider of the root certificates validating client certificates.
+    ///
+    /// Client certificates are not requested if not set.
+    pub root_certificate: Option<CertificateProviderInstance>,
+    /// Whether clients must present a certificate.
+    pub require_client_certificate: bool,
+}
+
+/// A reference to certificates of a certificate provider plugin instance of the
+/// bootstrap configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateProviderInstance {
+    /// The plugin instance name, a key of
+    /// [`ClientConfig::certificate_providers`](crate::ClientConfig::certificate_providers).
+    pub instance_name: String,
+    /// The name of the certificate within the instance.
+    pub certificate_name: String,
+}
+
+/// Validates a listener without an API listener as a server listener.
+pub(crate) fn convert_server_listener(
+    proto: listener::Listener,
+) -> std::result::Result<ServerListener, String> {
+    if proto.use_original_dst.is_some_and(|value| value.value) {
+        return Err("use_original_dst is not supported".to_string());
+    }
+    let address = convert_address(proto.address.as_ref())?;
+
+    let mut filter_chains = Vec::with_capacity(proto.filter_chains.len());
+    // The expanded matching criteria of the filter chains, which must not overlap.
+    let mut criteria = HashSet::new();
+    for filter_chain in proto.filter_chains {
+        let Some(filter_chain) = convert_filter_chain(filter_chain)? else {
+            continue;
+        };
+        let chain_criteria = expand_criteria(&filter_chain.filter_chain_match);
+        if chain_criteria.iter().any(|c| criteria.contains(c)) {
+            return Err(format!(
+                "filter chain {} overlaps with another filter chain",
+                filter_chain.name
+            ));
+        }
+        criteria.extend(chain_criteria);
+        filter_chains.push(filter_chain);
+    }
+    let default_filter_chain = match proto.default_filter_chain {
+        Some(filter_chain) => convert_filter_chain(filter_chain)?,
+        None => None,
+    };
+    Ok(ServerListener {
+        address,
+        filter_chains,
+        default_filter_chain,
+    })
+}
+
+/// Returns the IP address and port of the listener.
+fn convert_address(address: Option<&core::Address>) -> std::result::Result<SocketAddr, String> {
+    use core::address::Address;
+    use core::socket_address::PortSpecifier;
+
+    let Some(Address::SocketAddress(address)) = address.and_then(|a| a.address.as_ref()) else {
+        return Err("listener has no socket address".to_string());
+    };
+    let ip = address
+        .address
+        .parse::<IpAddr>()
+        .map_err(|_| format!("listener address {} is not an IP address", address.address))?;
+    let port = match address.port_specifier {
+        Some(PortSpecifier::PortValue(port)) => u16::try_from(port).ok(),
+        _ => None,
+    }
+    .ok_or_else(|| format!("listener address {} has an invalid port", address.address))?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Validates a filter chain, returning `None` if it can never match a connection.
+fn convert_filter_chain(
+    filter_chain: listener::FilterChain,
+) -> std::result::Result<Option<FilterChain>, String> {
+    use listener::filter::ConfigType;
+
+    let name = filter_chain.name;
+    let filter_chain_match = filter_chain.filter_chain_match.unwrap_or_default();
+    // gRPC listens on a single port, and does not support matching on the TLS handshake.
+    if filter_chain_match.destination_port.is_some()
+        || !filter_chain_match.server_names.is_empty()
+        || !matches!(
+            filter_chain_match.transport_protocol.as_str(),
+            "" | "raw_buffer"
+        )
+        || !filter_chain_match.application_protocols.is_empty()
+    {
+        return Ok(None);
+    }
+    let filter_chain_match = convert_filter_chain_match(filter_chain_match)
+        .map_err(|e| format!("filter chain {name}: {e}"))?;
+
+    let [filter] = <[listener::Filter; 1]>::try_from(filter_chain.filters).map_err(|_| {
+        format!("filter chain {name} must have exactly one filter, the http connection manager")
+    })?;
+    let Some(ConfigType::TypedConfig(typed_config)) = filter.config_type else {
+        return Err(format!(
+            "filter chain {name}: filter {} has no typed_config",
+            filter.name
+        ));
+    };
+    if typed_config.type_url != hcm::HttpConnectionManager::type_url() {
+        return Err(format!(
+            "filter chain {name}: unsupported filter type {}",
+            typed_config.type_url
+        ));
+    }
+    let manager = decode_any::<hcm::HttpConnectionManager>(&typed_config)
+        .map_err(|e| format!("filter chain {name}: {e}"))?;
+    let http_connection_manager = convert_http_connection_manager(manager, true)
+        .map_err(|e| format!("filter chain {name}: {e}"))?;
+
+    let tls = match filter_chain.transport_socket {
+        Some(transport_socket) => convert_transport_socket(transport_socket)
+            .map_err(|e| format!("filter chain {name}: {e}"))?,
+        None => None,
+    };
+    Ok(Some(FilterChain {
+        name,
+        filter_chain_match,
+        http_connection_manager,
+        tls,
+    }))
+}
+
+fn convert_filter_chain_match(
+    filter_chain_match: listener::FilterChainMatch,
+) -> std::result::Result<FilterChainMatch, String> {
+    use listener::filter_chain_match::ConnectionSourceType as SourceType;
+
+    let source_type = match SourceType::try_from(filter_chain_match.source_type) {
+        Ok(SourceType::Any) => ConnectionSourceType::Any,
+        Ok(SourceType::SameIpOrLoopback) => ConnectionSourceType::SameIpOrLoopback,
+        Ok(SourceType::External) => ConnectionSourceType::External,
+        Err(_) => {
+            return Err(format!(
+                "unknown source type {}",
+                filter_chain_match.source_type
+            ))
+        }
+    };
+    let source_ports = filter_chain_match
+        .source_ports
+        .iter()
+        .map(|&port| u16::try_from(port).map_err(|_| format!("invalid source port {port}")))
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(FilterChainMatch {
+        prefix_ranges: convert_cidr_ranges(&filter_chain_match.prefix_ranges)?,
+        source_type,
+        source_prefix_ranges: convert_cidr_ranges(&filter_chain_match.source_prefix_ranges)?,
+        source_ports,
+    })
+}
+
+fn convert_cidr_ranges(ranges: &[core::CidrRange]) -> std::result::Result<Vec<CidrRange>, String> {
+    ranges
+        .iter()
+        .map(|range| {
+            let address = range
+                .address_prefix
+                .parse::<IpAddr>()
+                .map_err(|_| format!("invalid address prefix {}", range.address_prefix))?;
+            let max_len = if address.is_ipv4() { 32 } else { 128 };
+            let prefix_len = range.prefix_len.map_or(0, |len| len.value);
+            let prefix_len = u8::try_from(prefix_len)
+                .ok()
+                .filter(|&len| len <= max_len)
+                .ok_or_else(|| {
+                    format!(
+                        "invalid prefix length {prefix_len} of {}",
+                        range.address_prefix
+                    )
+                })?;
+            let address = match address {
+                IpAddr::V4(ip) => {
+                    let masked = mask(u32::from(ip).into(), 32, prefix_len);
+                    IpAddr::V4(Ipv4Addr::from(u32::try_from(masked).unwrap_or_default()))
+                }
+                IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(mask(u128::from(ip), 128, prefix_len))),
+            };
+            Ok(CidrRange {
+                address,
+                prefix_len,
+            })
+        })
+        .collect()
+}
+
+/// One combination of the criteria of a [`FilterChainMatch`]. Two filter chains overlap
+/// if they share a combination.
+type Criterion = (
+    Option<CidrRange>,
+    ConnectionSourceType,
+    Option<CidrRange>,
+    Option<u16>,
+);
+
+fn expand_criteria(filter_chain_match: &FilterChainMatch) -> Vec<Criterion> {
+    fn or_any<T: Copy>(values: &[T]) -> Vec<Option<T>> {
+        if values.is_empty() {
+            vec![None]
+        } else {
+            values.iter().copied().map(Some).collect()
+        }
+    }
+
+    let mut criteria = Vec::new();
+    for prefix_range in or_any(&filter_chain_match.prefix_ranges) {
+        for source_prefix_range in or_any(&filter_chain_match.source_prefix_ranges) {
+            for source_port in or_any(&filter_chain_match.source_ports) {
+                criteria.push((
+                    prefix_range,
+                    filter_chain_match.source_type,
+                    source_prefix_range,
+                    source_port,
+                ));
+            }
+        }
+    }
+    criteria
+}
+
+/// Validates the transport socket of a filter chain, returning its TLS configuration.
+fn convert_transport_socket(
+    transport_socket: core::TransportSocket,
+) -> std::result::Result<Option<DownstreamTls>, String> {
+    use core::transport_socket::ConfigType;
+
+    if transport_socket.name != TLS_TRANSPORT_SOCKET {
+        return Err(format!(
+            "unsupported transport socket {}",
+            transport_socket.name
+        ));
+    }
+    let Some(ConfigType::TypedConfig(typed_config)) = transport_socket.config_type else {
+        return Err("transport socket has no typed_config".to_string());
+    };
+    let context =
+        decode_any::<tls::DownstreamTlsContext>(&typed_config).map_err(|e| e.to_string())?;
+    if context.require_sni.is_some_and(|value| value.value) {
+        return Err("require_sni is not supported".to_string());
+    }
+    let require_client_certificate = context
+        .require_client_certificate
+        .is_some_and(|value| value.value);
+    let common = context.common_tls_context.unwrap_or_default();
+    let identity_certificate = common
+        .tls_certificate_provider_instance
+        .map(convert_certificate_provider_instance)
+        .ok_or("missing tls_certificate_provider_instance")?;
+    let root_certificate = root_certificate_provider_instance(common.validation_context_type)?
+        .map(convert_certificate_provider_instance);
+    if require_client_certificate && root_certificate.is_none() {
+        return Err(
+            "require_client_certificate is set without a root certificate provider".to_string(),
+        );
+    }
+    Ok(Some(DownstreamTls {
+        identity_certificate,
+        root_certificate,
+        require_client_certificate,
+    }))
+}
+
+/// Returns the certificate provider instance of the validation context, if any.
+fn root_certificate_provider_instance(
+    validation_context_type: Option<tls::common_tls_context::ValidationContextType>,
+) -> std::result::Result<Option<tls::CertificateProviderPluginInstance>, String> {
+    use tls::common_tls_context::ValidationContextType;
+
+    let validation_context = match validation_context_type {
+        None => return Ok(None),
+        Some(ValidationContextType::ValidationContext(context)) => context,
+        Some(ValidationContextType::CombinedValidationContext(context)) => {
+            context.default_validation_context.unwrap_or_default()
+        }
+        Some(_) => return Err("unsupported validation context".to_string()),
+    };
+    Ok(validation_context.ca_certificate_provider_instance)
+}
+
+fn convert_certificate_provider_instance(
+    instance: tls::CertificateProviderPluginInstance,
+) -> CertificateProviderInstance {
+    CertificateProviderInstance {
+        instance_name: instance.instance_name,
+        certificate_name: instance.certificate_name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::prost::listener::{Listener, RouteSource};
+    use crate::resource::prost::to_any;
+    use crate::resource::Resource;
+    use envoy_types::pb::envoy::extensions::filters::http::router::v3::Router;
+    use envoy_types::pb::google::protobuf::{BoolValue, UInt32Value};
+    use prost::Message;
+
+    fn manager() -> hcm::HttpConnectionManager {
+        hcm::HttpConnectionManager {
+            route_specifier: Some(hcm::http_connection_manager::RouteSpecifier::RouteConfig(
+                Default::default(),
+            )),
+            http_filters: vec![hcm::HttpFilter {
+                name: "router".to_string(),
+                config_type: Some(hcm::http_filter::ConfigType::TypedConfig(to_any(
+                    &Router::default(),
+                ))),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn filter_chain(
+        name: &str,
+        filter_chain_match: listener::FilterChainMatch,
+    ) -> listener::FilterChain {
+        listener::FilterChain {
+            name: name.to_string(),
+            filter_chain_match: Some(filter_chain_match),
+            filters: vec![listener::Filter {
+                name: "hcm".to_string(),
+                config_type: Some(listener::filter::ConfigType::TypedConfig(
+                    to_any(&manager()),
+                )),
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn cidr_range(address_prefix: &str, prefix_len: u32) -> core::CidrRange {
+        core::CidrRange {
+            address_prefix: address_prefix.to_string(),
+            prefix_len: Some(UInt32Value { value: prefix_len }),
+        }
+    }
+
+    fn server_listener(filter_chains: Vec<listener::FilterChain>) -> listener::Listener {
+        listener::Listener {
+            name: "listener-1".to_string(),
+            address: Some(core::Address {
+                address: Some(core::address::Address::SocketAddress(core::SocketAddress {
+                    address: "0.0.0.0".to_string(),
+                    port_specifier: Some(core::socket_address::PortSpecifier::PortValue(8080)),
+                    ..Default::default()
+                })),
+            }),
+            filter_chains,
+            default_filter_chain: Some(filter_chain("default", Default::default())),
+            ..Default::default()
+        }
+    }
+
+    fn decode(proto: listener::Listener) -> crate::error::Result<Listener> {
+        Listener::decode(proto.encode_to_vec().into())
+    }
+
+    fn tls_socket(context: &tls::DownstreamTlsContext) -> core::TransportSocket {
+        core::TransportSocket {
+            name: TLS_TRANSPORT_SOCKET.to_string(),
+            config_type: Some(core::transport_socket::ConfigType::TypedConfig(to_any(
+                context,
+            ))),
+        }
+    }
+
+    fn provider_instance(name: &str) -> tls::CertificateProviderPluginInstance {
+        tls::CertificateProviderPluginInstance {
+            instance_name: name.to_string(),
+            certificate_name: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_decode_server_listener() {
+        let mut by_source = filter_chain(
+            "by-source",
+            listener::FilterChainMatch {
+                prefix_ranges: vec![cidr_range("192.168.1.7", 24)],
+                source_type: listener::filter_chain_match::ConnectionSourceType::External as i32,
+                source_ports: vec![443],
+                ..Default::default()
+            },
+        );
+        by_source.transport_socket = Some(tls_socket(&tls::DownstreamTlsContext {
+            common_tls_context: Some(tls::CommonTlsContext {
+                tls_certificate_provider_instance: Some(provider_instance("identity")),
+                validation_context_type: Some(
+                    tls::common_tls_context::ValidationContextType::ValidationContext(
+                        tls::CertificateValidationContext {
+                            ca_certificate_provider_instance: Some(provider_instance("roots")),
+                            ..Default::default()
+                        },
+                    ),
+                ),
+                ..Default::default()
+            }),
+            require_client_certificate: Some(BoolValue { value: true }),
+            ..Default::default()
+        }));
+        // Filter chains matching on the destination port or TLS handshake never match.
+        let by_port = filter_chain(
+            "by-port",
+            listener::FilterChainMatch {
+                destination_port: Some(UInt32Value { value: 8080 }),
+                ..Default::default()
+            },
+        );
+        let by_server_name = filter_chain(
+            "by-server-name",
+            listener::FilterChainMatch {
+                server_names: vec!["example.com".to_string()],
+                ..Default::default()
+            },
+        );
+        let listener = decode(server_listener(vec![by_source, by_port, by_server_name])).unwrap();
+        assert!(listener.http_connection_manager().is_none());
+
+        let server = listener.server_listener().unwrap();
+        assert_eq!(server.address, "0.0.0.0:8080".parse().unwrap());
+        assert_eq!(server.filter_chains.len(), 1);
+        let filter_chain = &server.filter_chains[0];
+        assert_eq!(filter_chain.name, "by-source");
+        assert_eq!(
+            filter_chain.filter_chain_match,
+            FilterChainMatch {
+                prefix_ranges: vec![CidrRange {
+                    address: "192.168.1.0".parse().unwrap(),
+                    prefix_len: 24,
+                }],
+                source_type: ConnectionSourceType::External,
+                source_prefix_ranges: Vec::new(),
+                source_ports: vec![443],
+            }
+        );
+        assert!(matches!(
+            filter_chain.http_connection_manager.route_config,
+            RouteSource::Inline(_)
+        ));
+        assert_eq!(
+            filter_chain.tls,
+            Some(DownstreamTls {
+                identity_certificate: CertificateProviderInstance {
+                    instance_name: "identity".to_string(),
+                    certificate_name: String::new(),
+                },
+                root_certificate: Some(CertificateProviderInstance {
+                    instance_name: "roots".to_string(),
+                    certificate_name: String::new(),
+                }),
+                require_client_certificate: true,
+            })
+        );
+        assert_eq!(
+            server.default_filter_chain.as_ref().unwrap().name,
+            "default"
+        );
+    }
+
+    #[test]
+    fn test_cidr_range_contains() {
+        let ranges = convert_cidr_ranges(&[
+            cidr_range("10.1.2.3", 16),
+            cidr_range("2001:db8::1", 32),
+            cidr_range("0.0.0.0", 0),
+        ])
+        .unwrap();
+        assert_eq!(ranges[0].address, "10.1.0.0".parse::<IpAddr>().unwrap());
+        assert!(ranges[0].contains("10.1.200.1".parse().unwrap()));
+        assert!(ranges[0].contains("::ffff:10.1.0.1".parse().unwrap()));
+        assert!(!ranges[0].contains("10.2.0.1".parse().unwrap()));
+        assert!(ranges[1].contains("2001:db8:ffff::1".parse().unwrap()));
+        assert!(!ranges[1].contains("2001:db9::1".parse().unwrap()));
+        assert!(!ranges[1].contains("10.1.0.1".parse().unwrap()));
+        assert!(ranges[2].contains("192.168.0.1".parse().unwrap()));
+
+        assert!(convert_cidr_ranges(&[cidr_range("10.0.0.0", 33)]).is_err());
+        assert!(convert_cidr_ranges(&[cidr_range("example.com", 8)]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_server_listener() {
+        // 10.0.0.1/8 is normalized to 10.0.0.0/8.
+        let overlapping = server_listener(vec![
+            filter_chain(
+                "a",
+                listener::FilterChainMatch {
+                    prefix_ranges: vec![cidr_range("10.0.0.0", 8), cidr_range("192.168.0.0", 16)],
+                    ..Default::default()
+                },
+            ),
+            filter_chain(
+                "b",
+                listener::FilterChainMatch {
+                    prefix_ranges: vec![cidr_range("10.0.0.1", 8)],
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let mut no_filters = server_listener(Vec::new());
+        no_filters
+            .filter_chains
+            .push(listener::FilterChain::default());
+        let mut fault_filter = filter_chain("fault", Default::default());
+        let mut fault_manager = manager();
+        fault_manager.http_filters.insert(
+            0,
+            hcm::HttpFilter {
+                name: "fault".to_string(),
+                config_type: Some(hcm::http_filter::ConfigType::TypedConfig(to_any(
+                    &envoy_types::pb::envoy::extensions::filters::http::fault::v3::HttpFault::default(),
+                ))),
+                ..Default::default()
+            },
+        );
+        fault_filter.filters[0].config_type = Some(listener::filter::ConfigType::TypedConfig(
+            to_any(&fault_manager),
+        ));
+        let client_only_filter = server_listener(vec![fault_filter]);
+        let mut no_identity = filter_chain("tls", Default::default());
+        no_identity.transport_socket = Some(tls_socket(&tls::DownstreamTlsContext::default()));
+        let no_identity = server_listener(vec![no_identity]);
+        let mut no_port = server_listener(Vec::new());
+        no_port.address = Some(core::Address {
+            address: Some(core::address::Address::SocketAddress(core::SocketAddress {
+                address: "0.0.0.0".to_string(),
+                ..Default::default()
+            })),
+        });
+
+        for proto in [
+            overlapping,
+            no_filters,
+            client_only_filter,
+            no_identity,
+            no_port,
+        ] {
+            let error = decode(proto).unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .starts_with("validation error: listener-1: "),
+                "{error}"
+            );
+        }
+
+        // Filter chains with distinct criteria do not overlap.
+        let distinct = server_listener(vec![
+            filter_chain(
+                "a",
+                listener::FilterChainMatch {
+                    source_ports: vec![1],
+                    ..Default::default()
+                },
+            ),
+            filter_chain(
+                "b",
+                listener::FilterChainMatch {
+                    source_ports: vec![2],
+                    ..Default::default()
+                },
+            ),
+        ]);
+        assert!(decode(distinct).is_ok());
+    }
+}
@@ -0,0 +1,374 @@
+//! LDS resource: `Listener` with an `HttpConnectionManager` API listener, or with the
+//! filter chains of an xDS-enabled server.
+
+use crate::error::Result;
+use crate::resource::prost::fault::{convert_fault_injection, FaultInjection};
+use crate::resource::prost::filter_chain::{convert_server_listener, ServerListener};
+use crate::resource::prost::route::RouteConfiguration;
+use crate::resource::prost::{decode_any, invalid, is_ads_config_source};
+use crate::resource::{Resource, TypeUrl};
+use bytes::Bytes;
+use envoy_types::pb::envoy::config::listener::v3 as listener;
+use envoy_types::pb::envoy::extensions::filters::http::fault::v3::HttpFault;
+use envoy_types::pb::envoy::extensions::filters::http::router::v3::Router;
+use envoy_types::pb::envoy::extensions::filters::network::http_connection_manager::v3 as hcm;
+use envoy_types::pb::google::protobuf::Any;
+use prost::{Message, Name};
+use std::collections::HashSet;
+
+/// A validated `envoy.config.listener.v3.Listener`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listener {
+    /// The listener name.
+    pub name: String,
+    /// Whether the listener is for a gRPC client or server.
+    pub kind: ListenerKind,
+}
+
+impl Listener {
+    /// Returns the HTTP connection manager of an API listener.
+    pub fn http_connection_manager(&self) -> Option<&HttpConnectionManager> {
+        match &self.kind {
+            ListenerKind::Api(manager) => Some(manager),
+            ListenerKind::Server(_) => None,
+        }
+    }
+
+    /// Returns the configuration of a server listener.
+    pub fn server_listener(&self) -> Option<&ServerListener> {
+        match &self.kind {
+            ListenerKind::Api(_) => None,
+            ListenerKind::Server(listener) => Some(listener),
+        }
+    }
+}
+
+/// The kind of a [`Listener`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenerKind {
+    /// An API listener, used by gRPC clients.
+    Api(HttpConnectionManager),
+    /// A listener with an address and filter chains, used by xDS-enabled servers, see
+    /// [gRFC A36](https://github.com/grpc/proposal/blob/master/A36-xds-for-servers.md).
+    Server(Box<ServerListener>),
+}
+
+/// A validated `HttpConnectionManager`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpConnectionManager {
+    /// Where the routes come from.
+    pub route_config: RouteSource,
+    /// The HTTP filters, ending with the router.
+    pub http_filters: Vec<HttpFilter>,
+}
+
+/// The source of the routes of an [`HttpConnectionManager`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteSource {
+    /// Routes are fetched over RDS with the given `RouteConfiguration` name.
+    Rds(String),
+    /// Routes are inlined in the listener.
+    Inline(RouteConfiguration),
+}
+
+/// An HTTP filter of an [`HttpConnectionManager`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpFilter {
+    /// The filter instance name.
+    pub name: String,
+    /// The filter configuration.
+    pub config: HttpFilterConfig,
+}
+
+/// The configuration of an [`HttpFilter`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum HttpFilterConfig {
+    /// The router filter, which terminates the filter chain.
+    Router,
+    /// The fault injection filter.
+    Fault(FaultInjection),
+}
+
+impl Resource for Listener {
+    const TYPE_URL: TypeUrl = TypeUrl::new("type.googleapis.com/envoy.config.listener.v3.Listener");
+    const ALL_RESOURCES_REQUIRED_IN_SOTW: bool = true;
+
+    fn decode(bytes: Bytes) -> Result<Self> {
+        let mut proto = listener::Listener::decode(bytes)?;
+        let name = std::mem::take(&mut proto.name);
+        let kind = match proto.api_listener.take() {
+            Some(api_listener) => {
+                let Some(api_listener) = api_listener.api_listener else {
+                    return Err(invalid(&name, "missing api_listener"));
+                };
+                let manager = decode_any::<hcm::HttpConnectionManager>(&api_listener)
+                    .map_err(|e| invalid(&name, e))?;
+                ListenerKind::Api(
+                    convert_http_connection_manager(manager, false)
+                        .map_err(|e| invalid(&name, e))?,
+                )
+            }
+            None if proto.address.is_some() => ListenerKind::Server(Box::new(
+                convert_server_listener(proto).map_err(|e| invalid(&name, e))?,
+            )),
+            None => return Err(invalid(&name, "missing api_listener or address")),
+        };
+        Ok(Self { name, kind })
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Validates an `HttpConnectionManager` of a client API listener, or of a server filter
+/// chain if `is_server` is set.
+pub(crate) fn convert_http_connection_manager(
+    manager: hcm::HttpConnectionManager,
+    is_server: bool,
+) -> std::result::Result<HttpConnectionManager, String> {
+    use hcm::http_connection_manager::RouteSpecifier;
+
+    let route_config = match manager.route_specifier {
+        Some(RouteSpecifier::Rds(rds)) => {
+            if !is_ads_config_source(rds.config_source.as_ref()) {
+                return Err("rds config source must be ads or self".to_string());
+            }
+            RouteSource::Rds(rds.route_config_name)
+        }
+        Some(RouteSpecifier::RouteConfig(config)) => {
+            RouteSource::Inline(RouteConfiguration::from_proto(config).map_err(|e| e.to_string())?)
+        }
+        _ => return Err("missing rds or inline route_config".to_string()),
+    };
+    Ok(HttpConnectionManager {
+        route_config,
+        http_filters: convert_http_filters(manager.http_filters, is_server)?,
+    })
+}
+
+/// Validates the HTTP filter chain, see
+/// [gRFC A39](https://github.com/grpc/proposal/blob/master/A39-xds-http-filters.md).
+///
+/// Client-side filters, such as fault injection, are not supported by servers.
+fn convert_http_filters(
+    filters: Vec<hcm::HttpFilter>,
+    is_server: bool,
+) -> std::result::Result<Vec<HttpFilter>, String> {
+    use hcm::http_filter::ConfigType;
+
+    let mut names = HashSet::new();
+    let mut http_filters = Vec::with_capacity(filters.len());
+    for filter in filters {
+        if !names.insert(filter.name.clone()) {
+            return Err(format!("duplicate http filter {}", filter.name));
+        }
+        let Some(ConfigType::TypedConfig(typed_config)) = filter.config_type else {
+            return Err(format!("http filter {} has no typed_config", filter.name));
+        };
+        let config = match convert_http_filter_config(&typed_config)? {
+            Some(HttpFilterConfig::Fault(_)) if is_server => None,
+            config => config,
+        };
+        let config = match config {
+            Some(config) => config,
+            None if filter.is_optional => continue,
+            None => {
+                return Err(format!(
+                    "http filter {} has unsupported type {}",
+                    filter.name, typed_config.type_url
+                ))
+            }
+        };
+        if http_filters
+            .last()
+            .is_some_and(|f: &HttpFilter| f.config == HttpFilterConfig::Router)
+        {
+            return Err("router must be the last http filter".to_string());
+        }
+        http_filters.push(HttpFilter {
+            name: filter.name,
+            config,
+        });
+    }
+    match http_filters.last() {
+        Some(filter) if filter.config == HttpFilterConfig::Router => Ok(http_filters),
+        _ => Err("missing router http filter".to_string()),
+    }
+}
+
+/// Validates and converts the config of an HTTP filter, returning `None` if its type is
+/// not supported.
+pub(crate) fn convert_http_filter_config(
+    typed_config: &Any,
+) -> std::result::Result<Option<HttpFilterConfig>, String> {
+    if typed_config.type_url == Router::type_url() {
+        Ok(Some(HttpFilterConfig::Router))
+    } else if typed_config.type_url == HttpFault::type_url() {
+        let fault = decode_any::<HttpFault>(typed_config).map_err(|e| e.to_string())?;
+        Ok(Some(HttpFilterConfig::Fault(convert_fault_injection(
+            fault,
+        )?)))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::prost::to_any;
+    use envoy_types::pb::envoy::config::core::v3 as core;
+
+    fn http_filter(name: &str, typed_config: Any, is_optional: bool) -> hcm::HttpFilter {
+        hcm::HttpFilter {
+            name: name.to_string(),
+            is_optional,
+            config_type: Some(hcm::http_filter::ConfigType::TypedConfig(typed_config)),
+            ..Default::default()
+        }
+    }
+
+    fn router() -> hcm::HttpFilter {
+        http_filter("router", to_any(&Router::default()), false)
+    }
+
+    fn unknown_filter(is_optional: bool) -> hcm::HttpFilter {
+        let typed_config = Any {
+            type_url: "type.googleapis.com/unknown.Filter".to_string(),
+            value: Vec::new(),
+        };
+        http_filter("unknown", typed_config, is_optional)
+    }
+
+    fn rds_manager(config_source: core::ConfigSource) -> hcm::HttpConnectionManager {
+        hcm::HttpConnectionManager {
+            route_specifier: Some(hcm::http_connection_manager::RouteSpecifier::Rds(
+                hcm::Rds {
+                    config_source: Some(config_source),
+                    route_config_name: "route-1".to_string(),
+                },
+            )),
+            http_filters: vec![router()],
+            ..Default::default()
+        }
+    }
+
+    fn ads() -> core::ConfigSource {
+        core::ConfigSource {
+            config_source_specifier: Some(core::config_source::ConfigSourceSpecifier::Ads(
+                Default::default(),
+            )),
+            ..Default::default()
+        }
+    }
+
+    fn decode(manager: hcm::HttpConnectionManager) -> Result<Listener> {
+        let proto = listener::Listener {
+            name: "listener-1".to_string(),
+            api_listener: Some(listener::ApiListener {
+                api_listener: Some(to_any(&manager)),
+            }),
+            ..Default::default()
+        };
+        Listener::decode(proto.encode_to_vec().into())
+    }
+
+    #[test]
+    fn test_decode_listener() {
+        let mut manager = rds_manager(ads());
+        manager.http_filters.insert(0, unknown_filter(true));
+        let listener = decode(manager).unwrap();
+
+        assert_eq!(listener.name(), "listener-1");
+        assert_eq!(
+            listener.http_connection_manager(),
+            Some(&HttpConnectionManager {
+                route_config: RouteSource::Rds("route-1".to_string()),
+                http_filters: vec![HttpFilter {
+                    name: "router".to_string(),
+                    config: HttpFilterConfig::Router,
+                }],
+            })
+        );
+        assert!(listener.server_listener().is_none());
+    }
+
+    #[test]
+    fn test_decode_fault_filter() {
+        let mut manager = rds_manager(ads());
+        manager.http_filters.insert(
+            0,
+            http_filter("fault", to_any(&HttpFault::default()), false),
+        );
+        let listener = decode(manager).unwrap();
+        let filters = &listener.http_connection_manager().unwrap().http_filters;
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[0].name, "fault");
+        assert!(matches!(filters[0].config, HttpFilterConfig::Fault(_)));
+
+        let mut manager = rds_manager(ads());
+        let invalid = HttpFault {
+            abort: Some(Default::default()),
+            ..Default::default()
+        };
+        manager
+            .http_filters
+            .insert(0, http_filter("fault", to_any(&invalid), true));
+        assert!(decode(manager).is_err());
+    }
+
+    #[test]
+    fn test_decode_listener_with_inline_routes() {
+        let mut manager = rds_manager(ads());
+        manager.route_specifier = Some(hcm::http_connection_manager::RouteSpecifier::RouteConfig(
+            Default::default(),
+        ));
+        let listener = decode(manager).unwrap();
+        assert!(matches!(
+            listener.http_connection_manager().unwrap().route_config,
+            RouteSource::Inline(_)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_listener() {
+        let mut required_unknown = rds_manager(ads());
+        required_unknown
+            .http_filters
+            .insert(0, unknown_filter(false));
+        let mut no_router = rds_manager(ads());
+        no_router.http_filters.clear();
+        let mut router_not_last = rds_manager(ads());
+        router_not_last.http_filters.push(http_filter(
+            "router-2",
+            to_any(&Router::default()),
+            false,
+        ));
+        let mut duplicate = rds_manager(ads());
+        duplicate.http_filters.push(router());
+        let non_ads = rds_manager(core::ConfigSource::default());
+
+        for manager in [
+            required_unknown,
+            no_router,
+            router_not_last,
+            duplicate,
+            non_ads,
+        ] {
+            let error = decode(manager).unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .starts_with("validation error: listener-1: "),
+                "{error}"
+            );
+        }
+
+        let proto = listener::Listener {
+            name: "listener-1".to_string(),
+            ..Default::default()
+        };
+        assert!(Listener::decode(proto.encode_to_vec().into()).is_err());
+    }
+}
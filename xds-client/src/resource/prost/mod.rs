@@ -0,0 +1,141 @@
+//! `prost` codec-specific resources.
+//!
+//! Provides the LDS, RDS, CDS and EDS resource types used by gRPC. Decoding applies
+//! the validation rules of gRPC's xDS implementation, see
+//! [gRFC A27](https://github.com/grpc/proposal/blob/master/A27-xds-global-load-balancing.md)
+//! and [gRFC A28](https://github.com/grpc/proposal/blob/master/A28-xds-traffic-splitting-and-routing.md).
+//! Invalid resources fail to decode with [`Error::InvalidResource`], which NACKs the response
+//! and reports the resource as NACKed in the client status.
+
+use crate::error::{Error, Result};
+use crate::message::Locality;
+use envoy_types::pb::envoy::config::core::v3 as core;
+use envoy_types::pb::envoy::r#type::v3::{fractional_percent, FractionalPercent};
+use envoy_types::pb::google::protobuf::{Any, Duration as ProtoDuration};
+use prost::{Message, Name};
+use std::time::Duration;
+
+pub mod cluster;
+pub mod endpoint;
+pub mod fault;
+pub mod filter_chain;
+pub mod listener;
+pub mod route;
+
+pub use cluster::Cluster;
+pub use endpoint::ClusterLoadAssignment;
+pub use listener::Listener;
+pub use route::RouteConfiguration;
+
+/// Returns a validation error for the resource with the given name.
+fn invalid(resource: &str, message: impl std::fmt::Display) -> Error {
+    Error::InvalidResource {
+        name: resource.to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// Decodes a message of type `M` from an `Any`, checking its type URL.
+fn decode_any<M: Message + Name + Default>(any: &Any) -> Result<M> {
+    let type_url = M::type_url();
+    if any.type_url != type_url {
+        return Err(Error::Validation(format!(
+            "unexpected type {}, expected {type_url}",
+            any.type_url
+        )));
+    }
+    Ok(M::decode(any.value.as_slice())?)
+}
+
+/// Returns whether a config source points at the ADS stream, the only source gRPC supports.
+fn is_ads_config_source(config_source: Option<&core::ConfigSource>) -> bool {
+    use core::config_source::ConfigSourceSpecifier;
+
+    matches!(
+        config_source.and_then(|source| source.config_source_specifier.as_ref()),
+        Some(ConfigSourceSpecifier::Ads(_) | ConfigSourceSpecifier::Self_(_))
+    )
+}
+
+/// Converts a fractional percent to parts per million.
+fn parts_per_million(fraction: &FractionalPercent) -> u32 {
+    use fractional_percent::DenominatorType;
+
+    let multiplier = match DenominatorType::try_from(fraction.denominator) {
+        Ok(DenominatorType::Hundred) => 10_000,
+        Ok(DenominatorType::TenThousand) => 100,
+        Ok(DenominatorType::Million) | Err(_) => 1,
+    };
+    fraction.numerator.saturating_mul(multiplier).min(1_000_000)
+}
+
+/// Converts a duration proto, rejecting negative and out of range durations.
+fn convert_duration(duration: &ProtoDuration) -> std::result::Result<Duration, String> {
+    let (Ok(seconds), Ok(nanos)) = (
+        u64::try_from(duration.seconds),
+        u32::try_from(duration.nanos),
+    ) else {
+        return Err(format!("negative duration {duration:?}"));
+    };
+    if seconds > 315_576_000_000 || nanos > 999_999_999 {
+        return Err(format!("duration {duration:?} out of range"));
+    }
+    Ok(Duration::new(seconds, nanos))
+}
+
+/// Converts a locality proto.
+fn convert_locality(locality: core::Locality) -> Locality {
+    Locality {
+        region: locality.region,
+        zone: locality.zone,
+        sub_zone: locality.sub_zone,
+    }
+}
+
+/// Wraps a message in an `Any`.
+#[cfg(test)]
+fn to_any<M: Message + Name>(message: &M) -> Any {
+    Any {
+        type_url: M::type_url(),
+        value: message.encode_to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parts_per_million() {
+        use fractional_percent::DenominatorType;
+
+        let fraction = |numerator, denominator: DenominatorType| FractionalPercent {
+            numerator,
+            denominator: denominator as i32,
+        };
+        assert_eq!(
+            parts_per_million(&fraction(5, DenominatorType::Hundred)),
+            50_000
+        );
+        assert_eq!(
+            parts_per_million(&fraction(5, DenominatorType::TenThousand)),
+            500
+        );
+        assert_eq!(parts_per_million(&fraction(5, DenominatorType::Million)), 5);
+        assert_eq!(
+            parts_per_million(&fraction(200, DenominatorType::Hundred)),
+            1_000_000
+        );
+    }
+
+    #[test]
+    fn test_convert_duration() {
+        let duration = |seconds, nanos| ProtoDuration { seconds, nanos };
+        assert_eq!(
+            convert_duration(&duration(1, 500_000_000)),
+            Ok(Duration::from_millis(1500))
+        );
+        assert!(convert_duration(&duration(-1, 0)).is_err());
+        assert!(convert_duration(&duration(0, 1_000_000_000)).is_err());
+    }
+}
@@ -0,0 +1,1138 @@
+//! RDS resource: `RouteConfiguration`.
+
+use crate::error::Result;
+use crate::resource::prost::listener::{convert_http_filter_config, HttpFilterConfig};
+use crate::resource::prost::{convert_duration, decode_any, invalid, parts_per_million};
+use crate::resource::{Resource, TypeUrl};
+use bytes::Bytes;
+use envoy_types::pb::envoy::config::route::v3 as route;
+use envoy_types::pb::envoy::r#type::matcher::v3 as matcher;
+use envoy_types::pb::google::protobuf::Any;
+use prost::{Message, Name};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// A validated `envoy.config.route.v3.RouteConfiguration`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteConfiguration {
+    /// The route configuration name.
+    pub name: String,
+    /// The virtual hosts, matched against the authority of a request.
+    pub virtual_hosts: Vec<VirtualHost>,
+}
+
+/// A group of routes for a set of domains.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VirtualHost {
+    /// The virtual host name.
+    pub name: String,
+    /// Domains matched against the authority of a request, possibly with a leading or
+    /// trailing `*` wildcard.
+    pub domains: Vec<String>,
+    /// The routes, in the order they are matched.
+    pub routes: Vec<Route>,
+    /// HTTP filter configs overriding those of the listener for these routes, keyed by
+    /// filter name.
+    pub filter_overrides: HashMap<String, HttpFilterConfig>,
+}
+
+/// A route: a match and the action applied to matching requests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    /// Conditions a request must satisfy to use this route.
+    pub route_match: RouteMatch,
+    /// The action for matching requests.
+    pub action: RouteAction,
+    /// HTTP filter configs overriding those of the listener and virtual host for this
+    /// route, keyed by filter name.
+    pub filter_overrides: HashMap<String, HttpFilterConfig>,
+}
+
+/// Conditions a request must satisfy to match a route.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteMatch {
+    /// The matcher for the request path, i.e. `/service/method`.
+    pub path: PathMatcher,
+    /// Whether prefix and exact path matching is case sensitive.
+    pub case_sensitive: bool,
+    /// Header matchers, all of which must match.
+    pub headers: Vec<HeaderMatcher>,
+    /// If set, only this fraction of requests, in parts per million, match.
+    pub fraction_per_million: Option<u32>,
+}
+
+/// A matcher for the request path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathMatcher {
+    /// The path starts with the prefix.
+    Prefix(String),
+    /// The path equals the given path.
+    Path(String),
+    /// The whole path matches the regular expression (RE2 syntax).
+    Regex(String),
+}
+
+/// A matcher for a request header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderMatcher {
+    /// The header name.
+    pub name: String,
+    /// How the header value is matched.
+    pub matcher: HeaderMatch,
+    /// Whether the match result is inverted.
+    pub invert_match: bool,
+}
+
+/// How a header value is matched.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderMatch {
+    /// The value matches the string matcher.
+    String(StringMatcher),
+    /// The value is an integer in `[start, end)`.
+    Range {
+        /// Inclusive start of the range.
+        start: i64,
+        /// Exclusive end of the range.
+        end: i64,
+    },
+    /// The header is present, or absent if `false`.
+    Present(bool),
+}
+
+/// A matcher for a string value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringMatcher {
+    /// The pattern to match.
+    pub pattern: StringPattern,
+    /// Whether matching ignores case. Does not apply to regular expressions.
+    pub ignore_case: bool,
+}
+
+/// The pattern of a [`StringMatcher`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPattern {
+    /// The value equals the string.
+    Exact(String),
+    /// The value starts with the string.
+    Prefix(String),
+    /// The value ends with the string.
+    Suffix(String),
+    /// The value contains the string.
+    Contains(String),
+    /// The whole value matches the regular expression (RE2 syntax).
+    Regex(String),
+}
+
+/// The action applied to requests matching a route.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteAction {
+    /// Forward the request to a cluster.
+    Forward(ForwardingAction),
+    /// Do not forward the request. Used by xDS-enabled servers.
+    NonForwarding,
+    /// An action gRPC does not support. Matching requests fail.
+    Unsupported,
+}
+
+/// Forwarding of requests to an upstream cluster.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardingAction {
+    /// The cluster or clusters to forward requests to.
+    pub cluster: ClusterSpecifier,
+    /// The policies computing the request hash for consistent hashing load balancing.
+    pub hash_policies: Vec<HashPolicy>,
+    /// The retry policy of the route, or else of its virtual host, if any.
+    pub retry_policy: Option<RetryPolicy>,
+    /// The maximum duration of requests, see
+    /// [gRFC A31](https://github.com/grpc/proposal/blob/master/A31-xds-timeout-support-and-config-selector.md).
+    /// Unlimited if `None`.
+    pub max_stream_duration: Option<Duration>,
+}
+
+/// A policy retrying failed requests, see
+/// [gRFC A44](https://github.com/grpc/proposal/blob/master/A44-xds-retry.md).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// The gRPC status codes that are retried. Empty if no supported condition is set.
+    pub retry_on: Vec<u32>,
+    /// The maximum number of retries of a request.
+    pub num_retries: u32,
+    /// The base interval of the exponential backoff between attempts.
+    pub base_interval: Duration,
+    /// The maximum interval of the exponential backoff between attempts.
+    pub max_interval: Duration,
+}
+
+/// A policy computing the hash of a request, see
+/// [gRFC A42](https://github.com/grpc/proposal/blob/master/A42-xds-ring-hash-lb-policy.md).
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashPolicy {
+    /// What the hash is computed from.
+    pub kind: HashPolicyKind,
+    /// Whether later policies are skipped if this one produces a hash.
+    pub terminal: bool,
+}
+
+/// What a [`HashPolicy`] computes the request hash from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HashPolicyKind {
+    /// The value of a request header. Produces no hash if the header is absent.
+    Header {
+        /// The header name.
+        name: String,
+        /// A rewrite applied to the header value before hashing.
+        regex_rewrite: Option<RegexRewrite>,
+    },
+    /// An identifier of the channel, so that all requests of a channel hash alike.
+    ChannelId,
+}
+
+/// Replacement of all the matches of a regular expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexRewrite {
+    /// The regular expression (RE2 syntax).
+    pub pattern: String,
+    /// The replacement, where `\N` refers to the N-th capture group.
+    pub substitution: String,
+}
+
+/// The cluster or clusters a route forwards requests to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClusterSpecifier {
+    /// A single cluster.
+    Cluster(String),
+    /// Clusters picked at random, proportionally to their weights.
+    WeightedClusters(Vec<ClusterWeight>),
+    /// A cluster picked for each request by a cluster specifier plugin of the route
+    /// configuration.
+    Plugin(ClusterSpecifierPlugin),
+}
+
+/// The type URL of the route lookup (RLS) cluster specifier plugin config.
+pub const ROUTE_LOOKUP_CLUSTER_SPECIFIER: &str =
+    "type.googleapis.com/grpc.lookup.v1.RouteLookupClusterSpecifier";
+
+/// A cluster specifier plugin of a route configuration, referenced by name by its routes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterSpecifierPlugin {
+    /// The plugin name.
+    pub name: String,
+    /// The plugin configuration.
+    pub config: ClusterSpecifierPluginConfig,
+}
+
+/// The configuration of a [`ClusterSpecifierPlugin`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClusterSpecifierPluginConfig {
+    /// The route lookup (RLS) plugin, with its serialized
+    /// `grpc.lookup.v1.RouteLookupClusterSpecifier` config.
+    RouteLookup(Bytes),
+}
+
+/// A cluster of a weighted cluster route.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterWeight {
+    /// The cluster name.
+    pub name: String,
+    /// The weight of the cluster, greater than zero.
+    pub weight: u32,
+}
+
+impl Resource for RouteConfiguration {
+    const TYPE_URL: TypeUrl =
+        TypeUrl::new("type.googleapis.com/envoy.config.route.v3.RouteConfiguration");
+
+    fn decode(bytes: Bytes) -> Result<Self> {
+        Self::from_proto(route::RouteConfiguration::decode(bytes)?)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl RouteConfiguration {
+    /// Validates and converts a route configuration proto, also used for routes inlined
+    /// in a Listener.
+    pub(crate) fn from_proto(proto: route::RouteConfiguration) -> Result<Self> {
+        let name = proto.name;
+        let plugins = convert_cluster_specifier_plugins(proto.cluster_specifier_plugins)
+            .map_err(|e| invalid(&name, e))?;
+        let virtual_hosts = proto
+            .virtual_hosts
+            .into_iter()
+            .map(|vhost| convert_virtual_host(vhost, &plugins).map_err(|e| invalid(&name, e)))
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Self {
+            name,
+            virtual_hosts,
+        })
+    }
+}
+
+/// The cluster specifier plugins of a route configuration, keyed by name. Optional plugins
+/// of unsupported types are `None`.
+type ClusterSpecifierPlugins = HashMap<String, Option<ClusterSpecifierPlugin>>;
+
+/// Validates and converts the cluster specifier plugins of a route configuration.
+///
+/// Plugins of unsupported types are rejected, unless they are optional.
+fn convert_cluster_specifier_plugins(
+    plugins: Vec<route::ClusterSpecifierPlugin>,
+) -> std::result::Result<ClusterSpecifierPlugins, String> {
+    let mut converted = HashMap::with_capacity(plugins.len());
+    for plugin in plugins {
+        let Some(extension) = plugin.extension else {
+            return Err("cluster specifier plugin has no extension".to_string());
+        };
+        let name = extension.name;
+        if converted.contains_key(&name) {
+            return Err(format!("duplicate cluster specifier plugin {name}"));
+        }
+        let config = match extension.typed_config {
+            Some(config) if config.type_url == ROUTE_LOOKUP_CLUSTER_SPECIFIER => Some(
+                ClusterSpecifierPluginConfig::RouteLookup(Bytes::from(config.value)),
+            ),
+            _ if plugin.is_optional => None,
+            Some(config) => {
+                return Err(format!(
+                    "cluster specifier plugin {name} has unsupported type {}",
+                    config.type_url
+                ));
+            }
+            None => return Err(format!("cluster specifier plugin {name} has no config")),
+        };
+        let plugin = config.map(|config| ClusterSpecifierPlugin {
+            name: name.clone(),
+            config,
+        });
+        converted.insert(name, plugin);
+    }
+    Ok(converted)
+}
+
+fn convert_virtual_host(
+    vhost: route::VirtualHost,
+    plugins: &ClusterSpecifierPlugins,
+) -> std::result::Result<VirtualHost, String> {
+    let retry_policy = vhost
+        .retry_policy
+        .map(convert_retry_policy)
+        .transpose()
+        .map_err(|e| format!("virtual host {}: {e}", vhost.name))?;
+    let mut routes = Vec::with_capacity(vhost.routes.len());
+    for route in vhost.routes {
+        // Routes gRPC cannot evaluate are skipped rather than rejected.
+        if let Some(route) = convert_route(route, retry_policy.as_ref(), plugins)
+            .map_err(|e| format!("virtual host {}: {e}", vhost.name))?
+        {
+            routes.push(route);
+        }
+    }
+    let filter_overrides = convert_filter_overrides(vhost.typed_per_filter_config)
+        .map_err(|e| format!("virtual host {}: {e}", vhost.name))?;
+    Ok(VirtualHost {
+        name: vhost.name,
+        domains: vhost.domains,
+        routes,
+        filter_overrides,
+    })
+}
+
+fn convert_route(
+    route: route::Route,
+    vhost_retry_policy: Option<&RetryPolicy>,
+    plugins: &ClusterSpecifierPlugins,
+) -> std::result::Result<Option<Route>, String> {
+    let Some(route_match) = route.r#match else {
+        return Err(format!("route {} has no match", route.name));
+    };
+    let Some(route_match) = convert_route_match(route_match)? else {
+        return Ok(None);
+    };
+    let action = match route.action {
+        Some(route::route::Action::Route(action)) => {
+            match convert_forwarding_action(action, vhost_retry_policy, plugins)? {
+                Some(action) => RouteAction::Forward(action),
+                None => return Ok(None),
+            }
+        }
+        Some(route::route::Action::NonForwardingAction(_)) => RouteAction::NonForwarding,
+        _ => RouteAction::Unsupported,
+    };
+    let filter_overrides = convert_filter_overrides(route.typed_per_filter_config)
+        .map_err(|e| format!("route {}: {e}", route.name))?;
+    Ok(Some(Route {
+        route_match,
+        action,
+        filter_overrides,
+    }))
+}
+
+/// Validates and converts the HTTP filter config overrides of a virtual host or route, see
+/// [gRFC A39](https://github.com/grpc/proposal/blob/master/A39-xds-http-filters.md).
+///
+/// Overrides of unsupported filters are rejected, unless wrapped in an optional
+/// `FilterConfig`.
+fn convert_filter_overrides(
+    configs: HashMap<String, Any>,
+) -> std::result::Result<HashMap<String, HttpFilterConfig>, String> {
+    let mut overrides = HashMap::with_capacity(configs.len());
+    for (name, config) in configs {
+        let (config, is_optional) = if config.type_url == route::FilterConfig::type_url() {
+            let wrapper = decode_any::<route::FilterConfig>(&config).map_err(|e| e.to_string())?;
+            let Some(config) = wrapper.config else {
+                return Err(format!("filter override {name} has no config"));
+            };
+            (config, wrapper.is_optional)
+        } else {
+            (config, false)
+        };
+        match convert_http_filter_config(&config)? {
+            Some(config) => {
+                overrides.insert(name, config);
+            }
+            None if is_optional => {}
+            None => {
+                return Err(format!(
+                    "filter override {name} has unsupported type {}",
+                    config.type_url
+                ))
+            }
+        }
+    }
+    Ok(overrides)
+}
+
+fn convert_route_match(
+    route_match: route::RouteMatch,
+) -> std::result::Result<Option<RouteMatch>, String> {
+    use route::route_match::PathSpecifier;
+
+    // Query parameters are not available to gRPC, so such routes never match.
+    if !route_match.query_parameters.is_empty() {
+        return Ok(None);
+    }
+    let path = match route_match.path_specifier {
+        Some(PathSpecifier::Prefix(prefix)) => PathMatcher::Prefix(prefix),
+        Some(PathSpecifier::Path(path)) => PathMatcher::Path(path),
+        Some(PathSpecifier::SafeRegex(regex)) => PathMatcher::Regex(check_regex(regex)?),
+        Some(PathSpecifier::ConnectMatcher(_)) => return Ok(None),
+        _ => return Err("unsupported path specifier".to_string()),
+    };
+    let headers = route_match
+        .headers
+        .into_iter()
+        .map(convert_header_matcher)
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(Some(RouteMatch {
+        path,
+        case_sensitive: route_match.case_sensitive.map_or(true, |v| v.value),
+        headers,
+        fraction_per_million: route_match
+            .runtime_fraction
+            .and_then(|fraction| fraction.default_value)
+            .map(|fraction| parts_per_million(&fraction)),
+    }))
+}
+
+/// Validates and converts a header matcher proto.
+#[allow(deprecated)]
+pub(crate) fn convert_header_matcher(
+    header: route::HeaderMatcher,
+) -> std::result::Result<HeaderMatcher, String> {
+    use route::header_matcher::HeaderMatchSpecifier;
+
+    let exact = |pattern| {
+        HeaderMatch::String(StringMatcher {
+            pattern,
+            ignore_case: false,
+        })
+    };
+    let matcher = match header.header_match_specifier {
+        Some(HeaderMatchSpecifier::ExactMatch(value)) => exact(StringPattern::Exact(value)),
+        Some(HeaderMatchSpecifier::SafeRegexMatch(regex)) => {
+            exact(StringPattern::Regex(check_regex(regex)?))
+        }
+        Some(HeaderMatchSpecifier::PrefixMatch(value)) => exact(StringPattern::Prefix(value)),
+        Some(HeaderMatchSpecifier::SuffixMatch(value)) => exact(StringPattern::Suffix(value)),
+        Some(HeaderMatchSpecifier::ContainsMatch(value)) => exact(StringPattern::Contains(value)),
+        Some(HeaderMatchSpecifier::RangeMatch(range)) => HeaderMatch::Range {
+            start: range.start,
+            end: range.end,
+        },
+        Some(HeaderMatchSpecifier::PresentMatch(present)) => HeaderMatch::Present(present),
+        Some(HeaderMatchSpecifier::StringMatch(matcher)) => {
+            HeaderMatch::String(convert_string_matcher(matcher)?)
+        }
+        None => return Err(format!("header matcher for {} has no match", header.name)),
+    };
+    Ok(HeaderMatcher {
+        name: header.name,
+        matcher,
+        invert_match: header.invert_match,
+    })
+}
+
+/// Validates and converts a string matcher proto.
+pub(crate) fn convert_string_matcher(
+    matcher: matcher::StringMatcher,
+) -> std::result::Result<StringMatcher, String> {
+    use matcher::string_matcher::MatchPattern;
+
+    let pattern = match matcher.match_pattern {
+        Some(MatchPattern::Exact(value)) => StringPattern::Exact(value),
+        Some(MatchPattern::Prefix(value)) => StringPattern::Prefix(value),
+        Some(MatchPattern::Suffix(value)) => StringPattern::Suffix(value),
+        Some(MatchPattern::Contains(value)) => StringPattern::Contains(value),
+        Some(MatchPattern::SafeRegex(regex)) => StringPattern::Regex(check_regex(regex)?),
+        _ => return Err("unsupported string matcher".to_string()),
+    };
+    Ok(StringMatcher {
+        pattern,
+        ignore_case: matcher.ignore_case,
+    })
+}
+
+/// Checks that a regular expression compiles.
+fn check_regex(regex: matcher::RegexMatcher) -> std::result::Result<String, String> {
+    regex::Regex::new(&regex.regex)
+        .map(|_| regex.regex)
+        .map_err(|e| format!("invalid regex: {e}"))
+}
+
+fn convert_forwarding_action(
+    action: route::RouteAction,
+    vhost_retry_policy: Option<&RetryPolicy>,
+    plugins: &ClusterSpecifierPlugins,
+) -> std::result::Result<Option<ForwardingAction>, String> {
+    use route::route_action::ClusterSpecifier as Specifier;
+
+    let cluster = match action.cluster_specifier {
+        Some(Specifier::Cluster(name)) if name.is_empty() => {
+            return Err("empty cluster name".to_string());
+        }
+        Some(Specifier::Cluster(name)) => ClusterSpecifier::Cluster(name),
+        Some(Specifier::WeightedClusters(weighted)) => {
+            let mut total: u64 = 0;
+            let mut clusters = Vec::with_capacity(weighted.clusters.len());
+            for cluster in weighted.clusters {
+                let weight = cluster.weight.map_or(0, |w| w.value);
+                if weight == 0 {
+                    continue;
+                }
+                total += u64::from(weight);
+                clusters.push(ClusterWeight {
+                    name: cluster.name,
+                    weight,
+                });
+            }
+            if total == 0 {
+                return Err("weighted clusters have a total weight of 0".to_string());
+            }
+            if total > u64::from(u32::MAX) {
+                return Err(format!(
+                    "weighted clusters total weight {total} exceeds uint32"
+                ));
+            }
+            ClusterSpecifier::WeightedClusters(clusters)
+        }
+        Some(Specifier::ClusterSpecifierPlugin(name)) => match plugins.get(&name) {
+            Some(Some(plugin)) => ClusterSpecifier::Plugin(plugin.clone()),
+            // The plugin is optional and its type is not supported.
+            Some(None) => return Ok(None),
+            None => return Err(format!("unknown cluster specifier plugin {name}")),
+        },
+        // Cluster headers and inline cluster specifier plugins are not supported, so these
+        // routes are skipped.
+        Some(_) => return Ok(None),
+        None => return Err("route action has no cluster specifier".to_string()),
+    };
+    let hash_policies = action
+        .hash_policy
+        .into_iter()
+        .filter_map(|policy| convert_hash_policy(policy).transpose())
+        .collect::<std::result::Result<_, _>>()?;
+    // A retry policy of the route replaces that of the virtual host.
+    let retry_policy = match action.retry_policy {
+        Some(policy) => Some(convert_retry_policy(policy)?),
+        None => vhost_retry_policy.cloned(),
+    };
+    // The `grpc-timeout` header limit takes precedence over the stream duration. Zero
+    // means unlimited.
+    let max_stream_duration = match action.max_stream_duration {
+        Some(max) => match max.grpc_timeout_header_max.or(max.max_stream_duration) {
+            Some(duration) => Some(convert_duration(&duration)?).filter(|d| !d.is_zero()),
+            None => None,
+        },
+        None => None,
+    };
+    Ok(Some(ForwardingAction {
+        cluster,
+        hash_policies,
+        retry_policy,
+        max_stream_duration,
+    }))
+}
+
+/// The default base interval of the retry backoff.
+const DEFAULT_RETRY_BASE_INTERVAL: Duration = Duration::from_millis(25);
+
+/// Validates and converts a retry policy, see
+/// [gRFC A44](https://github.com/grpc/proposal/blob/master/A44-xds-retry.md).
+///
+/// Retry conditions other than gRPC status codes are ignored.
+fn convert_retry_policy(policy: route::RetryPolicy) -> std::result::Result<RetryPolicy, String> {
+    let retry_on = policy
+        .retry_on
+        .split(',')
+        .filter_map(|condition| match condition.trim() {
+            "cancelled" => Some(1),
+            "deadline-exceeded" => Some(4),
+            "resource-exhausted" => Some(8),
+            "internal" => Some(13),
+            "unavailable" => Some(14),
+            _ => None,
+        })
+        .collect();
+    let (base_interval, max_interval) = match policy.retry_back_off {
+        Some(back_off) => {
+            let Some(base_interval) = &back_off.base_interval else {
+                return Err("retry backoff has no base interval".to_string());
+            };
+            let base_interval = convert_duration(base_interval)?;
+            if base_interval.is_zero() {
+                return Err("retry backoff base interval is zero".to_string());
+            }
+            let max_interval = match &back_off.max_interval {
+                Some(max_interval) => convert_duration(max_interval)?,
+                None => base_interval * 10,
+            };
+            if max_interval < base_interval {
+                return Err(format!(
+                    "retry backoff max interval {max_interval:?} is less than the base interval {base_interval:?}"
+                ));
+            }
+            (base_interval, max_interval)
+        }
+        None => (
+            DEFAULT_RETRY_BASE_INTERVAL,
+            DEFAULT_RETRY_BASE_INTERVAL * 10,
+        ),
+    };
+    Ok(RetryPolicy {
+        retry_on,
+        num_retries: policy.num_retries.map_or(1, |n| n.value),
+        base_interval,
+        max_interval,
+    })
+}
+
+/// The filter state key of the channel id, see gRFC A42.
+const CHANNEL_ID_KEY: &str = "io.grpc.channel_id";
+
+/// Converts a hash policy. Policies gRPC does not support are ignored.
+fn convert_hash_policy(
+    policy: route::route_action::HashPolicy,
+) -> std::result::Result<Option<HashPolicy>, String> {
+    use route::route_action::hash_policy::PolicySpecifier;
+
+    let kind = match policy.policy_specifier {
+        Some(PolicySpecifier::Header(header)) => {
+            let regex_rewrite = match header.regex_rewrite {
+                Some(rewrite) => {
+                    let Some(pattern) = rewrite.pattern else {
+                        return Err("hash policy regex_rewrite has no pattern".to_string());
+                    };
+                    Some(RegexRewrite {
+                        pattern: check_regex(pattern)?,
+                        substitution: rewrite.substitution,
+                    })
+                }
+                None => None,
+            };
+            HashPolicyKind::Header {
+                name: header.header_name,
+                regex_rewrite,
+            }
+        }
+        Some(PolicySpecifier::FilterState(state)) if state.key == CHANNEL_ID_KEY => {
+            HashPolicyKind::ChannelId
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(HashPolicy {
+        kind,
+        terminal: policy.terminal,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::prost::to_any;
+    use envoy_types::pb::envoy::config::core::v3 as core;
+    use envoy_types::pb::google::protobuf::{Duration as ProtoDuration, UInt32Value};
+
+    fn route_config(routes: Vec<route::Route>) -> route::RouteConfiguration {
+        route::RouteConfiguration {
+            name: "route-1".to_string(),
+            virtual_hosts: vec![route::VirtualHost {
+                name: "vhost-1".to_string(),
+                domains: vec!["*".to_string()],
+                routes,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn route(
+        path: route::route_match::PathSpecifier,
+        cluster: route::route_action::ClusterSpecifier,
+    ) -> route::Route {
+        route::Route {
+            r#match: Some(route::RouteMatch {
+                path_specifier: Some(path),
+                ..Default::default()
+            }),
+            action: Some(route::route::Action::Route(route::RouteAction {
+                cluster_specifier: Some(cluster),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn decode(config: route::RouteConfiguration) -> Result<RouteConfiguration> {
+        RouteConfiguration::decode(config.encode_to_vec().into())
+    }
+
+    #[test]
+    fn test_decode_route_configuration() {
+        use route::route_action::ClusterSpecifier as Specifier;
+        use route::route_match::PathSpecifier;
+
+        let weighted = route::WeightedCluster {
+            clusters: vec![
+                route::weighted_cluster::ClusterWeight {
+                    name: "a".to_string(),
+                    weight: Some(UInt32Value { value: 3 }),
+                    ..Default::default()
+                },
+                route::weighted_cluster::ClusterWeight {
+                    name: "b".to_string(),
+                    weight: Some(UInt32Value { value: 0 }),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let config = decode(route_config(vec![
+            route(
+                PathSpecifier::Prefix("/svc/".to_string()),
+                Specifier::WeightedClusters(weighted),
+            ),
+            route(
+                PathSpecifier::ConnectMatcher(Default::default()),
+                Specifier::Cluster("ignored".to_string()),
+            ),
+            route(
+                PathSpecifier::Path("/svc/Method".to_string()),
+                Specifier::Cluster("c".to_string()),
+            ),
+        ]))
+        .unwrap();
+
+        assert_eq!(config.name(), "route-1");
+        let routes = &config.virtual_hosts[0].routes;
+        assert_eq!(routes.len(), 2);
+        assert_eq!(
+            routes[0].route_match.path,
+            PathMatcher::Prefix("/svc/".to_string())
+        );
+        assert!(routes[0].route_match.case_sensitive);
+        assert_eq!(
+            routes[0].action,
+            RouteAction::Forward(ForwardingAction {
+                cluster: ClusterSpecifier::WeightedClusters(vec![ClusterWeight {
+                    name: "a".to_string(),
+                    weight: 3,
+                }]),
+                hash_policies: Vec::new(),
+                retry_policy: None,
+                max_stream_duration: None,
+            })
+        );
+        assert_eq!(
+            routes[1].action,
+            RouteAction::Forward(ForwardingAction {
+                cluster: ClusterSpecifier::Cluster("c".to_string()),
+                hash_policies: Vec::new(),
+                retry_policy: None,
+                max_stream_duration: None,
+            })
+        );
+    }
+
+    fn with_hash_policies(
+        mut route: route::Route,
+        policies: Vec<route::route_action::hash_policy::PolicySpecifier>,
+    ) -> route::Route {
+        if let Some(route::route::Action::Route(action)) = &mut route.action {
+            action.hash_policy = policies
+                .into_iter()
+                .map(|policy| route::route_action::HashPolicy {
+                    policy_specifier: Some(policy),
+                    terminal: true,
+                })
+                .collect();
+        }
+        route
+    }
+
+    #[test]
+    fn test_decode_hash_policies() {
+        use route::route_action::hash_policy::{self, PolicySpecifier};
+        use route::route_action::ClusterSpecifier as Specifier;
+        use route::route_match::PathSpecifier;
+
+        let header = hash_policy::Header {
+            header_name: "session".to_string(),
+            regex_rewrite: Some(matcher::RegexMatchAndSubstitute {
+                pattern: Some(matcher::RegexMatcher {
+                    regex: "v(\\d+)".to_string(),
+                    ..Default::default()
+                }),
+                substitution: "\\1".to_string(),
+            }),
+        };
+        let filter_state = |key: &str| {
+            PolicySpecifier::FilterState(hash_policy::FilterState {
+                key: key.to_string(),
+            })
+        };
+        let config = decode(route_config(vec![with_hash_policies(
+            route(
+                PathSpecifier::Prefix(String::new()),
+                Specifier::Cluster("c".to_string()),
+            ),
+            vec![
+                PolicySpecifier::Header(header),
+                PolicySpecifier::Cookie(Default::default()),
+                filter_state("unknown"),
+                filter_state(CHANNEL_ID_KEY),
+            ],
+        )]))
+        .unwrap();
+
+        let RouteAction::Forward(action) = &config.virtual_hosts[0].routes[0].action else {
+            panic!("not a forwarding route");
+        };
+        assert_eq!(
+            action.hash_policies,
+            vec![
+                HashPolicy {
+                    kind: HashPolicyKind::Header {
+                        name: "session".to_string(),
+                        regex_rewrite: Some(RegexRewrite {
+                            pattern: "v(\\d+)".to_string(),
+                            substitution: "\\1".to_string(),
+                        }),
+                    },
+                    terminal: true,
+                },
+                HashPolicy {
+                    kind: HashPolicyKind::ChannelId,
+                    terminal: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_filter_overrides() {
+        use envoy_types::pb::envoy::extensions::filters::http::fault::v3::HttpFault;
+        use route::route_action::ClusterSpecifier as Specifier;
+        use route::route_match::PathSpecifier;
+
+        let unknown = Any {
+            type_url: "type.googleapis.com/unknown.Filter".to_string(),
+            value: Vec::new(),
+        };
+        let optional = |config: Any| {
+            to_any(&route::FilterConfig {
+                config: Some(config),
+                is_optional: true,
+                ..Default::default()
+            })
+        };
+        let mut route = route(
+            PathSpecifier::Prefix(String::new()),
+            Specifier::Cluster("c".to_string()),
+        );
+        route.typed_per_filter_config = HashMap::from([
+            ("fault".to_string(), optional(to_any(&HttpFault::default()))),
+            ("unknown".to_string(), optional(unknown.clone())),
+        ]);
+        let mut config = route_config(vec![route.clone()]);
+        config.virtual_hosts[0].typed_per_filter_config =
+            HashMap::from([("fault".to_string(), to_any(&HttpFault::default()))]);
+        let config = decode(config).unwrap();
+        let vhost = &config.virtual_hosts[0];
+        assert!(matches!(
+            vhost.filter_overrides.get("fault"),
+            Some(HttpFilterConfig::Fault(_))
+        ));
+        let overrides = &vhost.routes[0].filter_overrides;
+        assert_eq!(overrides.len(), 1);
+        assert!(matches!(
+            overrides.get("fault"),
+            Some(HttpFilterConfig::Fault(_))
+        ));
+
+        // Overrides of unsupported filters are only ignored if optional.
+        route.typed_per_filter_config = HashMap::from([("unknown".to_string(), unknown)]);
+        assert!(decode(route_config(vec![route])).is_err());
+    }
+
+    fn with_retry_policy(mut route: route::Route, policy: route::RetryPolicy) -> route::Route {
+        if let Some(route::route::Action::Route(action)) = &mut route.action {
+            action.retry_policy = Some(policy);
+        }
+        route
+    }
+
+    #[test]
+    fn test_decode_retry_policy_and_timeout() {
+        use route::retry_policy::RetryBackOff;
+        use route::route_action::{ClusterSpecifier as Specifier, MaxStreamDuration};
+        use route::route_match::PathSpecifier;
+
+        let seconds = |seconds| ProtoDuration { seconds, nanos: 0 };
+        let mut with_timeout = route(
+            PathSpecifier::Prefix("/timeout/".to_string()),
+            Specifier::Cluster("c".to_string()),
+        );
+        if let Some(route::route::Action::Route(action)) = &mut with_timeout.action {
+            action.max_stream_duration = Some(MaxStreamDuration {
+                max_stream_duration: Some(seconds(10)),
+                grpc_timeout_header_max: Some(seconds(5)),
+                ..Default::default()
+            });
+        }
+        let with_retries = with_retry_policy(
+            route(
+                PathSpecifier::Prefix("/retry/".to_string()),
+                Specifier::Cluster("c".to_string()),
+            ),
+            route::RetryPolicy {
+                retry_on: "unavailable, reset,cancelled".to_string(),
+                num_retries: Some(UInt32Value { value: 3 }),
+                retry_back_off: Some(RetryBackOff {
+                    base_interval: Some(seconds(1)),
+                    max_interval: None,
+                }),
+                ..Default::default()
+            },
+        );
+        let mut config = route_config(vec![with_timeout, with_retries]);
+        config.virtual_hosts[0].retry_policy = Some(route::RetryPolicy {
+            retry_on: "internal".to_string(),
+            ..Default::default()
+        });
+        let config = decode(config).unwrap();
+
+        let actions: Vec<_> = config.virtual_hosts[0]
+            .routes
+            .iter()
+            .map(|route| match &route.action {
+                RouteAction::Forward(action) => action,
+                _ => panic!("not a forwarding route"),
+            })
+            .collect();
+        // The route inherits the retry policy of the virtual host.
+        assert_eq!(actions[0].max_stream_duration, Some(Duration::from_secs(5)));
+        assert_eq!(
+            actions[0].retry_policy,
+            Some(RetryPolicy {
+                retry_on: vec![13],
+                num_retries: 1,
+                base_interval: Duration::from_millis(25),
+                max_interval: Duration::from_millis(250),
+            })
+        );
+        assert_eq!(actions[1].max_stream_duration, None);
+        assert_eq!(
+            actions[1].retry_policy,
+            Some(RetryPolicy {
+                retry_on: vec![14, 1],
+                num_retries: 3,
+                base_interval: Duration::from_secs(1),
+                max_interval: Duration::from_secs(10),
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_route_configuration() {
+        use route::route_action::ClusterSpecifier as Specifier;
+        use route::route_match::PathSpecifier;
+
+        let invalid_regex = route(
+            PathSpecifier::SafeRegex(matcher::RegexMatcher {
+                regex: "(".to_string(),
+                ..Default::default()
+            }),
+            Specifier::Cluster("c".to_string()),
+        );
+        let zero_weight = route(
+            PathSpecifier::Prefix(String::new()),
+            Specifier::WeightedClusters(route::WeightedCluster::default()),
+        );
+        let empty_cluster = route(
+            PathSpecifier::Prefix(String::new()),
+            Specifier::Cluster(String::new()),
+        );
+        let invalid_rewrite = with_hash_policies(
+            route(
+                PathSpecifier::Prefix(String::new()),
+                Specifier::Cluster("c".to_string()),
+            ),
+            vec![route::route_action::hash_policy::PolicySpecifier::Header(
+                route::route_action::hash_policy::Header {
+                    header_name: "session".to_string(),
+                    regex_rewrite: Some(matcher::RegexMatchAndSubstitute {
+                        pattern: Some(matcher::RegexMatcher {
+                            regex: "(".to_string(),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }),
+                },
+            )],
+        );
+        let invalid_backoff = with_retry_policy(
+            route(
+                PathSpecifier::Prefix(String::new()),
+                Specifier::Cluster("c".to_string()),
+            ),
+            route::RetryPolicy {
+                retry_back_off: Some(route::retry_policy::RetryBackOff {
+                    base_interval: Some(ProtoDuration {
+                        seconds: 2,
+                        nanos: 0,
+                    }),
+                    max_interval: Some(ProtoDuration {
+                        seconds: 1,
+                        nanos: 0,
+                    }),
+                }),
+                ..Default::default()
+            },
+        );
+        for route in [
+            invalid_regex,
+            zero_weight,
+            empty_cluster,
+            invalid_rewrite,
+            invalid_backoff,
+        ] {
+            let error = decode(route_config(vec![route])).unwrap_err();
+            assert!(
+                error.to_string().starts_with("validation error: route-1: "),
+                "{error}"
+            );
+        }
+    }
+
+    fn cluster_specifier_plugin(
+        name: &str,
+        typed_config: Any,
+        is_optional: bool,
+    ) -> route::ClusterSpecifierPlugin {
+        route::ClusterSpecifierPlugin {
+            extension: Some(core::TypedExtensionConfig {
+                name: name.to_string(),
+                typed_config: Some(typed_config),
+            }),
+            is_optional,
+        }
+    }
+
+    #[test]
+    fn test_decode_cluster_specifier_plugins() {
+        use route::route_action::ClusterSpecifier as Specifier;
+        use route::route_match::PathSpecifier;
+
+        let route_lookup = Any {
+            type_url: ROUTE_LOOKUP_CLUSTER_SPECIFIER.to_string(),
+            value: vec![1, 2, 3],
+        };
+        let unsupported = to_any(&UInt32Value { value: 1 });
+        let plugin_route = |prefix: &str, plugin: &str| {
+            route(
+                PathSpecifier::Prefix(prefix.to_string()),
+                Specifier::ClusterSpecifierPlugin(plugin.to_string()),
+            )
+        };
+        let with_plugins = |routes, plugins| route::RouteConfiguration {
+            cluster_specifier_plugins: plugins,
+            ..route_config(routes)
+        };
+
+        // Routes referencing an optional plugin of an unsupported type are skipped.
+        let config = decode(with_plugins(
+            vec![plugin_route("/rls", "rls"), plugin_route("/other", "other")],
+            vec![
+                cluster_specifier_plugin("rls", route_lookup.clone(), false),
+                cluster_specifier_plugin("other", unsupported.clone(), true),
+            ],
+        ))
+        .unwrap();
+        let routes = &config.virtual_hosts[0].routes;
+        assert_eq!(routes.len(), 1);
+        assert_eq!(
+            routes[0].action,
+            RouteAction::Forward(ForwardingAction {
+                cluster: ClusterSpecifier::Plugin(ClusterSpecifierPlugin {
+                    name: "rls".to_string(),
+                    config: ClusterSpecifierPluginConfig::RouteLookup(Bytes::from_static(&[
+                        1, 2, 3
+                    ])),
+                }),
+                hash_policies: Vec::new(),
+                retry_policy: None,
+                max_stream_duration: None,
+            })
+        );
+
+        let invalid = [
+            // Duplicate plugin names.
+            with_plugins(
+                Vec::new(),
+                vec![
+                    cluster_specifier_plugin("rls", route_lookup.clone(), false),
+                    cluster_specifier_plugin("rls", route_lookup.clone(), false),
+                ],
+            ),
+            // A required plugin of an unsupported type.
+            with_plugins(
+                Vec::new(),
+                vec![cluster_specifier_plugin("other", unsupported, false)],
+            ),
+            // A route referencing a plugin that is not defined.
+            with_plugins(
+                vec![plugin_route("/", "missing")],
+                vec![cluster_specifier_plugin("rls", route_lookup, false)],
+            ),
+        ];
+        for config in invalid {
+            let error = decode(config).unwrap_err();
+            assert!(
+                error.to_string().starts_with("validation error: route-1: "),
+                "{error}"
+            );
+        }
+    }
+}
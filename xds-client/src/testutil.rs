@@ -0,0 +1,357 @@
+//! Test utilities: an in-memory ADS transport and simple resource types.
+
+use crate::client::config::ServerConfig;
+use crate::error::{Error, Result};
+use crate::resource::{Resource, TypeUrl};
+use crate::transport::{Transport, TransportBuilder, TransportStream};
+use bytes::Bytes;
+use envoy_types::pb::envoy::service::discovery::v3 as discovery;
+use envoy_types::pb::envoy::service::load_stats::v3 as load_stats;
+use envoy_types::pb::google::protobuf::Any;
+use futures_channel::mpsc;
+use futures_util::StreamExt;
+use prost::Message;
+use std::time::Duration;
+
+/// Upper bound for waiting on the worker in tests, so that a bug fails the test instead of hanging it.
+const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Decodes a test resource encoded as `name=value`. The name may contain `=` itself.
+fn decode_name_value(bytes: &[u8]) -> Result<(String, String)> {
+    let s = std::str::from_utf8(bytes).map_err(|e| Error::Validation(e.to_string()))?;
+    let (name, value) = s
+        .rsplit_once('=')
+        .ok_or_else(|| Error::Validation(format!("malformed test resource: {s}")))?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// A resource used for testing, encoded as `name=value`. Resources with an empty value
+/// are invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TestResource {
+    pub(crate) name: String,
+    pub(crate) value: String,
+}
+
+impl TestResource {
+    pub(crate) fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    /// Wraps the resource in an `Any`.
+    pub(crate) fn to_any(&self) -> Any {
+        Any {
+            type_url: Self::TYPE_URL.as_str().to_string(),
+            value: format!("{}={}", self.name, self.value).into_bytes(),
+        }
+    }
+
+    /// Returns an `Any` of this type that fails to decode.
+    pub(crate) fn malformed_any() -> Any {
+        Any {
+            type_url: Self::TYPE_URL.as_str().to_string(),
+            value: b"malformed".to_vec(),
+        }
+    }
+}
+
+impl Resource for TestResource {
+    const TYPE_URL: TypeUrl = TypeUrl::new("type.googleapis.com/test.Resource");
+
+    fn decode(bytes: Bytes) -> Result<Self> {
+        let (name, value) = decode_name_value(&bytes)?;
+        if value.is_empty() {
+            return Err(Error::InvalidResource {
+                name,
+                message: "empty value".to_string(),
+            });
+        }
+        Ok(Self { name, value })
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// A second resource type, for tests involving multiple types. Like Listeners, its
+/// state-of-the-world responses contain all the subscribed resources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct OtherTestResource {
+    pub(crate) name: String,
+}
+
+impl Resource for OtherTestResource {
+    const TYPE_URL: TypeUrl = TypeUrl::new("type.googleapis.com/test.OtherResource");
+    const ALL_RESOURCES_REQUIRED_IN_SOTW: bool = true;
+
+    fn decode(bytes: Bytes) -> Result<Self> {
+        let (name, _) = decode_name_value(&bytes)?;
+        Ok(Self { name })
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Builds a response of [`TestResource`]s.
+pub(crate) fn test_response(
+    version_info: &str,
+    nonce: &str,
+    resources: &[TestResource],
+) -> discovery::DiscoveryResponse {
+    discovery::DiscoveryResponse {
+        version_info: version_info.to_string(),
+        type_url: TestResource::TYPE_URL.as_str().to_string(),
+        nonce: nonce.to_string(),
+        resources: resources.iter().map(TestResource::to_any).collect(),
+        ..Default::default()
+    }
+}
+
+/// Builds a response of [`OtherTestResource`]s with the given names.
+pub(crate) fn other_test_response(
+    version_info: &str,
+    nonce: &str,
+    names: &[&str],
+) -> discovery::DiscoveryResponse {
+    discovery::DiscoveryResponse {
+        version_info: version_info.to_string(),
+        type_url: OtherTestResource::TYPE_URL.as_str().to_string(),
+        nonce: nonce.to_string(),
+        resources: names
+            .iter()
+            .map(|name| Any {
+                type_url: OtherTestResource::TYPE_URL.as_str().to_string(),
+                value: format!("{name}=").into_bytes(),
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Builds a delta response of [`TestResource`]s at the given version, removing the
+/// `removed` resources.
+pub(crate) fn test_delta_response(
+    version: &str,
+    nonce: &str,
+    resources: &[TestResource],
+    removed: &[&str],
+) -> discovery::DeltaDiscoveryResponse {
+    discovery::DeltaDiscoveryResponse {
+        type_url: TestResource::TYPE_URL.as_str().to_string(),
+        nonce: nonce.to_string(),
+        resources: resources
+            .iter()
+            .map(|resource| discovery::Resource {
+                name: resource.name.clone(),
+                version: version.to_string(),
+                resource: Some(resource.to_any()),
+                ..Default::default()
+            })
+            .collect(),
+        removed_resources: removed.iter().map(|name| name.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+/// Creates a [`MockTransportBuilder`] and the [`MockServer`] that drives the streams of
+/// all its transports.
+pub(crate) fn mock_transport() -> (MockTransportBuilder, MockServer) {
+    let (streams_tx, streams_rx) = mpsc::unbounded();
+    (
+        MockTransportBuilder {
+            streams: streams_tx,
+        },
+        MockServer {
+            streams: streams_rx,
+        },
+    )
+}
+
+/// Builds [`MockTransport`]s sharing a single [`MockServer`].
+#[derive(Debug)]
+pub(crate) struct MockTransportBuilder {
+    streams: mpsc::UnboundedSender<MockServerStream>,
+}
+
+impl TransportBuilder for MockTransportBuilder {
+    type Transport = MockTransport;
+
+    fn build(&self, server: &ServerConfig) -> Result<Self::Transport> {
+        Ok(MockTransport {
+            server_uri: server.server_uri.clone(),
+            streams: self.streams.clone(),
+        })
+    }
+}
+
+/// An in-memory [`Transport`] whose streams are handed to a [`MockServer`].
+#[derive(Debug)]
+pub(crate) struct MockTransport {
+    server_uri: String,
+    streams: mpsc::UnboundedSender<MockServerStream>,
+}
+
+impl MockTransport {
+    fn open_stream(&self, delta: bool, load_stats: bool) -> Result<MockAdsStream> {
+        let (requests_tx, requests_rx) = mpsc::unbounded();
+        let (responses_tx, responses_rx) = mpsc::unbounded();
+        self.streams
+            .unbounded_send(MockServerStream {
+                server_uri: self.server_uri.clone(),
+                delta,
+                load_stats,
+                requests: requests_rx,
+                responses: responses_tx,
+            })
+            .map_err(|_| Error::Connection("mock server dropped".to_string()))?;
+        Ok(MockAdsStream {
+            requests: requests_tx,
+            responses: responses_rx,
+        })
+    }
+}
+
+impl Transport for MockTransport {
+    type Stream = MockAdsStream;
+
+    async fn new_stream(&self) -> Result<Self::Stream> {
+        self.open_stream(false, false)
+    }
+
+    async fn new_delta_stream(&self) -> Result<Self::Stream> {
+        self.open_stream(true, false)
+    }
+
+    async fn new_load_stats_stream(&self) -> Result<Self::Stream> {
+        self.open_stream(false, true)
+    }
+}
+
+/// Client side of a mock ADS stream.
+#[derive(Debug)]
+pub(crate) struct MockAdsStream {
+    requests: mpsc::UnboundedSender<Bytes>,
+    responses: mpsc::UnboundedReceiver<Result<Bytes>>,
+}
+
+impl TransportStream for MockAdsStream {
+    async fn send(&mut self, request: Bytes) -> Result<()> {
+        self.requests
+            .unbounded_send(request)
+            .map_err(|_| Error::StreamClosed)
+    }
+
+    async fn recv(&mut self) -> Result<Option<Bytes>> {
+        self.responses.next().await.transpose()
+    }
+}
+
+/// Accepts the streams opened by a [`MockTransport`].
+#[derive(Debug)]
+pub(crate) struct MockServer {
+    streams: mpsc::UnboundedReceiver<MockServerStream>,
+}
+
+impl MockServer {
+    /// Waits for the client to open the next stream.
+    pub(crate) async fn next_stream(&mut self) -> MockServerStream {
+        tokio::time::timeout(TEST_TIMEOUT, self.streams.next())
+            .await
+            .expect("timed out waiting for stream")
+            .expect("transport dropped")
+    }
+}
+
+/// Server side of a mock ADS stream. Dropping it closes the stream.
+#[derive(Debug)]
+pub(crate) struct MockServerStream {
+    /// The URI of the server the stream was opened to.
+    pub(crate) server_uri: String,
+    /// Whether this is a delta ADS stream.
+    pub(crate) delta: bool,
+    /// Whether this is an LRS stream rather than an ADS stream.
+    pub(crate) load_stats: bool,
+    requests: mpsc::UnboundedReceiver<Bytes>,
+    responses: mpsc::UnboundedSender<Result<Bytes>>,
+}
+
+impl MockServerStream {
+    /// Waits for the next request from the client.
+    pub(crate) async fn recv_request(&mut self) -> discovery::DiscoveryRequest {
+        let bytes = tokio::time::timeout(TEST_TIMEOUT, self.requests.next())
+            .await
+            .expect("timed out waiting for request")
+            .expect("stream closed");
+        discovery::DiscoveryRequest::decode(bytes).unwrap()
+    }
+
+    /// Returns the next request if one has already been sent.
+    pub(crate) fn try_recv_request(&mut self) -> Option<discovery::DiscoveryRequest> {
+        let bytes = self.requests.try_recv().ok()?;
+        Some(discovery::DiscoveryRequest::decode(bytes).unwrap())
+    }
+
+    /// Sends a response to the client.
+    pub(crate) fn send_response(&self, response: discovery::DiscoveryResponse) {
+        let _ = self
+            .responses
+            .unbounded_send(Ok(response.encode_to_vec().into()));
+    }
+
+    /// Sends raw bytes to the client as a response.
+    pub(crate) fn send_raw(&self, bytes: Bytes) {
+        let _ = self.responses.unbounded_send(Ok(bytes));
+    }
+
+    /// Waits for the next delta request from the client.
+    pub(crate) async fn recv_delta_request(&mut self) -> discovery::DeltaDiscoveryRequest {
+        let bytes = tokio::time::timeout(TEST_TIMEOUT, self.requests.next())
+            .await
+            .expect("timed out waiting for delta request")
+            .expect("stream closed");
+        discovery::DeltaDiscoveryRequest::decode(bytes).unwrap()
+    }
+
+    /// Sends a delta response to the client.
+    pub(crate) fn send_delta_response(&self, response: discovery::DeltaDiscoveryResponse) {
+        let _ = self
+            .responses
+            .unbounded_send(Ok(response.encode_to_vec().into()));
+    }
+
+    /// Waits for the next load report from the client.
+    pub(crate) async fn recv_load_stats_request(&mut self) -> load_stats::LoadStatsRequest {
+        let bytes = tokio::time::timeout(TEST_TIMEOUT, self.requests.next())
+            .await
+            .expect("timed out waiting for load report")
+            .expect("stream closed");
+        load_stats::LoadStatsRequest::decode(bytes).unwrap()
+    }
+
+    /// Sends an LRS response to the client.
+    pub(crate) fn send_load_stats_response(&self, response: load_stats::LoadStatsResponse) {
+        let _ = self
+            .responses
+            .unbounded_send(Ok(response.encode_to_vec().into()));
+    }
+
+    /// Waits for the client to close the stream, ignoring pending requests.
+    pub(crate) async fn closed(&mut self) {
+        tokio::time::timeout(TEST_TIMEOUT, async {
+            while self.requests.next().await.is_some() {}
+        })
+        .await
+        .expect("timed out waiting for the stream to close");
+    }
+
+    /// Fails the stream with the given error.
+    pub(crate) fn send_error(&self, error: Error) {
+        let _ = self.responses.unbounded_send(Err(error));
+    }
+}
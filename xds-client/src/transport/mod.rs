@@ -1,5 +1,6 @@
 //! Provides abstraction for transport layers.
 
+use crate::client::config::ServerConfig;
 use crate::error::Result;
 use bytes::Bytes;
 use std::future::Future;
@@ -26,11 +27,39 @@ pub trait Transport: Send + Sync + 'static {
     ///
     /// This may be called multiple times for reconnection.
     fn new_stream(&self) -> impl Future<Output = Result<Self::Stream>> + Send;
+
+    /// Creates a new bidirectional incremental (delta) ADS stream to the xDS server,
+    /// carrying serialized DeltaDiscoveryRequest/DeltaDiscoveryResponse bytes.
+    ///
+    /// This may be called multiple times for reconnection.
+    fn new_delta_stream(&self) -> impl Future<Output = Result<Self::Stream>> + Send;
+
+    /// Creates a new bidirectional LRS stream to the xDS server, carrying serialized
+    /// LoadStatsRequest/LoadStatsResponse bytes.
+    ///
+    /// This may be called multiple times for reconnection.
+    fn new_load_stats_stream(&self) -> impl Future<Output = Result<Self::Stream>> + Send;
 }
 
-/// A bidirectional byte stream for xDS ADS communication.
+/// Factory for creating [`Transport`]s to xDS servers.
 ///
-/// Raw byte transport where the bytes are serialized DiscoveryRequest/DiscoveryResponse
+/// The client creates a transport for each xDS server it talks to, e.g. one per
+/// authority when `xdstp://` resource names are used.
+pub trait TransportBuilder: Send + Sync + 'static {
+    /// The transport type produced by this builder.
+    type Transport: Transport;
+
+    /// Creates a transport to the given server.
+    ///
+    /// This should not connect eagerly; connection errors are reported by
+    /// [`Transport::new_stream`].
+    fn build(&self, server: &ServerConfig) -> Result<Self::Transport>;
+}
+
+/// A bidirectional byte stream for xDS ADS or LRS communication.
+///
+/// Raw byte transport where the bytes are serialized DiscoveryRequest/DiscoveryResponse,
+/// DeltaDiscoveryRequest/DeltaDiscoveryResponse, or LoadStatsRequest/LoadStatsResponse;
 /// (de)serialization is handled at the xDS client worker layer.
 // Sealed for now to limit API surface.
 pub trait TransportStream: sealed::Sealed + Send + 'static {
@@ -48,3 +77,6 @@ pub trait TransportStream: sealed::Sealed + Send + 'static {
 
 #[cfg(feature = "transport-tonic")]
 impl sealed::Sealed for tonic::TonicAdsStream {}
+
+#[cfg(test)]
+impl sealed::Sealed for crate::testutil::MockAdsStream {}
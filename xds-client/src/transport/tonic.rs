@@ -4,8 +4,9 @@
 //! to send and receive raw bytes, allowing the xDS client layer to handle
 //! serialization/deserialization independently.
 
+use crate::client::config::ServerConfig;
 use crate::error::{Error, Result};
-use crate::transport::{Transport, TransportStream};
+use crate::transport::{Transport, TransportBuilder, TransportStream};
 use bytes::{Buf, BufMut, Bytes};
 use http::uri::PathAndQuery;
 use tokio::sync::mpsc;
@@ -18,6 +19,13 @@ use tonic::{Status, Streaming};
 const ADS_PATH: &str =
     "/envoy.service.discovery.v3.AggregatedDiscoveryService/StreamAggregatedResources";
 
+/// The gRPC path for the ADS DeltaAggregatedResources RPC.
+const DELTA_ADS_PATH: &str =
+    "/envoy.service.discovery.v3.AggregatedDiscoveryService/DeltaAggregatedResources";
+
+/// The gRPC path for the LRS StreamLoadStats RPC.
+const LRS_PATH: &str = "/envoy.service.load_stats.v3.LoadReportingService/StreamLoadStats";
+
 const ADS_CHANNEL_BUFFER_SIZE: usize = 16;
 
 /// A codec that passes bytes through without serialization.
@@ -93,10 +101,43 @@ impl TonicTransport {
     }
 }
 
-impl Transport for TonicTransport {
-    type Stream = TonicAdsStream;
+/// Builder for [`TonicTransport`]s to the servers of a [`ClientConfig`](crate::ClientConfig).
+///
+/// Only `insecure` channel credentials are supported.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TonicTransportBuilder;
+
+impl TransportBuilder for TonicTransportBuilder {
+    type Transport = TonicTransport;
+
+    fn build(&self, server: &ServerConfig) -> Result<Self::Transport> {
+        if !server
+            .channel_creds
+            .iter()
+            .any(|creds| creds.type_name == "insecure")
+        {
+            return Err(Error::Connection(format!(
+                "no supported channel credentials for {}",
+                server.server_uri
+            )));
+        }
+        // Bootstrap server URIs are gRPC targets such as `dns:///host:port` or `host:port`.
+        let uri = &server.server_uri;
+        let uri = if uri.starts_with("http://") || uri.starts_with("https://") {
+            uri.clone()
+        } else {
+            format!("http://{}", uri.strip_prefix("dns:///").unwrap_or(uri))
+        };
+        let channel = Channel::from_shared(uri)
+            .map_err(|e| Error::Connection(e.to_string()))?
+            .connect_lazy();
+        Ok(TonicTransport { channel })
+    }
+}
 
-    async fn new_stream(&self) -> Result<Self::Stream> {
+impl TonicTransport {
+    /// Opens a bidirectional streaming RPC with the given path.
+    async fn open_stream(&self, path: &'static str) -> Result<TonicAdsStream> {
         let mut grpc = Grpc::new(self.channel.clone());
 
         grpc.ready()
@@ -106,7 +147,7 @@ impl Transport for TonicTransport {
         let (tx, rx) = mpsc::channel::<Bytes>(ADS_CHANNEL_BUFFER_SIZE);
         let request_stream = tokio_stream::wrappers::ReceiverStream::new(rx);
 
-        let path = PathAndQuery::from_static(ADS_PATH);
+        let path = PathAndQuery::from_static(path);
 
         let response = grpc
             .streaming(tonic::Request::new(request_stream), path, BytesCodec)
@@ -120,7 +161,23 @@ impl Transport for TonicTransport {
     }
 }
 
-/// A bidirectional ADS stream backed by tonic.
+impl Transport for TonicTransport {
+    type Stream = TonicAdsStream;
+
+    async fn new_stream(&self) -> Result<Self::Stream> {
+        self.open_stream(ADS_PATH).await
+    }
+
+    async fn new_delta_stream(&self) -> Result<Self::Stream> {
+        self.open_stream(DELTA_ADS_PATH).await
+    }
+
+    async fn new_load_stats_stream(&self) -> Result<Self::Stream> {
+        self.open_stream(LRS_PATH).await
+    }
+}
+
+/// A bidirectional ADS, delta ADS or LRS stream backed by tonic.
 #[derive(Debug)]
 pub struct TonicAdsStream {
     sender: mpsc::Sender<Bytes>,
@@ -249,4 +306,22 @@ mod tests {
         );
         assert_eq!(response.nonce, "nonce-1");
     }
+
+    #[tokio::test]
+    async fn test_tonic_transport_builder() {
+        let addr = start_mock_server().await;
+        let server = ServerConfig::new(format!("dns:///{addr}"));
+
+        let transport = TonicTransportBuilder.build(&server).unwrap();
+        let mut stream = transport.new_stream().await.unwrap();
+        stream
+            .send(DiscoveryRequest::default().encode_to_vec().into())
+            .await
+            .unwrap();
+        assert!(stream.recv().await.unwrap().is_some());
+
+        let mut server = ServerConfig::new(addr.to_string());
+        server.channel_creds[0].type_name = "google_default".to_string();
+        assert!(TonicTransportBuilder.build(&server).is_err());
+    }
 }